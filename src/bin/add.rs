@@ -0,0 +1,276 @@
+//! An implementation of `git add`, which updates the index with the current contents of the files
+//! in the working tree, including recording the removal of files that no longer exist.
+
+extern crate gitters;
+
+extern crate rustc_serialize;
+extern crate docopt;
+
+use docopt::Docopt;
+use gitters::cli;
+use gitters::config;
+use gitters::ignore;
+use gitters::index;
+use gitters::objects;
+use gitters::pathspec::Pathspec;
+use gitters::status;
+use gitters::trees;
+use gitters::untracked;
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+
+const USAGE: &'static str = "
+add - Add file contents to the index
+
+Usage:
+  add [options] [--] [<pathspec>...]
+  add (-h | --help)
+
+Options:
+  -h --help           Show this screen.
+  -n --dry-run        Don't actually add the files, just show which ones would be added or
+                      removed.
+  -v --verbose        Show the files as they are added or removed.
+  -f --force          Allow adding files that are otherwise ignored.
+  -u --update         Only update the files that are already tracked, adding their new contents
+                      and removing the ones that no longer exist. Without a pathspec, updates the
+                      whole working tree.
+  -A --all            Like --update, but also add the untracked files.
+  -N --intent-to-add  Record only that the untracked files will be added later, without staging
+                      their contents.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_dry_run: bool,
+    flag_verbose: bool,
+    flag_force: bool,
+    flag_update: bool,
+    flag_all: bool,
+    flag_intent_to_add: bool,
+    arg_pathspec: Vec<String>,
+}
+
+/// The changes to make to the index, collected up front so that an invalid pathspec leaves the
+/// index untouched.
+struct Changes {
+    /// The paths to update, mapped to whether they should be added or removed.
+    paths: BTreeMap<Vec<u8>, Action>,
+    ignored: Vec<String>,
+}
+
+#[derive(PartialEq, Eq)]
+enum Action {
+    Add,
+    Remove,
+}
+
+fn display(path: &[u8]) -> String {
+    String::from_utf8_lossy(path).into_owned()
+}
+
+/// Record the current contents of the file in the index, writing the blob to the object database.
+fn stage_file(index: &mut index::Index, path: &[u8], intent_to_add: bool,
+              trust_executable_bit: bool) -> cli::Result {
+    let metadata = try!(cli::wrap_with_status(fs::symlink_metadata(status::fs_path(path)), 128));
+    let existing_mode = index.entry(path).map(|entry| entry.mode).unwrap_or(trees::MODE_FILE);
+    let mode = status::worktree_mode(&metadata, existing_mode, trust_executable_bit);
+    let stat = index::StatData::from_metadata(&metadata);
+
+    // An intent-to-add entry records the empty blob, which needs to exist like any other object
+    // referenced by the index.
+    let contents = if intent_to_add {
+        Vec::new()
+    } else {
+        try!(cli::wrap_with_status(status::read_worktree_file(path, &metadata), 128))
+    };
+    let objects::Name(sha1) =
+        try!(cli::wrap_with_status(objects::write_object(objects::Type::Blob, &contents), 128));
+
+    let entry = if intent_to_add {
+        index::Entry::intent_to_add(path.to_vec(), mode, stat)
+    } else {
+        index::Entry::new(path.to_vec(), mode, sha1, stat)
+    };
+
+    index.add(entry);
+    cli::success()
+}
+
+fn collect_changes(cfg: &config::Config, index: &mut index::Index, pathspec: &Pathspec,
+                   args: &Args) -> Result<Changes, cli::Error> {
+    let mut changes = Changes { paths: BTreeMap::new(), ignored: Vec::new() };
+    let mut matched = vec![false; pathspec.originals.len()];
+
+    // Without a pathspec, --update and --all apply to the whole working tree.
+    let selects = |path: &[u8], matched: &mut Vec<bool>| {
+        if pathspec.is_empty() {
+            return true;
+        }
+        match pathspec.matching_item(path) {
+            Some(item) => { matched[item] = true; true },
+            None => false,
+        }
+    };
+
+    let index_timestamp = index.timestamp;
+    let trust_executable_bit = status::trust_executable_bit(cfg);
+    for entry in index.entries.iter_mut() {
+        if !selects(&entry.path, &mut matched) {
+            continue;
+        }
+
+        let exists = fs::symlink_metadata(status::fs_path(&entry.path)).is_ok();
+        let change = if entry.stage() > 0 || entry.is_intent_to_add() {
+            // Adding a conflicted file marks the conflict as resolved, and adding an intent-to-add
+            // entry stages its contents, even if the file is unchanged.
+            Some(if exists { status::Change::Modified } else { status::Change::Deleted })
+        } else {
+            let (change, _, _) =
+                try!(cli::wrap_with_status(
+                        status::compare_worktree(entry, index_timestamp, trust_executable_bit),
+                        128));
+            change
+        };
+
+        match change {
+            Some(status::Change::Deleted) => {
+                changes.paths.insert(entry.path.clone(), Action::Remove);
+            },
+            Some(_) => { changes.paths.insert(entry.path.clone(), Action::Add); },
+            None => {},
+        }
+    }
+
+    if !args.flag_update {
+        let mut excludes = try!(cli::wrap_with_status(ignore::Excludes::standard(cfg), 128));
+        let options = untracked::Options {
+            collapse_directories: false,
+            hide_empty_directories: false,
+            show_ignored: true,
+            collapse_ignored_directories: !args.flag_force,
+            use_untracked_cache: false,
+        };
+        let (files, _) =
+            try!(cli::wrap_with_status(untracked::scan(index, cfg, &mut excludes, &options), 128));
+
+        for path in files.untracked.iter() {
+            let path = path.as_os_str().as_bytes();
+            if !path.ends_with(b"/") && selects(path, &mut matched) {
+                changes.paths.insert(path.to_vec(), Action::Add);
+            }
+        }
+
+        // Ignored files are only added when forced. Otherwise, like in git, it's an error to name
+        // an ignored path, or a path inside an ignored directory, but not to match one by naming a
+        // directory containing it, or with a wildcard.
+        for path in files.ignored.iter() {
+            let path = path.as_os_str().as_bytes();
+            if pathspec.is_empty() {
+                continue;
+            }
+
+            if args.flag_force {
+                if !path.ends_with(b"/") && selects(path, &mut matched) {
+                    changes.paths.insert(path.to_vec(), Action::Add);
+                }
+                continue;
+            }
+
+            let is_dir = path.ends_with(b"/");
+            let name = if is_dir { &path[..path.len() - 1] } else { path };
+            for item in 0..pathspec.originals.len() {
+                if pathspec.names_exactly(item, name) ||
+                        (is_dir && pathspec.names_path_inside(item, name)) {
+                    matched[item] = true;
+                    let original = &pathspec.originals[item];
+                    if !changes.ignored.contains(original) {
+                        changes.ignored.push(original.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    for (item, was_matched) in matched.into_iter().enumerate() {
+        if !was_matched {
+            return Err(cli::Error {
+                message: format!("fatal: pathspec '{}' did not match any files",
+                                 pathspec.originals[item]),
+                status: 128,
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+fn dispatch_for_args(args: &Args) -> cli::Result {
+    let pathspec = Pathspec::new(&args.arg_pathspec);
+    if pathspec.is_empty() && !args.flag_update && !args.flag_all {
+        eprintln!("Nothing specified, nothing added.");
+        cli::hint("Maybe you wanted to say 'git add .'?");
+        return cli::success();
+    }
+
+    let cfg = try!(cli::wrap_with_status(config::read_all(), 128));
+
+    // The lock is taken before reading the index, so no other process can modify the index between
+    // reading and writing it.
+    let lock = if args.flag_dry_run {
+        None
+    } else {
+        Some(try!(cli::wrap_with_status(index::Index::lock(), 128)))
+    };
+    let mut index = try!(cli::wrap_with_status(index::Index::read(), 128));
+
+    let changes = try!(collect_changes(&cfg, &mut index, &pathspec, args));
+    let show = args.flag_verbose || args.flag_dry_run;
+    let trust_executable_bit = status::trust_executable_bit(&cfg);
+
+    for (path, action) in changes.paths.iter() {
+        if *action == Action::Remove {
+            if show {
+                println!("remove '{}'", display(path));
+            }
+            if !args.flag_dry_run {
+                index.remove(path);
+            }
+        } else {
+            if show {
+                println!("add '{}'", display(path));
+            }
+            if !args.flag_dry_run {
+                let is_tracked = index.entries.iter().any(|entry| entry.path == *path);
+                try!(stage_file(&mut index, path, args.flag_intent_to_add && !is_tracked,
+                                trust_executable_bit));
+            }
+        }
+    }
+
+    if let Some(lock) = lock {
+        try!(cli::wrap_with_status(index.write_locked(lock), 128));
+    }
+
+    if !changes.ignored.is_empty() {
+        let mut message =
+            "The following paths are ignored by one of your .gitignore files:\n".to_string();
+        for path in changes.ignored.iter() {
+            message.push_str(path);
+            message.push('\n');
+        }
+        message.push_str("hint: Use -f if you really want to add them.");
+        return Err(cli::Error { message: message, status: 1 });
+    }
+
+    cli::success()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.decode())
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
+
+    cli::exit_with(dispatch_for_args(&args))
+}
@@ -6,30 +6,47 @@ extern crate docopt;
 use docopt::Docopt;
 use gitters::cli;
 use gitters::branch;
+use gitters::color;
+use gitters::config;
+use gitters::pager;
 
 const USAGE: &'static str = "
 branch - List branches
 
 Usage:
-  branch
+  branch [options]
   branch (-h | --help)
 
 Options:
-  -h --help  Show this screen.
+  -h --help       Show this screen.
+  --color=<when>  Color the output always, never or only when it's going to a terminal (auto),
+                  overriding color.branch and color.ui.
+  --no-color      Don't color the output.
 ";
 
 #[derive(RustcDecodable)]
-struct Args {}
+struct Args {
+    flag_color: Option<String>,
+    flag_no_color: bool,
+}
+
+fn list_branches(args: &Args) -> cli::Result {
+    let color_mode = try!(cli::color_mode(&args.flag_color, args.flag_no_color));
+    let cfg = try!(cli::wrap_with_status(config::read_all(), 128));
+    try!(cli::wrap_with_status(pager::setup(&cfg, "branch", false), 128));
+
+    let colors = try!(cli::wrap_with_status(color::Colors::new(&cfg, "branch", color_mode), 128));
+    let current_color = try!(cli::wrap_with_status(colors.get(&color::BRANCH_CURRENT), 128));
+    let local_color = try!(cli::wrap_with_status(colors.get(&color::BRANCH_LOCAL), 128));
 
-fn list_branches() -> cli::Result {
-    let current_branch = try!(cli::wrap_with_status(branch::current_branch(), 1));
-    let all_branches = try!(cli::wrap_with_status(branch::all_branches(), 1));
+    let current_branch = try!(cli::wrap_with_status(branch::current_branch(), 128));
+    let all_branches = try!(cli::wrap_with_status(branch::all_branches(), 128));
 
     for branch in all_branches {
         if branch == current_branch {
-            println!("\x1B[0;32m* {}\x1B[0m", branch);
+            println!("* {}{}{}", current_color, branch, colors.reset());
         } else {
-            println!("  {}", branch);
+            println!("  {}{}{}", local_color, branch, colors.reset());
         }
     }
 
@@ -37,9 +54,9 @@ fn list_branches() -> cli::Result {
 }
 
 fn main() {
-    let _: Args = Docopt::new(USAGE)
+    let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.decode())
-        .unwrap_or_else(|e| e.exit());
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
 
-    cli::exit_with(list_branches())
+    cli::exit_with(list_branches(&args))
 }
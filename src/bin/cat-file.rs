@@ -8,6 +8,7 @@ use gitters::cli;
 use gitters::commits;
 use gitters::objects;
 use gitters::revisions;
+use gitters::trees;
 
 const USAGE: &'static str = "
 cat-file - Provide content or type and size information for repository objects
@@ -38,12 +39,13 @@ struct Args {
 }
 
 fn show_type(name: &objects::Name) -> cli::Result {
-    let header = try!(cli::wrap_with_status(objects::read_header(&name), 1));
+    let header = try!(cli::wrap_with_status(objects::read_header(&name), 128));
 
     let object_type = match header.object_type {
         objects::Type::Blob => "blob",
         objects::Type::Tree => "tree",
         objects::Type::Commit => "commit",
+        objects::Type::Tag => "tag",
     };
     println!("{}", object_type);
 
@@ -51,19 +53,20 @@ fn show_type(name: &objects::Name) -> cli::Result {
 }
 
 fn show_size(name: &objects::Name) -> cli::Result {
-    let header = try!(cli::wrap_with_status(objects::read_header(&name), 1));
+    let header = try!(cli::wrap_with_status(objects::read_header(&name), 128));
     println!("{}", header.content_length);
 
     cli::success()
 }
 
 fn check_validity(name: &objects::Name) -> cli::Result {
-    try!(cli::wrap_with_status(objects::read_header(&name), 1));
+    // Like in git, a missing object is only reported through the status.
+    try!(objects::read_header(&name).map_err(|_| cli::Error { message: String::new(), status: 1 }));
     cli::success()
 }
 
 fn show_contents(name: &objects::Name) -> cli::Result {
-    let obj = try!(cli::wrap_with_status(objects::read_object(&name), 1));
+    let obj = try!(cli::wrap_with_status(objects::read_object(&name), 128));
     match obj {
         objects::Object::Commit(commit) => {
             let objects::Name(name) = commit.name;
@@ -72,8 +75,7 @@ fn show_contents(name: &objects::Name) -> cli::Result {
             let objects::Name(tree) = commit.tree;
             println!("tree     : {}", tree);
 
-            if commit.parent.is_some() {
-                let objects::Name(parent) = commit.parent.unwrap();
+            for objects::Name(parent) in commit.parents {
                 println!("parent   : {}", parent);
             }
 
@@ -86,19 +88,32 @@ fn show_contents(name: &objects::Name) -> cli::Result {
             println!("");
             println!("{}", commit.message);
         },
-        objects::Object::Blob(contents) => {
+        objects::Object::Blob(contents) | objects::Object::Tag(contents) => {
             // Don't use println, as we don't want to include an extra newline at the end of the
             // blob contents.
             print!("{}", contents);
         },
-        _ => { /* Not handled yet */ }
+        objects::Object::Tree(tree) => {
+            for entry in tree.entries {
+                let entry_type = if entry.is_tree() {
+                    "tree"
+                } else if entry.mode == trees::MODE_GITLINK {
+                    "commit"
+                } else {
+                    "blob"
+                };
+                let objects::Name(sha1) = entry.sha1;
+                println!("{:06o} {} {}\t{}",
+                         entry.mode, entry_type, sha1, String::from_utf8_lossy(&entry.name));
+            }
+        },
     }
 
     cli::success()
 }
 
 fn dispatch_for_args(args: &Args) -> cli::Result {
-    let name = try!(cli::wrap_with_status(revisions::resolve(&args.arg_object), 1));
+    let name = try!(cli::wrap_with_status(revisions::resolve(&args.arg_object), 128));
 
     if args.flag_t {
         show_type(&name)
@@ -109,14 +124,17 @@ fn dispatch_for_args(args: &Args) -> cli::Result {
     } else if args.flag_p {
         show_contents(&name)
     } else {
-        Err(cli::Error { message: "No flags specified".to_string(), status: 2 })
+        Err(cli::Error {
+            message: "error: no flags specified".to_string(),
+            status: cli::USAGE_STATUS,
+        })
     }
 }
 
 fn main() {
     let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.decode())
-        .unwrap_or_else(|e| e.exit());
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
 
     cli::exit_with(dispatch_for_args(&args))
 }
@@ -0,0 +1,110 @@
+extern crate gitters;
+
+extern crate rustc_serialize;
+extern crate docopt;
+
+use docopt::Docopt;
+use gitters::cli;
+use gitters::config;
+use gitters::ignore;
+use gitters::index;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+const USAGE: &'static str = "
+check-ignore - Debug gitignore / exclude files
+
+Usage:
+  check-ignore [options] <pathname>...
+  check-ignore (-h | --help)
+
+Options:
+  -h --help          Show this screen.
+  -v --verbose       Output details about the matching pattern (if any) for each pathname.
+  -n --non-matching  Show given paths which don't match any pattern. Only useful with --verbose.
+  --no-index         Don't look in the index when undertaking the checks.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_v: bool,
+    flag_n: bool,
+    flag_no_index: bool,
+    arg_pathname: Vec<String>,
+}
+
+/// Normalize a path given on the command line into one relative to the root of the repository,
+/// also returning whether the user indicated that the path is a directory.
+fn normalize_path(pathname: &str) -> (PathBuf, bool) {
+    let mut path = pathname;
+    while path.starts_with("./") {
+        path = &path[2..];
+    }
+
+    let has_trailing_slash = path.ends_with('/');
+    let path = path.trim_right_matches('/');
+    let is_dir = has_trailing_slash || Path::new(path).is_dir();
+    (PathBuf::from(path), is_dir)
+}
+
+fn format_pattern(pattern: &ignore::Pattern) -> String {
+    match pattern.source {
+        Some(ref source) =>
+            format!("{}:{}:{}", source.file.display(), source.line_number, pattern.original),
+        None => format!("-:0:{}", pattern.original),
+    }
+}
+
+fn check_paths(args: &Args) -> cli::Result {
+    let cfg = try!(cli::wrap_with_status(config::read_all(), 128));
+    let mut excludes = try!(cli::wrap_with_status(ignore::Excludes::standard(&cfg), 128));
+
+    let tracked_files: HashSet<PathBuf> = if args.flag_no_index {
+        HashSet::new()
+    } else {
+        let index = try!(cli::wrap_with_status(index::Index::read(), 128));
+        index.entries.iter().map(|e| e.path().to_path_buf()).collect()
+    };
+
+    let mut any_ignored = false;
+    for pathname in args.arg_pathname.iter() {
+        let (path, is_dir) = normalize_path(pathname);
+
+        // Tracked files are not subject to the exclude rules, so they never match.
+        let pattern = if tracked_files.contains(&path) {
+            None
+        } else {
+            try!(cli::wrap_with_status(excludes.matching_pattern(&path, is_dir), 128))
+        };
+
+        match pattern {
+            Some(ref pattern) if args.flag_v => {
+                any_ignored = any_ignored || !pattern.negated;
+                println!("{}\t{}", format_pattern(pattern), pathname);
+            },
+            Some(ref pattern) if !pattern.negated => {
+                any_ignored = true;
+                println!("{}", pathname);
+            },
+            _ => {
+                if args.flag_v && args.flag_n {
+                    println!("::\t{}", pathname);
+                }
+            },
+        }
+    }
+
+    if any_ignored {
+        cli::success()
+    } else {
+        Err(cli::Error { message: String::new(), status: 1 })
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.decode())
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
+
+    cli::exit_with(check_paths(&args))
+}
@@ -3,51 +3,479 @@
 
 extern crate gitters;
 
+extern crate regex;
 extern crate rustc_serialize;
 extern crate docopt;
 
 use docopt::Docopt;
 use gitters::cli;
 use gitters::config;
+use regex::Regex;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
 
 const USAGE: &'static str = "
 config - Get and set repository or global options
 
-Selecting a subset of the config files is not supported, and setting options is not supported yet.
+With just a name, or with --get, shows the value of the variable, taking the last one if it's set
+more than once. --get-all shows every value, and --get-regexp shows the name and value of every
+variable whose name matches the regular expression. A value regex limits the values to the ones
+matching it, or, if it starts with '!', to the ones not matching it.
+
+With a value, sets the variable, replacing its existing value. --add adds a value without
+replacing any, and --replace-all replaces every matching value with a single one. In the same
+way, --unset removes the matching value, and --unset-all removes every matching value. Changes are
+made to the repository config file, unless another file is selected.
+
+With --type, values are checked and shown in a canonical form: bool as true or false, int with
+any k, m or g suffix applied, path with a leading ~ expanded, color as the escape sequence that
+produces it, and expiry-date as a timestamp. When setting a variable, bool and int values are
+written in that form as well.
+
+The files included with include.path and includeIf are read along with the whole hierarchy, but
+not along with a single selected file, unless --includes is given.
+
+When listing or getting values, --show-origin shows the file each value comes from, or the
+command line, and --show-scope shows which part of the hierarchy it comes from: system, global,
+local, worktree or command.
 
 Usage:
-  config [options] <name> [<value>]
+  config [options] <name> [<value> [<value-regex>]]
+  config [options] --get <name> [<value-regex>]
+  config [options] --get-all <name> [<value-regex>]
+  config [options] --get-regexp <name-regex> [<value-regex>]
+  config [options] --add <name> <value>
+  config [options] --replace-all <name> <value> [<value-regex>]
+  config [options] --unset <name> [<value-regex>]
+  config [options] --unset-all <name> [<value-regex>]
+  config [options] --rename-section <old-name> <new-name>
+  config [options] --remove-section <name>
   config [options] (-l | --list)
   config (-h | --help)
 
 Options:
-  -h --help  Show this screen.
-  --global   Use global config file.
-  --local    Use repository config file.
-  -l --list  List all.
+  -h --help          Show this screen.
+  --system           Use system config file.
+  --global           Use global config file.
+  --local            Use repository config file.
+  --worktree         Use per-worktree config file, or the repository config file if the
+                     worktreeConfig extension isn't enabled.
+  -f --file=<file>   Use the given config file.
+  --includes         Read included files, even with a single config file.
+  --no-includes      Don't read included files.
+  -t --type=<type>   Check and canonicalize values as bool, int, path, color or expiry-date.
+  --show-origin      Show the origin of each value.
+  --show-scope       Show the scope of each value.
+  -l --list          List all.
 ";
 
 #[derive(RustcDecodable)]
 struct Args {
+    flag_system: bool,
+    flag_global: bool,
+    flag_local: bool,
+    flag_worktree: bool,
+    flag_file: Option<String>,
+    flag_includes: bool,
+    flag_no_includes: bool,
+    flag_type: Option<String>,
+    flag_show_origin: bool,
+    flag_show_scope: bool,
     flag_list: bool,
+    flag_get: bool,
+    flag_get_all: bool,
+    flag_get_regexp: bool,
+    flag_add: bool,
+    flag_replace_all: bool,
+    flag_unset: bool,
+    flag_unset_all: bool,
+    flag_rename_section: bool,
+    flag_remove_section: bool,
+    arg_name: String,
+    arg_value: Option<String>,
+    arg_name_regex: String,
+    arg_value_regex: Option<String>,
+    arg_old_name: String,
+    arg_new_name: String,
+}
+
+/// The types that values can be checked and canonicalized as.
+#[derive(Clone, Copy)]
+enum Type {
+    Bool,
+    Int,
+    Path,
+    Color,
+    ExpiryDate,
+}
+
+fn value_type(args: &Args) -> Result<Option<Type>, cli::Error> {
+    let name = match args.flag_type {
+        Some(ref name) => name,
+        None => return Ok(None),
+    };
+
+    match name.as_str() {
+        "bool" => Ok(Some(Type::Bool)),
+        "int" => Ok(Some(Type::Int)),
+        "path" => Ok(Some(Type::Path)),
+        "color" => Ok(Some(Type::Color)),
+        "expiry-date" => Ok(Some(Type::ExpiryDate)),
+        _ => Err(cli::Error {
+            message: format!("fatal: unrecognized --type argument, {}", name),
+            status: 128,
+        }),
+    }
+}
+
+/// Turn an error from interpreting a value as a type into the message used by git, which only
+/// treats some of them as fatal. Those that aren't are followed by where the value was set.
+fn value_error(err: config::Error, entry: &config::Entry, value_type: Type) -> cli::Error {
+    let is_fatal = match (&err, value_type) {
+        (&config::Error::MissingValue(_), _) | (_, Type::Color) | (_, Type::ExpiryDate) => false,
+        _ => true,
+    };
+    if is_fatal {
+        return cli::Error { message: format!("fatal: {}", err), status: 128 };
+    }
+
+    cli::Error {
+        message: format!("error: {}\nfatal: {}", err, entry.origin.parse_failure()),
+        status: 128,
+    }
+}
+
+/// Show a value as the given type, if any. A variable without a value has nothing to show, unless
+/// it's shown as a type, such as a boolean that's true.
+fn format_value(entry: &config::Entry, value_type: Option<Type>)
+        -> Result<Option<String>, cli::Error> {
+    let value_type = match value_type {
+        Some(value_type) => value_type,
+        None => return Ok(entry.value.clone()),
+    };
+
+    match value_type {
+        Type::Bool => entry.as_bool().map(|value| value.to_string()),
+        Type::Int => entry.as_int().map(|value| value.to_string()),
+        Type::Path => entry.as_path().map(|path| path.display().to_string()),
+        Type::Color => entry.as_color(),
+        Type::ExpiryDate => entry.as_expiry_date().map(|value| value.to_string()),
+    }.map(Some).map_err(|err| value_error(err, entry, value_type))
+}
+
+/// Normalize a value that's being set as the given type. Only booleans and integers are written
+/// in their canonical form, while colors are checked and otherwise written as they were given.
+fn normalize_value(name: &str, value: &str, value_type: Option<Type>)
+        -> Result<String, cli::Error> {
+    let entry = config::Entry {
+        name: name.to_string(),
+        value: Some(value.to_string()),
+        origin: config::Origin::CommandLine,
+        scope: config::Scope::Command,
+    };
+
+    match value_type {
+        Some(Type::Bool) | Some(Type::Int) =>
+            format_value(&entry, value_type).map(|value| value.unwrap_or_default()),
+        Some(Type::Color) => match entry.as_color() {
+            Ok(_) => Ok(value.to_string()),
+            Err(err) => Err(cli::Error {
+                message: format!("error: {}\nfatal: cannot parse color '{}'", err, value),
+                status: 128,
+            }),
+        },
+        _ => Ok(value.to_string()),
+    }
+}
+
+/// What to show before a value, depending on whether its scope and origin were asked for. Both
+/// are followed by a tab, and the origin is a quoted file name or the command line.
+fn value_prefix(args: &Args, entry: &config::Entry) -> String {
+    let mut prefix = String::new();
+    if args.flag_show_scope {
+        prefix.push_str(entry.scope.name());
+        prefix.push('\t');
+    }
+    if args.flag_show_origin {
+        match entry.origin {
+            config::Origin::File(ref path, _) => {
+                let path = path.as_os_str().as_bytes();
+                prefix.push_str(&format!("file:{}\t", cli::quote_path(path, false)));
+            },
+            config::Origin::CommandLine => prefix.push_str("command line:\t"),
+        }
+    }
+    prefix
+}
+
+/// The single file to read from or write to, if one was selected.
+fn selected_file(args: &Args) -> Option<PathBuf> {
+    if let Some(ref file) = args.flag_file {
+        Some(PathBuf::from(file))
+    } else if args.flag_system {
+        Some(config::system_file())
+    } else if args.flag_global {
+        config::global_file()
+    } else if args.flag_local {
+        Some(config::repository_file())
+    } else if args.flag_worktree {
+        if config::worktree_config_enabled() {
+            Some(config::worktree_file())
+        } else {
+            Some(config::repository_file())
+        }
+    } else {
+        None
+    }
+}
+
+/// The scope of the values in the selected file. A file given by name is treated as if it were
+/// given on the command line.
+fn selected_scope(args: &Args) -> config::Scope {
+    if args.flag_file.is_some() {
+        config::Scope::Command
+    } else if args.flag_system {
+        config::Scope::System
+    } else if args.flag_global {
+        config::Scope::Global
+    } else if args.flag_local {
+        config::Scope::Local
+    } else if args.flag_worktree && config::worktree_config_enabled() {
+        config::Scope::Worktree
+    } else if args.flag_worktree {
+        config::Scope::Local
+    } else {
+        config::Scope::Command
+    }
+}
+
+/// Read the selected file, or every file if none was selected. A selected file that doesn't
+/// exist is treated as empty, unless it's required to exist, such as when listing every variable.
+fn read_config(args: &Args, required: bool) -> Result<config::Config, cli::Error> {
+    let path = match selected_file(args) {
+        Some(path) => path,
+        None if args.flag_no_includes =>
+            return config::read_all_without_includes().map_err(read_error),
+        None => return config::read_all().map_err(read_error),
+    };
+
+    let follow_includes = args.flag_includes && !args.flag_no_includes;
+    match config::read_file(&path, selected_scope(args), follow_includes) {
+        Err(config::Error::IOError(ref err)) if err.kind() == io::ErrorKind::NotFound => {
+            if required {
+                Err(cli::Error {
+                    message: format!("fatal: unable to read config file '{}': \
+                                      No such file or directory", path.display()),
+                    status: 128,
+                })
+            } else {
+                Ok(config::Config::new())
+            }
+        },
+        result => result.map_err(read_error),
+    }
+}
+
+fn read_error(err: config::Error) -> cli::Error {
+    match err {
+        config::Error::InvalidFile(ref description) |
+                config::Error::InvalidInclude(ref description) |
+                config::Error::InvalidValue(ref description) => cli::Error {
+            message: format!("fatal: {}", description),
+            status: 128,
+        },
+        config::Error::InvalidParameters(_) | config::Error::IncompleteKey(_) |
+                config::Error::InvalidKey(_) => cli::Error {
+            message: format!("error: {}\nfatal: unable to parse command-line config", err),
+            status: 128,
+        },
+        err => cli::Error { message: format!("error: {}", err), status: 1 },
+    }
+}
+
+fn value_pattern(pattern: &Option<String>) -> Result<config::ValuePattern, cli::Error> {
+    match *pattern {
+        Some(ref pattern) => config::ValuePattern::parse(pattern).map_err(|err| cli::Error {
+            message: format!("error: {}", err),
+            status: 6,
+        }),
+        None => Ok(config::ValuePattern::Any),
+    }
+}
+
+/// Lowercase the parts of a name regex that correspond to the section and variable names, so that
+/// it matches the canonical names, in the same way as git.
+fn canonical_name_regex(pattern: &str) -> String {
+    match (pattern.find('.'), pattern.rfind('.')) {
+        (Some(first_dot), Some(last_dot)) => format!("{}{}{}",
+                                                     pattern[..first_dot].to_lowercase(),
+                                                     &pattern[first_dot..last_dot],
+                                                     pattern[last_dot..].to_lowercase()),
+        _ => pattern.to_lowercase(),
+    }
+}
+
+/// Every value that matches the value regex, if any, shown as the given type. Like in git, a
+/// variable without a value is matched as if it were empty.
+fn matching_values<'a>(cfg: &'a config::Config, name_matches: &Fn(&str) -> bool,
+                       value_regex: &Option<String>, value_type: Option<Type>)
+        -> Result<Vec<(&'a config::Entry, Option<String>)>, cli::Error> {
+    let pattern = try!(value_pattern(value_regex));
+
+    let mut values = Vec::new();
+    for entry in cfg.entries() {
+        let value = entry.value.as_ref().map(|value| value.as_str()).unwrap_or("");
+        if name_matches(&entry.name) && pattern.matches(Some(value)) {
+            values.push((entry, try!(format_value(entry, value_type))));
+        }
+    }
+    Ok(values)
+}
+
+fn get(args: &Args, cfg: &config::Config, value_regex: &Option<String>, all: bool,
+       value_type: Option<Type>) -> cli::Result {
+    let name = try!(config::canonical_name(&args.arg_name).map_err(|err| cli::Error {
+        message: format!("error: {}", err),
+        status: 1,
+    }));
+
+    let mut values = try!(matching_values(cfg, &|key| key == name, value_regex, value_type));
+    if values.is_empty() {
+        return Err(cli::Error { message: String::new(), status: 1 });
+    }
+
+    if !all {
+        values = values.split_off(values.len() - 1);
+    }
+    for (entry, value) in values {
+        println!("{}{}", value_prefix(args, entry), value.unwrap_or_default());
+    }
+    cli::success()
+}
+
+fn get_regexp(args: &Args, cfg: &config::Config, value_type: Option<Type>) -> cli::Result {
+    let name_regex = &args.arg_name_regex;
+    let name_regex = try!(Regex::new(&canonical_name_regex(name_regex)).map_err(|_| cli::Error {
+        message: format!("error: invalid key pattern: {}", name_regex),
+        status: 6,
+    }));
+
+    let values = try!(matching_values(cfg, &|key| name_regex.is_match(key),
+                                      &args.arg_value_regex, value_type));
+    if values.is_empty() {
+        return Err(cli::Error { message: String::new(), status: 1 });
+    }
+
+    for (entry, value) in values {
+        let prefix = value_prefix(args, entry);
+        match value {
+            Some(value) => println!("{}{} {}", prefix, entry.name, value),
+            None => println!("{}{}", prefix, entry.name),
+        }
+    }
+    cli::success()
+}
+
+/// Turn an error from editing the config file into the message and status code used by git.
+fn edit_error(err: config::Error, path: &PathBuf) -> cli::Error {
+    let (message, status) = match err {
+        config::Error::IncompleteKey(_) => (format!("error: {}", err), 2),
+        config::Error::InvalidKey(_) => (format!("error: {}", err), 1),
+        config::Error::InvalidPattern(_) => (format!("error: {}", err), 6),
+        config::Error::InvalidSection(_) => (format!("error: {}", err), 255),
+        config::Error::InvalidFile(ref description) => (format!("fatal: {}", description), 3),
+        config::Error::InvalidInclude(ref description) |
+                config::Error::InvalidValue(ref description) =>
+            (format!("fatal: {}", description), 128),
+        config::Error::MissingValue(_) => (format!("error: {}", err), 128),
+        config::Error::InvalidParameters(_) => (format!("fatal: {}", err), 128),
+        config::Error::MultipleValues(_) => (format!("warning: {}", err), 5),
+        config::Error::NothingToUnset(_) => (String::new(), 5),
+        config::Error::IOError(ref io_err) =>
+            (format!("error: could not write config file {}: {}", path.display(), io_err), 4),
+    };
+    cli::Error { message: message, status: status }
+}
+
+fn set(args: &Args, value: Option<&str>, pattern: config::ValuePattern, replace_all: bool)
+        -> cli::Result {
+    let path = selected_file(args).unwrap_or(config::repository_file());
+    config::set_in_file(&path, &args.arg_name, value, &pattern, replace_all).map_err(|err| {
+        let is_multiple_values = match err {
+            config::Error::MultipleValues(_) => true,
+            _ => false,
+        };
+
+        let mut cli_err = edit_error(err, &path);
+        if is_multiple_values && value.is_some() {
+            cli_err.message.push_str(&format!(
+                    "\nerror: cannot overwrite multiple values with a single value\n       \
+                     Use a regexp, --add or --replace-all to change {}.", args.arg_name));
+        }
+        cli_err
+    })
+}
+
+fn rename_section(args: &Args, old_name: &str, new_name: Option<&str>) -> cli::Result {
+    let path = selected_file(args).unwrap_or(config::repository_file());
+    let found = try!(config::rename_section_in_file(&path, old_name, new_name)
+                     .map_err(|err| edit_error(err, &path)));
+    if found == 0 {
+        return Err(cli::Error {
+            message: format!("fatal: no such section: {}", old_name),
+            status: 128,
+        });
+    }
+    cli::success()
 }
 
 fn dispatch_for_args(args: &Args) -> cli::Result {
+    let value_type = try!(value_type(args));
+    let value = match args.arg_value {
+        Some(ref value) => Some(try!(normalize_value(&args.arg_name, value, value_type))),
+        None => None,
+    };
+    let value = value.as_ref().map(|value| value.as_str());
+
     if args.flag_list {
-        let cfg = try!(cli::wrap_with_status(config::read_all(), 1));
-        for (k, v) in cfg.all() {
-            println!("{}={}", k, v);
+        let cfg = try!(read_config(args, true));
+        for entry in cfg.entries() {
+            let prefix = value_prefix(args, entry);
+            match entry.value {
+                Some(ref value) => println!("{}{}={}", prefix, entry.name, value),
+                None => println!("{}{}", prefix, entry.name),
+            }
         }
         cli::success()
+    } else if args.flag_get_regexp {
+        let cfg = try!(read_config(args, false));
+        get_regexp(args, &cfg, value_type)
+    } else if args.flag_get || args.flag_get_all {
+        let cfg = try!(read_config(args, false));
+        get(args, &cfg, &args.arg_value_regex, args.flag_get_all, value_type)
+    } else if args.flag_add {
+        set(args, value, config::ValuePattern::Nothing, false)
+    } else if args.flag_unset || args.flag_unset_all {
+        let pattern = try!(value_pattern(&args.arg_value_regex));
+        set(args, None, pattern, args.flag_unset_all)
+    } else if args.flag_rename_section {
+        rename_section(args, &args.arg_old_name, Some(&args.arg_new_name))
+    } else if args.flag_remove_section {
+        rename_section(args, &args.arg_name, None)
+    } else if value.is_some() {
+        let pattern = try!(value_pattern(&args.arg_value_regex));
+        set(args, value, pattern, args.flag_replace_all)
     } else {
-        Err(cli::Error { message: "Invalid options".to_string(), status: 2 })
+        let cfg = try!(read_config(args, false));
+        get(args, &cfg, &None, false, value_type)
     }
 }
 
 fn main() {
     let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.decode())
-        .unwrap_or_else(|e| e.exit());
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
 
     cli::exit_with(dispatch_for_args(&args))
 }
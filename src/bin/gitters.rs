@@ -0,0 +1,338 @@
+//! The entry point that runs every other command, like `git` itself. Built-in commands are the
+//! binaries next to this one, and any other command is run as `gitters-<command>` from `$PATH`,
+//! failing which it's expanded as an alias from `alias.<command>`.
+//!
+//! The options that come before the command apply to every command. Since each command is a
+//! separate process, they're passed on the same way as in git: through the current directory and
+//! environment variables such as `$GIT_DIR` and `$GIT_CONFIG_PARAMETERS`.
+
+#[macro_use]
+extern crate gitters;
+
+extern crate rustc_serialize;
+extern crate docopt;
+
+use docopt::Docopt;
+use gitters::cli;
+use gitters::config;
+use gitters::pager;
+use gitters::repository;
+use std::env;
+use std::io;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const USAGE: &'static str = "
+gitters - The stupid content tracker, in Rust
+
+Runs one of the built-in commands, such as log or status, or any gitters-<command> executable
+found on the PATH. Otherwise, the command is expanded as an alias from alias.<command>. An alias
+starting with '!' is run by the shell from the root of the working tree, with $GIT_PREFIX set to
+the directory it was run from.
+
+Usage:
+  gitters [options] [-C <path>]... [-c <name=value>]... <command> [<args>...]
+  gitters (-h | --help)
+
+Options:
+  -h --help           Show this screen.
+  -C <path>           Run as if started in <path>. When given more than once, each path is
+                      relative to the one before it.
+  -c <name=value>     Set a config variable for this command, overriding the config files. A name
+                      without a value sets a boolean variable.
+  --git-dir=<path>    Use the repository at <path>, rather than .git.
+  --work-tree=<path>  Use <path> as the root of the working tree, rather than the current
+                      directory.
+  --bare              Treat the current directory as a bare repository.
+  -p --paginate       Send the output to a pager, even if it isn't going to a terminal.
+  -P --no-pager       Don't send the output to a pager.
+";
+
+#[allow(non_snake_case)]
+#[derive(RustcDecodable)]
+struct Args {
+    flag_C: Vec<String>,
+    flag_c: Vec<String>,
+    flag_git_dir: Option<String>,
+    flag_work_tree: Option<String>,
+    flag_bare: bool,
+    flag_paginate: bool,
+    flag_no_pager: bool,
+    arg_command: String,
+    arg_args: Vec<String>,
+}
+
+/// The commands that are built as binaries alongside this one.
+const BUILTINS: [&'static str; 12] = [
+    "add",
+    "branch",
+    "cat-file",
+    "check-ignore",
+    "config",
+    "log",
+    "ls-files",
+    "mv",
+    "rev-list",
+    "rev-parse",
+    "rm",
+    "status",
+];
+
+/// Apply the options given before the command. Directories are changed right away, and the rest
+/// are put in the environment, where every command and library function that cares about them
+/// looks for them.
+fn apply_global_options(args: &Args) -> cli::Result {
+    // An empty path leaves the directory as it is, rather than being an error.
+    for dir in args.flag_C.iter().filter(|dir| !dir.is_empty()) {
+        try!(env::set_current_dir(dir).map_err(|err| cli::Error {
+            message: format!("fatal: cannot change to '{}': {}", dir, cli::io_error_message(&err)),
+            status: 128,
+        }));
+    }
+
+    for parameter in &args.flag_c {
+        try!(config::push_parameter(parameter).map_err(|err| cli::Error {
+            message: format!("error: {}\nfatal: unable to parse command-line config", err),
+            status: 128,
+        }));
+    }
+
+    if let Some(ref dir) = args.flag_git_dir {
+        env::set_var("GIT_DIR", dir);
+    } else if args.flag_bare && env::var_os("GIT_DIR").is_none() {
+        let cwd = try!(cli::wrap_with_status(env::current_dir(), 128));
+        env::set_var("GIT_DIR", cwd);
+    }
+    if let Some(ref dir) = args.flag_work_tree {
+        env::set_var("GIT_WORK_TREE", dir);
+    }
+    if args.flag_paginate {
+        env::set_var(pager::PAGINATE_VARIABLE, "1");
+    }
+    if args.flag_no_pager {
+        env::set_var("GIT_PAGER", "cat");
+    }
+
+    if args.flag_git_dir.is_some() && !repository::git_dir().is_dir() {
+        return Err(cli::Error {
+            message: format!("fatal: not a git repository: '{}'",
+                             repository::git_dir().display()),
+            status: 128,
+        });
+    }
+
+    // Commands run from the root of the working tree, so the repository has to be found before
+    // moving there, in case it's given relative to the current directory.
+    if let Some(work_tree) = env::var_os("GIT_WORK_TREE") {
+        let cwd = try!(cli::wrap_with_status(env::current_dir(), 128));
+        env::set_var("GIT_DIR", cwd.join(repository::git_dir()));
+        try!(env::set_current_dir(&work_tree).map_err(|err| cli::Error {
+            message: format!("fatal: cannot chdir to '{}': {}", Path::new(&work_tree).display(),
+                             cli::io_error_message(&err)),
+            status: 128,
+        }));
+        env::set_var("GIT_WORK_TREE", try!(cli::wrap_with_status(env::current_dir(), 128)));
+    }
+
+    cli::success()
+}
+
+/// Show a command line in the trace.
+fn command_line(program: &str, args: &[String]) -> String {
+    let mut words = vec![program.to_string()];
+    words.extend(args.iter().cloned());
+    words.join(" ")
+}
+
+/// Turn an error from running a command into the status the command would have exited with.
+fn exec_error(command: &str, err: io::Error) -> cli::Error {
+    cli::Error { message: format!("fatal: cannot run {}: {}", command, err), status: 128 }
+}
+
+/// Replace this process with a built-in command. Only returns if the command couldn't be run.
+fn run_builtin(command: &str, args: &[String]) -> cli::Error {
+    let dir = env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))
+        .unwrap_or(PathBuf::from("."));
+
+    trace!("exec: gitters {}", command_line(command, args));
+    exec_error(command, Command::new(dir.join(command)).args(args).exec())
+}
+
+/// Replace this process with `gitters-<command>`, if it can be found on the `$PATH`. Returns
+/// nothing if it can't be found, so that the command can be tried as an alias.
+fn run_external(command: &str, args: &[String]) -> Option<cli::Error> {
+    let program = format!("gitters-{}", command);
+    trace!("exec: {}", command_line(&program, args));
+    match Command::new(&program).args(args).exec() {
+        ref err if err.kind() == io::ErrorKind::NotFound => None,
+        err => Some(exec_error(&program, err)),
+    }
+}
+
+/// Find the root of the working tree containing the current directory, along with the path from
+/// there back to the current directory, which ends with a `/` unless it's empty.
+fn worktree_root() -> Option<(PathBuf, String)> {
+    let cwd = match env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(_) => return None,
+    };
+
+    cwd.ancestors().find(|dir| dir.join(".git").exists()).map(|root| {
+        let prefix = cwd.strip_prefix(root).unwrap_or(Path::new(""));
+        let prefix = prefix.to_string_lossy();
+        let prefix = if prefix.is_empty() { String::new() } else { format!("{}/", prefix) };
+        (root.to_path_buf(), prefix)
+    })
+}
+
+/// Run an alias starting with `!` through the shell, from the root of the working tree, passing
+/// it the rest of the arguments. Exits with the status of the shell.
+fn run_shell_alias(name: &str, script: &str, args: &[String]) -> cli::Result {
+    // Every command the shell runs would be paged separately, so `--paginate` doesn't apply.
+    let mut command = Command::new("sh");
+    command.env_remove(pager::PAGINATE_VARIABLE);
+    if args.is_empty() {
+        command.arg("-c").arg(script);
+    } else {
+        command.arg("-c").arg(format!("{} \"$@\"", script)).arg(script).args(args);
+    }
+
+    // With an explicit repository, the working tree is wherever the command was run from.
+    let root = if env::var_os("GIT_DIR").is_some() {
+        env::current_dir().ok().map(|cwd| (cwd, String::new()))
+    } else {
+        worktree_root()
+    };
+    match root {
+        Some((root, prefix)) => command.current_dir(root).env("GIT_PREFIX", prefix),
+        None => command.env("GIT_PREFIX", ""),
+    };
+
+    trace!("run_command: {}", command_line(script, args));
+    let status = try!(command.status().map_err(|err| cli::Error {
+        message: format!("fatal: while expanding alias '{}': '{}': {}", name, script, err),
+        status: 128,
+    }));
+
+    match status.code() {
+        Some(0) => cli::success(),
+        Some(code) => Err(cli::Error { message: String::new(), status: code }),
+        None => Err(cli::Error {
+            message: String::new(),
+            status: 128 + status.signal().unwrap_or(0),
+        }),
+    }
+}
+
+/// Look up the alias for a command, if there is one. Like any other variable, the last value
+/// wins.
+fn alias_for(config: &config::Config, command: &str) -> Result<Option<String>, cli::Error> {
+    let name = match config::canonical_name(&format!("alias.{}", command)) {
+        Ok(name) => name,
+        Err(_) => return Ok(None),
+    };
+
+    match config.entries().iter().rev().find(|entry| entry.name == name) {
+        Some(entry) => entry.required_value().map(|alias| Some(alias.to_string())).map_err(|err| {
+            cli::Error {
+                message: format!("error: {}\nfatal: {}", err, entry.origin.parse_failure()),
+                status: 128,
+            }
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Describe the aliases that expanded into each other, pointing out where the loop starts.
+fn alias_loop(expanded: &[String], repeated: &str) -> cli::Error {
+    let mut message = format!("fatal: alias loop detected: expansion of '{}' does not terminate:",
+                              expanded[0]);
+    for (i, command) in expanded.iter().enumerate() {
+        message.push_str(&format!("\n  {}", command));
+        if command == repeated {
+            message.push_str(" <==");
+        } else if i == expanded.len() - 1 {
+            message.push_str(" ==>");
+        }
+    }
+    cli::Error { message: message, status: 128 }
+}
+
+/// Run the command, expanding aliases until it's either a built-in or external command, in the
+/// same order of precedence as git.
+fn dispatch(mut command: String, mut args: Vec<String>) -> cli::Result {
+    let mut config = None;
+    let mut expanded: Vec<String> = Vec::new();
+    loop {
+        if BUILTINS.contains(&command.as_str()) {
+            return Err(run_builtin(&command, &args));
+        } else if let Some(err) = run_external(&command, &args) {
+            return Err(err);
+        }
+
+        if expanded.contains(&command) {
+            return Err(alias_loop(&expanded, &command));
+        }
+
+        // The config is only read once a command turns out to be an alias.
+        if config.is_none() {
+            config = Some(try!(config::read_all().map_err(|err| cli::Error {
+                message: format!("fatal: {}", err),
+                status: 128,
+            })));
+        }
+
+        let alias = match try!(alias_for(config.as_ref().unwrap(), &command)) {
+            Some(alias) => alias,
+            None if expanded.is_empty() => return Err(cli::Error {
+                message: format!("gitters: '{}' is not a gitters command. See 'gitters --help'.",
+                                 command),
+                status: 1,
+            }),
+            None => return Err(cli::Error {
+                message: format!("fatal: expansion of alias '{}' failed; '{}' is not a gitters \
+                                  command", expanded[0], command),
+                status: 128,
+            }),
+        };
+
+        if alias.starts_with('!') {
+            return run_shell_alias(&command, &alias[1..], &args);
+        }
+
+        let mut words = try!(cli::split_cmdline(&alias).map_err(|err| cli::Error {
+            message: format!("fatal: bad alias.{} string: {}", command, err),
+            status: 128,
+        }));
+        if words.is_empty() {
+            return Err(cli::Error {
+                message: format!("fatal: empty alias for {}", command),
+                status: 128,
+            });
+        }
+        trace!("alias expansion: {} => {}", command, command_line(&words[0], &words[1..]));
+        if words[0].starts_with('-') {
+            return Err(cli::Error {
+                message: format!("fatal: alias '{}' changes environment variables.\n\
+                                  You can use '!gitters' in the alias to do this", command),
+                status: 128,
+            });
+        }
+
+        expanded.push(command);
+        command = words.remove(0);
+        words.extend(args);
+        args = words;
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.options_first(true).decode())
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
+
+    cli::exit_with(apply_global_options(&args)
+                   .and_then(|_| dispatch(args.arg_command, args.arg_args)))
+}
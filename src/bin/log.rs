@@ -1,69 +1,326 @@
+extern crate chrono;
 extern crate gitters;
 
 extern crate rustc_serialize;
 extern crate docopt;
 
+use chrono::Local;
 use docopt::Docopt;
 use gitters::cli;
-use gitters::commits;
-use gitters::objects;
+use gitters::color;
+use gitters::config;
+use gitters::graph;
 use gitters::pager;
-use gitters::revisions;
+use gitters::pretty;
+use gitters::revwalk;
 
 const USAGE: &'static str = "
 log - Show commit logs
 
 Usage:
-  log [<object>]
+  log [options] [--author=<regex>]... [--committer=<regex>]... [--grep=<regex>]... [<revision>...]
   log (-h | --help)
 
 Options:
-  -h --help  Show this screen.
+  -h --help           Show this screen.
+  --topo-order        Show no parents before all of their children, and avoid showing commits
+                      from multiple lines of history intermixed.
+  --date-order        Show no parents before all of their children, but otherwise show commits
+                      by commit date.
+  --author-date-order  Show no parents before all of their children, but otherwise show commits
+                      by author date.
+  --reverse           Show the commits that would be shown in reverse.
+  --first-parent      Only follow the first parent of merge commits.
+  --ancestry-path     With a range like A..B, only show commits that are descendants of A.
+  --graph             Draw the history as a graph next to the commits. Unless another order is
+                      given, commits are shown in the same order as with --topo-order.
+  --pretty=<format>   Show commits in a built-in format: oneline, short, medium, full, fuller,
+                      reference, email or raw. Otherwise, <format> is a template, such as
+                      'format:%h %s', or the name of one defined by pretty.<name>. Defaults to
+                      format.pretty, or else medium.
+  --format=<format>   Show commits using the template <format>, like --pretty=tformat:<format>.
+  --oneline           Show each commit on a single line, like --pretty=oneline --abbrev-commit.
+  --abbrev-commit     Abbreviate the name of each commit, to at least core.abbrev characters.
+  --no-abbrev-commit  Show the full name of each commit, even with --oneline.
+  --date=<format>     Show dates as relative, short, iso, iso-strict, rfc, raw, unix, default
+                      or format:<strftime>. Defaults to log.date.
+  --decorate          Show the names of the refs pointing to each commit.
+  --no-decorate       Don't show the names of refs, even if log.decorate is set.
+  -n --max-count=<number>  Show at most <number> commits.
+  --skip=<number>     Skip <number> commits before starting to show them.
+  --since=<date>      Show commits more recent than <date>, which can be relative, such as
+                      '2 weeks ago'.
+  --after=<date>      The same as --since.
+  --until=<date>      Show commits older than <date>.
+  --before=<date>     The same as --until.
+  --author=<regex>    Show commits whose author matches <regex>. When given more than once, the
+                      author has to match any of them.
+  --committer=<regex>  Show commits whose committer matches <regex>.
+  --grep=<regex>      Show commits whose message matches <regex>.
+  --all-match         Show commits whose message matches all of the --grep patterns, rather
+                      than any of them.
+  --invert-grep       Show commits whose message doesn't match the --grep patterns.
+  -i --regexp-ignore-case  Match the patterns regardless of case.
+  --merges            Only show merge commits, like --min-parents=2.
+  --no-merges         Don't show merge commits, like --max-parents=1.
+  --min-parents=<number>  Only show commits with at least <number> parents.
+  --max-parents=<number>  Only show commits with at most <number> parents.
+  --color=<when>      Color the output always, never or only when it's going to a terminal
+                      (auto), overriding color.diff and color.ui.
+  --no-color          Don't color the output.
 ";
 
 #[derive(RustcDecodable)]
 struct Args {
-    arg_object: Option<String>,
+    arg_revision: Vec<String>,
+    flag_topo_order: bool,
+    flag_date_order: bool,
+    flag_author_date_order: bool,
+    flag_reverse: bool,
+    flag_first_parent: bool,
+    flag_ancestry_path: bool,
+    flag_graph: bool,
+    flag_pretty: Option<String>,
+    flag_format: Option<String>,
+    flag_oneline: bool,
+    flag_abbrev_commit: bool,
+    flag_no_abbrev_commit: bool,
+    flag_date: Option<String>,
+    flag_decorate: bool,
+    flag_no_decorate: bool,
+    flag_max_count: Option<usize>,
+    flag_skip: Option<usize>,
+    flag_since: Option<String>,
+    flag_after: Option<String>,
+    flag_until: Option<String>,
+    flag_before: Option<String>,
+    flag_author: Vec<String>,
+    flag_committer: Vec<String>,
+    flag_grep: Vec<String>,
+    flag_all_match: bool,
+    flag_invert_grep: bool,
+    flag_regexp_ignore_case: bool,
+    flag_merges: bool,
+    flag_no_merges: bool,
+    flag_min_parents: Option<usize>,
+    flag_max_parents: Option<usize>,
+    flag_color: Option<String>,
+    flag_no_color: bool,
 }
 
-fn print_full_commit(commit: &commits::Commit) {
-    let &commits::Commit {
-        name: objects::Name(ref name),
-        author: commits::CommitUser { name: ref author_name, date: ref author_date },
-        ref message,
-        ..
-    } = commit;
-
-    println!("\x1B[33mcommit {}\x1B[0m", name);
-    println!("Author: {}", author_name);
-    println!("Date:   {}", author_date.format("%a %b %-d %H:%M:%S %Y %z"));
-    println!("");
-    println!("    {}", str::replace(&message, "\n", "\n    "));
-    println!("");
+/// The options for walking history, which log shares with rev-list.
+fn walk_options(args: &Args) -> revwalk::Options {
+    revwalk::Options {
+        topo_order: args.flag_topo_order,
+        date_order: args.flag_date_order,
+        author_date_order: args.flag_author_date_order,
+        reverse: args.flag_reverse,
+        first_parent: args.flag_first_parent,
+        ancestry_path: args.flag_ancestry_path,
+        max_count: args.flag_max_count,
+        skip: args.flag_skip,
+        since: args.flag_since.clone(),
+        after: args.flag_after.clone(),
+        until: args.flag_until.clone(),
+        before: args.flag_before.clone(),
+        authors: args.flag_author.clone(),
+        committers: args.flag_committer.clone(),
+        greps: args.flag_grep.clone(),
+        ignore_case: args.flag_regexp_ignore_case,
+        all_match: args.flag_all_match,
+        invert_grep: args.flag_invert_grep,
+        merges: args.flag_merges,
+        no_merges: args.flag_no_merges,
+        min_parents: args.flag_min_parents,
+        max_parents: args.flag_max_parents,
+    }
 }
 
-fn print_history(commit_rev: String) -> cli::Result {
-    let resolved = try!(cli::wrap_with_status(revisions::resolve(&commit_rev), 1));
-    try!(cli::wrap_with_status(pager::setup(), 1));
+/// Choose the format from the options, falling back to `format.pretty`.
+fn choose_format(args: &Args, cfg: &config::Config) -> Result<pretty::Format, pretty::Error> {
+    if let Some(ref format) = args.flag_format {
+        return pretty::parse_format(&format!("tformat:{}", format), cfg);
+    } else if let Some(ref format) = args.flag_pretty {
+        return pretty::parse_format(format, cfg);
+    } else if args.flag_oneline {
+        return Ok(pretty::Format::Oneline);
+    }
 
-    let mut current_commit_rev = Some(resolved);
-    while current_commit_rev.is_some() {
-        let name = current_commit_rev.unwrap();
-        let obj = try!(cli::wrap_with_status(objects::read_object(&name), 1));
+    match try!(cfg.get_string("format.pretty").map_err(pretty::Error::ConfigError)) {
+        Some(format) => pretty::parse_format(format, cfg),
+        None => Ok(pretty::Format::Medium),
+    }
+}
 
-        match obj {
-            objects::Object::Commit(commit) => {
-                // In the future, print in the format specified by the command line arguments.
-                print_full_commit(&commit);
-                current_commit_rev = commit.parent;
+/// The colors of the columns of the graph, from `log.graphColors`, a comma-separated list of
+/// colors, or else the default ones. Only used if the output is colored.
+fn graph_colors(cfg: &config::Config) -> Result<Vec<String>, cli::Error> {
+    let value = try!(cli::wrap_with_status(cfg.get_string("log.graphColors"), 128));
+    let mut colors = Vec::new();
+    for spec in value.unwrap_or("").split(',') {
+        if spec.trim().is_empty() {
+            continue;
+        }
+        match color::parse(spec) {
+            Some(color) => colors.push(color),
+            None => {
+                eprintln!("error: invalid color value: {}", spec);
+                cli::warning(&format!("ignored invalid color '{}' in log.graphColors", spec));
             },
-            _ => {
+        }
+    }
+
+    if colors.is_empty() {
+        colors = graph::DEFAULT_COLORS.iter().map(|spec| color::parse(spec).unwrap()).collect();
+    }
+    Ok(colors)
+}
+
+/// Decide whether to show decorations from the options and `log.decorate`, which can be `short`,
+/// `full`, `auto` or a boolean. Returns whether to show them, and whether to show full ref names.
+fn choose_decorate(args: &Args, cfg: &config::Config) -> Result<(bool, bool), cli::Error> {
+    if args.flag_no_decorate {
+        return Ok((false, false));
+    }
+
+    let value = try!(cli::wrap_with_status(cfg.get_string("log.decorate"), 128));
+    let (decorate, full) = match value {
+        Some("short") => (true, false),
+        Some("full") => (true, true),
+        Some("auto") | None => (pager::output_to_terminal(), false),
+        Some(value) => match config::parse_bool(value) {
+            Some(decorate) => (decorate, false),
+            None => {
                 return Err(cli::Error {
-                    message: format!("object {} is not a commit", name),
-                    status: 2
+                    message: format!("fatal: bad boolean config value '{}' for 'log.decorate'",
+                                     value),
+                    status: 128,
                 });
+            },
+        },
+    };
+    Ok((decorate || args.flag_decorate, full && !args.flag_decorate))
+}
+
+fn print_history(args: &Args) -> cli::Result {
+    if args.flag_reverse && args.flag_graph {
+        return Err(cli::Error {
+            message: "fatal: options '--reverse' and '--graph' cannot be used together"
+                .to_string(),
+            status: 128,
+        });
+    }
+
+    let color_mode = try!(cli::color_mode(&args.flag_color, args.flag_no_color));
+    let cfg = try!(cli::wrap_with_status(config::read_all(), 128));
+    let now = Local::now().timestamp();
+
+    let options = walk_options(args);
+    let mut walk = try!(cli::wrap_with_status(options.walk(now), 128));
+    if args.flag_graph && options.order() == revwalk::Order::Walk {
+        walk.sort(revwalk::Order::Topo);
+    }
+    if args.arg_revision.is_empty() {
+        try!(cli::wrap_with_status(walk.push_revision("HEAD"), 128));
+    }
+    for rev in args.arg_revision.iter() {
+        try!(cli::wrap_with_status(walk.push_revision(rev), 128));
+    }
+
+    let format = try!(cli::wrap_with_status(choose_format(args, &cfg), 128));
+    let date_format = match args.flag_date {
+        Some(ref date) => Some(date.as_str()),
+        None => try!(cli::wrap_with_status(cfg.get_string("log.date"), 128)),
+    };
+    let date_format = match date_format {
+        Some(date) => Some(try!(cli::wrap_with_status(pretty::parse_date_format(date), 128))),
+        None => None,
+    };
+    let abbrev = try!(cli::wrap_with_status(cfg.get_int("core.abbrev"), 128)).unwrap_or(7);
+    let abbrev_commit = !args.flag_no_abbrev_commit &&
+        (args.flag_abbrev_commit || (args.flag_oneline && args.flag_format.is_none() &&
+                                     args.flag_pretty.is_none()) ||
+         try!(cli::wrap_with_status(cfg.get_bool("log.abbrevCommit"), 128)).unwrap_or(false));
+
+    try!(cli::wrap_with_status(pager::setup(&cfg, "log", true), 128));
+
+    // Whether to decorate by default depends on whether the output goes to a terminal, so it's
+    // only decided once the pager is set up.
+    let (decorate, full_refnames) = try!(choose_decorate(args, &cfg));
+    let decorations = if decorate || format.uses_decorations() {
+        Some(try!(cli::wrap_with_status(pretty::Decorations::load(full_refnames), 128)))
+    } else {
+        None
+    };
+
+    // Like in git, the colors of log are the ones configured for diff.
+    let colors = try!(cli::wrap_with_status(color::Colors::new(&cfg, "diff", color_mode), 128));
+    let context = pretty::Context {
+        palette: try!(cli::wrap_with_status(pretty::Palette::new(&colors), 128)),
+        abbrev: abbrev.max(4).min(40) as usize,
+        abbrev_commit: abbrev_commit,
+        date_format: date_format,
+        decorations: decorations.as_ref(),
+        decorate: decorate,
+        now: now,
+    };
+
+    let mut graph = if args.flag_graph {
+        let colors = if colors.enabled() { try!(graph_colors(&cfg)) } else { Vec::new() };
+        Some(graph::Graph::new(colors))
+    } else {
+        None
+    };
+
+    // Like in git, the graph continues next to the blank lines between commits, unless the
+    // previous commit didn't end in a newline.
+    let mut missing_newline = false;
+    let mut shown_one = false;
+    while let Some(commit) = walk.next() {
+        let commit = try!(cli::wrap_with_status(commit, 128));
+        let text = pretty::format_commit(&commit, &format, &context);
+
+        let mut output = String::new();
+        if let Some(ref mut graph) = graph {
+            let mut parents = Vec::new();
+            for parent in commit.parents.iter() {
+                if try!(cli::wrap_with_status(walk.is_interesting(parent), 128)) {
+                    parents.push(parent.clone());
+                }
+                if args.flag_first_parent {
+                    break;
+                }
             }
+            graph.update(commit.name.clone(), parents);
         }
+
+        let separator = if shown_one { format.separator() } else { "" };
+        if !separator.is_empty() {
+            if let (Some(ref mut graph), false) = (graph.as_mut(), missing_newline) {
+                output.push_str(&graph.padding_line());
+            }
+            output.push_str(separator);
+        }
+
+        missing_newline = !text.ends_with('\n');
+        match graph {
+            Some(ref mut graph) => {
+                output.push_str(&graph.commit_lines());
+                output.push_str(&graph.message_lines(&text));
+            },
+            None => output.push_str(&text),
+        }
+
+        let terminator = format.terminator();
+        if !terminator.is_empty() {
+            if let (Some(ref mut graph), false) = (graph.as_mut(), missing_newline) {
+                output.push_str(&graph.padding_line());
+            }
+            output.push_str(terminator);
+        }
+
+        print!("{}", output);
+        shown_one = true;
     }
 
     return cli::success();
@@ -72,7 +329,7 @@ fn print_history(commit_rev: String) -> cli::Result {
 fn main() {
     let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.decode())
-        .unwrap_or_else(|e| e.exit());
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
 
-    cli::exit_with(print_history(args.arg_object.unwrap_or("HEAD".to_string())))
+    cli::exit_with(print_history(&args))
 }
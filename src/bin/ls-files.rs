@@ -2,56 +2,83 @@ extern crate gitters;
 
 extern crate rustc_serialize;
 extern crate docopt;
-#[macro_use]
-extern crate lazy_static;
 
 use docopt::Docopt;
 use gitters::cli;
+use gitters::config;
+use gitters::ignore;
 use gitters::index;
-use std::env;
-use std::path::{Path, PathBuf};
+use gitters::untracked;
+use std::path::Path;
 
 const USAGE: &'static str = "
 ls-files - Show information about files in the index
 
 Usage:
-  ls-files
-  ls-files (-c | --cached)
-  ls-files (-o | --others)
+  ls-files [options]
   ls-files (-h | --help)
 
 Options:
-  -h --help    Show this screen.
-  -c --cached  Show cached files in the output (default).
-  -o --others  Show other (i.e. untracked files) in the output
+  -h --help           Show this screen.
+  -c --cached         Show cached files in the output (default).
+  -o --others         Show other (i.e. untracked files) in the output
+  -i --ignored        Show only ignored files in the output. Requires --exclude-standard, and is
+                      applied to either the cached files or, with -o, the untracked files.
+  --exclude-standard  Add the standard git exclusions: .git/info/exclude, .gitignore in each
+                      directory, and the user's global exclusion file.
+  --directory         If a whole directory is untracked, show just its name (with a trailing
+                      slash) and not its whole contents.
+  --no-empty-directory  Do not list empty directories. Has no effect without --directory.
 ";
 
 #[derive(RustcDecodable)]
 struct Args {
+    flag_c: bool,
     flag_o: bool,
+    flag_i: bool,
+    flag_exclude_standard: bool,
+    flag_directory: bool,
+    flag_no_empty_directory: bool,
 }
 
-fn path_display(path: &Path) -> Result<String, cli::Error> {
-    lazy_static! {
-        static ref CURRENT_DIR_PREFIX: PathBuf = env::current_dir().unwrap();
-    }
-
-    let relative_path =
-        try!(cli::wrap_with_status(path.strip_prefix(CURRENT_DIR_PREFIX.as_path()), 2));
-    Ok(relative_path.display().to_string())
+/// Convert a path relative to the root of the repository into one suitable for display to the
+/// user. Gitters is always run from the root of the working tree, so the repository-relative path
+/// is also the path relative to the current directory, and only needs to be made printable.
+fn path_display(path: &Path) -> String {
+    path.display().to_string()
 }
 
-fn list_cached_files() -> cli::Result {
-    let index = try!(cli::wrap_with_status(index::Index::read(), 2));
+fn list_cached_files(excludes: &mut ignore::Excludes, only_ignored: bool) -> cli::Result {
+    let index = try!(cli::wrap_with_status(index::Index::read(), 128));
     for entry in index.entries {
-        println!("{}", try!(path_display(&entry.path)));
+        if only_ignored {
+            let is_excluded =
+                try!(cli::wrap_with_status(excludes.is_excluded(entry.path(), false), 128));
+            if !is_excluded {
+                continue;
+            }
+        }
+
+        println!("{}", path_display(entry.path()));
     }
 
     cli::success()
 }
 
-fn list_other_files() -> cli::Result {
-    let files = try!(cli::wrap_with_status(index::untracked_files(), 2));
+fn list_other_files(cfg: &config::Config,
+                    excludes: &mut ignore::Excludes,
+                    args: &Args) -> cli::Result {
+    let options = untracked::Options {
+        collapse_directories: args.flag_directory,
+        hide_empty_directories: args.flag_no_empty_directory,
+        show_ignored: args.flag_i,
+        collapse_ignored_directories: false,
+        use_untracked_cache: args.flag_exclude_standard,
+    };
+
+    let files =
+        try!(cli::wrap_with_status(index::untracked_files(cfg, excludes, &options), 128));
+    let files = if args.flag_i { files.ignored } else { files.untracked };
 
     let mut sorted_files = files
         .into_iter()
@@ -59,24 +86,40 @@ fn list_other_files() -> cli::Result {
     sorted_files.sort();
 
     for file in sorted_files {
-        println!("{}", try!(path_display(&file)));
+        println!("{}", path_display(&file));
     }
 
     cli::success()
 }
 
 fn dispatch_for_args(args: &Args) -> cli::Result {
-    if args.flag_o {
-        list_other_files()
+    let cfg = try!(cli::wrap_with_status(config::read_all(), 128));
+    let mut excludes = if args.flag_exclude_standard {
+        try!(cli::wrap_with_status(ignore::Excludes::standard(&cfg), 128))
+    } else if args.flag_i {
+        return Err(cli::Error {
+            message: "fatal: ls-files -i must be used with --exclude-standard".to_string(),
+            status: 128,
+        });
     } else {
-        list_cached_files()
+        ignore::Excludes::new()
+    };
+
+    if args.flag_c || !args.flag_o {
+        try!(list_cached_files(&mut excludes, args.flag_i));
     }
+
+    if args.flag_o {
+        try!(list_other_files(&cfg, &mut excludes, args));
+    }
+
+    cli::success()
 }
 
 fn main() {
     let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.decode())
-        .unwrap_or_else(|e| e.exit());
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
 
     cli::exit_with(dispatch_for_args(&args))
 }
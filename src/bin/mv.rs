@@ -0,0 +1,197 @@
+//! An implementation of `git mv`, which moves or renames a file or directory in the working tree
+//! and updates the index to match.
+
+extern crate gitters;
+
+extern crate rustc_serialize;
+extern crate docopt;
+
+use docopt::Docopt;
+use gitters::cli;
+use gitters::index;
+use std::fs;
+use std::path::Path;
+
+const USAGE: &'static str = "
+mv - Move or rename a file or a directory
+
+Usage:
+  mv [options] <source>... <destination>
+  mv (-h | --help)
+
+Options:
+  -h --help     Show this screen.
+  -f --force    Force renaming a file even if the destination exists.
+  -k            Skip the moves that would lead to an error, instead of aborting.
+  -n --dry-run  Don't actually move anything, just show what would be moved.
+  -v --verbose  Show the files as they are moved.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_force: bool,
+    flag_k: bool,
+    flag_dry_run: bool,
+    flag_verbose: bool,
+    arg_source: Vec<String>,
+    arg_destination: String,
+}
+
+/// A single move, from a tracked file or a directory containing tracked files.
+struct Move {
+    source: String,
+    destination: String,
+}
+
+fn normalize(path: &str) -> String {
+    path.trim_right_matches('/').to_string()
+}
+
+fn is_inside(path: &[u8], dir: &str) -> bool {
+    path.len() > dir.len() && path.starts_with(dir.as_bytes()) && path[dir.len()] == b'/'
+}
+
+/// Check that the move can be made, returning the reason it can't otherwise. This is done for
+/// every move before anything is changed, so that an invalid move leaves everything as it was.
+fn check_move(index: &index::Index, mv: &Move, earlier: &[Move], force: bool)
+        -> Result<(), &'static str> {
+    let source_metadata = try!(fs::symlink_metadata(&mv.source).map_err(|_| "bad source"));
+    if mv.source == mv.destination || mv.destination.starts_with(&format!("{}/", mv.source)) {
+        return Err("can not move directory into itself");
+    }
+
+    if source_metadata.is_dir() {
+        if !index.entries.iter().any(|entry| is_inside(&entry.path, &mv.source)) {
+            return Err("source directory is empty");
+        }
+    } else {
+        let source = mv.source.as_bytes();
+        if !index.entries.iter().any(|entry| entry.path == source) {
+            return Err("not under version control");
+        }
+        if index.entry(source).is_none() {
+            return Err("conflicted");
+        }
+    }
+
+    if let Ok(destination_metadata) = fs::symlink_metadata(&mv.destination) {
+        // Only a file can be replaced by another file.
+        if !force || destination_metadata.is_dir() || source_metadata.is_dir() {
+            return Err("destination exists");
+        }
+    }
+
+    let destination_parent = Path::new(&mv.destination).parent()
+        .filter(|parent| !parent.as_os_str().is_empty());
+    if let Some(parent) = destination_parent {
+        if !parent.is_dir() {
+            return Err("destination directory does not exist");
+        }
+    }
+
+    if earlier.iter().any(|other| other.destination == mv.destination) {
+        return Err("multiple sources for the same target");
+    }
+
+    Ok(())
+}
+
+/// Move the index entries for the source, which may be a directory, to the destination. When
+/// moving a directory, each of the files in it is shown as well if requested.
+fn move_entries(index: &mut index::Index, mv: &Move, verbose: bool) {
+    let moved = index.entries.iter()
+        .filter(|entry| entry.path == mv.source.as_bytes() || is_inside(&entry.path, &mv.source))
+        .map(|entry| {
+            let mut path = mv.destination.as_bytes().to_vec();
+            path.extend_from_slice(&entry.path[mv.source.len()..]);
+            (entry.path.clone(), index::Entry { path: path, ..entry.clone() })
+        })
+        .collect::<Vec<_>>();
+
+    for (old_path, entry) in moved {
+        if verbose && old_path != mv.source.as_bytes() {
+            println!("Renaming {} to {}",
+                     String::from_utf8_lossy(&old_path), String::from_utf8_lossy(&entry.path));
+        }
+        index.remove(&old_path);
+        index.add(entry);
+    }
+}
+
+fn dispatch_for_args(args: &Args) -> cli::Result {
+    let destination = normalize(&args.arg_destination);
+    let destination_is_dir = Path::new(&destination).is_dir();
+    if args.arg_source.len() > 1 && !destination_is_dir {
+        return Err(cli::Error {
+            message: format!("fatal: destination '{}' is not a directory", destination),
+            status: 128,
+        });
+    }
+
+    let moves = args.arg_source.iter()
+        .map(|source| {
+            let source = normalize(source);
+            let destination = if destination_is_dir {
+                let name = Path::new(&source).file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or(source.clone());
+                format!("{}/{}", destination, name)
+            } else {
+                destination.clone()
+            };
+            Move { source: source, destination: destination }
+        })
+        .collect::<Vec<_>>();
+
+    // The lock is taken before reading the index, so no other process can modify the index between
+    // reading and writing it.
+    let lock = if args.flag_dry_run {
+        None
+    } else {
+        Some(try!(cli::wrap_with_status(index::Index::lock(), 128)))
+    };
+    let mut index = try!(cli::wrap_with_status(index::Index::read(), 128));
+
+    let mut valid_moves = Vec::new();
+    for mv in moves {
+        if args.flag_dry_run {
+            println!("Checking rename of '{}' to '{}'", mv.source, mv.destination);
+        }
+
+        match check_move(&index, &mv, &valid_moves, args.flag_force) {
+            Ok(_) => valid_moves.push(mv),
+            Err(_) if args.flag_k => continue,
+            Err(reason) => return Err(cli::Error {
+                message: format!("fatal: {}, source={}, destination={}",
+                                 reason, mv.source, mv.destination),
+                status: 128,
+            }),
+        }
+    }
+
+    for mv in valid_moves.iter() {
+        let verbose = args.flag_verbose || args.flag_dry_run;
+        if verbose {
+            println!("Renaming {} to {}", mv.source, mv.destination);
+        }
+        if !args.flag_dry_run {
+            try!(cli::wrap_with_status(fs::rename(&mv.source, &mv.destination), 128));
+            index.remove(mv.destination.as_bytes());
+        }
+        move_entries(&mut index, mv, verbose);
+    }
+
+    if let Some(lock) = lock {
+        try!(cli::wrap_with_status(index.write_locked(lock), 128));
+    }
+
+    cli::success()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.decode())
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
+
+    cli::exit_with(dispatch_for_args(&args))
+}
@@ -0,0 +1,125 @@
+extern crate chrono;
+extern crate gitters;
+
+extern crate rustc_serialize;
+extern crate docopt;
+
+use chrono::Local;
+use docopt::Docopt;
+use gitters::cli;
+use gitters::revwalk;
+
+const USAGE: &'static str = "
+rev-list - Lists commit objects in reverse chronological order
+
+Usage:
+  rev-list [options] <revision>...
+  rev-list (-h | --help)
+
+Revisions are commits to list, along with their ancestors. Prefixing a commit with '^' excludes
+it, along with its ancestors, and a range like A..B lists the commits reachable from B but not A.
+
+Options:
+  -h --help           Show this screen.
+  -n --max-count=<number>  List at most <number> commits.
+  --skip=<number>     Skip <number> commits before starting to list them.
+  --since=<date>      List commits more recent than <date>, which can be relative, such as
+                      '2 weeks ago'.
+  --after=<date>      The same as --since.
+  --until=<date>      List commits older than <date>.
+  --before=<date>     The same as --until.
+  --merges            Only list merge commits.
+  --no-merges         Don't list merge commits.
+  --topo-order        List no parents before all of their children, and avoid listing commits
+                      from multiple lines of history intermixed.
+  --date-order        List no parents before all of their children, but otherwise list commits
+                      by commit date.
+  --author-date-order  List no parents before all of their children, but otherwise list
+                      commits by author date.
+  --reverse           List the commits that would be listed in reverse.
+  --first-parent      Only follow the first parent of merge commits.
+  --ancestry-path     With a range like A..B, only list commits that are descendants of A.
+  --parents           Also list the parents of each commit.
+  --count             Print the number of commits that would be listed, rather than the commits.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    arg_revision: Vec<String>,
+    flag_max_count: Option<usize>,
+    flag_skip: Option<usize>,
+    flag_since: Option<String>,
+    flag_after: Option<String>,
+    flag_until: Option<String>,
+    flag_before: Option<String>,
+    flag_merges: bool,
+    flag_no_merges: bool,
+    flag_topo_order: bool,
+    flag_date_order: bool,
+    flag_author_date_order: bool,
+    flag_reverse: bool,
+    flag_first_parent: bool,
+    flag_ancestry_path: bool,
+    flag_parents: bool,
+    flag_count: bool,
+}
+
+/// The options for walking history, which rev-list shares with log.
+fn walk_options(args: &Args) -> revwalk::Options {
+    revwalk::Options {
+        topo_order: args.flag_topo_order,
+        date_order: args.flag_date_order,
+        author_date_order: args.flag_author_date_order,
+        reverse: args.flag_reverse,
+        first_parent: args.flag_first_parent,
+        ancestry_path: args.flag_ancestry_path,
+        max_count: args.flag_max_count,
+        skip: args.flag_skip,
+        since: args.flag_since.clone(),
+        after: args.flag_after.clone(),
+        until: args.flag_until.clone(),
+        before: args.flag_before.clone(),
+        merges: args.flag_merges,
+        no_merges: args.flag_no_merges,
+        ..revwalk::Options::default()
+    }
+}
+
+fn list_commits(args: &Args) -> cli::Result {
+    let now = Local::now().timestamp();
+    let mut walk = try!(cli::wrap_with_status(walk_options(args).walk(now), 128));
+    for rev in args.arg_revision.iter() {
+        try!(cli::wrap_with_status(walk.push_revision(rev), 128));
+    }
+
+    let mut count = 0;
+    for commit in walk {
+        let commit = try!(cli::wrap_with_status(commit, 128));
+        count += 1;
+        if args.flag_count {
+            continue;
+        }
+
+        let mut line = commit.name.0;
+        if args.flag_parents {
+            for parent in commit.parents.iter() {
+                line.push(' ');
+                line.push_str(&parent.0);
+            }
+        }
+        println!("{}", line);
+    }
+
+    if args.flag_count {
+        println!("{}", count);
+    }
+    cli::success()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.decode())
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
+
+    cli::exit_with(list_commits(&args))
+}
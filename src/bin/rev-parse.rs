@@ -26,7 +26,7 @@ struct Args {
 
 fn parse_and_print_revisions(revs: Vec<String>) -> cli::Result {
     for rev in revs {
-        let objects::Name(parsed) = try!(cli::wrap_with_status(revisions::resolve(&rev), 1));
+        let objects::Name(parsed) = try!(cli::wrap_with_status(revisions::resolve(&rev), 128));
         println!("{}", parsed);
     }
 
@@ -36,7 +36,7 @@ fn parse_and_print_revisions(revs: Vec<String>) -> cli::Result {
 fn main() {
     let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.decode())
-        .unwrap_or_else(|e| e.exit());
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
 
     cli::exit_with(parse_and_print_revisions(args.arg_revision))
 }
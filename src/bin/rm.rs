@@ -0,0 +1,243 @@
+//! An implementation of `git rm`, which removes files from the index and, unless asked to keep
+//! them, from the working tree as well.
+
+extern crate gitters;
+
+extern crate rustc_serialize;
+extern crate docopt;
+
+use docopt::Docopt;
+use gitters::cli;
+use gitters::config;
+use gitters::index;
+use gitters::pathspec::Pathspec;
+use gitters::refs;
+use gitters::status;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+
+const USAGE: &'static str = "
+rm - Remove files from the working tree and from the index
+
+Usage:
+  rm [options] [--] <pathspec>...
+  rm (-h | --help)
+
+Options:
+  -h --help     Show this screen.
+  -f --force    Override the check that the files being removed have no changes.
+  -n --dry-run  Don't actually remove any files, just show which ones would be removed.
+  -r            Allow recursive removal when a leading directory name is given.
+  --cached      Only remove the files from the index, leaving them in the working tree.
+  -q --quiet    Don't show the files as they are removed.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_force: bool,
+    flag_dry_run: bool,
+    flag_r: bool,
+    flag_cached: bool,
+    flag_quiet: bool,
+    arg_pathspec: Vec<String>,
+}
+
+fn display(path: &[u8]) -> String {
+    String::from_utf8_lossy(path).into_owned()
+}
+
+/// Format an error listing the files that can't be removed, in the same style as git.
+fn safety_error(files: &[Vec<u8>], singular: &str, plural: &str, hint: &str) -> String {
+    let mut message = format!("error: the following {}:\n",
+                              if files.len() == 1 { singular } else { plural });
+    for path in files {
+        message.push_str(&format!("    {}\n", display(path)));
+    }
+    message.push_str(hint);
+    message
+}
+
+/// Make sure removing the files won't lose any changes that haven't been committed. Without
+/// `--cached`, that means the file must match both `HEAD` and the index. With `--cached`, the file
+/// in the working tree is kept, so it's enough for either of them to match the index.
+fn check_up_to_date(cfg: &config::Config, index: &mut index::Index, paths: &HashSet<&[u8]>,
+                    cached: bool) -> cli::Result {
+    let head = try!(cli::wrap_with_status(refs::read_head(), 128));
+    let head_tree = try!(cli::wrap_with_status(status::read_head_tree(&head), 128));
+    let index_timestamp = index.timestamp;
+    let trust_executable_bit = status::trust_executable_bit(cfg);
+
+    let mut staged_and_local = Vec::new();
+    let mut staged = Vec::new();
+    let mut local = Vec::new();
+
+    for entry in index.entries.iter_mut() {
+        if entry.stage() > 0 || !paths.contains(entry.path.as_slice()) {
+            continue;
+        }
+
+        let (change, _, _) =
+            try!(cli::wrap_with_status(
+                    status::compare_worktree(entry, index_timestamp, trust_executable_bit), 128));
+        let has_local_changes = match change {
+            // A file that is already gone can't lose anything by being removed.
+            Some(status::Change::Deleted) | None => false,
+            Some(_) => true,
+        };
+        let has_staged_changes = match head_tree.get(&entry.path) {
+            Some(head_entry) => head_entry.mode != entry.mode || head_entry.sha1.0 != entry.sha1,
+            None => true,
+        };
+
+        if has_local_changes && has_staged_changes {
+            if !cached || !entry.is_intent_to_add() {
+                staged_and_local.push(entry.path.clone());
+            }
+        } else if !cached {
+            if has_staged_changes {
+                staged.push(entry.path.clone());
+            }
+            if has_local_changes {
+                local.push(entry.path.clone());
+            }
+        }
+    }
+
+    let mut errors = Vec::new();
+    if !staged_and_local.is_empty() {
+        errors.push(safety_error(
+                &staged_and_local,
+                "file has staged content different from both the\nfile and the HEAD",
+                "files have staged content different\nfrom both the file and the HEAD",
+                "(use -f to force removal)"));
+    }
+    if !staged.is_empty() {
+        errors.push(safety_error(
+                &staged,
+                "file has changes staged in the index",
+                "files have changes staged in the index",
+                "(use --cached to keep the file, or -f to force removal)"));
+    }
+    if !local.is_empty() {
+        errors.push(safety_error(
+                &local,
+                "file has local modifications",
+                "files have local modifications",
+                "(use --cached to keep the file, or -f to force removal)"));
+    }
+
+    if errors.is_empty() {
+        cli::success()
+    } else {
+        Err(cli::Error { message: errors.join("\n"), status: 1 })
+    }
+}
+
+/// Remove the file from the working tree, along with any of its parent directories that are left
+/// empty as a result.
+fn remove_from_worktree(path: &[u8]) -> cli::Result {
+    match fs::remove_file(status::fs_path(path)) {
+        Ok(_) => {},
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return cli::success(),
+        Err(err) => return Err(cli::Error {
+            message: format!("fatal: unable to remove {}: {}", display(path), err),
+            status: 128,
+        }),
+    }
+
+    let mut parent = status::fs_path(path).parent();
+    while let Some(dir) = parent {
+        if dir.as_os_str().is_empty() || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+
+    cli::success()
+}
+
+fn dispatch_for_args(args: &Args) -> cli::Result {
+    let pathspec = Pathspec::new(&args.arg_pathspec);
+    let cfg = try!(cli::wrap_with_status(config::read_all(), 128));
+
+    // The lock is taken before reading the index, so no other process can modify the index between
+    // reading and writing it.
+    let lock = if args.flag_dry_run {
+        None
+    } else {
+        Some(try!(cli::wrap_with_status(index::Index::lock(), 128)))
+    };
+    let mut index = try!(cli::wrap_with_status(index::Index::read(), 128));
+
+    let mut matched = vec![false; pathspec.originals.len()];
+    let mut paths: Vec<Vec<u8>> = Vec::new();
+    for entry in index.entries.iter() {
+        let item = match pathspec.matching_item(&entry.path) {
+            Some(item) => item,
+            None => continue,
+        };
+
+        if !args.flag_r && !pathspec.is_wildcard(item) &&
+                !pathspec.names_exactly(item, &entry.path) {
+            return Err(cli::Error {
+                message: format!("fatal: not removing '{}' recursively without -r",
+                                 pathspec.originals[item]),
+                status: 128,
+            });
+        }
+
+        matched[item] = true;
+        if paths.last() != Some(&entry.path) {
+            paths.push(entry.path.clone());
+        }
+    }
+
+    for (item, was_matched) in matched.into_iter().enumerate() {
+        if !was_matched {
+            return Err(cli::Error {
+                message: format!("fatal: pathspec '{}' did not match any files",
+                                 pathspec.originals[item]),
+                status: 128,
+            });
+        }
+    }
+
+    // The paths are looked up for every entry in the index, so a set keeps removing a large
+    // directory from taking time proportional to its size squared.
+    let path_set: HashSet<&[u8]> = paths.iter().map(|path| path.as_slice()).collect();
+    if !args.flag_force {
+        try!(check_up_to_date(&cfg, &mut index, &path_set, args.flag_cached));
+    }
+
+    if !args.flag_quiet {
+        for path in paths.iter() {
+            println!("rm '{}'", display(path));
+        }
+    }
+    if !args.flag_dry_run {
+        index.remove_all(&path_set);
+    }
+
+    if let Some(lock) = lock {
+        // The index is written before touching the working tree, so that a failure to remove a
+        // file leaves it untracked rather than losing it.
+        try!(cli::wrap_with_status(index.write_locked(lock), 128));
+
+        if !args.flag_cached {
+            for path in paths.iter() {
+                try!(remove_from_worktree(path));
+            }
+        }
+    }
+
+    cli::success()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.decode())
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
+
+    cli::exit_with(dispatch_for_args(&args))
+}
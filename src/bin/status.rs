@@ -0,0 +1,385 @@
+//! An implementation of `git status`, showing the changes staged in the index, the changes in the
+//! working tree that haven't been staged yet, and the untracked files. Rename detection and the
+//! hints for what to do next are not supported.
+
+extern crate gitters;
+
+extern crate rustc_serialize;
+extern crate docopt;
+
+use docopt::Docopt;
+use gitters::cli;
+use gitters::config;
+use gitters::objects;
+use gitters::pager;
+use gitters::status;
+use gitters::status::{Status, UntrackedMode};
+use std::env;
+use std::io;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+const USAGE: &'static str = "
+status - Show the working tree status
+
+Usage:
+  status [options]
+  status (-h | --help)
+
+Options:
+  -h --help                 Show this screen.
+  -s --short                Give the output in the short format.
+  -b --branch               Show the branch and tracking info, even in short format.
+  --porcelain=<version>     Give the output in an easy-to-parse format for scripts, either v1
+                            (the default when no version is given) or v2.
+  -z                        Terminate entries with NUL instead of LF, and don't quote paths.
+                            Implies --porcelain=v1 if no other format is given.
+  --untracked-files=<mode>  Show untracked files: no, normal (collapsing untracked directories)
+                            or all. Also accepted as -u<mode>, and -u alone means all.
+                            [default: normal]
+  --ignored                 Show ignored files as well.
+";
+
+#[derive(RustcDecodable)]
+struct Args {
+    flag_short: bool,
+    flag_branch: bool,
+    flag_porcelain: Option<String>,
+    flag_z: bool,
+    flag_untracked_files: String,
+    flag_ignored: bool,
+}
+
+#[derive(PartialEq, Eq)]
+enum Format {
+    Long,
+    Short,
+    PorcelainV1,
+    PorcelainV2,
+}
+
+/// Docopt doesn't support options whose value is optional, so `--porcelain` and `-u` without a
+/// value are rewritten to spell out their default values before the arguments are parsed.
+fn normalize_args() -> Vec<String> {
+    env::args()
+        .map(|arg| match arg.as_ref() {
+            "--porcelain" => "--porcelain=v1".to_string(),
+            "-u" | "--untracked-files" => "--untracked-files=all".to_string(),
+            _ if arg.starts_with("-u") => format!("--untracked-files={}", &arg[2..]),
+            _ => arg.clone(),
+        })
+        .collect()
+}
+
+struct Printer {
+    nul_terminated: bool,
+    quote_spaces: bool,
+    out: io::Stdout,
+}
+
+impl Printer {
+    fn path(&self, path: &[u8]) -> String {
+        if self.nul_terminated {
+            String::from_utf8_lossy(path).into_owned()
+        } else {
+            cli::quote_path(path, self.quote_spaces)
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        let terminator = if self.nul_terminated { "\0" } else { "\n" };
+        let _ = write!(self.out, "{}{}", line, terminator);
+    }
+}
+
+fn path_bytes(path: &PathBuf) -> &[u8] {
+    path.as_os_str().as_bytes()
+}
+
+fn abbreviate(name: &objects::Name) -> &str {
+    &name.0[..7]
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "commit" } else { "commits" }
+}
+
+fn print_short_branch(status: &Status, printer: &mut Printer) {
+    let mut line = String::from("## ");
+    match status.head.branch {
+        Some(ref branch) if status.head.commit.is_none() =>
+            line.push_str(&format!("No commits yet on {}", branch)),
+        Some(ref branch) => line.push_str(branch),
+        None => line.push_str("HEAD (no branch)"),
+    }
+
+    if let Some(ref upstream) = status.upstream {
+        line.push_str(&format!("...{}", upstream.name));
+        match upstream.divergence {
+            None => line.push_str(" [gone]"),
+            Some((0, 0)) => {},
+            Some((ahead, 0)) => line.push_str(&format!(" [ahead {}]", ahead)),
+            Some((0, behind)) => line.push_str(&format!(" [behind {}]", behind)),
+            Some((ahead, behind)) =>
+                line.push_str(&format!(" [ahead {}, behind {}]", ahead, behind)),
+        }
+    }
+
+    printer.line(&line);
+}
+
+fn print_short(status: &Status, args: &Args, printer: &mut Printer) {
+    if args.flag_branch {
+        print_short_branch(status, printer);
+    }
+
+    // Tracked changes and conflicts are listed together, in path order.
+    let mut lines = Vec::new();
+    for file in status.files.iter() {
+        let code = |change: Option<status::Change>| change.map(|c| c.code()).unwrap_or(' ');
+        lines.push((&file.path,
+                    format!("{}{} {}", code(file.staged), code(file.unstaged),
+                            printer.path(&file.path))));
+    }
+    for file in status.unmerged.iter() {
+        lines.push((&file.path, format!("{} {}", file.conflict.code(), printer.path(&file.path))));
+    }
+    lines.sort();
+
+    for (_, line) in lines {
+        printer.line(&line);
+    }
+    for path in status.untracked.iter() {
+        let line = format!("?? {}", printer.path(path_bytes(path)));
+        printer.line(&line);
+    }
+    for path in status.ignored.iter() {
+        let line = format!("!! {}", printer.path(path_bytes(path)));
+        printer.line(&line);
+    }
+}
+
+fn print_porcelain_v2(status: &Status, args: &Args, printer: &mut Printer) {
+    const NULL_SHA1: &'static str = "0000000000000000000000000000000000000000";
+
+    if args.flag_branch {
+        let oid = status.head.commit.as_ref().map(|name| name.0.as_str()).unwrap_or("(initial)");
+        printer.line(&format!("# branch.oid {}", oid));
+        let head = status.head.branch.as_ref().map(|b| b.as_str()).unwrap_or("(detached)");
+        printer.line(&format!("# branch.head {}", head));
+
+        if let Some(ref upstream) = status.upstream {
+            printer.line(&format!("# branch.upstream {}", upstream.name));
+            if let Some((ahead, behind)) = upstream.divergence {
+                printer.line(&format!("# branch.ab +{} -{}", ahead, behind));
+            }
+        }
+    }
+
+    let mode = |mode: Option<u32>| format!("{:06o}", mode.unwrap_or(0));
+    let version_mode = |version: &Option<status::Version>| mode(version.as_ref().map(|v| v.mode));
+    let version_sha1 = |version: &Option<status::Version>|
+        version.as_ref().map(|v| v.sha1.0.clone()).unwrap_or(NULL_SHA1.to_string());
+
+    let mut lines = Vec::new();
+    for file in status.files.iter() {
+        let code = |change: Option<status::Change>| change.map(|c| c.code()).unwrap_or('.');
+        lines.push((&file.path, format!(
+            "1 {}{} N... {} {} {} {} {} {}",
+            code(file.staged), code(file.unstaged),
+            version_mode(&file.head), version_mode(&file.index), mode(file.worktree_mode),
+            version_sha1(&file.head), version_sha1(&file.index),
+            printer.path(&file.path))));
+    }
+    for file in status.unmerged.iter() {
+        lines.push((&file.path, format!(
+            "u {} N... {} {} {} {} {} {} {} {}",
+            file.conflict.code(),
+            version_mode(&file.stages[0]), version_mode(&file.stages[1]),
+            version_mode(&file.stages[2]), mode(file.worktree_mode),
+            version_sha1(&file.stages[0]), version_sha1(&file.stages[1]),
+            version_sha1(&file.stages[2]),
+            printer.path(&file.path))));
+    }
+    lines.sort();
+
+    for (_, line) in lines {
+        printer.line(&line);
+    }
+    for path in status.untracked.iter() {
+        let line = format!("? {}", printer.path(path_bytes(path)));
+        printer.line(&line);
+    }
+    for path in status.ignored.iter() {
+        let line = format!("! {}", printer.path(path_bytes(path)));
+        printer.line(&line);
+    }
+}
+
+fn print_long_branch(status: &Status, printer: &mut Printer) {
+    match (&status.head.branch, &status.head.commit) {
+        (&Some(ref branch), _) => printer.line(&format!("On branch {}", branch)),
+        (&None, &Some(ref commit)) =>
+            printer.line(&format!("HEAD detached at {}", abbreviate(commit))),
+        (&None, &None) => printer.line("Not currently on any branch."),
+    }
+
+    if let Some(ref upstream) = status.upstream {
+        match upstream.divergence {
+            None => printer.line(&format!(
+                "Your branch is based on '{}', but the upstream is gone.", upstream.name)),
+            Some((0, 0)) => printer.line(&format!(
+                "Your branch is up to date with '{}'.", upstream.name)),
+            Some((ahead, 0)) => printer.line(&format!(
+                "Your branch is ahead of '{}' by {} {}.", upstream.name, ahead, plural(ahead))),
+            Some((0, behind)) => printer.line(&format!(
+                "Your branch is behind '{}' by {} {}, and can be fast-forwarded.",
+                upstream.name, behind, plural(behind))),
+            Some((ahead, behind)) => {
+                printer.line(&format!("Your branch and '{}' have diverged,", upstream.name));
+                printer.line(&format!("and have {} and {} different commits each, respectively.",
+                                      ahead, behind));
+            },
+        }
+        printer.line("");
+    }
+
+    if status.merge_in_progress {
+        if status.unmerged.is_empty() {
+            printer.line("All conflicts fixed but you are still merging.");
+        } else {
+            printer.line("You have unmerged paths.");
+        }
+        printer.line("");
+    }
+
+    if status.head.commit.is_none() {
+        printer.line("");
+        printer.line("No commits yet");
+        printer.line("");
+    }
+}
+
+fn print_long(status: &Status, show_untracked: bool, printer: &mut Printer) {
+    print_long_branch(status, printer);
+
+    // The labels are padded to the width of the longest possible label in each section.
+    let staged = status.files.iter()
+        .filter_map(|file| file.staged.map(|change| (change, &file.path)))
+        .collect::<Vec<_>>();
+    if !staged.is_empty() {
+        printer.line("Changes to be committed:");
+        for (change, path) in staged {
+            let line = format!("\t{:<12}{}", format!("{}:", change.description()),
+                               printer.path(path));
+            printer.line(&line);
+        }
+        printer.line("");
+    }
+
+    if !status.unmerged.is_empty() {
+        printer.line("Unmerged paths:");
+        for file in status.unmerged.iter() {
+            let line = format!("\t{:<17}{}", format!("{}:", file.conflict.description()),
+                               printer.path(&file.path));
+            printer.line(&line);
+        }
+        printer.line("");
+    }
+
+    let unstaged = status.files.iter()
+        .filter_map(|file| file.unstaged.map(|change| (change, &file.path)))
+        .collect::<Vec<_>>();
+    if !unstaged.is_empty() {
+        printer.line("Changes not staged for commit:");
+        for (change, path) in unstaged {
+            let line = format!("\t{:<12}{}", format!("{}:", change.description()),
+                               printer.path(path));
+            printer.line(&line);
+        }
+        printer.line("");
+    }
+
+    if show_untracked {
+        for &(ref heading, ref paths) in [("Untracked files:", &status.untracked),
+                                          ("Ignored files:", &status.ignored)].iter() {
+            if paths.is_empty() {
+                continue;
+            }
+
+            printer.line(heading);
+            for path in paths.iter() {
+                let line = format!("\t{}", printer.path(path_bytes(path)));
+                printer.line(&line);
+            }
+            printer.line("");
+        }
+    } else if status.has_staged_changes() {
+        printer.line("Untracked files not listed");
+    }
+
+    if status.has_staged_changes() {
+        return;
+    }
+
+    if status.has_unstaged_changes() {
+        printer.line("no changes added to commit");
+    } else if !status.untracked.is_empty() {
+        printer.line("nothing added to commit but untracked files present");
+    } else if status.head.commit.is_none() || !show_untracked {
+        printer.line("nothing to commit");
+    } else {
+        printer.line("nothing to commit, working tree clean");
+    }
+}
+
+fn dispatch_for_args(args: &Args) -> cli::Result {
+    let format = match args.flag_porcelain.as_ref().map(|v| v.as_str()) {
+        Some("v1") => Format::PorcelainV1,
+        Some("v2") => Format::PorcelainV2,
+        Some(version) => return Err(cli::Error {
+            message: format!("fatal: unsupported porcelain version '{}'", version),
+            status: 128,
+        }),
+        None if args.flag_short => Format::Short,
+        None if args.flag_z => Format::PorcelainV1,
+        None => Format::Long,
+    };
+
+    let untracked = match args.flag_untracked_files.as_ref() {
+        "no" => UntrackedMode::No,
+        "normal" => UntrackedMode::Normal,
+        "all" => UntrackedMode::All,
+        mode => return Err(cli::Error {
+            message: format!("fatal: Invalid untracked files mode '{}'", mode),
+            status: 128,
+        }),
+    };
+
+    let cfg = try!(cli::wrap_with_status(config::read_all(), 128));
+    try!(cli::wrap_with_status(pager::setup(&cfg, "status", false), 128));
+    let options = status::Options { untracked: untracked, show_ignored: args.flag_ignored };
+    let status = try!(cli::wrap_with_status(status::status(&cfg, &options), 128));
+
+    let mut printer = Printer {
+        nul_terminated: args.flag_z,
+        quote_spaces: format == Format::Short || format == Format::PorcelainV1,
+        out: io::stdout(),
+    };
+    match format {
+        Format::Long => print_long(&status, untracked != UntrackedMode::No, &mut printer),
+        Format::Short | Format::PorcelainV1 => print_short(&status, args, &mut printer),
+        Format::PorcelainV2 => print_porcelain_v2(&status, args, &mut printer),
+    }
+
+    cli::success()
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.argv(normalize_args()).decode())
+        .unwrap_or_else(|e| cli::exit_with_usage(e));
+
+    cli::exit_with(dispatch_for_args(&args))
+}
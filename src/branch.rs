@@ -1,17 +1,29 @@
 use regex::Regex;
-use std::{error, fmt, fs};
+use repository;
+use std::{error, fmt, fs, io};
 use std::fs::File;
 use std::io::Read;
+use std::path::PathBuf;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub enum Error {
-    BranchReadError,
+    /// A file or directory storing branches couldn't be read.
+    IOError(PathBuf, io::Error),
+
+    /// `HEAD` doesn't point to a branch, such as when it's detached.
+    NotOnBranch,
+
+    /// A file storing a branch has a name that isn't valid UTF-8.
+    InvalidName(PathBuf),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::BranchReadError => write!(f, "unable to read branch(es)"),
+            Error::IOError(ref path, ref err) =>
+                write!(f, "unable to read branch(es): {}: {}", path.display(), err),
+            Error::NotOnBranch => write!(f, "HEAD is not on a branch"),
+            Error::InvalidName(ref path) => write!(f, "invalid branch name: {}", path.display()),
         }
     }
 }
@@ -19,30 +31,34 @@ impl fmt::Display for Error {
 impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
-            Error::BranchReadError => "unable to read branch(es)",
+            Error::IOError(..) => "unable to read branch(es)",
+            Error::NotOnBranch => "HEAD is not on a branch",
+            Error::InvalidName(_) => "invalid branch name",
         }
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
         match *self {
-            Error::BranchReadError => None,
+            Error::IOError(_, ref err) => Some(err),
+            Error::NotOnBranch | Error::InvalidName(_) => None,
         }
     }
 }
 
 pub fn all_branches() -> Result<Vec<String>, Error> {
-    let branch_paths = try!(fs::read_dir(".git/refs/heads").map_err(|_| Error::BranchReadError));
+    let heads = repository::git_path("refs/heads");
+    let branch_paths = try!(fs::read_dir(&heads).map_err(|err| Error::IOError(heads.clone(), err)));
 
     let mut branch_names = Vec::new();
     for branch_path in branch_paths {
-        let branch_path = try!(branch_path.map_err(|_| Error::BranchReadError));
+        let branch_path = try!(branch_path.map_err(|err| Error::IOError(heads.clone(), err)));
         let name = try!(
             branch_path
             .path()
             .file_name()
             .and_then(|fname| fname.to_str())
             .map(|fname| fname.to_string())
-            .ok_or(Error::BranchReadError));
+            .ok_or(Error::InvalidName(branch_path.path())));
         branch_names.push(name.to_string());
     }
 
@@ -55,10 +71,12 @@ pub fn current_branch() -> Result<String, Error> {
             Regex::new(r"^ref: refs/heads/(?P<branch>.+)\s*$").unwrap();
     }
 
-    let mut head_file = try!(File::open(".git/HEAD").map_err(|_| Error::BranchReadError));
+    let head = repository::git_path("HEAD");
     let mut head_contents = String::new();
-    try!(head_file.read_to_string(&mut head_contents).map_err(|_| Error::BranchReadError));
+    try!(File::open(&head)
+         .and_then(|mut head_file| head_file.read_to_string(&mut head_contents))
+         .map_err(|err| Error::IOError(head.clone(), err)));
 
-    let caps = try!(SYMBOLIC_REF_REGEX.captures(&head_contents).ok_or(Error::BranchReadError));
+    let caps = try!(SYMBOLIC_REF_REGEX.captures(&head_contents).ok_or(Error::NotOnBranch));
     Ok(caps["branch"].to_string())
 }
@@ -1,9 +1,15 @@
 //! Provides utilities for creating standardized command-line interfaces (CLIs), such as a means of
 //! propagating error status codes.
 
+extern crate docopt;
+
+use color;
+
 use std::error::Error as StdError;
 use std::fmt;
+use std::io;
 use std::process;
+use std::ptr;
 use std::result;
 
 #[derive(Debug)]
@@ -12,16 +18,54 @@ pub struct Error {
     pub status: i32
 }
 
+/// The status of a command that died from an error it can't recover from, which is reported with a
+/// `fatal:` prefix.
+pub const FATAL_STATUS: i32 = 128;
+
+/// The status of a command that was run with the wrong arguments.
+pub const USAGE_STATUS: i32 = 129;
+
 impl Error {
-    /// Coerce a standard `Error` into a CLI `Error`, with a status code. Private because we will
-    /// typically be using `wrap_with_status` to map a standard `Result` containing an error into
-    /// one containing a CLI `Error`. Can be made public if necessary in the future.
+    /// Coerce a standard `Error` into a CLI `Error`, with a status code. The message includes the
+    /// whole chain of causes, and is prefixed the same way as in git, with `fatal:` for the status
+    /// git uses when it dies, and `error:` otherwise. Private because we will typically be using
+    /// `wrap_with_status` to map a standard `Result` containing an error into one containing a CLI
+    /// `Error`. Can be made public if necessary in the future.
     fn from_error<T>(cause: T, status: i32) -> Error
             where T: StdError, {
-        Error { message: cause.description().to_string(), status: status }
+        let prefix = if status == FATAL_STATUS { "fatal" } else { "error" };
+        Error { message: format!("{}: {}", prefix, describe(&cause)), status: status }
     }
 }
 
+/// The most causes `describe` follows, so that a chain of errors that loops back on itself can't
+/// keep it going forever.
+const MAX_CAUSES: usize = 16;
+
+/// Describe an error along with everything that caused it, such as `unable to read branch(es):
+/// IO error: No such file or directory`. Causes that the message of the error already includes
+/// aren't repeated, and an error that claims to be its own cause ends the chain.
+pub fn describe(err: &StdError) -> String {
+    let mut message = err.to_string();
+    let mut previous = err as *const StdError as *const ();
+    let mut cause = err.source();
+    for _ in 0..MAX_CAUSES {
+        let err = match cause {
+            Some(err) if !ptr::eq(err as *const StdError as *const (), previous) => err,
+            _ => break,
+        };
+
+        let description = err.to_string();
+        if !message.contains(&description) {
+            message.push_str(": ");
+            message.push_str(&description);
+        }
+        previous = err as *const StdError as *const ();
+        cause = err.source();
+    }
+    message
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.message)
@@ -33,7 +77,7 @@ impl StdError for Error {
         &self.message
     }
 
-    fn cause(&self) -> Option<&StdError> {
+    fn source(&self) -> Option<&(StdError + 'static)> {
         None
     }
 }
@@ -55,14 +99,162 @@ pub fn success() -> Result {
     Ok(())
 }
 
+/// Describe an IO error the way git does, with the system's message for the error, but without
+/// the error number that Rust adds to it.
+pub fn io_error_message(err: &io::Error) -> String {
+    let message = err.to_string();
+    match message.find(" (os error ") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    }
+}
+
+/// Quote a path the way git does when `core.quotePath` is on: if it contains any special
+/// characters, the path is surrounded by double quotes, with C-style escapes for quotes,
+/// backslashes and control characters, and octal escapes for bytes outside ASCII. The short format
+/// of `status` also quotes paths containing spaces, so that each line can be split on whitespace.
+pub fn quote_path(path: &[u8], quote_spaces: bool) -> String {
+    let needs_quoting = path.iter().any(|&b| b < 0x20 || b >= 0x7f || b == b'"' || b == b'\\' ||
+                                        (quote_spaces && b == b' '));
+    if !needs_quoting {
+        return String::from_utf8_lossy(path).into_owned();
+    }
+
+    let mut quoted = String::from("\"");
+    for &b in path {
+        match b {
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b'\x07' => quoted.push_str("\\a"),
+            b'\x08' => quoted.push_str("\\b"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            b'\x0b' => quoted.push_str("\\v"),
+            b'\x0c' => quoted.push_str("\\f"),
+            b'\r' => quoted.push_str("\\r"),
+            b if b < 0x20 || b >= 0x7f => quoted.push_str(&format!("\\{:03o}", b)),
+            b => quoted.push(b as char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Split a command line into its arguments the way git splits aliases: on whitespace, except
+/// inside single or double quotes, with backslashes escaping the next character outside single
+/// quotes. Unlike a shell, nothing else is expanded. An argument only starts once something other
+/// than whitespace is seen, so that an empty pair of quotes is an empty argument, but repeated
+/// whitespace doesn't produce any.
+pub fn split_cmdline(cmdline: &str) -> result::Result<Vec<String>, &'static str> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    let mut chars = cmdline.chars();
+    while let Some(chr) = chars.next() {
+        match (chr, quote) {
+            (chr, None) if chr.is_whitespace() => args.extend(arg.take()),
+            ('\'', None) | ('"', None) => {
+                quote = Some(chr);
+                arg.get_or_insert_with(String::new);
+            },
+            (chr, Some(quoted)) if chr == quoted => quote = None,
+            ('\\', quoted) if quoted != Some('\'') => match chars.next() {
+                Some(escaped) => arg.get_or_insert_with(String::new).push(escaped),
+                None => return Err("cmdline ends with \\"),
+            },
+            (chr, _) => arg.get_or_insert_with(String::new).push(chr),
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unclosed quote");
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// Interpret the `--color=<when>` and `--no-color` options shared by the commands that color their
+/// output, returning nothing if neither was given, so that the configuration decides.
+pub fn color_mode(color: &Option<String>, no_color: bool)
+        -> result::Result<Option<color::Mode>, Error> {
+    if no_color {
+        return Ok(Some(color::Mode::Never));
+    }
+
+    match *color {
+        Some(ref when) => color::parse_mode(when).map(Some).ok_or_else(|| Error {
+            message: "error: option `color' expects \"always\", \"auto\", or \"never\"".to_string(),
+            status: USAGE_STATUS,
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Print a warning to STDERR, in the same style as git.
+pub fn warning(message: &str) {
+    eprintln!("warning: {}", message);
+}
+
+/// Print a hint about what to do next to STDERR, in the same style as git, prefixing each line.
+pub fn hint(message: &str) {
+    for line in message.lines() {
+        eprintln!("hint: {}", line);
+    }
+}
+
+/// Exit after the arguments couldn't be parsed, or help was asked for. Help goes to STDOUT, but
+/// anything else is a usage error, which goes to STDERR with the status git uses for them.
+pub fn exit_with_usage(err: docopt::Error) -> ! {
+    if !err.fatal() {
+        err.exit();
+    }
+
+    eprintln!("{}", err);
+    process::exit(USAGE_STATUS)
+}
+
+/// Exit with the status of the command, printing the error, if there is one, to STDERR, so that it
+/// isn't mistaken for the command's output.
 pub fn exit_with(result: Result) -> ! {
     match result {
         Ok(_) => process::exit(0),
         Err(err) => match err {
             Error { message, status } => {
-                println!("{}", message);
+                if !message.is_empty() {
+                    eprintln!("{}", message);
+                }
                 process::exit(status)
             }
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config;
+
+    #[test]
+    fn splits_command_lines() {
+        assert_eq!(vec!["log", "--oneline", "-5"], split_cmdline("log  --oneline -5").unwrap());
+        assert_eq!(vec!["log", "--format=%h %s", "it's"],
+                   split_cmdline("log --format='%h %s' \"it's\"").unwrap());
+        assert_eq!(vec!["a b", "c\\d"], split_cmdline("a\\ b 'c\\d'").unwrap());
+        assert_eq!(Err("unclosed quote"), split_cmdline("log 'x"));
+        assert_eq!(vec!["log", "", "x"], split_cmdline("log '' x").unwrap());
+        assert_eq!(Err("cmdline ends with \\"), split_cmdline("log \\"));
+    }
+
+    #[test]
+    fn ignores_repeated_whitespace_in_command_lines() {
+        assert_eq!(vec!["log", "--oneline"], split_cmdline("log  --oneline").unwrap());
+        assert_eq!(vec!["log", "--format=%h"], split_cmdline("  log \t '--format=%h'  ").unwrap());
+        assert_eq!(Vec::<String>::new(), split_cmdline("   ").unwrap());
+    }
+
+    #[test]
+    fn describes_config_errors() {
+        let err = config::Config::new().add_from_string("[core\n".to_string()).unwrap_err();
+        assert_eq!("invalid file: bad config line 1 in string", describe(&err));
+    }
+}
@@ -0,0 +1,327 @@
+//! Parses the colors used in git's configuration, such as `bold red` or `#ff0000 ul`, into the
+//! ANSI escape sequences that produce them. A color is made up of whitespace-separated words: up to
+//! two colors, for the foreground and background, and any number of attributes.
+//!
+//! Commands color their output through `Colors`, which decides whether to use color at all, from
+//! `color.ui`, the command's own `color.<command>` and its `--color` option, and looks up the color
+//! of each part of the output, its slot, from `color.<command>.<slot>`.
+
+extern crate libc;
+
+use config;
+use pager;
+
+use std::env;
+
+/// The escape sequence that resets the color and attributes back to the terminal's default.
+pub const RESET: &'static str = "\x1b[m";
+
+/// The basic colors, in the order of their ANSI codes.
+const COLOR_NAMES: [&'static str; 8] =
+    ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+/// The attributes that can be turned on, along with the ANSI codes that turn them on and off.
+const ATTRIBUTES: [(&'static str, u32, u32); 7] = [
+    ("bold", 1, 22),
+    ("dim", 2, 22),
+    ("italic", 3, 23),
+    ("ul", 4, 24),
+    ("blink", 5, 25),
+    ("reverse", 7, 27),
+    ("strike", 9, 29),
+];
+
+/// A single foreground or background color.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Color {
+    /// The terminal's current color, which is left alone.
+    Normal,
+
+    /// The terminal's default color, which is explicitly switched back to.
+    Default,
+
+    /// One of the basic or bright colors, given by its foreground code, such as 31 for red.
+    Ansi(u32),
+
+    /// One of the colors of the 256-color palette.
+    Palette(u8),
+
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// The ANSI parameters that select the color, or nothing for the normal color.
+    fn code(&self, background: bool) -> Option<String> {
+        let (offset, kind) = if background { (10, '4') } else { (0, '3') };
+        match *self {
+            Color::Normal => None,
+            Color::Default => Some(format!("{}", 39 + offset)),
+            Color::Ansi(code) => Some(format!("{}", code + offset)),
+            Color::Palette(index) => Some(format!("{}8;5;{}", kind, index)),
+            Color::Rgb(r, g, b) => Some(format!("{}8;2;{};{};{}", kind, r, g, b)),
+        }
+    }
+}
+
+fn parse_rgb(word: &str) -> Option<Color> {
+    if word.len() != 7 || !word.starts_with('#') || !word.is_ascii() {
+        return None;
+    }
+
+    let component = |i: usize| u8::from_str_radix(&word[i..i + 2], 16).ok();
+    match (component(1), component(3), component(5)) {
+        (Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+        _ => None,
+    }
+}
+
+/// Parse a single word as a color: a name, optionally prefixed with `bright`, `normal`, `default`,
+/// a number from the 256-color palette, or `#rrggbb`. Names are case-insensitive.
+fn parse_color(word: &str) -> Option<Color> {
+    let name = word.to_ascii_lowercase();
+    if name == "normal" {
+        return Some(Color::Normal);
+    } else if name == "default" {
+        return Some(Color::Default);
+    } else if let Some(color) = parse_rgb(word) {
+        return Some(color);
+    }
+
+    if let Some(index) = COLOR_NAMES.iter().position(|color| *color == name) {
+        return Some(Color::Ansi(30 + index as u32));
+    }
+    if name.starts_with("bright") {
+        if let Some(index) = COLOR_NAMES.iter().position(|color| *color == &name[6..]) {
+            return Some(Color::Ansi(90 + index as u32));
+        }
+    }
+
+    // The first 16 colors of the palette are the basic and bright colors, which have shorter
+    // codes of their own.
+    match word.parse::<i32>() {
+        Ok(-1) => Some(Color::Normal),
+        Ok(number) if 0 <= number && number < 8 => Some(Color::Ansi(30 + number as u32)),
+        Ok(number) if 8 <= number && number < 16 => Some(Color::Ansi(90 + number as u32 - 8)),
+        Ok(number) if 16 <= number && number < 256 => Some(Color::Palette(number as u8)),
+        _ => None,
+    }
+}
+
+/// Parse a single word as an attribute, returning the ANSI code that sets it. A `no` or `no-`
+/// prefix turns the attribute off instead.
+fn parse_attribute(word: &str) -> Option<u32> {
+    let (name, negated) = if word.starts_with("no-") {
+        (&word[3..], true)
+    } else if word.starts_with("no") {
+        (&word[2..], true)
+    } else {
+        (word, false)
+    };
+
+    ATTRIBUTES.iter()
+        .find(|&&(attribute, _, _)| attribute == name)
+        .map(|&(_, on, off)| if negated { off } else { on })
+}
+
+/// Parse a color as it's given in the configuration, returning the escape sequence that produces
+/// it. Returns `None` if the color isn't valid. An empty color produces an empty sequence, leaving
+/// the output as it is.
+pub fn parse(spec: &str) -> Option<String> {
+    if spec.to_ascii_lowercase() == "reset" {
+        return Some(RESET.to_string());
+    }
+
+    let mut reset = false;
+    let mut foreground = None;
+    let mut background = None;
+    let mut attributes = 0u32;
+    for word in spec.split_whitespace() {
+        if word.to_ascii_lowercase() == "reset" {
+            reset = true;
+        } else if let Some(color) = parse_color(word) {
+            if foreground.is_none() {
+                foreground = Some(color);
+            } else if background.is_none() {
+                background = Some(color);
+            } else {
+                return None;
+            }
+        } else if let Some(code) = parse_attribute(word) {
+            attributes |= 1 << code;
+        } else {
+            return None;
+        }
+    }
+
+    let foreground = foreground.and_then(|color| color.code(false));
+    let background = background.and_then(|color| color.code(true));
+    if !reset && attributes == 0 && foreground.is_none() && background.is_none() {
+        return Some(String::new());
+    }
+
+    // A reset is an empty parameter, which comes before everything else.
+    let mut codes = Vec::new();
+    if reset {
+        codes.push(String::new());
+    }
+    codes.extend((0..32).filter(|code| attributes & (1 << code) != 0).map(|code| code.to_string()));
+    codes.extend(foreground);
+    codes.extend(background);
+    Some(format!("\x1b[{}m", codes.join(";")))
+}
+
+/// When to color the output, as given by `color.ui`, `color.<command>` or `--color`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Never,
+    Always,
+
+    /// Only color output going to a terminal, including through the pager.
+    Auto,
+}
+
+/// Parse when to color the output: `never`, `always` or `auto`. Any boolean is accepted as well,
+/// with true meaning `auto`, since that's what turning color on is meant to do.
+pub fn parse_mode(value: &str) -> Option<Mode> {
+    match value.to_ascii_lowercase().as_str() {
+        "never" => Some(Mode::Never),
+        "always" => Some(Mode::Always),
+        "auto" => Some(Mode::Auto),
+        _ => config::parse_bool(value).map(|on| if on { Mode::Auto } else { Mode::Never }),
+    }
+}
+
+/// A part of a command's output that has its own color, along with the color it has unless it's
+/// configured otherwise.
+pub struct Slot {
+    pub name: &'static str,
+    default: &'static str,
+}
+
+pub const BRANCH_CURRENT: Slot = Slot { name: "color.branch.current", default: "green" };
+pub const BRANCH_LOCAL: Slot = Slot { name: "color.branch.local", default: "normal" };
+pub const BRANCH_REMOTE: Slot = Slot { name: "color.branch.remote", default: "red" };
+
+pub const DECORATE_HEAD: Slot = Slot { name: "color.decorate.head", default: "bold cyan" };
+pub const DECORATE_BRANCH: Slot = Slot { name: "color.decorate.branch", default: "bold green" };
+pub const DECORATE_REMOTE: Slot =
+    Slot { name: "color.decorate.remoteBranch", default: "bold red" };
+pub const DECORATE_TAG: Slot = Slot { name: "color.decorate.tag", default: "bold yellow" };
+
+pub const DIFF_COMMIT: Slot = Slot { name: "color.diff.commit", default: "yellow" };
+pub const DIFF_META: Slot = Slot { name: "color.diff.meta", default: "bold" };
+pub const DIFF_FRAG: Slot = Slot { name: "color.diff.frag", default: "cyan" };
+pub const DIFF_OLD: Slot = Slot { name: "color.diff.old", default: "red" };
+pub const DIFF_NEW: Slot = Slot { name: "color.diff.new", default: "green" };
+
+/// Whether output in the given mode is colored, given whether STDOUT is a terminal, either
+/// directly or through the pager, and the values of `$TERM` and `$NO_COLOR`. Neither of those
+/// variables stops color that's explicitly asked for.
+fn enabled(mode: Mode, terminal: bool, term: Option<String>, no_color: Option<String>) -> bool {
+    match mode {
+        Mode::Never => false,
+        Mode::Always => true,
+        Mode::Auto => {
+            terminal &&
+                term.map(|term| term != "dumb").unwrap_or(false) &&
+                no_color.map(|value| value.is_empty()).unwrap_or(true)
+        },
+    }
+}
+
+/// The colors a command uses for its output, which are all empty if its output isn't colored.
+pub struct Colors<'a> {
+    config: &'a config::Config,
+    enabled: bool,
+}
+
+impl<'a> Colors<'a> {
+    /// Decide whether to color the output of a command, from the `--color` option if it was
+    /// given, then `color.<command>`, then `color.ui`, coloring output going to a terminal by
+    /// default. Since the pager's output goes to the terminal, this should be called after the
+    /// pager is set up, and output going to the pager is only colored if `color.pager` is on.
+    ///
+    /// The command is the one whose colors are configured, which isn't necessarily the command
+    /// being run: like in git, `log` uses the colors of `diff`.
+    pub fn new(config: &'a config::Config, command: &str, option: Option<Mode>)
+            -> Result<Colors<'a>, config::Error> {
+        let mode = match option {
+            Some(mode) => mode,
+            None => match try!(config.get_color_mode(&format!("color.{}", command))) {
+                Some(mode) => mode,
+                None => try!(config.get_color_mode("color.ui")).unwrap_or(Mode::Auto),
+            },
+        };
+
+        let terminal = if pager::in_use() {
+            try!(config.get_bool("color.pager")).unwrap_or(true)
+        } else {
+            unsafe { libc::isatty(libc::STDOUT_FILENO) != 0 }
+        };
+
+        Ok(Colors {
+            config: config,
+            enabled: enabled(mode, terminal, env::var("TERM").ok(), env::var("NO_COLOR").ok()),
+        })
+    }
+
+    /// The escape sequence that starts a slot's color, or nothing if the output isn't colored.
+    pub fn get(&self, slot: &Slot) -> Result<String, config::Error> {
+        if !self.enabled {
+            return Ok(String::new());
+        }
+
+        match try!(self.config.get_color(slot.name)) {
+            Some(color) => Ok(color),
+            None => Ok(parse(slot.default).unwrap()),
+        }
+    }
+
+    /// Whether the output is colored.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The escape sequence that ends a color, or nothing if the output isn't colored.
+    pub fn reset(&self) -> &'static str {
+        if self.enabled { RESET } else { "" }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(Some("\x1b[1;4;31m".to_string()), parse("bold red ul"));
+        assert_eq!(Some("\x1b[;1m".to_string()), parse("reset bold"));
+        assert_eq!(Some("\x1b[2;39m".to_string()), parse("default normal dim"));
+        assert_eq!(Some("\x1b[22;24;38;2;255;0;0;48;5;254m".to_string()),
+                   parse("#ff0000 254 nobold no-ul"));
+        assert_eq!(Some("\x1b[92;41m".to_string()), parse("10 Red"));
+        assert_eq!(Some(RESET.to_string()), parse("RESET"));
+        assert_eq!(Some(String::new()), parse(""));
+        assert_eq!(None, parse("red blue green"));
+        assert_eq!(None, parse("Bold"));
+    }
+
+    #[test]
+    fn decides_when_to_color() {
+        let term = || Some("xterm".to_string());
+        assert_eq!(Some(Mode::Auto), parse_mode("true"));
+        assert_eq!(Some(Mode::Never), parse_mode("off"));
+        assert_eq!(Some(Mode::Always), parse_mode("Always"));
+        assert_eq!(None, parse_mode("sometimes"));
+
+        assert!(super::enabled(Mode::Auto, true, term(), None));
+        assert!(super::enabled(Mode::Auto, true, term(), Some(String::new())));
+        assert!(!super::enabled(Mode::Auto, false, term(), None));
+        assert!(!super::enabled(Mode::Auto, true, Some("dumb".to_string()), None));
+        assert!(!super::enabled(Mode::Auto, true, None, None));
+        assert!(!super::enabled(Mode::Auto, true, term(), Some("1".to_string())));
+        assert!(super::enabled(Mode::Always, false, None, Some("1".to_string())));
+        assert!(!super::enabled(Mode::Never, true, term(), None));
+    }
+}
@@ -7,12 +7,20 @@ use regex::Regex;
 
 use std::fmt;
 use std::error::Error as StdError;
+use std::io;
 use std::io::BufRead;
+use std::num::ParseIntError;
 
 #[derive(Debug)]
 pub enum Error {
     InvalidCommit(String),
     MissingField(String),
+
+    /// A number in the date of the author or committer is out of range.
+    InvalidDate(String, ParseIntError),
+
+    /// The contents of the commit couldn't be read.
+    IOError(io::Error),
 }
 
 impl fmt::Display for Error {
@@ -20,6 +28,8 @@ impl fmt::Display for Error {
         match *self {
             Error::InvalidCommit(ref description) => write!(f, "invalid commit: {}", description),
             Error::MissingField(ref field) => write!(f, "missing commit field: {}", field),
+            Error::InvalidDate(ref date, ref err) => write!(f, "invalid date {}: {}", date, err),
+            Error::IOError(ref err) => write!(f, "unable to read commit: {}", err),
         }
     }
 }
@@ -29,11 +39,17 @@ impl StdError for Error {
         match *self {
             Error::InvalidCommit(ref description) => description,
             Error::MissingField(ref field) => field,
+            Error::InvalidDate(..) => "invalid date",
+            Error::IOError(ref err) => err.description(),
         }
     }
 
-    fn cause(&self) -> Option<&StdError> {
-        None
+    fn source(&self) -> Option<&(StdError + 'static)> {
+        match *self {
+            Error::InvalidCommit(_) | Error::MissingField(_) => None,
+            Error::InvalidDate(_, ref err) => Some(err),
+            Error::IOError(ref err) => Some(err),
+        }
     }
 }
 
@@ -47,7 +63,10 @@ pub struct CommitUser {
 pub struct Commit {
     pub name: Name,
     pub tree: Name,
-    pub parent: Option<Name>,
+
+    /// The parents of the commit, in the order they appear in the commit object. A root commit has
+    /// no parents, and a merge commit has more than one.
+    pub parents: Vec<Name>,
     pub author: CommitUser,
     pub committer: CommitUser,
     pub message: String,
@@ -56,7 +75,7 @@ pub struct Commit {
 struct CommitBuilder {
     name: Name,
     tree: Option<Name>,
-    parent: Option<Name>,
+    parents: Vec<Name>,
     author: Option<CommitUser>,
     committer: Option<CommitUser>,
     message: Option<String>,
@@ -67,7 +86,7 @@ impl CommitBuilder {
         CommitBuilder {
             name: (*name).to_owned(),
             tree: None,
-            parent: None,
+            parents: Vec::new(),
             author: None,
             committer: None,
             message: None,
@@ -80,7 +99,7 @@ impl CommitBuilder {
     }
 
     pub fn parent(&mut self, parent: String) -> &mut CommitBuilder {
-        self.parent = Some(Name(parent));
+        self.parents.push(Name(parent));
         self
     }
 
@@ -112,7 +131,7 @@ impl CommitBuilder {
             Ok(Commit {
                 name: self.name,
                 tree: self.tree.unwrap(),
-                parent: self.parent,
+                parents: self.parents,
                 committer: self.committer.unwrap(),
                 author: self.author.unwrap(),
                 message: self.message.unwrap(),
@@ -121,11 +140,6 @@ impl CommitBuilder {
     }
 }
 
-fn std_error_to_objects_error<T>(e: T) -> Error
-        where T: StdError {
-    Error::InvalidCommit(e.description().to_string())
-}
-
 fn parse_commit_date(date_str: String) -> Result<CommitDateTime, Error> {
     lazy_static! {
         static ref DATETIME_REGEX: Regex =
@@ -138,14 +152,15 @@ fn parse_commit_date(date_str: String) -> Result<CommitDateTime, Error> {
     let caps = try!(DATETIME_REGEX.captures(&date_str)
                     .ok_or(Error::InvalidCommit(format!("invalid date: {}", date_str))));
 
+    let invalid_date = |err| Error::InvalidDate(date_str.clone(), err);
     let utc = try!(
         caps["timestamp"].parse::<i64>()
-        .map_err(std_error_to_objects_error)
+        .map_err(&invalid_date)
         .and_then(|t| NaiveDateTime::from_timestamp_opt(t, 0)
                   .ok_or(Error::InvalidCommit(format!("invalid timestamp: {}", date_str)))));
 
-    let tz_hours = try!(caps["tz_hours"].parse::<i32>().map_err(std_error_to_objects_error));
-    let tz_minutes = try!(caps["tz_minutes"].parse::<i32>().map_err(std_error_to_objects_error));
+    let tz_hours = try!(caps["tz_hours"].parse::<i32>().map_err(&invalid_date));
+    let tz_minutes = try!(caps["tz_minutes"].parse::<i32>().map_err(&invalid_date));
     let tz = try!(FixedOffset::east_opt(tz_hours * 3600 + tz_minutes * 60)
                   .ok_or(Error::InvalidCommit(format!("invalid timezone: {}", date_str))));
 
@@ -167,7 +182,7 @@ pub fn parse_commit<R>(mut reader: &mut R, name: &Name) -> Result<Commit, Error>
     let mut line = String::new();
     loop {
         line.clear();
-        try!(reader.read_line(&mut line).map_err(std_error_to_objects_error));
+        try!(reader.read_line(&mut line).map_err(Error::IOError));
 
         line.pop();
         let trimmed = line.trim();
@@ -214,7 +229,7 @@ pub fn parse_commit<R>(mut reader: &mut R, name: &Name) -> Result<Commit, Error>
     }
 
     let mut message = String::new();
-    try!(reader.read_to_string(&mut message).map_err(std_error_to_objects_error));
+    try!(reader.read_to_string(&mut message).map_err(Error::IOError));
     message.pop();
     commit_builder.message(message.trim().to_string());
 
@@ -1,18 +1,39 @@
 //! Provides functionality for building up a model of the configuration files used by git, as well
 //! as editing them.
 
-use std::{env, fmt, io, str};
-use std::collections::HashMap;
+extern crate libc;
+
+use chrono::Local;
+use color;
+use date;
+use ignore;
+use lockfile::LockFile;
+use refs;
+use regex::Regex;
+use repository;
+
+use std::{env, fmt, fs, io, str};
 use std::error::Error as StdError;
+use std::ffi::{CStr, CString};
 use std::fs::File;
-use std::iter::Peekable;
-use std::io::Read;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::Chars;
 
 #[derive(Debug)]
 pub enum Error {
     IOError(io::Error),
     InvalidFile(String),
+    InvalidValue(String),
+    MissingValue(String),
+    IncompleteKey(String),
+    InvalidInclude(String),
+    InvalidKey(String),
+    InvalidPattern(String),
+    InvalidParameters(String),
+    InvalidSection(String),
+    MultipleValues(String),
+    NothingToUnset(String),
 }
 
 impl fmt::Display for Error {
@@ -20,6 +41,16 @@ impl fmt::Display for Error {
         match *self {
             Error::IOError(ref err) => write!(f, "IO error: {}", err),
             Error::InvalidFile(ref description) => write!(f, "invalid file: {}", description),
+            Error::InvalidValue(ref description) => write!(f, "{}", description),
+            Error::MissingValue(ref name) => write!(f, "missing value for '{}'", name),
+            Error::IncompleteKey(ref description) => write!(f, "{}", description),
+            Error::InvalidInclude(ref description) => write!(f, "{}", description),
+            Error::InvalidKey(ref name) => write!(f, "invalid key: {}", name),
+            Error::InvalidPattern(ref pattern) => write!(f, "invalid pattern: {}", pattern),
+            Error::InvalidParameters(ref description) => write!(f, "{}", description),
+            Error::InvalidSection(ref name) => write!(f, "invalid section name: {}", name),
+            Error::MultipleValues(ref name) => write!(f, "{} has multiple values", name),
+            Error::NothingToUnset(ref name) => write!(f, "no value of {} to unset", name),
         }
     }
 }
@@ -29,248 +60,1624 @@ impl StdError for Error {
         match *self {
             Error::IOError(ref err) => err.description(),
             Error::InvalidFile(ref description) => description,
+            Error::InvalidValue(ref description) => description,
+            Error::MissingValue(_) => "missing value",
+            Error::IncompleteKey(ref description) => description,
+            Error::InvalidInclude(ref description) => description,
+            Error::InvalidKey(_) => "invalid key",
+            Error::InvalidPattern(_) => "invalid pattern",
+            Error::InvalidParameters(ref description) => description,
+            Error::InvalidSection(_) => "invalid section name",
+            Error::MultipleValues(_) => "variable has multiple values",
+            Error::NothingToUnset(_) => "no value to unset",
         }
     }
 
-    fn cause(&self) -> Option<&StdError> {
+    fn source(&self) -> Option<&(StdError + 'static)> {
         match *self {
             Error::IOError(ref err) => Some(err),
-            ref err => Some(err)
+            _ => None,
         }
     }
 }
 
+/// Whether the character counts as whitespace in a config file, which matches the C `isspace`.
+fn is_space(chr: char) -> bool {
+    chr == ' ' || chr == '\t' || chr == '\n' || chr == '\r' || chr == '\x0b' || chr == '\x0c'
+}
+
+/// Whether the character can appear in a section or variable name.
+fn is_key_char(chr: char) -> bool {
+    chr.is_ascii_alphanumeric() || chr == '-'
+}
+
+/// The parts of a config file recognized by the parser. Comments and whitespace are kept track of
+/// so that the file can be edited without disturbing them.
+#[derive(Debug, PartialEq, Eq)]
+enum EventKind {
+    Whitespace,
+    Comment,
+
+    /// A section header, with the full name of the section, as it's used in variable names.
+    Section(String),
+
+    /// A variable, with its full name and value. A variable without a value has none, rather
+    /// than an empty one.
+    Entry(String, Option<String>),
+}
+
+/// A part of a config file, along with the byte offsets of where it begins and ends. Every byte of
+/// the file belongs to exactly one event. An entry includes the newline at the end of its line.
+#[derive(Debug)]
+struct Event {
+    kind: EventKind,
+    begin: usize,
+    end: usize,
+
+    /// The line the event ends on, which is the line an entry is reported on, like in git.
+    line: usize,
+}
+
+/// A parser for the syntax described in the "CONFIGURATION FILE" section of git-config(1). It
+/// deliberately mirrors the structure of the parser in git itself, reading one character at a time
+/// and treating the end of the input as a final newline, so that the same files are accepted and
+/// rejected, and errors are reported on the same lines.
 struct Parser<'a> {
-    map: HashMap<String, String>,
-    chars: Peekable<Chars<'a>>,
-    current_section_names: Vec<String>,
+    events: Vec<Event>,
+    contents: &'a str,
+    chars: Chars<'a>,
+
+    /// A description of where the contents came from, such as "file .git/config", for errors.
+    source: &'a str,
+    line: usize,
+    at_line_start: bool,
+    eof: bool,
+
+    /// The name of the current section, including the subsection if there is one.
+    section: String,
 }
 
 impl <'a> Parser<'a> {
-    fn new(contents: &'a String) -> Parser<'a> {
+    fn new(contents: &'a str, source: &'a str) -> Parser<'a> {
         Parser {
-            map: HashMap::new(),
-            chars: contents.chars().peekable(),
-            current_section_names: Vec::new(),
+            events: Vec::new(),
+            contents: contents,
+            chars: contents.trim_left_matches('\u{feff}').chars(),
+            source: source,
+            line: 0,
+            at_line_start: true,
+            eof: false,
+            section: String::new(),
+        }
+    }
+
+    /// Read the next character, turning CRLF line endings into a single newline. Once the input
+    /// runs out, this keeps returning newlines with `eof` set.
+    fn next_char(&mut self) -> char {
+        if self.at_line_start {
+            self.line += 1;
+            self.at_line_start = false;
+        }
+
+        let chr = match self.chars.next() {
+            Some('\r') if self.chars.clone().next() == Some('\n') => {
+                self.chars.next();
+                '\n'
+            },
+            Some(chr) => chr,
+            None => {
+                self.eof = true;
+                '\n'
+            },
+        };
+
+        if chr == '\n' && !self.eof {
+            self.at_line_start = true;
         }
+        chr
+    }
+
+    /// Start a new event at the given offset, which ends the previous one. Consecutive whitespace
+    /// is merged into a single event.
+    fn start_event(&mut self, kind: EventKind, offset: usize) {
+        if let Some(previous) = self.events.last_mut() {
+            if previous.kind == EventKind::Whitespace && kind == EventKind::Whitespace {
+                return;
+            }
+            previous.end = offset;
+        }
+        let line = self.line;
+        self.events.push(Event { kind: kind, begin: offset, end: offset, line: line });
+    }
+
+    fn error(&self) -> Error {
+        Error::InvalidFile(format!("bad config line {} in {}", self.line, self.source))
     }
 
     fn parse(&mut self) -> Result<(), Error> {
+        let mut in_comment = false;
         loop {
-            match self.chars.peek() {
-                Some(&' ') | Some(&'\t') => { self.chars.next(); },
-                Some(&'#') => try!(self.parse_comment()),
-                Some(&'[') => try!(self.parse_section()),
-                Some(_) => { self.chars.next(); },
-                None => { self.chars.next(); break; },
+            let offset = self.contents.len() - self.chars.as_str().len();
+            let chr = self.next_char();
+            if chr == '\n' {
+                if self.eof {
+                    if let Some(last) = self.events.last_mut() {
+                        last.end = offset;
+                    }
+                    return Ok(());
+                }
+                self.start_event(EventKind::Whitespace, offset);
+                in_comment = false;
+                continue;
             }
-        }
 
-        Ok(())
+            if in_comment {
+                continue;
+            }
+
+            if is_space(chr) {
+                self.start_event(EventKind::Whitespace, offset);
+            } else if chr == '#' || chr == ';' {
+                self.start_event(EventKind::Comment, offset);
+                in_comment = true;
+            } else if chr == '[' {
+                self.start_event(EventKind::Section(String::new()), offset);
+                self.section = try!(self.parse_section().ok_or_else(|| self.error()));
+                self.events.last_mut().unwrap().kind = EventKind::Section(self.section.clone());
+            } else if chr.is_ascii_alphabetic() {
+                self.start_event(EventKind::Entry(String::new(), None), offset);
+                try!(self.parse_variable(chr).ok_or_else(|| self.error()));
+            } else {
+                return Err(self.error());
+            }
+        }
     }
 
-    fn parse_comment(&mut self) -> Result<(), Error> {
+    /// Parse a section header, after the opening bracket. Section names can only contain
+    /// alphanumeric characters, `-` and `.`, and are case-insensitive, so they're lowercased. The
+    /// subsection name in `[section "subsection"]` is case-sensitive, and can contain anything
+    /// except a newline, with `"` and `\` escaped by a backslash. The deprecated `[section.sub]`
+    /// syntax is lowercased in its entirety, just like in git.
+    fn parse_section(&mut self) -> Option<String> {
+        let mut name = String::new();
         loop {
-            match self.chars.next() {
-                Some('\n') | None => break,
-                _ => continue,
+            let chr = self.next_char();
+            if self.eof {
+                return None;
+            } else if chr == ']' {
+                break;
+            } else if is_space(chr) {
+                return self.parse_subsection(name, chr);
+            } else if is_key_char(chr) || chr == '.' {
+                name.push(chr.to_ascii_lowercase());
+            } else {
+                return None;
             }
         }
 
-        Ok(())
+        if name.is_empty() { None } else { Some(name) }
     }
 
-    fn parse_section(&mut self) -> Result<(), Error> {
-        let mut section_name = String::new();
-        let mut subsection_name = String::new();
-        let mut is_reading_subsection = false;
+    fn parse_subsection(&mut self, section: String, first_space: char) -> Option<String> {
+        let mut chr = first_space;
+        while is_space(chr) {
+            if chr == '\n' {
+                return None;
+            }
+            chr = self.next_char();
+        }
+        if chr != '"' || section.is_empty() {
+            return None;
+        }
 
-        self.chars.next();
+        let mut name = section;
+        name.push('.');
         loop {
-            match self.chars.peek() {
-                Some(&chr @ ' ') | Some(&chr @ '\t') => {
-                    self.chars.next();
+            let mut chr = self.next_char();
+            if chr == '"' {
+                break;
+            } else if chr == '\\' {
+                chr = self.next_char();
+            }
 
-                    if is_reading_subsection {
-                        subsection_name.push(chr);
-                    }
-                },
-                Some(&'"') => {
-                    self.chars.next();
+            if chr == '\n' {
+                return None;
+            }
+            name.push(chr);
+        }
 
-                    // We're deliberately ignoring escaped quotes for now.
-                    is_reading_subsection = !is_reading_subsection;
-                },
-                Some(&']') | None => {
-                    self.chars.next();
+        if self.next_char() == ']' { Some(name) } else { None }
+    }
 
-                    self.current_section_names.push(section_name.trim().to_string());
-                    if !subsection_name.is_empty() {
-                        self.current_section_names.push(subsection_name.clone());
-                    }
+    /// Parse a variable, starting with the first character of its name. A variable can be on a
+    /// line of its own, without a value, which is how a boolean is usually set.
+    fn parse_variable(&mut self, first_char: char) -> Option<()> {
+        let mut key = String::new();
+        let mut chr = first_char;
+        while !self.eof && is_key_char(chr) {
+            key.push(chr.to_ascii_lowercase());
+            chr = self.next_char();
+        }
+        while chr == ' ' || chr == '\t' {
+            chr = self.next_char();
+        }
 
-                    try!(self.parse_variables());
+        let value = if chr == '\n' {
+            None
+        } else if chr == '=' {
+            match self.parse_value() {
+                Some(value) => Some(value),
+                None => return None,
+            }
+        } else {
+            return None;
+        };
 
-                    if !subsection_name.is_empty() {
-                        self.current_section_names.pop();
-                    }
-                    self.current_section_names.pop();
-                    break;
-                },
-                Some(&chr) => {
-                    if is_reading_subsection {
-                        subsection_name.push(chr);
-                    } else {
-                        section_name.push(chr);
-                    }
+        let name = if self.section.is_empty() {
+            key
+        } else {
+            format!("{}.{}", self.section, key)
+        };
+        let event = self.events.last_mut().unwrap();
+        event.kind = EventKind::Entry(name, value);
+        event.line = self.line;
+        Some(())
+    }
+
+    /// Parse a value, after the `=`, up to the end of the line. Leading and trailing whitespace
+    /// is removed, and any other whitespace outside quotes becomes a single space for each
+    /// character. Comments start at `#` or `;` outside quotes, and a backslash at the end of a line
+    /// continues the value on the next line.
+    fn parse_value(&mut self) -> Option<String> {
+        let mut value = String::new();
+        let mut in_quotes = false;
+        let mut in_comment = false;
+        let mut spaces = 0;
 
-                    self.chars.next();
+        loop {
+            let mut chr = self.next_char();
+            if chr == '\n' {
+                if in_quotes {
+                    return None;
                 }
+                return Some(value);
             }
-        }
 
-        Ok(())
-    }
+            if in_comment {
+                continue;
+            }
+            if is_space(chr) && !in_quotes {
+                if !value.is_empty() {
+                    spaces += 1;
+                }
+                continue;
+            }
+            if !in_quotes && (chr == '#' || chr == ';') {
+                in_comment = true;
+                continue;
+            }
 
-    fn parse_variables(&mut self) -> Result<(), Error> {
-        loop {
-            match self.chars.peek() {
-                Some(&'#') => try!(self.parse_comment()),
-                Some(&' ') | Some(&'\t') => { self.chars.next(); },
-                Some(&'[') | None => break,
-                _ => try!(self.parse_single_variable()),
+            for _ in 0..spaces {
+                value.push(' ');
+            }
+            spaces = 0;
+
+            if chr == '\\' {
+                chr = match self.next_char() {
+                    '\n' => continue,
+                    't' => '\t',
+                    'b' => '\x08',
+                    'n' => '\n',
+                    chr @ '\\' | chr @ '"' => chr,
+                    _ => return None,
+                };
+            } else if chr == '"' {
+                in_quotes = !in_quotes;
+                continue;
             }
+
+            value.push(chr);
         }
+    }
+}
 
-        Ok(())
+/// Turn the full name of a variable, such as `Remote.origin.URL`, into the form used to store it,
+/// by lowercasing the section and variable names, but not the subsection name in between them.
+/// Names that couldn't appear in a config file are rejected with the same errors as git.
+pub fn canonical_name(name: &str) -> Result<String, Error> {
+    let (first_dot, last_dot) = match (name.find('.'), name.rfind('.')) {
+        (Some(first_dot), Some(last_dot)) => (first_dot, last_dot),
+        _ => return Err(Error::IncompleteKey(format!("key does not contain a section: {}",
+                                                     name))),
+    };
+    if last_dot == name.len() - 1 {
+        return Err(Error::IncompleteKey(format!("key does not contain variable name: {}",
+                                                name)));
     }
 
-    fn parse_single_variable(&mut self) -> Result<(), Error> {
-        let mut key_name = String::new();
-        loop {
-            match self.chars.peek() {
-                Some(&'#') => { try!(self.parse_comment()); break; },
-                Some(&' ') | Some(&'\t') => { self.chars.next(); },
-                Some(&'\n') => { self.chars.next(); break; },
-                Some(&'=') => {
-                    self.chars.next();
-                    try!(self.parse_key_value_pair(key_name.clone()));
-                    key_name.clear();
-                    break;
-                }
-                Some(&'[') | None => break,
-                Some(&chr) => {
-                    self.chars.next();
-                    key_name.push(chr);
-                },
-            }
+    let section = &name[..first_dot];
+    let subsection = &name[first_dot..last_dot + 1];
+    let key = &name[last_dot + 1..];
+    let is_valid = !section.is_empty() && section.chars().all(is_key_char) &&
+        !subsection.contains('\n') &&
+        key.starts_with(|chr: char| chr.is_ascii_alphabetic()) && key.chars().all(is_key_char);
+    if !is_valid {
+        return Err(Error::InvalidKey(name.to_string()));
+    }
+
+    Ok(format!("{}{}{}", section.to_ascii_lowercase(), subsection, key.to_ascii_lowercase()))
+}
+
+/// Which part of the hierarchy of config files a value comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    System,
+    Global,
+    Local,
+    Worktree,
+
+    /// Set on the command line, either with `-c` or by selecting a single file to read.
+    Command,
+}
+
+impl Scope {
+    /// The name git uses for the scope, as shown by `config --show-scope`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Scope::System => "system",
+            Scope::Global => "global",
+            Scope::Local => "local",
+            Scope::Worktree => "worktree",
+            Scope::Command => "command",
         }
+    }
+}
+
+/// Where a value was set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// A config file, which may have been included by another one, along with the line the value
+    /// is on.
+    File(PathBuf, usize),
+
+    /// The command line, with `-c`, or the environment, through `$GIT_CONFIG_COUNT` and the
+    /// variables that go with it.
+    CommandLine,
+}
 
-        if !key_name.is_empty() {
-            let full_key_name = self.variable_name_for_current_section(&key_name);
-            self.map.insert(full_key_name, "true".to_string());
+impl Origin {
+    /// Point out where a value that can't be interpreted was set, in the same words as git.
+    pub fn parse_failure(&self) -> String {
+        match *self {
+            Origin::File(ref path, line) =>
+                format!("bad config line {} in file {}", line, path.display()),
+            Origin::CommandLine => "unable to parse command-line config".to_string(),
         }
-        Ok(())
     }
+}
 
-    fn parse_key_value_pair(&mut self, key_name: String) -> Result<(), Error> {
-        let mut value = String::new();
+/// A single value of a variable, along with where it was set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The full name of the variable, with the section and variable names lowercased.
+    pub name: String,
 
-        loop {
-            match self.chars.peek() {
-                Some(&'#') => { try!(self.parse_comment()); break; },
-                Some(&'\n') => { self.chars.next(); break; },
-                Some(&'[') | None => break,
-                Some(&chr) => {
-                    self.chars.next();
-                    value.push(chr);
-                },
-            }
+    /// The value, which a variable that's set without one doesn't have.
+    pub value: Option<String>,
+
+    pub origin: Origin,
+    pub scope: Scope,
+}
+
+impl Entry {
+    /// The value, which has to be there for anything but a boolean.
+    pub fn required_value(&self) -> Result<&str, Error> {
+        self.value.as_ref().map(|value| value.as_str())
+            .ok_or_else(|| Error::MissingValue(self.name.clone()))
+    }
+
+    /// Interpret the value as a boolean, as described by `parse_bool`. A variable without a value
+    /// is true.
+    pub fn as_bool(&self) -> Result<bool, Error> {
+        match self.value {
+            Some(ref value) => parse_bool(value).ok_or_else(|| {
+                Error::InvalidValue(format!("bad boolean config value '{}' for '{}'",
+                                            value, self.name))
+            }),
+            None => Ok(true),
         }
+    }
 
-        let full_key_name = self.variable_name_for_current_section(&key_name);
-        self.map.insert(full_key_name, value.trim().to_string());
-        Ok(())
+    /// Interpret the value as an integer, as described by `parse_int`.
+    pub fn as_int(&self) -> Result<i64, Error> {
+        let value = self.value.as_ref().map(|value| value.as_str()).unwrap_or("");
+        parse_int(value).map_err(|reason| {
+            let location = match self.origin {
+                Origin::File(ref path, _) => format!(" in file {}", path.display()),
+                Origin::CommandLine => String::new(),
+            };
+            Error::InvalidValue(format!("bad numeric config value '{}' for '{}'{}: {}",
+                                        value, self.name, location, reason))
+        })
     }
 
-    fn variable_name_for_current_section(&self, key_name: &String) -> String {
-        let mut name = String::new();
-        for section in self.current_section_names.iter() {
-            name.push_str(&section);
-            name.push('.');
+    /// Interpret the value as a path, expanding a leading `~` or `~<user>`.
+    pub fn as_path(&self) -> Result<PathBuf, Error> {
+        let value = try!(self.required_value());
+        expand_user_path(value).ok_or_else(|| {
+            Error::InvalidValue(format!("failed to expand user dir in: '{}'", value))
+        })
+    }
+
+    /// Interpret the value as a color, returning the escape sequence that produces it.
+    pub fn as_color(&self) -> Result<String, Error> {
+        let value = try!(self.required_value());
+        color::parse(value).ok_or_else(|| {
+            Error::InvalidValue(format!("invalid color value: {}", value))
+        })
+    }
+
+    /// Interpret the value as when to color output, as described by `color::parse_mode`. A
+    /// variable without a value is true, the same as for a boolean.
+    pub fn as_color_mode(&self) -> Result<color::Mode, Error> {
+        match self.value {
+            Some(ref value) => color::parse_mode(value).ok_or_else(|| {
+                Error::InvalidValue(format!("bad boolean config value '{}' for '{}'",
+                                            value, self.name))
+            }),
+            None => Ok(color::Mode::Auto),
         }
+    }
 
-        name.push_str(key_name);
-        name
+    /// Interpret the value as an expiry date, as described by `parse_expiry_date`.
+    pub fn as_expiry_date(&self) -> Result<u64, Error> {
+        let value = try!(self.required_value());
+        parse_expiry_date(value).ok_or_else(|| {
+            Error::InvalidValue(format!("'{}' for '{}' is not a valid timestamp",
+                                        value, self.name))
+        })
     }
 }
 
 /// The fundamental data structure representing the configuration for this process. Instead of
 /// having specific fields for each configuration item, this structure exposes a map-like interface
 /// indexed by strings.
+///
+/// A variable can be set more than once, either in the same file or in different ones, so every
+/// value is kept, in the order they were read. Single-valued variables take the last value, which
+/// is what lets the repository configuration override the global one.
 pub struct Config {
-    map: HashMap<String, String>,
+    entries: Vec<Entry>,
+
+    /// The scope of the values being read.
+    scope: Scope,
+
+    /// Whether `include.path` and `includeIf.<condition>.path` pull in other files.
+    follow_includes: bool,
+
+    /// Whether this configuration is only being read to collect the remote URLs that `hasconfig:`
+    /// conditions are checked against. Those conditions are treated as true in that case, since
+    /// checking them would need the very URLs being collected.
+    collecting_remote_urls: bool,
+
+    /// Whether the file being read was included by an `includeIf`, directly or indirectly. When
+    /// collecting remote URLs, such a file can't set any, and its variables are otherwise skipped.
+    conditionally_included: bool,
+
+    /// The single file this configuration was read from, if it wasn't read from the whole
+    /// hierarchy. `hasconfig:` conditions only look at the remote URLs in the same place.
+    file: Option<PathBuf>,
+
+    /// Every `remote.<name>.url` in the same place as this configuration, collected the first time
+    /// a `hasconfig:` condition needs them.
+    remote_urls: Option<Vec<String>>,
 }
 
 impl Config {
-    fn new() -> Config {
-        Config { map: HashMap::new() }
+    pub fn new() -> Config {
+        Config {
+            entries: Vec::new(),
+            scope: Scope::Command,
+            follow_includes: true,
+            collecting_remote_urls: false,
+            conditionally_included: false,
+            file: None,
+            remote_urls: None,
+        }
     }
 
-    fn add_from_file(&mut self, filename: String) -> Result<&Config, Error> {
-        println!("reading from {}", filename);
-        let mut file = try!(File::open(filename).map_err(|e| Error::IOError(e)));
+    fn add_from_file(&mut self, path: &Path, depth: usize) -> Result<(), Error> {
+        trace!("config: reading '{}'", path.display());
+        let mut file = try!(File::open(path).map_err(|e| Error::IOError(e)));
         let mut contents = String::new();
         try!(file.read_to_string(&mut contents).map_err(|e| Error::IOError(e)));
-        self.add_from_string(contents.to_string())
+        let source = format!("file {}", path.display());
+        self.add_from_source(&contents, &source, Some(path), depth)
+    }
+
+    #[cfg(test)]
+    pub fn add_from_string(&mut self, contents: String) -> Result<(), Error> {
+        self.add_from_source(&contents, "string", None, 0)
     }
 
-    fn add_from_string(&mut self, contents: String) -> Result<&Config, Error> {
-        let mut parser = Parser::new(&contents);
+    /// Add the variables from the contents of a config file. The path of the file, if the contents
+    /// came from one, is what relative includes are resolved against, and the depth is how many
+    /// includes deep the file is.
+    fn add_from_source(&mut self, contents: &str, source: &str, path: Option<&Path>, depth: usize)
+            -> Result<(), Error> {
+        let mut parser = Parser::new(contents, source);
         try!(parser.parse());
 
-        for (k, v) in parser.map.drain() {
-            self.map.insert(k.to_string(), v.to_string());
+        for event in parser.events.drain(..) {
+            if let EventKind::Entry(name, value) = event.kind {
+                let origin = match path {
+                    Some(path) => Origin::File(path.to_path_buf(), event.line),
+                    None => Origin::CommandLine,
+                };
+                let entry = Entry { name: name, value: value, origin: origin, scope: self.scope };
+                try!(self.add_entry(entry, depth));
+            }
         }
+        Ok(())
+    }
+
+    /// Add a single variable, followed by the contents of the file it includes, if it's an
+    /// include whose condition holds.
+    fn add_entry(&mut self, entry: Entry, depth: usize) -> Result<(), Error> {
+        let included = if self.follow_includes {
+            try!(self.included_file(&entry))
+        } else {
+            None
+        };
 
-        Ok(self)
+        let origin = entry.origin.clone();
+        if !(self.collecting_remote_urls && self.conditionally_included) {
+            self.entries.push(entry);
+        } else if is_remote_url(&entry.name) {
+            return Err(Error::InvalidInclude(
+                    "remote URLs cannot be configured in file directly or indirectly included by \
+                     includeIf.hasconfig:remote.*.url".to_string()));
+        }
+
+        match included {
+            Some((included, conditional)) => {
+                let outer = self.conditionally_included;
+                self.conditionally_included = outer || conditional;
+                let result = self.include(&included, &origin, depth);
+                self.conditionally_included = outer;
+                result
+            },
+            None => Ok(()),
+        }
     }
 
-    pub fn all(&self) -> Vec<(String, String)> {
-        let mut list: Vec<(String, String)> = self.map
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_string()))
-            .collect();
+    /// Look up every entry of a variable, given its full name, such as `core.editor`.
+    fn entries_for(&self, key: &str) -> Vec<&Entry> {
+        let key = match canonical_name(key) {
+            Ok(key) => key,
+            Err(_) => return Vec::new(),
+        };
+
+        self.entries.iter().filter(|entry| entry.name == key).collect()
+    }
+
+    /// Look up the last value of a variable, converting it with the given function.
+    fn get_as<T, F>(&self, key: &str, convert: F) -> Result<Option<T>, Error>
+            where F: Fn(&Entry) -> Result<T, Error> {
+        match self.entries_for(key).pop() {
+            Some(entry) => convert(entry).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Look up the value of a single variable, given its full name, such as `core.editor`. If the
+    /// variable is set more than once, the last value wins. A variable without a value is treated
+    /// as empty.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).pop()
+    }
+
+    /// Look up every value of a multi-valued variable, such as `remote.origin.fetch`.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.entries_for(key).into_iter()
+            .map(|entry| entry.value.as_ref().map(|value| value.as_str()).unwrap_or(""))
+            .collect()
+    }
+
+    /// Look up a string variable, which has to have a value if it's set.
+    pub fn get_string(&self, key: &str) -> Result<Option<&str>, Error> {
+        match self.entries_for(key).pop() {
+            Some(entry) => entry.required_value().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Look up a boolean variable. See `parse_bool` for the values that are accepted.
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, Error> {
+        self.get_as(key, Entry::as_bool)
+    }
+
+    /// Look up an integer variable, which can have a `k`, `m` or `g` suffix.
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>, Error> {
+        self.get_as(key, Entry::as_int)
+    }
+
+    /// Look up a path variable, expanding a leading `~` to the home directory.
+    pub fn get_path(&self, key: &str) -> Result<Option<PathBuf>, Error> {
+        self.get_as(key, Entry::as_path)
+    }
+
+    /// Look up a color variable, returning the escape sequence that produces the color.
+    pub fn get_color(&self, key: &str) -> Result<Option<String>, Error> {
+        self.get_as(key, Entry::as_color)
+    }
+
+    /// Look up when to color output, such as `color.ui`.
+    pub fn get_color_mode(&self, key: &str) -> Result<Option<color::Mode>, Error> {
+        self.get_as(key, Entry::as_color_mode)
+    }
+
+    /// List every variable, in the order they were read, including each value of a variable that
+    /// is set more than once.
+    pub fn all(&self) -> Vec<(String, Option<String>)> {
+        self.entries.iter().map(|entry| (entry.name.clone(), entry.value.clone())).collect()
+    }
+
+    /// Every value, in the order they were read, along with where it was set.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+}
+
+/// Read the configuration from a single file, rather than the whole hierarchy of files, taking its
+/// values to be in the given scope. Like in git, the files it includes are only read if requested.
+pub fn read_file<P>(path: P, scope: Scope, follow_includes: bool) -> Result<Config, Error>
+        where P: AsRef<Path> {
+    let mut config = Config::new();
+    config.scope = scope;
+    config.follow_includes = follow_includes;
+    config.file = Some(path.as_ref().to_path_buf());
+    try!(config.add_from_file(path.as_ref(), 0));
+    Ok(config)
+}
+
+/// Selects which of the values of a variable to operate on, for variables that can be set more
+/// than once.
+pub enum ValuePattern {
+    /// Every value matches.
+    Any,
+
+    /// No value matches, so that setting a variable always adds a new value.
+    Nothing,
+
+    /// The values matching the regex match, or, if the pattern is negated, the ones that don't.
+    Regex(Regex, bool),
+}
+
+impl ValuePattern {
+    /// Parse a value pattern as given on the command line, where a leading `!` negates it.
+    pub fn parse(pattern: &str) -> Result<ValuePattern, Error> {
+        let (negated, regex) = if pattern.starts_with('!') {
+            (true, &pattern[1..])
+        } else {
+            (false, pattern)
+        };
 
-        list.sort();
-        list
+        Regex::new(regex)
+            .map(|regex| ValuePattern::Regex(regex, negated))
+            .map_err(|_| Error::InvalidPattern(regex.to_string()))
     }
+
+    /// Whether the value matches. A variable without a value never matches a regex, so it only
+    /// matches a negated one.
+    pub fn matches(&self, value: Option<&str>) -> bool {
+        match *self {
+            ValuePattern::Any => true,
+            ValuePattern::Nothing => false,
+            ValuePattern::Regex(ref regex, negated) =>
+                value.map_or(false, |value| regex.is_match(value)) != negated,
+        }
+    }
+}
+
+/// Read the file being edited, which is treated as empty if it doesn't exist yet.
+fn read_for_edit(path: &Path) -> Result<Option<String>, Error> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::IOError(err)),
+    };
+
+    let mut contents = String::new();
+    try!(file.read_to_string(&mut contents).map_err(Error::IOError));
+    Ok(Some(contents))
+}
+
+/// Replace the file being edited with the new contents, keeping the permissions of the original.
+fn write_for_edit(path: &Path, mut lock: LockFile, contents: &str) -> Result<(), Error> {
+    if let Ok(metadata) = fs::metadata(path) {
+        try!(lock.set_permissions(metadata.permissions()).map_err(Error::IOError));
+    }
+    try!(lock.write_all(contents.as_bytes()).map_err(Error::IOError));
+    lock.commit().map_err(Error::IOError)
+}
+
+/// Format a section header for the section part of a variable name, keeping the case it was given
+/// in. The subsection name is quoted, escaping any quotes and backslashes inside it.
+fn section_header(section: &str) -> String {
+    match section.find('.') {
+        Some(dot) => {
+            let mut header = format!("[{} \"", &section[..dot]);
+            for chr in section[dot + 1..].chars() {
+                if chr == '"' || chr == '\\' {
+                    header.push('\\');
+                }
+                header.push(chr);
+            }
+            header.push_str("\"]\n");
+            header
+        },
+        None => format!("[{}]\n", section),
+    }
+}
+
+/// Format a variable as a line in its section. The value is quoted if it would otherwise lose
+/// leading or trailing whitespace, or be cut short by a comment character.
+fn variable_line(key: &str, value: &str) -> String {
+    let needs_quotes = value.starts_with(' ') || value.ends_with(' ') ||
+        value.contains(|chr| chr == ';' || chr == '#');
+    let quote = if needs_quotes { "\"" } else { "" };
+
+    let mut line = format!("\t{} = {}", key, quote);
+    for chr in value.chars() {
+        match chr {
+            '\n' => line.push_str("\\n"),
+            '\t' => line.push_str("\\t"),
+            '"' | '\\' => {
+                line.push('\\');
+                line.push(chr);
+            },
+            _ => line.push(chr),
+        }
+    }
+    line.push_str(quote);
+    line.push('\n');
+    line
+}
+
+/// When the last variables in a section are being removed, extend the range being removed to
+/// cover the whole section, as long as there are no comments that might be about the section.
+/// `matches[*current]` is the variable being removed, and `current` is moved past any later
+/// variables in the same section that are removed along with it.
+fn maybe_remove_section(events: &[Event], section: &str, matches: &[usize], current: &mut usize,
+                        begin: &mut usize, end: &mut usize) {
+    // The variable has to be the first in the section, with no comments before it or the header.
+    let mut section_seen = false;
+    let mut i = matches[*current];
+    while i > 0 {
+        match events[i - 1].kind {
+            EventKind::Comment => return,
+            EventKind::Entry(..) if !section_seen => return,
+            EventKind::Entry(..) => break,
+            EventKind::Section(ref name) if name != section => break,
+            EventKind::Section(_) => section_seen = true,
+            EventKind::Whitespace => {},
+        }
+        i -= 1;
+    }
+    let section_begin = events[i].begin;
+
+    // Every variable after it in the section has to be removed too, with no comments after them.
+    let mut removed = 0;
+    let mut i = matches[*current] + 1;
+    while i < events.len() {
+        match events[i].kind {
+            EventKind::Comment => return,
+            EventKind::Section(ref name) if name != section => break,
+            EventKind::Entry(..) => {
+                removed += 1;
+                if *current + removed >= matches.len() || matches[*current + removed] != i {
+                    return;
+                }
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+
+    *current += removed;
+    *begin = section_begin;
+    *end = if i < events.len() { events[i].begin } else { events[events.len() - 1].end };
 }
 
+/// Set, replace or remove the values of a variable in a single config file, editing the file in
+/// place so that its comments and formatting are kept. The values matching the pattern are
+/// replaced by the new value, or removed if there is none. More than one value can only be
+/// replaced or removed at once if `replace_all` is set. If no value matches, the new value is
+/// added after the last variable in the variable's section, creating the section if necessary.
+pub fn set_in_file(path: &Path, name: &str, value: Option<&str>, pattern: &ValuePattern,
+                   replace_all: bool) -> Result<(), Error> {
+    let key = try!(canonical_name(name));
+    let last_dot = name.rfind('.').unwrap();
+    let section = &key[..last_dot];
+
+    let lock = try!(LockFile::acquire(path).map_err(Error::IOError));
+    let contents = try!(read_for_edit(path)).unwrap_or(String::new());
+    let source = format!("file {}", path.display());
+    let mut parser = Parser::new(&contents, &source);
+    try!(parser.parse());
+    let events = parser.events;
+
+    // Find the values to replace, or if there aren't any, the end of the variable's section.
+    let mut matches = Vec::new();
+    let mut section_end = None;
+    let mut in_section = false;
+    for (i, event) in events.iter().enumerate() {
+        match event.kind {
+            EventKind::Section(ref name) => {
+                in_section = name == section;
+                if in_section {
+                    section_end = Some(i);
+                }
+            },
+            EventKind::Entry(ref name, ref existing_value) => {
+                if *name == key && pattern.matches(existing_value.as_ref().map(|v| v.as_str())) {
+                    matches.push(i);
+                } else if in_section && matches.is_empty() {
+                    section_end = Some(i);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    if matches.len() > 1 && !replace_all {
+        return Err(Error::MultipleValues(name.to_string()));
+    }
+    if matches.is_empty() && value.is_none() {
+        return Err(Error::NothingToUnset(name.to_string()));
+    }
+
+    let bytes = contents.as_bytes();
+    let key_seen = !matches.is_empty();
+    let section_seen = key_seen || section_end.is_some();
+    let insert_after = if key_seen {
+        matches.clone()
+    } else if let Some(section_end) = section_end {
+        vec![section_end]
+    } else if !events.is_empty() {
+        vec![events.len() - 1]
+    } else {
+        Vec::new()
+    };
+
+    // Copy everything except the values being replaced, and put the new value where the last of
+    // them was, or at the end of the section.
+    let mut edited = String::new();
+    let mut copy_begin = 0;
+    let mut current = 0;
+    while current < insert_after.len() {
+        let event = &events[insert_after[current]];
+        let mut copy_end;
+        let replace_end;
+        if key_seen {
+            copy_end = event.begin;
+            let mut end = event.end;
+            if value.is_none() {
+                maybe_remove_section(&events, section, &matches, &mut current, &mut copy_end,
+                                     &mut end);
+            }
+            replace_end = end;
+
+            // Indentation before the variable is removed along with it.
+            while copy_end > 0 && bytes[copy_end - 1] != b'\n' &&
+                    is_space(bytes[copy_end - 1] as char) {
+                copy_end -= 1;
+            }
+        } else {
+            // A section header is followed by the rest of its line.
+            copy_end = event.end;
+            if copy_end > 0 && copy_end < bytes.len() && bytes[copy_end - 1] != b'\n' &&
+                    bytes[copy_end] == b'\n' {
+                copy_end += 1;
+            }
+            replace_end = copy_end;
+        }
+
+        if copy_end > copy_begin {
+            edited.push_str(&contents[copy_begin..copy_end]);
+            if bytes[copy_end - 1] != b'\n' {
+                edited.push('\n');
+            }
+        }
+        copy_begin = replace_end;
+        current += 1;
+    }
+
+    if let Some(value) = value {
+        if !section_seen {
+            edited.push_str(&section_header(&name[..last_dot]));
+        }
+        edited.push_str(&variable_line(&name[last_dot + 1..], value));
+    }
+    if copy_begin < contents.len() {
+        edited.push_str(&contents[copy_begin..]);
+    }
+
+    write_for_edit(path, lock, &edited)
+}
+
+/// If the line is a header for the named section, find the offset just past the header and any
+/// whitespace following it. Unlike variable names, the section name has to match exactly.
+fn section_header_end(line: &[u8], name: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    while i < line.len() && is_space(line[i] as char) {
+        i += 1;
+    }
+    if i == line.len() || line[i] != b'[' {
+        return None;
+    }
+
+    let at = |i: usize| if i < line.len() { line[i] } else { 0 };
+    let mut j = 0;
+    let mut in_subsection = false;
+    i += 1;
+    while at(i) != 0 && at(i) != b']' {
+        if !in_subsection && is_space(at(i) as char) {
+            in_subsection = true;
+            if name.get(j) != Some(&b'.') {
+                break;
+            }
+            j += 1;
+            i += 1;
+            while is_space(at(i) as char) {
+                i += 1;
+            }
+            if at(i) != b'"' {
+                break;
+            }
+            i += 1;
+            continue;
+        }
+
+        if at(i) == b'\\' && in_subsection {
+            i += 1;
+        } else if at(i) == b'"' && in_subsection {
+            i += 1;
+            while is_space(at(i) as char) {
+                i += 1;
+            }
+            break;
+        }
+        if name.get(j) != Some(&at(i)) {
+            break;
+        }
+        i += 1;
+        j += 1;
+    }
+
+    if at(i) != b']' || j != name.len() {
+        return None;
+    }
+    i += 1;
+    while is_space(at(i) as char) {
+        i += 1;
+    }
+    Some(i)
+}
+
+/// Rename every section with the given name in a single config file, or remove it along with all
+/// of its variables if there is no new name. Returns the number of sections that were found.
+pub fn rename_section_in_file(path: &Path, old_name: &str, new_name: Option<&str>)
+        -> Result<usize, Error> {
+    if let Some(new_name) = new_name {
+        let section = new_name.split('.').next().unwrap();
+        if section.is_empty() || !section.chars().all(is_key_char) {
+            return Err(Error::InvalidSection(new_name.to_string()));
+        }
+    }
+
+    let lock = try!(LockFile::acquire(path).map_err(Error::IOError));
+    let contents = match try!(read_for_edit(path)) {
+        Some(contents) => contents,
+        None => return Ok(0),
+    };
+
+    let mut edited = String::new();
+    let mut found = 0;
+    let mut removing = false;
+    let mut rest = &contents[..];
+    while !rest.is_empty() {
+        let line_end = rest.find('\n').map(|newline| newline + 1).unwrap_or(rest.len());
+        let (line, next) = rest.split_at(line_end);
+        rest = next;
+
+        let is_section = line.trim_left().starts_with('[');
+        if is_section {
+            removing = false;
+            if let Some(header_end) = section_header_end(line.as_bytes(), old_name.as_bytes()) {
+                found += 1;
+                match new_name {
+                    Some(new_name) => {
+                        // Anything after the old header stays on a line of its own.
+                        edited.push_str(&section_header(new_name));
+                        if header_end < line.len() {
+                            edited.push('\t');
+                            edited.push_str(&line[header_end..]);
+                        }
+                    },
+                    None => removing = true,
+                }
+                continue;
+            }
+        }
+
+        if !removing {
+            edited.push_str(line);
+        }
+    }
+
+    if found > 0 {
+        try!(write_for_edit(path, lock, &edited));
+    }
+    Ok(found)
+}
+
+/// The prefix we were installed under, which determines where the system config file is, set
+/// with `$GITTERS_PREFIX` at build time.
+fn prefix() -> &'static str {
+    option_env!("GITTERS_PREFIX").unwrap_or("/usr")
+}
+
+/// Interpret a value as a boolean in the same way as git, returning `None` if it isn't one.
+/// Besides the usual words, any integer counts, with zero being false.
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" | "" => Some(false),
+        number => parse_int(number).ok().map(|number| number != 0),
+    }
+}
+
+/// Parse an integer in the same way as git: in decimal, or in hexadecimal or octal with a `0x` or
+/// `0` prefix, followed by an optional `k`, `m` or `g` suffix, which multiplies it by 1024,
+/// 1024^2 or 1024^3. The error is the reason it isn't a valid integer.
+pub fn parse_int(value: &str) -> Result<i64, &'static str> {
+    let value = value.trim_left_matches(is_space);
+    let (sign, value) = if value.starts_with('-') || value.starts_with('+') {
+        (&value[..1], &value[1..])
+    } else {
+        ("", value)
+    };
+
+    let is_hex = (value.starts_with("0x") || value.starts_with("0X")) &&
+        value[2..].chars().next().map_or(false, |chr| chr.is_digit(16));
+    let (radix, value) = if is_hex {
+        (16, &value[2..])
+    } else if value.starts_with('0') {
+        (8, value)
+    } else {
+        (10, value)
+    };
+
+    let digits = value.find(|chr: char| !chr.is_digit(radix)).unwrap_or(value.len());
+    let factor = match value[digits..].to_ascii_lowercase().as_str() {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        _ => return Err("invalid unit"),
+    };
+    if digits == 0 {
+        return Err("invalid unit");
+    }
+
+    i64::from_str_radix(&format!("{}{}", sign, &value[..digits]), radix).ok()
+        .and_then(|number| number.checked_mul(factor))
+        .ok_or("out of range")
+}
+
+/// Parse a date after which something is considered expired, as git does for settings such as
+/// `gc.reflogExpire`. Besides absolute and relative dates, or a Unix timestamp, `never` and
+/// `false` mean nothing expires, giving 0, and `now` and `all` mean everything does, giving the
+/// largest timestamp there is.
+pub fn parse_expiry_date(value: &str) -> Option<u64> {
+    match value {
+        "never" | "false" => return Some(0),
+        "now" | "all" => return Some(u64::max_value()),
+        _ => {},
+    }
+
+    let timestamp = date::approxidate(value, Local::now().timestamp());
+    timestamp.map(|timestamp| if timestamp < 0 { 0 } else { timestamp as u64 })
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+/// The path of the system-wide config file, `$(prefix)/etc/gitconfig`, which can be overridden by
+/// `$GIT_CONFIG_SYSTEM`. Like in git, an installation under `/usr` uses `/etc/gitconfig` instead.
+pub fn system_file() -> PathBuf {
+    match env_var("GIT_CONFIG_SYSTEM") {
+        Some(path) => PathBuf::from(path),
+        None if prefix() == "/usr" => PathBuf::from("/etc/gitconfig"),
+        None => Path::new(prefix()).join("etc/gitconfig"),
+    }
+}
+
+/// The path of the global config file under the XDG base directory, `$XDG_CONFIG_HOME/git/config`
+/// or `~/.config/git/config`.
+fn xdg_file() -> Option<PathBuf> {
+    match env_var("XDG_CONFIG_HOME") {
+        Some(ref dir) if !dir.is_empty() => Some(Path::new(dir).join("git/config")),
+        _ => env::home_dir().map(|home| home.join(".config/git/config")),
+    }
+}
+
+/// The paths of the user's global config files, in the order they're read: the XDG one and then
+/// `~/.gitconfig`. If `$GIT_CONFIG_GLOBAL` is set, it's the only global config file.
+fn global_files() -> Vec<PathBuf> {
+    if let Some(path) = env_var("GIT_CONFIG_GLOBAL") {
+        return vec![PathBuf::from(path)];
+    }
+
+    xdg_file().into_iter().chain(env::home_dir().map(|home| home.join(".gitconfig"))).collect()
+}
+
+/// The path of the user's global config file to read from or write to on its own. This is
+/// `~/.gitconfig`, unless only the XDG one exists.
+pub fn global_file() -> Option<PathBuf> {
+    if let Some(path) = env_var("GIT_CONFIG_GLOBAL") {
+        return Some(PathBuf::from(path));
+    }
+
+    let home_file = env::home_dir().map(|home| home.join(".gitconfig"));
+    match (home_file, xdg_file()) {
+        (Some(ref home_file), Some(ref xdg_file)) if !home_file.exists() && xdg_file.exists() =>
+            Some(xdg_file.clone()),
+        (home_file, _) => home_file,
+    }
+}
+
+/// The path of the config file for the repository in the current directory.
+pub fn repository_file() -> PathBuf {
+    repository::git_path("config")
+}
+
+/// The path of the config file specific to the current worktree, which is only read when the
+/// repository enables the `worktreeConfig` extension.
+pub fn worktree_file() -> PathBuf {
+    repository::git_path("config.worktree")
+}
+
+/// Whether the repository enables the `worktreeConfig` extension. Like the rest of the repository
+/// format, this can only be set in the repository config file.
+pub fn worktree_config_enabled() -> bool {
+    read_file(repository_file(), Scope::Local, false).ok()
+        .and_then(|config| config.get("extensions.worktreeConfig").and_then(parse_bool))
+        .unwrap_or(false)
+}
+
+/// Quote a string for the shell, in the format `$GIT_CONFIG_PARAMETERS` uses.
+fn shell_quote(text: &str) -> String {
+    let mut quoted = "'".to_string();
+    for chr in text.chars() {
+        match chr {
+            '\'' | '!' => {
+                quoted.push_str("'\\");
+                quoted.push(chr);
+                quoted.push('\'');
+            },
+            _ => quoted.push(chr),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Remove the quoting added by `shell_quote` from the quoted string at the start of the text,
+/// returning it along with the rest of the text.
+fn shell_unquote(text: &str) -> Option<(String, &str)> {
+    if !text.starts_with('\'') {
+        return None;
+    }
+
+    let mut unquoted = String::new();
+    let mut chars = text[1..].char_indices();
+    while let Some((_, chr)) = chars.next() {
+        if chr != '\'' {
+            unquoted.push(chr);
+            continue;
+        }
+
+        // A closing quote either ends the string, or is followed by an escaped character and a
+        // quote reopening the string.
+        let rest = chars.as_str();
+        match (rest.chars().nth(0), rest.chars().nth(1), rest.chars().nth(2)) {
+            (Some('\\'), Some(escaped), Some('\'')) if escaped == '\'' || escaped == '!' => {
+                unquoted.push(escaped);
+                chars.nth(2);
+            },
+            _ => return Some((unquoted, rest)),
+        }
+    }
+
+    None
+}
+
+/// Record a `<name>=<value>` setting given on the command line with `-c`, so that it overrides the
+/// config files in this process and any processes it starts, just like in git. A name without a
+/// value sets a boolean variable.
+pub fn push_parameter(text: &str) -> Result<(), Error> {
+    let (name, value) = match text.find('=') {
+        Some(equals) => (&text[..equals], Some(&text[equals + 1..])),
+        None => (text, None),
+    };
+    try!(canonical_name(name));
+
+    let mut parameters = env_var("GIT_CONFIG_PARAMETERS").unwrap_or(String::new());
+    if !parameters.is_empty() {
+        parameters.push(' ');
+    }
+    parameters.push_str(&shell_quote(name));
+    parameters.push('=');
+    if let Some(value) = value {
+        parameters.push_str(&shell_quote(value));
+    }
+
+    env::set_var("GIT_CONFIG_PARAMETERS", parameters);
+    Ok(())
+}
+
+/// The deepest includes can be nested, which stops circular includes from going on forever.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Expand a leading `~` or `~<user>` in a path to the home directory of the current or the given
+/// user. Returns `None` if the home directory can't be found.
+pub fn expand_user_path(path: &str) -> Option<PathBuf> {
+    if !path.starts_with('~') {
+        return Some(PathBuf::from(path));
+    }
+
+    let (user, rest) = match path.find('/') {
+        Some(slash) => (&path[1..slash], &path[slash + 1..]),
+        None => (&path[1..], ""),
+    };
+
+    let home = if user.is_empty() {
+        match env_var("HOME") {
+            Some(home) => home,
+            None => return None,
+        }
+    } else {
+        let user = match CString::new(user) {
+            Ok(user) => user,
+            Err(_) => return None,
+        };
+        let passwd = unsafe { libc::getpwnam(user.as_ptr()) };
+        if passwd.is_null() {
+            return None;
+        }
+        let dir = unsafe { CStr::from_ptr((*passwd).pw_dir) };
+        dir.to_string_lossy().into_owned()
+    };
+
+    Some(Path::new(&home).join(rest))
+}
+
+/// Whether the variable is the URL of a remote, `remote.<name>.url`.
+fn is_remote_url(name: &str) -> bool {
+    name.starts_with("remote.") && name.ends_with(".url") && name.len() > "remote..url".len()
+}
+
+/// Append `**` to a pattern ending in a slash, so that it matches everything inside the directory.
+fn add_trailing_starstar(pattern: &mut String) {
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+}
+
+/// Whether the repository's `.git` directory matches the pattern of a `gitdir:` condition. The
+/// pattern is matched against the real path of the directory, and failing that, against the path
+/// it's reached through, which may go through symbolic links.
+fn gitdir_matches(pattern: &str, from: Option<&Path>, icase: bool) -> Result<bool, Error> {
+    let real_git_dir = match fs::canonicalize(repository::git_dir()) {
+        Ok(path) => path,
+        Err(_) => return Ok(false),
+    };
+
+    let mut pattern = expand_user_path(pattern)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or(pattern.to_string());
+
+    // A pattern starting with `./` is relative to the directory of the including file, and that
+    // directory has to match exactly, wildcards and all.
+    let mut prefix = 0;
+    if pattern.starts_with("./") {
+        let from = try!(from.ok_or_else(|| {
+            Error::InvalidParameters(
+                "relative config include conditionals must come from files".to_string())
+        }));
+        let real_from = try!(fs::canonicalize(from).map_err(|e| Error::IOError(e)));
+        let dir = real_from.parent().unwrap_or(Path::new("/")).to_string_lossy().into_owned();
+        pattern = format!("{}{}", dir, &pattern[1..]);
+        prefix = dir.len() + 1;
+    } else if !Path::new(&pattern).is_absolute() {
+        pattern.insert_str(0, "**/");
+    }
+    add_trailing_starstar(&mut pattern);
+
+    let flags = ignore::WM_PATHNAME | if icase { ignore::WM_CASEFOLD } else { 0 };
+    let absolute_git_dir = try!(env::current_dir().map_err(|e| Error::IOError(e)))
+        .join(repository::git_dir());
+    for git_dir in [real_git_dir, absolute_git_dir].iter() {
+        // The prefix is a length in the pattern, which may fall inside a character of the path, so
+        // it's compared byte by byte, and then only a path it splits cleanly can match.
+        let text = git_dir.to_string_lossy();
+        if text.len() < prefix || !text.is_char_boundary(prefix) {
+            continue;
+        }
+
+        let pattern_prefix = &pattern.as_bytes()[..prefix];
+        let text_prefix = &text.as_bytes()[..prefix];
+        let prefix_matches = if icase {
+            pattern_prefix.eq_ignore_ascii_case(text_prefix)
+        } else {
+            pattern_prefix == text_prefix
+        };
+        if prefix_matches && ignore::wildmatch(&pattern[prefix..], &text[prefix..], flags) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Whether the current branch matches the pattern of an `onbranch:` condition. There's no match
+/// when `HEAD` is detached, or outside a repository.
+fn branch_matches(pattern: &str) -> bool {
+    let branch = match refs::read_head() {
+        Ok(refs::Head { branch: Some(branch), .. }) => branch,
+        _ => return false,
+    };
+
+    let mut pattern = pattern.to_string();
+    add_trailing_starstar(&mut pattern);
+    ignore::wildmatch(&pattern, &branch, ignore::WM_PATHNAME)
+}
+
+impl Config {
+    /// Add a file to the configuration, unless it doesn't exist.
+    fn add_from_file_if_exists(&mut self, path: &Path) -> Result<(), Error> {
+        if path.exists() {
+            try!(self.add_from_file(path, 0));
+        }
+        Ok(())
+    }
+
+    fn add_parameter(&mut self, name: &str, value: Option<String>) -> Result<(), Error> {
+        let entry = Entry {
+            name: try!(canonical_name(name)),
+            value: value,
+            origin: Origin::CommandLine,
+            scope: Scope::Command,
+        };
+        self.add_entry(entry, 0)
+    }
+
+    /// The file a variable includes, if it's `include.path`, or `includeIf.<condition>.path` with
+    /// a condition that holds, along with whether the include is conditional. A relative path is
+    /// relative to the directory of the including file.
+    fn included_file(&mut self, entry: &Entry) -> Result<Option<(PathBuf, bool)>, Error> {
+        let name = &entry.name;
+        let from = match entry.origin {
+            Origin::File(ref path, _) => Some(path.as_path()),
+            Origin::CommandLine => None,
+        };
+
+        let conditional = name.starts_with("includeif.") && name.ends_with(".path") &&
+            name.len() > "includeif..path".len();
+        if conditional {
+            let condition = &name["includeif.".len()..name.len() - ".path".len()];
+            if !try!(self.condition_holds(condition, from)) {
+                return Ok(None);
+            }
+        } else if name != "include.path" {
+            return Ok(None);
+        }
+
+        let value = try!(entry.required_value());
+        let path = try!(expand_user_path(value).ok_or_else(|| {
+            Error::InvalidInclude(format!("could not expand include path '{}'", value))
+        }));
+        if path.is_absolute() {
+            return Ok(Some((path, conditional)));
+        }
+
+        match from {
+            Some(from) => {
+                let dir = from.parent().unwrap_or(Path::new(""));
+                Ok(Some((dir.join(path), conditional)))
+            },
+            None => Err(Error::InvalidParameters(
+                    "relative config includes must come from files".to_string())),
+        }
+    }
+
+    /// Add the contents of an included file, which is skipped if it doesn't exist.
+    fn include(&mut self, path: &Path, from: &Origin, depth: usize) -> Result<(), Error> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        if depth >= MAX_INCLUDE_DEPTH {
+            let from = match *from {
+                Origin::File(ref from, _) => from.display().to_string(),
+                Origin::CommandLine => "the command line".to_string(),
+            };
+            return Err(Error::InvalidInclude(format!(
+                        "exceeded maximum include depth ({}) while including\n\t{}\nfrom\n\t{}\n\
+                         This might be due to circular includes.",
+                        MAX_INCLUDE_DEPTH, path.display(), from)));
+        }
+
+        self.add_from_file(path, depth + 1)
+    }
+
+    /// Check the condition of an `includeIf`. Unknown conditions never hold, so that newer ones
+    /// are ignored instead of being treated as errors.
+    fn condition_holds(&mut self, condition: &str, from: Option<&Path>) -> Result<bool, Error> {
+        if condition.starts_with("gitdir:") {
+            gitdir_matches(&condition["gitdir:".len()..], from, false)
+        } else if condition.starts_with("gitdir/i:") {
+            gitdir_matches(&condition["gitdir/i:".len()..], from, true)
+        } else if condition.starts_with("onbranch:") {
+            Ok(branch_matches(&condition["onbranch:".len()..]))
+        } else if condition.starts_with("hasconfig:remote.*.url:") {
+            self.remote_url_matches(&condition["hasconfig:remote.*.url:".len()..])
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Whether the URL of any remote, anywhere in the hierarchy, matches the pattern of a
+    /// `hasconfig:remote.*.url:` condition.
+    fn remote_url_matches(&mut self, pattern: &str) -> Result<bool, Error> {
+        if self.collecting_remote_urls {
+            return Ok(true);
+        }
+
+        if self.remote_urls.is_none() {
+            let mut config = Config::new();
+            config.collecting_remote_urls = true;
+            let config = match self.file {
+                Some(ref path) => {
+                    try!(config.add_from_file(path, 0));
+                    config
+                },
+                None => try!(read_hierarchy(config)),
+            };
+            self.remote_urls = Some(config.entries.into_iter()
+                                    .filter(|entry| is_remote_url(&entry.name))
+                                    .filter_map(|entry| entry.value)
+                                    .collect());
+        }
+
+        let urls = self.remote_urls.as_ref().unwrap();
+        Ok(urls.iter().any(|url| ignore::wildmatch(pattern, url, ignore::WM_PATHNAME)))
+    }
+
+    /// Add the variables set through `$GIT_CONFIG_COUNT`, `$GIT_CONFIG_KEY_<n>` and
+    /// `$GIT_CONFIG_VALUE_<n>`.
+    fn add_from_environment(&mut self) -> Result<(), Error> {
+        let count = match env_var("GIT_CONFIG_COUNT") {
+            Some(ref count) if count.is_empty() => 0,
+            Some(count) => try!(count.parse::<usize>().map_err(|_| {
+                Error::InvalidParameters("bogus count in GIT_CONFIG_COUNT".to_string())
+            })),
+            None => 0,
+        };
+
+        for i in 0..count {
+            let key_var = format!("GIT_CONFIG_KEY_{}", i);
+            let value_var = format!("GIT_CONFIG_VALUE_{}", i);
+            let key = try!(env_var(&key_var).ok_or_else(|| {
+                Error::InvalidParameters(format!("missing config key {}", key_var))
+            }));
+            let value = try!(env_var(&value_var).ok_or_else(|| {
+                Error::InvalidParameters(format!("missing config value {}", value_var))
+            }));
+            try!(self.add_parameter(&key, Some(value)));
+        }
+
+        Ok(())
+    }
+
+    /// Add the variables set on the command line with `-c`, which are passed in
+    /// `$GIT_CONFIG_PARAMETERS` as a list of shell-quoted `'<name>'='<value>'` pairs. The older
+    /// format, where the whole `'<name>=<value>'` is quoted together, is accepted as well.
+    fn add_from_parameters(&mut self) -> Result<(), Error> {
+        let parameters = env_var("GIT_CONFIG_PARAMETERS").unwrap_or(String::new());
+        let bogus_format = || {
+            Error::InvalidParameters("bogus format in GIT_CONFIG_PARAMETERS".to_string())
+        };
+
+        let mut rest = parameters.trim_left();
+        while !rest.is_empty() {
+            let (first, after_first) = try!(shell_unquote(rest).ok_or_else(&bogus_format));
+            let (name, value, after) = if after_first.starts_with('=') {
+                let after_equals = &after_first[1..];
+                if after_equals.starts_with('\'') {
+                    let (value, after_value) =
+                        try!(shell_unquote(after_equals).ok_or_else(&bogus_format));
+                    (first, Some(value), after_value)
+                } else {
+                    (first, None, after_equals)
+                }
+            } else {
+                match first.find('=') {
+                    Some(equals) =>
+                        (first[..equals].to_string(), Some(first[equals + 1..].to_string()),
+                         after_first),
+                    None => (first.clone(), None, after_first),
+                }
+            };
+
+            if !after.is_empty() && !after.starts_with(char::is_whitespace) {
+                return Err(bogus_format());
+            }
+            try!(self.add_parameter(&name, value));
+            rest = after.trim_left();
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the whole hierarchy of config files, with each one overriding the ones before it:
+///
+/// - The system config file, unless `$GIT_CONFIG_NOSYSTEM` is set
+/// - The global config files, `$XDG_CONFIG_HOME/git/config` and then `~/.gitconfig`
+/// - The repository config file, `.git/config`
+/// - The worktree config file, `.git/config.worktree`, if the repository enables it
+/// - The variables set in the environment, and then those set on the command line with `-c`
+///
+/// Any of the files can be missing. The files they include are read as well.
 pub fn read_all() -> Result<Config, Error> {
+    read_hierarchy(Config::new())
+}
+
+/// Read the whole hierarchy of config files, like `read_all`, but without reading the files they
+/// include.
+pub fn read_all_without_includes() -> Result<Config, Error> {
     let mut config = Config::new();
+    config.follow_includes = false;
+    read_hierarchy(config)
+}
+
+fn read_hierarchy(mut config: Config) -> Result<Config, Error> {
+    let no_system = env_var("GIT_CONFIG_NOSYSTEM").and_then(|value| parse_bool(&value));
+    if !no_system.unwrap_or(false) {
+        config.scope = Scope::System;
+        try!(config.add_from_file_if_exists(&system_file()));
+    }
 
-    let home_gitconfig = env::home_dir()
-        .and_then(|mut path| {
-            path.push(".gitconfig");
-            path.to_str().map(|s| s.to_string())
-        });
-    match home_gitconfig {
-        Some(path) => { try!(config.add_from_file(path)); },
-        None => {}
+    config.scope = Scope::Global;
+    for path in global_files() {
+        try!(config.add_from_file_if_exists(&path));
     }
 
-    let repo_gitconfig = env::current_dir()
-        .ok()
-        .and_then(|mut path| {
-            path.push(".git/config");
-            path.to_str().map(|s| s.to_string())
-        });
-    match repo_gitconfig {
-        Some(path) => { try!(config.add_from_file(path)); },
-        None => {}
+    config.scope = Scope::Local;
+    try!(config.add_from_file_if_exists(&repository_file()));
+    if worktree_config_enabled() {
+        config.scope = Scope::Worktree;
+        try!(config.add_from_file_if_exists(&worktree_file()));
     }
 
+    config.scope = Scope::Command;
+    try!(config.add_from_environment());
+    try!(config.add_from_parameters());
     Ok(config)
 }
 
@@ -289,29 +1696,194 @@ key2 = val2 with spaces
 
 [Complicated-123] key3 = val3
 key4
-key-5 # here's a comment
+key-5
+; here's a comment
 key6 = val6 # and another comment
 
 [simple "subsection (with spaces)"]
 key7 = val7
 
-# TODO: multiline values
+[quoting "with \"escaped\" \\quotes"]
+key8 = "  leading ; and # kept  "  trailing; comment
+key9 = tab\there\nnewline \"quoted\" back\\slash
+key10 = this value \
+    continues\
+here
 "#;
 
         let mut config = Config::new();
         config.add_from_string(contents.to_string()).unwrap();
         assert_eq!(config.all(), vec![
-                   ("Complicated-123.key-5", "true"),
-                   ("Complicated-123.key3", "val3"),
-                   ("Complicated-123.key4", "true"),
-                   ("Complicated-123.key6", "val6"),
-                   ("simple.key0", "val0"),
-                   ("simple.key1", "val1"),
-                   ("simple.key2", "val2 with spaces"),
-                   ("simple.subsection (with spaces).key7", "val7"),
+                   ("simple.key0", Some("val0")),
+                   ("simple.key1", Some("val1")),
+                   ("simple.key2", Some("val2 with spaces")),
+                   ("complicated-123.key3", Some("val3")),
+                   ("complicated-123.key4", None),
+                   ("complicated-123.key-5", None),
+                   ("complicated-123.key6", Some("val6")),
+                   ("simple.subsection (with spaces).key7", Some("val7")),
+                   ("quoting.with \"escaped\" \\quotes.key8",
+                    Some("  leading ; and # kept    trailing")),
+                   ("quoting.with \"escaped\" \\quotes.key9",
+                    Some("tab\there\nnewline \"quoted\" back\\slash")),
+                   ("quoting.with \"escaped\" \\quotes.key10",
+                    Some("this value     continueshere")),
                    ]
                    .iter()
-                   .map(|s| (s.0.to_string(), s.1.to_string()))
+                   .map(|s| (s.0.to_string(), s.1.map(|value| value.to_string())))
                    .collect::<Vec<_> >());
+        assert_eq!(Some(true), config.get_bool("complicated-123.key4").unwrap());
+        assert!(config.get_string("complicated-123.key4").is_err());
+    }
+
+    #[test]
+    fn keeps_every_value_with_case_insensitive_names() {
+        let contents = r#"
+[Remote "Origin"]
+    URL = first
+    Fetch = +refs/heads/*:refs/remotes/Origin/*
+[remote "Origin"]
+    url = second
+    fetch = +refs/tags/*:refs/tags/*
+[remote "origin"]
+    url = other
+"#;
+
+        let mut config = Config::new();
+        config.add_from_string(contents.to_string()).unwrap();
+        assert_eq!(Some("second"), config.get("remote.Origin.url"));
+        assert_eq!(Some("second"), config.get("REMOTE.Origin.Url"));
+        assert_eq!(Some("other"), config.get("remote.origin.url"));
+        assert_eq!(vec!["+refs/heads/*:refs/remotes/Origin/*", "+refs/tags/*:refs/tags/*"],
+                   config.get_all("remote.Origin.fetch"));
+        assert_eq!(None, config.get("remote.Origin"));
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        let invalid = [
+            "[simple]\nkey # comments need a value\n",
+            "[simple]\nkey = \"unterminated\n",
+            "[simple]\nkey = invalid \\escape\n",
+            "[simple \"subsection\" ]\nkey = val\n",
+            "[simple \"multi-line\nsubsection\"]\n",
+        ];
+
+        for contents in invalid.iter() {
+            let mut config = Config::new();
+            assert!(config.add_from_string(contents.to_string()).is_err(), "{}", contents);
+        }
+    }
+
+    #[test]
+    fn interprets_typed_values() {
+        assert_eq!(Some(true), parse_bool("Yes"));
+        assert_eq!(Some(true), parse_bool("1k"));
+        assert_eq!(Some(false), parse_bool(""));
+        assert_eq!(None, parse_bool("maybe"));
+
+        assert_eq!(Ok(1024), parse_int("1k"));
+        assert_eq!(Ok(-3 << 20), parse_int("-3M"));
+        assert_eq!(Ok(16), parse_int("0x10"));
+        assert_eq!(Ok(8), parse_int("010"));
+        assert_eq!(Err("invalid unit"), parse_int("12x"));
+        assert_eq!(Err("invalid unit"), parse_int(""));
+        assert_eq!(Err("out of range"), parse_int("99999999999g"));
+
+        assert_eq!(Some(0), parse_expiry_date("never"));
+        assert_eq!(Some(u64::max_value()), parse_expiry_date("now"));
+        assert_eq!(Some(1234567890), parse_expiry_date("@1234567890"));
+        assert!(parse_expiry_date("2.weeks.ago").is_some());
+        assert_eq!(None, parse_expiry_date("bogus"));
+
+    }
+
+    #[test]
+    fn edits_files_in_place() {
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::process;
+
+        let path = env::temp_dir().join(format!("gitters-config-test-{}", process::id()));
+        File::create(&path).unwrap()
+            .write_all(b"# keep me\n[core]\n\tbare = false ; and me\n[remote \"origin\"]\n\
+                         \turl = one\n").unwrap();
+
+        set_in_file(&path, "core.FileMode", Some("true"), &ValuePattern::Any, false).unwrap();
+        set_in_file(&path, "remote.origin.url", Some("two"), &ValuePattern::Nothing, false)
+            .unwrap();
+        assert!(set_in_file(&path, "remote.origin.url", Some("three"), &ValuePattern::Any, false)
+                .is_err());
+        set_in_file(&path, "user.name", Some(" A # B"), &ValuePattern::Any, false).unwrap();
+        set_in_file(&path, "remote.origin.url", None, &ValuePattern::Any, true).unwrap();
+        assert_eq!(1, rename_section_in_file(&path, "core", Some("Core2")).unwrap());
+
+        let mut contents = String::new();
+        File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!("# keep me\n[Core2]\n\tbare = false ; and me\n\tFileMode = true\n\
+                    [user]\n\tname = \" A # B\"\n", contents);
+    }
+
+    #[test]
+    fn follows_includes() {
+        use std::fs::File;
+        use std::io::Write;
+        use std::process;
+
+        let dir = env::temp_dir().join(format!("gitters-include-test-{}", process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        File::create(dir.join("main")).unwrap()
+            .write_all(b"[include]\n\tpath = sub/inc\n\tpath = missing\n\
+                         [includeIf \"unknown:x\"]\n\tpath = sub/inc\n[a]\n\tb = last\n").unwrap();
+        File::create(dir.join("sub/inc")).unwrap().write_all(b"[a]\n\tb = included\n").unwrap();
+        File::create(dir.join("loop")).unwrap().write_all(b"[include]\n\tpath = loop\n").unwrap();
+
+        let config = read_file(dir.join("main"), Scope::Global, true).unwrap();
+        assert_eq!(vec!["included", "last"], config.get_all("a.b"));
+        let origins: Vec<_> = config.entries().iter()
+            .filter(|entry| entry.name == "a.b")
+            .map(|entry| (entry.origin.clone(), entry.scope))
+            .collect();
+        assert_eq!(vec![(Origin::File(dir.join("sub/inc"), 2), Scope::Global),
+                        (Origin::File(dir.join("main"), 7), Scope::Global)], origins);
+
+        let config = read_file(dir.join("main"), Scope::Command, false).unwrap();
+        assert_eq!(vec!["last"], config.get_all("a.b"));
+        assert!(read_file(dir.join("loop"), Scope::Command, true).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn matches_relative_gitdir_conditions_byte_by_byte() {
+        use repository::TestRepository;
+        use std::fs::File;
+        use std::io::Write;
+
+        let repo = TestRepository::new("gitdir-include");
+        fs::create_dir_all(repo.dir.join("ab/.git")).unwrap();
+        fs::create_dir_all(repo.dir.join("ab\u{e9}")).unwrap();
+        File::create(repo.dir.join("ab/config")).unwrap()
+            .write_all(b"[includeIf \"gitdir:./\"]\n\tpath = inc\n").unwrap();
+        File::create(repo.dir.join("ab/inc")).unwrap().write_all(b"[a]\n\tb = included\n").unwrap();
+
+        env::set_var("GIT_DIR", repo.dir.join("ab/.git"));
+        let config = read_file(repo.dir.join("ab/config"), Scope::Local, true).unwrap();
+        assert_eq!(vec!["included"], config.get_all("a.b"));
+
+        // The length of the including file's directory ends inside the last character of this one.
+        env::set_var("GIT_DIR", repo.dir.join("ab\u{e9}"));
+        let config = read_file(repo.dir.join("ab/config"), Scope::Local, true).unwrap();
+        assert!(config.get_all("a.b").is_empty());
+    }
+
+    #[test]
+    fn quotes_command_line_parameters() {
+        let quoted = shell_quote("it's done!");
+        assert_eq!("'it'\\''s done'\\!''", quoted);
+        assert_eq!(Some(("it's done!".to_string(), " rest")),
+                   shell_unquote(&format!("{} rest", quoted)));
+        assert_eq!(None, shell_unquote("'unterminated"));
     }
 }
@@ -0,0 +1,168 @@
+//! Parses dates the way git's "approxidate" does, which is used wherever git takes a date from the
+//! user, such as `log --since` and settings like `gc.reflogExpire`.
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+/// Subtract a number of calendar months from a date, keeping the day of the month and the time of
+/// day. Like in git, which uses `mktime`, a day past the end of the resulting month carries over
+/// into the next one, so a month before the 31st of March is the 2nd or 3rd of March.
+fn months_before(date: NaiveDateTime, months: i64) -> Option<NaiveDateTime> {
+    let month = match (date.year() as i64 * 12 + date.month0() as i64).checked_sub(months) {
+        Some(month) if month >= 0 && month / 12 <= i32::max_value() as i64 => month,
+        _ => return None,
+    };
+    NaiveDate::from_ymd_opt((month / 12) as i32, (month % 12) as u32 + 1, 1)
+        .and_then(|first| first.checked_add(Duration::days(date.day() as i64 - 1)))
+        .map(|date_in_month| date_in_month.and_time(date.time()))
+}
+
+/// Subtract a number of units of time, each lasting the given number of seconds, from a date.
+/// Returns `None` if the amount of time is too large to be represented, rather than panicking like
+/// the `Duration` constructors do.
+fn seconds_before(date: NaiveDateTime, count: i64, unit: i64) -> Option<NaiveDateTime> {
+    let max = Duration::max_value().num_seconds();
+    match count.checked_mul(unit) {
+        Some(seconds) if -max <= seconds && seconds <= max =>
+            date.checked_sub(Duration::seconds(seconds)),
+        _ => None,
+    }
+}
+
+/// Parse an absolute date in local time, without a timezone, such as `2023-11-14` or
+/// `2023-11-14 18:13`. Without a time, the current time of day is used.
+fn parse_absolute_date(text: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"].iter() {
+        if let Ok(date) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(date);
+        }
+    }
+    for format in ["%Y-%m-%d", "%Y/%m/%d", "%Y.%m.%d"].iter() {
+        if let Ok(date) = NaiveDate::parse_from_str(text, format) {
+            return Some(date.and_time(now.time()));
+        }
+    }
+    None
+}
+
+/// Parse a time relative to now, made up of amounts of time before it, such as `2.weeks.ago` or
+/// `1 year, 2 months ago`. An amount without a number, like `a week` or `last month`, counts once,
+/// and `yesterday` is a day ago.
+fn parse_relative_date(text: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let mut date = now;
+    let mut number = None;
+    let mut found = false;
+    let words = text.split(|chr: char| chr == '.' || chr == ',' || chr.is_whitespace())
+        .filter(|word| !word.is_empty());
+    for word in words {
+        if let Ok(parsed) = word.parse::<i64>() {
+            number = Some(parsed);
+            continue;
+        }
+
+        let unit = word.trim_right_matches('s');
+        match unit {
+            "ago" | "and" | "a" | "an" | "one" | "last" => continue,
+            _ => {},
+        }
+
+        let count = number.take().unwrap_or(1);
+        let before = match unit {
+            "now" => Some(date),
+            "second" | "sec" => seconds_before(date, count, 1),
+            "minute" | "min" => seconds_before(date, count, 60),
+            "hour" => seconds_before(date, count, 3600),
+            "day" => seconds_before(date, count, 86400),
+            "yesterday" => seconds_before(date, 1, 86400),
+            "week" => seconds_before(date, count, 7 * 86400),
+            "month" => months_before(date, count),
+            "year" => count.checked_mul(12).and_then(|months| months_before(date, months)),
+            _ => None,
+        };
+        date = match before {
+            Some(date) => date,
+            None => return None,
+        };
+        found = true;
+    }
+
+    if found && number.is_none() {
+        Some(date)
+    } else {
+        None
+    }
+}
+
+/// Parse a date given by the user, returning it as a Unix timestamp. It can be:
+///
+/// - a timestamp, either as `@<seconds>` or a number large enough not to be taken for a year;
+/// - a date with a timezone, such as `2023-11-14 18:13:20 -0500`;
+/// - a date in local time, such as `2023-11-14` or `2023-11-14 18:13`;
+/// - a time relative to `now`, such as `2 weeks ago` or `yesterday`.
+///
+/// Returns `None` if the text isn't any of those.
+pub fn approxidate(text: &str, now: i64) -> Option<i64> {
+    let text = text.trim();
+    if text.starts_with('@') {
+        return text[1..].parse().ok();
+    }
+
+    if let Ok(timestamp) = text.parse::<i64>() {
+        if timestamp >= 100000000 {
+            return Some(timestamp);
+        }
+    }
+
+    for format in ["%Y-%m-%d %H:%M:%S %z", "%Y-%m-%dT%H:%M:%S%z"].iter() {
+        if let Ok(date) = DateTime::parse_from_str(text, format) {
+            return Some(date.timestamp());
+        }
+    }
+
+    let local_now = Local.timestamp(now, 0).naive_local();
+    parse_absolute_date(text, local_now)
+        .or_else(|| parse_relative_date(&text.to_lowercase(), local_now))
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+        .map(|date| date.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::months_before;
+
+    #[test]
+    fn parses_approximate_dates() {
+        let now = 1700000000;
+        assert_eq!(Some(now), approxidate("now", now));
+        assert_eq!(Some(now - 2 * 7 * 86400), approxidate("2.weeks.ago", now));
+        assert_eq!(Some(now - 7 * 86400 - 3600), approxidate("a week and an hour ago", now));
+        assert_eq!(Some(now - 3 * 3600 - 90), approxidate("3.hours.90.seconds.ago", now));
+        assert_eq!(Some(now - 86400), approxidate("yesterday", now));
+        assert_eq!(Some(1699992000), approxidate("@1699992000", now));
+        assert_eq!(Some(1699992000), approxidate("1699992000", now));
+        assert_eq!(Some(1700003600), approxidate("2023-11-14T23:13:20+0000", now));
+        assert_eq!(Some(1700003600), approxidate("2023-11-14 18:13:20 -0500", now));
+        assert_eq!(None, approxidate("2", now));
+        assert_eq!(None, approxidate("someday", now));
+        assert_eq!(None, approxidate("2 fortnights ago", now));
+    }
+
+    #[test]
+    fn rejects_relative_dates_too_far_back() {
+        let now = 1700000000;
+        assert_eq!(None, approxidate("100000000 days ago", now));
+        assert_eq!(None, approxidate("9223372036854775807 seconds ago", now));
+        assert_eq!(None, approxidate("9223372036854775807 weeks ago", now));
+        assert_eq!(None, approxidate("1000000000000000000 years ago", now));
+        assert_eq!(None, approxidate("-9223372036854775808 months ago", now));
+    }
+
+    #[test]
+    fn carries_days_past_the_end_of_months_over() {
+        let date = NaiveDate::from_ymd(2024, 3, 31).and_hms(12, 0, 0);
+        assert_eq!(Some(NaiveDate::from_ymd(2024, 3, 2).and_hms(12, 0, 0)),
+                   months_before(date, 1));
+        assert_eq!(Some(NaiveDate::from_ymd(2023, 3, 31).and_hms(12, 0, 0)),
+                   months_before(date, 12));
+    }
+}
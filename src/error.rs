@@ -0,0 +1,231 @@
+//! Provides the error type shared by the whole library, for callers that need to react to what
+//! went wrong, rather than just report it. Modules that only deal with one part of the repository
+//! return their own `Error`, describing failures in its terms, and these convert into this type
+//! with `From`, keeping the underlying cause, such as the IO error, available through `source()`.
+//! Modules that bring several parts together, like `status`, return this type directly.
+
+use branch;
+use config;
+use ignore;
+use index;
+use objects;
+use refs;
+use revisions;
+use revwalk;
+use trees;
+
+use std::{error, fmt, io, result};
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The object isn't in the database.
+    ObjectNotFound(objects::Name),
+
+    /// The object is in the database, but it can't be read.
+    CorruptObject { name: objects::Name, reason: String },
+
+    /// Nothing is named by the ref or revision, such as a branch that doesn't exist.
+    RefNotFound(String),
+
+    /// The ref exists, but can't be resolved, such as when its file can't be parsed.
+    InvalidRef(String),
+
+    /// `HEAD` doesn't point to a branch, so there is no current branch.
+    DetachedHead,
+
+    /// A file in the repository couldn't be read or written.
+    Io { path: PathBuf, source: io::Error },
+
+    /// The configuration couldn't be read, or contains an invalid value.
+    Config(config::Error),
+
+    /// The index couldn't be read or written.
+    Index(index::Error),
+
+    /// A tree couldn't be read, or isn't a valid tree.
+    Tree(trees::Error),
+
+    /// The exclude patterns deciding which files are ignored couldn't be read.
+    Excludes(ignore::Error),
+
+    /// The history couldn't be walked, such as when a parent of a commit isn't a commit.
+    Walk(revwalk::Error),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::ObjectNotFound(ref name) => write!(f, "object not found: {}", name.0),
+            Error::CorruptObject { ref name, ref reason } =>
+                write!(f, "object {} is corrupt: {}", name.0, reason),
+            Error::RefNotFound(ref name) => write!(f, "ref not found: {}", name),
+            Error::InvalidRef(ref name) => write!(f, "invalid ref: {}", name),
+            Error::DetachedHead => write!(f, "HEAD is not on a branch"),
+            Error::Io { ref path, ref source } => write!(f, "{}: {}", path.display(), source),
+            Error::Config(ref err) => write!(f, "{}", err),
+            Error::Index(ref err) => write!(f, "{}", err),
+            Error::Tree(ref err) => write!(f, "{}", err),
+            Error::Excludes(ref err) => write!(f, "{}", err),
+            Error::Walk(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::ObjectNotFound(_) => "object not found",
+            Error::CorruptObject { ref reason, .. } => reason,
+            Error::RefNotFound(_) => "ref not found",
+            Error::InvalidRef(_) => "invalid ref",
+            Error::DetachedHead => "HEAD is not on a branch",
+            Error::Io { ref source, .. } => source.description(),
+            Error::Config(ref err) => err.description(),
+            Error::Index(ref err) => err.description(),
+            Error::Tree(ref err) => err.description(),
+            Error::Excludes(ref err) => err.description(),
+            Error::Walk(ref err) => err.description(),
+        }
+    }
+
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::Io { ref source, .. } => Some(source),
+            Error::Config(ref err) => Some(err),
+            Error::Index(ref err) => Some(err),
+            Error::Tree(ref err) => Some(err),
+            Error::Excludes(ref err) => Some(err),
+            Error::Walk(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<objects::Error> for Error {
+    fn from(err: objects::Error) -> Error {
+        match err {
+            objects::Error::IOError(path, source) => Error::Io { path: path, source: source },
+            objects::Error::NotFound(name) => Error::ObjectNotFound(name),
+            objects::Error::Corrupt(name, reason) =>
+                Error::CorruptObject { name: name, reason: reason },
+        }
+    }
+}
+
+impl From<refs::Error> for Error {
+    fn from(err: refs::Error) -> Error {
+        match err {
+            refs::Error::IOError(path, source) => Error::Io { path: path, source: source },
+            refs::Error::InvalidRef(name) => Error::InvalidRef(name),
+        }
+    }
+}
+
+impl From<revisions::Error> for Error {
+    fn from(err: revisions::Error) -> Error {
+        match err {
+            revisions::Error::InvalidRevision(rev) => Error::RefNotFound(rev),
+            revisions::Error::IOError(path, source) => Error::Io { path: path, source: source },
+            revisions::Error::ObjectError(err) => Error::from(err),
+        }
+    }
+}
+
+impl From<branch::Error> for Error {
+    fn from(err: branch::Error) -> Error {
+        match err {
+            branch::Error::IOError(path, source) => Error::Io { path: path, source: source },
+            branch::Error::NotOnBranch => Error::DetachedHead,
+            branch::Error::InvalidName(path) => Error::InvalidRef(path.display().to_string()),
+        }
+    }
+}
+
+impl From<config::Error> for Error {
+    fn from(err: config::Error) -> Error {
+        Error::Config(err)
+    }
+}
+
+impl From<index::Error> for Error {
+    fn from(err: index::Error) -> Error {
+        Error::Index(err)
+    }
+}
+
+impl From<trees::Error> for Error {
+    fn from(err: trees::Error) -> Error {
+        match err {
+            trees::Error::ObjectError(err) => Error::from(err),
+            err => Error::Tree(err),
+        }
+    }
+}
+
+impl From<revwalk::Error> for Error {
+    fn from(err: revwalk::Error) -> Error {
+        match err {
+            revwalk::Error::ObjectError(err) => Error::from(err),
+            revwalk::Error::RevisionError(err) => Error::from(err),
+            err => Error::Walk(err),
+        }
+    }
+}
+
+impl From<ignore::Error> for Error {
+    fn from(err: ignore::Error) -> Error {
+        Error::Excludes(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use index;
+    use objects;
+    use revisions;
+    use trees;
+
+    use std::error::Error as StdError;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[test]
+    fn converts_module_errors() {
+        let name = objects::Name("4ddb0025ef5914b51fb835495f5259a6d962df21".to_string());
+        match Error::from(revisions::Error::ObjectError(objects::Error::NotFound(name.clone()))) {
+            Error::ObjectNotFound(ref missing) => assert_eq!(&name, missing),
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        match Error::from(revisions::Error::InvalidRevision("topic~2".to_string())) {
+            Error::RefNotFound(ref rev) => assert_eq!("topic~2", rev),
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        let err = Error::from(objects::Error::IOError(
+            PathBuf::from(".git/objects/4d"),
+            io::Error::new(io::ErrorKind::PermissionDenied, "permission denied")));
+        assert_eq!(".git/objects/4d: permission denied", err.to_string());
+        assert_eq!("permission denied", err.source().unwrap().to_string());
+    }
+
+    #[test]
+    fn keeps_the_causes_of_wrapped_errors() {
+        let name = objects::Name("4ddb0025ef5914b51fb835495f5259a6d962df21".to_string());
+        match Error::from(trees::Error::ObjectError(objects::Error::NotFound(name.clone()))) {
+            Error::ObjectNotFound(ref missing) => assert_eq!(&name, missing),
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        let err = Error::from(index::Error::IOError(
+            PathBuf::from(".git/index"),
+            io::Error::new(io::ErrorKind::PermissionDenied, "permission denied")));
+        let index_err = err.source().unwrap();
+        assert_eq!(".git/index: permission denied", index_err.to_string());
+        assert_eq!("permission denied", index_err.source().unwrap().to_string());
+    }
+}
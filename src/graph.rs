@@ -0,0 +1,729 @@
+//! Draws the history of commits as a graph alongside `log`, with a column for each line of
+//! history, a `*` for each commit, and `|`, `/`, `\` and `_` for the lines between them. This
+//! follows git's graph.c closely, so that the same history is drawn the same way.
+//!
+//! The graph is drawn a line at a time. For each commit, there are the lines leading up to it and
+//! the commit's own line, followed by the lines that move the other lines of history into place
+//! for the next commit, which are drawn next to the lines of the commit's message.
+
+use color;
+use objects;
+
+use std::mem;
+
+/// The colors of the columns when `log.graphColors` isn't set, used in turn for each new column.
+pub const DEFAULT_COLORS: [&'static str; 12] = [
+    "red", "green", "yellow", "blue", "magenta", "cyan",
+    "bold red", "bold green", "bold yellow", "bold blue", "bold magenta", "bold cyan",
+];
+
+/// What the next line drawn for the current commit is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// The commit and everything after it has been drawn, so only the lines of history continue.
+    Padding,
+
+    /// The previous commit wasn't finished, so an ellipsis shows that part of the graph is missing.
+    Skip,
+
+    /// A line making room for the edges of an octopus merge, before the commit itself.
+    PreCommit,
+    Commit,
+
+    /// The line with the edges from a merge to each of its parents.
+    PostMerge,
+
+    /// A line moving the lines of history to the left, to where they are for the next commit.
+    Collapsing,
+}
+
+/// A line of history, ending in the commit it's waiting for.
+#[derive(Clone, Debug)]
+struct Column {
+    commit: objects::Name,
+
+    /// The index of its color, which is past the end of the colors if the graph isn't colored.
+    color: usize,
+}
+
+/// A line of the graph being drawn, along with the number of columns it takes up on the screen.
+struct Line {
+    text: String,
+    width: usize,
+}
+
+impl Line {
+    fn new() -> Line {
+        Line { text: String::new(), width: 0 }
+    }
+
+    fn push(&mut self, chr: char) {
+        self.text.push(chr);
+        self.width += 1;
+    }
+
+    fn push_repeated(&mut self, chr: char, count: usize) {
+        for _ in 0..count {
+            self.push(chr);
+        }
+    }
+}
+
+/// The characters drawn from a merge to its parents, starting from its merge layout.
+const MERGE_CHARS: [char; 3] = ['/', '|', '\\'];
+
+pub struct Graph {
+    /// The commit being drawn, and the parents that are drawn for it, which are the ones that are
+    /// also shown.
+    commit: Option<objects::Name>,
+    parents: Vec<objects::Name>,
+
+    /// The number of columns all the lines for the commit take up, so that the text next to them
+    /// lines up.
+    width: usize,
+
+    /// The number of `PreCommit` lines drawn so far for an octopus merge.
+    expansion_row: usize,
+    state: State,
+    prev_state: State,
+
+    /// The column the commit is in, and the one the previous commit was in.
+    commit_index: usize,
+    prev_commit_index: usize,
+
+    /// Whether the edge to the first parent of a merge goes straight down, as 1, or to the left,
+    /// as 0.
+    merge_layout: isize,
+
+    /// The number of columns a merge adds, to the right of itself, and the same for the previous
+    /// commit.
+    edges_added: isize,
+    prev_edges_added: isize,
+
+    /// The lines of history before the commit, and the ones after it.
+    columns: Vec<Column>,
+    new_columns: Vec<Column>,
+
+    /// Where each screen column of the current line ends up, as an index into `new_columns`, or -1
+    /// if it's empty. Only the first `mapping_size` entries are in use.
+    mapping: Vec<isize>,
+    old_mapping: Vec<isize>,
+    mapping_size: usize,
+
+    /// The escape sequences of the colors the columns are drawn in, if the graph is colored.
+    colors: Vec<String>,
+    default_color: usize,
+}
+
+impl Graph {
+    /// Start drawing a graph, using the given escape sequences for the columns in turn, or no
+    /// colors at all if there aren't any.
+    pub fn new(colors: Vec<String>) -> Graph {
+        // The color is incremented before the first commit, so that it starts with the first.
+        let default_color = colors.len().saturating_sub(1);
+        Graph {
+            commit: None,
+            parents: Vec::new(),
+            width: 0,
+            expansion_row: 0,
+            state: State::Padding,
+            prev_state: State::Padding,
+            commit_index: 0,
+            prev_commit_index: 0,
+            merge_layout: 0,
+            edges_added: 0,
+            prev_edges_added: 0,
+            columns: Vec::new(),
+            new_columns: Vec::new(),
+            mapping: Vec::new(),
+            old_mapping: Vec::new(),
+            mapping_size: 0,
+            colors: colors,
+            default_color: default_color,
+        }
+    }
+
+    fn write_column(&self, line: &mut Line, color: usize, chr: char) {
+        match self.colors.get(color) {
+            Some(escape) => {
+                line.text.push_str(escape);
+                line.push(chr);
+                line.text.push_str(color::RESET);
+            },
+            None => line.push(chr),
+        }
+    }
+
+    fn update_state(&mut self, state: State) {
+        self.prev_state = self.state;
+        self.state = state;
+    }
+
+    fn increment_column_color(&mut self) {
+        if !self.colors.is_empty() {
+            self.default_color = (self.default_color + 1) % self.colors.len();
+        }
+    }
+
+    /// The color of a new column for a commit, which is the color of the column it's already in,
+    /// if there is one.
+    fn find_commit_color(&self, commit: &objects::Name) -> usize {
+        match self.columns.iter().find(|column| column.commit == *commit) {
+            Some(column) => column.color,
+            None if self.colors.is_empty() => 0,
+            None => self.default_color,
+        }
+    }
+
+    fn find_new_column(&self, commit: &objects::Name) -> Option<usize> {
+        self.new_columns.iter().position(|column| column.commit == *commit)
+    }
+
+    /// Add a commit to the columns for the next commit, unless it's already in them, and record
+    /// where the edge leading to it is drawn. For the first parent of a merge, `index` is the
+    /// column of the merge, which decides how the merge's edges are laid out.
+    fn insert_into_new_columns(&mut self, commit: objects::Name, index: Option<usize>) {
+        let i = match self.find_new_column(&commit) {
+            Some(i) => i,
+            None => {
+                let color = self.find_commit_color(&commit);
+                self.new_columns.push(Column { commit: commit, color: color });
+                self.new_columns.len() - 1
+            },
+        };
+
+        let mapping_index;
+        let is_first_parent_of_merge = self.parents.len() > 1 && self.merge_layout == -1;
+        if let (Some(index), true) = (index, is_first_parent_of_merge) {
+            // The edge to the first parent of a merge goes to the left if the parent is already
+            // in a column to the left of the merge, and straight down otherwise.
+            let distance = index as isize - i as isize;
+            let shift = if distance > 1 { 2 * distance - 3 } else { 1 };
+            self.merge_layout = if distance > 0 { 0 } else { 1 };
+            self.edges_added = self.parents.len() as isize + self.merge_layout - 2;
+            mapping_index = self.width as isize + (self.merge_layout - 1) * shift;
+            self.width += 2 * self.merge_layout as usize;
+        } else if self.edges_added > 0 && self.width >= 2 &&
+            i as isize == self.mapping[self.width - 2] {
+            // The merge added columns, but this commit is in the last column that was already
+            // there, so the edges join right away.
+            mapping_index = self.width as isize - 2;
+            self.edges_added = -1;
+        } else {
+            mapping_index = self.width as isize;
+            self.width += 2;
+        }
+
+        self.mapping[mapping_index as usize] = i as isize;
+    }
+
+    /// Work out the columns after the current commit, and where each line of history goes to get
+    /// there.
+    fn update_columns(&mut self) {
+        mem::swap(&mut self.columns, &mut self.new_columns);
+        self.new_columns.clear();
+
+        let max_new_columns = self.columns.len() + self.parents.len();
+        if self.mapping.len() < 2 * max_new_columns {
+            self.mapping.resize(2 * max_new_columns, -1);
+            self.old_mapping.resize(2 * max_new_columns, -1);
+        }
+        self.mapping_size = 2 * max_new_columns;
+        for i in 0..self.mapping_size {
+            self.mapping[i] = -1;
+        }
+
+        self.width = 0;
+        self.prev_edges_added = self.edges_added;
+        self.edges_added = 0;
+
+        // The commit takes the place of its own column, if it has one. Otherwise, it's added in a
+        // new column on the right.
+        let commit = self.commit.clone().unwrap();
+        let mut seen_this = false;
+        let mut is_commit_in_columns = true;
+        for i in 0..(self.columns.len() + 1) {
+            let column_commit = if i == self.columns.len() {
+                if seen_this {
+                    break;
+                }
+                is_commit_in_columns = false;
+                commit.clone()
+            } else {
+                self.columns[i].commit.clone()
+            };
+
+            if column_commit != commit {
+                self.insert_into_new_columns(column_commit, None);
+                continue;
+            }
+
+            seen_this = true;
+            self.commit_index = i;
+            self.merge_layout = -1;
+            for parent in self.parents.clone() {
+                // Merges, and commits starting a new line of history, start a new color.
+                if self.parents.len() > 1 || !is_commit_in_columns {
+                    self.increment_column_color();
+                }
+                self.insert_into_new_columns(parent, Some(i));
+            }
+
+            // The commit always takes up a column, even if it has no parents.
+            if self.parents.is_empty() {
+                self.width += 2;
+            }
+        }
+
+        while self.mapping_size > 1 && self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+    }
+
+    fn num_dashed_parents(&self) -> isize {
+        self.parents.len() as isize + self.merge_layout - 3
+    }
+
+    /// Whether there's room to make for the edges of an octopus merge, because there are lines of
+    /// history to its right.
+    fn needs_pre_commit_line(&self) -> bool {
+        self.parents.len() >= 3 && self.commit_index + 1 < self.columns.len() &&
+            (self.expansion_row as isize) < self.num_dashed_parents() * 2
+    }
+
+    /// Move on to drawing a commit, with the given parents, which are the ones that are shown.
+    pub fn update(&mut self, commit: objects::Name, parents: Vec<objects::Name>) {
+        self.commit = Some(commit);
+        self.parents = parents;
+        self.prev_commit_index = self.commit_index;
+        self.update_columns();
+        self.expansion_row = 0;
+
+        // The previous commit may not have been finished, if it was cut short.
+        self.state = if self.state != State::Padding {
+            State::Skip
+        } else if self.needs_pre_commit_line() {
+            State::PreCommit
+        } else {
+            State::Commit
+        };
+    }
+
+    /// Whether every line of history is where it needs to be for the next commit, allowing for a
+    /// `/` moving it there on the next line.
+    fn is_mapping_correct(&self) -> bool {
+        (0..self.mapping_size).all(|i| {
+            let target = self.mapping[i];
+            target < 0 || target == (i / 2) as isize
+        })
+    }
+
+    /// Pad the line so that all the lines for a commit are the same width.
+    fn pad_horizontally(&self, line: &mut Line) {
+        if line.width < self.width {
+            let padding = self.width - line.width;
+            line.push_repeated(' ', padding);
+        }
+    }
+
+    fn output_padding_line(&self, line: &mut Line) {
+        for column in self.new_columns.iter() {
+            self.write_column(line, column.color, '|');
+            line.push(' ');
+        }
+    }
+
+    fn output_skip_line(&mut self, line: &mut Line) {
+        line.text.push_str("...");
+        line.width += 3;
+        let state = if self.needs_pre_commit_line() { State::PreCommit } else { State::Commit };
+        self.update_state(state);
+    }
+
+    fn output_pre_commit_line(&mut self, line: &mut Line) {
+        let commit = self.commit.clone().unwrap();
+        let mut seen_this = false;
+        for (i, column) in self.columns.iter().enumerate() {
+            if column.commit == commit {
+                seen_this = true;
+                self.write_column(line, column.color, '|');
+                line.push_repeated(' ', self.expansion_row);
+            } else if seen_this && self.expansion_row == 0 {
+                // Lines to the right of the previous merge were drawn as `\` after it, so they
+                // continue that way.
+                if self.prev_state == State::PostMerge && self.prev_commit_index < i {
+                    self.write_column(line, column.color, '\\');
+                } else {
+                    self.write_column(line, column.color, '|');
+                }
+            } else if seen_this {
+                self.write_column(line, column.color, '\\');
+            } else {
+                self.write_column(line, column.color, '|');
+            }
+            line.push(' ');
+        }
+
+        self.expansion_row += 1;
+        if !self.needs_pre_commit_line() {
+            self.update_state(State::Commit);
+        }
+    }
+
+    /// Draw the dashes from an octopus merge towards the columns of its parents past the second.
+    fn draw_octopus_merge(&self, line: &mut Line) {
+        let dashed_parents = self.num_dashed_parents().max(0) as usize;
+        for i in 0..dashed_parents {
+            let j = self.mapping[(self.commit_index + i + 2) * 2] as usize;
+            let color = self.new_columns[j].color;
+            self.write_column(line, color, '-');
+            self.write_column(line, color, if i == dashed_parents - 1 { '.' } else { '-' });
+        }
+    }
+
+    fn output_commit_line(&mut self, line: &mut Line) {
+        let commit = self.commit.clone().unwrap();
+        let mut seen_this = false;
+        for i in 0..(self.columns.len() + 1) {
+            if i == self.columns.len() && seen_this {
+                break;
+            }
+
+            if i == self.columns.len() || self.columns[i].commit == commit {
+                seen_this = true;
+                line.push('*');
+                if self.parents.len() > 2 {
+                    self.draw_octopus_merge(line);
+                }
+                line.push(' ');
+                continue;
+            }
+
+            let color = self.columns[i].color;
+            if seen_this && self.edges_added > 1 {
+                self.write_column(line, color, '\\');
+            } else if seen_this && self.edges_added == 1 {
+                // After a merge whose lines to the right were drawn as `\`, they continue that way.
+                if self.prev_state == State::PostMerge && self.prev_edges_added > 0 &&
+                    self.prev_commit_index < i {
+                    self.write_column(line, color, '\\');
+                } else {
+                    self.write_column(line, color, '|');
+                }
+            } else if self.prev_state == State::Collapsing &&
+                self.old_mapping[2 * i + 1] == i as isize &&
+                self.mapping[2 * i] < i as isize {
+                self.write_column(line, color, '/');
+            } else {
+                self.write_column(line, color, '|');
+            }
+            line.push(' ');
+        }
+
+        let state = if self.parents.len() > 1 {
+            State::PostMerge
+        } else if self.is_mapping_correct() {
+            State::Padding
+        } else {
+            State::Collapsing
+        };
+        self.update_state(state);
+    }
+
+    fn output_post_merge_line(&mut self, line: &mut Line) {
+        let commit = self.commit.clone().unwrap();
+        let mut seen_this = false;
+        let mut parent_color = None;
+        for i in 0..(self.columns.len() + 1) {
+            let column = self.columns.get(i);
+            if column.is_none() && seen_this {
+                break;
+            }
+
+            if column.map(|column| column.commit == commit).unwrap_or(true) {
+                // Draw an edge to the column of each parent.
+                seen_this = true;
+                let mut merge_char = self.merge_layout as usize;
+                for (j, parent) in self.parents.iter().enumerate() {
+                    let parent_column = self.find_new_column(parent).unwrap();
+                    let color = self.new_columns[parent_column].color;
+                    self.write_column(line, color, MERGE_CHARS[merge_char]);
+                    if merge_char < 2 {
+                        merge_char += 1;
+                    } else if self.edges_added > 0 || j < self.parents.len() - 1 {
+                        line.push(' ');
+                    }
+                }
+                if self.edges_added == 0 {
+                    line.push(' ');
+                }
+            } else if seen_this {
+                let color = column.unwrap().color;
+                if self.edges_added > 0 {
+                    self.write_column(line, color, '\\');
+                } else {
+                    self.write_column(line, color, '|');
+                }
+                line.push(' ');
+            } else {
+                self.write_column(line, column.unwrap().color, '|');
+                if self.merge_layout != 0 || i + 1 != self.commit_index {
+                    match parent_color {
+                        Some(color) => self.write_column(line, color, '_'),
+                        None => line.push(' '),
+                    }
+                }
+            }
+
+            if let Some(column) = column {
+                if column.commit == self.parents[0] {
+                    parent_color = Some(column.color);
+                }
+            }
+        }
+
+        let state = if self.is_mapping_correct() { State::Padding } else { State::Collapsing };
+        self.update_state(state);
+    }
+
+    fn output_collapsing_line(&mut self, line: &mut Line) {
+        let mut used_horizontal = false;
+        let mut horizontal_edge = -1;
+        let mut horizontal_edge_target = -1;
+
+        mem::swap(&mut self.mapping, &mut self.old_mapping);
+        for i in 0..self.mapping_size {
+            self.mapping[i] = -1;
+        }
+
+        // Each line of history moves one place to the left, towards its target, or joins the line
+        // to its left if it's going to the same place. Only one can move horizontally at a time.
+        for i in 0..self.mapping_size {
+            let target = self.old_mapping[i];
+            if target < 0 {
+                continue;
+            }
+
+            let i = i as isize;
+            let edge_start = if target * 2 == i {
+                self.mapping[i as usize] = target;
+                None
+            } else if self.mapping[i as usize - 1] < 0 {
+                self.mapping[i as usize - 1] = target;
+                Some(i)
+            } else if self.mapping[i as usize - 1] == target {
+                None
+            } else {
+                // Cross over the line to the left, which isn't going to the same place.
+                self.mapping[i as usize - 2] = target;
+                Some(i - 1)
+            };
+
+            if let Some(edge) = edge_start {
+                if horizontal_edge == -1 {
+                    horizontal_edge = edge;
+                    horizontal_edge_target = target;
+                    let mut j = target * 2 + 3;
+                    while j < i - 2 {
+                        self.mapping[j as usize] = target;
+                        j += 2;
+                    }
+                }
+            }
+        }
+
+        let size = self.mapping_size;
+        self.old_mapping[..size].copy_from_slice(&self.mapping[..size]);
+        if self.mapping[size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+
+        for i in 0..self.mapping_size {
+            let target = self.mapping[i];
+            if target < 0 {
+                line.push(' ');
+                continue;
+            }
+
+            let color = self.new_columns[target as usize].color;
+            let i = i as isize;
+            if target * 2 == i {
+                self.write_column(line, color, '|');
+            } else if target == horizontal_edge_target && i != horizontal_edge - 1 {
+                // Only the first part of the horizontal edge continues onto the next line.
+                if i != target * 2 + 3 {
+                    self.mapping[i as usize] = -1;
+                }
+                used_horizontal = true;
+                self.write_column(line, color, '_');
+            } else {
+                if used_horizontal && i < horizontal_edge {
+                    self.mapping[i as usize] = -1;
+                }
+                self.write_column(line, color, '/');
+            }
+        }
+
+        if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        }
+    }
+
+    /// Draw the next line of the graph, returning it and whether it's the commit's own line.
+    fn next_line(&mut self) -> (String, bool) {
+        let mut line = Line::new();
+        let state = self.state;
+        match state {
+            State::Padding => self.output_padding_line(&mut line),
+            State::Skip => self.output_skip_line(&mut line),
+            State::PreCommit => self.output_pre_commit_line(&mut line),
+            State::Commit => self.output_commit_line(&mut line),
+            State::PostMerge => self.output_post_merge_line(&mut line),
+            State::Collapsing => self.output_collapsing_line(&mut line),
+        }
+        self.pad_horizontally(&mut line);
+        (line.text, state == State::Commit)
+    }
+
+    /// Whether everything for the current commit has been drawn.
+    pub fn is_commit_finished(&self) -> bool {
+        self.state == State::Padding
+    }
+
+    /// A line that only continues the lines of history, such as next to a blank line between
+    /// commits, without moving on to the next line of the graph.
+    pub fn padding_line(&mut self) -> String {
+        if self.state != State::Commit {
+            return self.next_line().0;
+        }
+
+        let mut line = Line::new();
+        let commit = self.commit.clone().unwrap();
+        for column in self.columns.iter() {
+            self.write_column(&mut line, column.color, '|');
+            if column.commit == commit && self.parents.len() > 2 {
+                line.push_repeated(' ', (self.parents.len() - 2) * 2);
+            } else {
+                line.push(' ');
+            }
+        }
+        self.pad_horizontally(&mut line);
+        self.prev_state = State::Padding;
+        line.text
+    }
+
+    /// The lines leading up to the current commit, each ending in a newline, followed by the start
+    /// of the commit's own line, which the first line of its message goes after.
+    pub fn commit_lines(&mut self) -> String {
+        if self.is_commit_finished() {
+            return self.padding_line();
+        }
+
+        let mut output = String::new();
+        while !self.is_commit_finished() {
+            let (line, is_commit_line) = self.next_line();
+            output.push_str(&line);
+            if is_commit_line {
+                break;
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    /// The lines of the graph still to be drawn for the current commit, separated by newlines.
+    fn remainder(&mut self) -> String {
+        let mut output = String::new();
+        while !self.is_commit_finished() {
+            output.push_str(&self.next_line().0);
+            if !self.is_commit_finished() {
+                output.push('\n');
+            }
+        }
+        output
+    }
+
+    /// Draw the graph next to the message of the current commit, which goes after the lines
+    /// returned by `commit_lines`. If the message is shorter than the graph for the commit, the
+    /// rest of the graph follows it.
+    pub fn message_lines(&mut self, message: &str) -> String {
+        let mut output = String::new();
+        let mut rest = message;
+        while let Some(end) = rest.find('\n') {
+            output.push_str(&rest[..end + 1]);
+            rest = &rest[end + 1..];
+            if rest.is_empty() {
+                break;
+            }
+            output.push_str(&self.next_line().0);
+        }
+        output.push_str(rest);
+
+        if !self.is_commit_finished() {
+            let terminated = message.ends_with('\n');
+            if !terminated {
+                output.push('\n');
+            }
+            output.push_str(&self.remainder());
+            if terminated {
+                output.push('\n');
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use objects::Name;
+
+    /// Draw the graph of a history, given as each commit followed by its parents, with each commit
+    /// as a one-line message.
+    fn draw(history: &[&str]) -> String {
+        let mut graph = Graph::new(Vec::new());
+        let mut output = String::new();
+        for commit in history.iter() {
+            let mut names = commit.split(' ').map(|name| Name(name.to_string()));
+            let name = names.next().unwrap();
+            graph.update(name.clone(), names.collect());
+            output.push_str(&graph.commit_lines());
+            output.push_str(&graph.message_lines(&name.0));
+            output.push('\n');
+        }
+        output
+    }
+
+    #[test]
+    fn draws_merges() {
+        assert_eq!("*   m\n\
+                    |\\  \n\
+                    | * s\n\
+                    * | c\n\
+                    |/  \n\
+                    * a\n",
+                   draw(&["m c s", "s a", "c a", "a"]));
+    }
+
+    #[test]
+    fn draws_octopus_merges() {
+        assert_eq!("*-.   m\n\
+                    |\\ \\  \n\
+                    | | * y\n\
+                    | * | x\n\
+                    | |/  \n\
+                    * / c\n\
+                    |/  \n\
+                    * a\n",
+                   draw(&["m c x y", "y a", "x a", "c a", "a"]));
+    }
+
+    #[test]
+    fn colors_columns() {
+        let colors = vec!["<r>".to_string(), "<g>".to_string()];
+        let mut graph = Graph::new(colors);
+        graph.update(Name("m".to_string()), vec![Name("c".to_string()), Name("s".to_string())]);
+        assert_eq!("*   ", graph.commit_lines());
+        assert_eq!("m\n<r>|\x1b[m<g>\\\x1b[m  \n", graph.message_lines("m\n"));
+    }
+}
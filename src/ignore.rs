@@ -0,0 +1,683 @@
+//! Provides functionality for deciding which files in the working directory are ignored, based on
+//! the exclude patterns found in `.gitignore` files, `.git/info/exclude` and the file named by the
+//! `core.excludesFile` configuration. See gitignore(5) for the full specification.
+//!
+//! Patterns are matched using the same rules as git's `wildmatch`, which is implemented here as
+//! well, since the glob semantics used by git differ subtly from those of the usual `fnmatch`.
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use config;
+use repository;
+
+#[derive(Debug)]
+pub enum Error {
+    IOError(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::IOError(ref err) => write!(f, "IO error: {}", err),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::IOError(ref err) => err.description(),
+        }
+    }
+
+    fn source(&self) -> Option<&(StdError + 'static)> {
+        match *self {
+            Error::IOError(ref err) => Some(err),
+        }
+    }
+}
+
+/// Match case-insensitively, as when `core.ignoreCase` is set.
+pub const WM_CASEFOLD: u32 = 1;
+
+/// Treat `/` specially: wildcards don't match it, and `**` matches across directories.
+pub const WM_PATHNAME: u32 = 2;
+
+#[derive(Debug, PartialEq, Eq)]
+enum WildResult {
+    Match,
+    NoMatch,
+    AbortAll,
+    AbortToStarStar,
+}
+
+/// Reads the pattern byte at the given position, treating the end of the pattern as a NUL byte,
+/// which keeps the port of git's pointer-based matcher straightforward.
+fn byte_at(bytes: &[u8], i: usize) -> u8 {
+    if i < bytes.len() { bytes[i] } else { 0 }
+}
+
+fn fold(ch: u8, flags: u32) -> u8 {
+    if flags & WM_CASEFOLD != 0 { ch.to_ascii_lowercase() } else { ch }
+}
+
+fn matches_class(class: &[u8], ch: u8) -> Option<bool> {
+    let ch = ch as char;
+    match class {
+        b"alnum" => Some(ch.is_ascii_alphanumeric()),
+        b"alpha" => Some(ch.is_ascii_alphabetic()),
+        b"blank" => Some(ch == ' ' || ch == '\t'),
+        b"cntrl" => Some(ch.is_ascii_control()),
+        b"digit" => Some(ch.is_ascii_digit()),
+        b"graph" => Some(ch.is_ascii_graphic()),
+        b"lower" => Some(ch.is_ascii_lowercase()),
+        b"print" => Some(ch.is_ascii_graphic() || ch == ' '),
+        b"punct" => Some(ch.is_ascii_punctuation()),
+        b"space" => Some(ch.is_ascii_whitespace()),
+        b"upper" => Some(ch.is_ascii_uppercase()),
+        b"xdigit" => Some(ch.is_ascii_hexdigit()),
+        _ => None,
+    }
+}
+
+/// A port of `dowild` from git's `wildmatch.c`. The structure is kept close to the original so
+/// the two can be compared easily.
+fn dowild(p: &[u8], text: &[u8], flags: u32) -> WildResult {
+    let pathname = flags & WM_PATHNAME != 0;
+    let mut pi = 0;
+    let mut ti = 0;
+
+    while pi < p.len() {
+        let mut p_ch = p[pi];
+        let mut t_ch = byte_at(text, ti);
+        if ti >= text.len() && p_ch != b'*' {
+            return WildResult::AbortAll;
+        }
+        t_ch = fold(t_ch, flags);
+        p_ch = fold(p_ch, flags);
+
+        match p_ch {
+            b'\\' => {
+                // Literal match with the following character.
+                pi += 1;
+                if fold(byte_at(p, pi), flags) != t_ch {
+                    return WildResult::NoMatch;
+                }
+            },
+            b'?' => {
+                if pathname && t_ch == b'/' {
+                    return WildResult::NoMatch;
+                }
+            },
+            b'*' => {
+                let match_slash;
+                pi += 1;
+                if byte_at(p, pi) == b'*' {
+                    let prev_p = pi - 1;
+                    while byte_at(p, pi) == b'*' {
+                        pi += 1;
+                    }
+
+                    if !pathname {
+                        // Without WM_PATHNAME, "*" and "**" are the same.
+                        match_slash = true;
+                    } else if (prev_p == 0 || p[prev_p - 1] == b'/') &&
+                            (pi == p.len() || p[pi] == b'/' ||
+                             (p[pi] == b'\\' && byte_at(p, pi + 1) == b'/')) {
+                        // Either "**/" at the beginning of a component, or a trailing "/**".
+                        // "**/" can also match nothing at all, so "a/**/b" matches "a/b".
+                        if byte_at(p, pi) == b'/' &&
+                                dowild(&p[pi + 1..], &text[ti..], flags) == WildResult::Match {
+                            return WildResult::Match;
+                        }
+                        match_slash = true;
+                    } else {
+                        // A "**" that isn't a whole path component acts like a single "*".
+                        match_slash = false;
+                    }
+                } else {
+                    match_slash = !pathname;
+                }
+
+                if pi == p.len() {
+                    // A trailing star matches the rest of the text, as long as it doesn't need to
+                    // cross a directory boundary when it isn't allowed to.
+                    if !match_slash && text[ti..].contains(&b'/') {
+                        return WildResult::AbortToStarStar;
+                    }
+                    return WildResult::Match;
+                } else if !match_slash && p[pi] == b'/' {
+                    // A single star followed by a slash only has to find the next slash in the
+                    // text. The slashes themselves are consumed at the bottom of the loop.
+                    match text[ti..].iter().position(|&c| c == b'/') {
+                        Some(offset) => ti += offset,
+                        None => return WildResult::NoMatch,
+                    }
+                } else {
+                    while ti < text.len() {
+                        let matched = dowild(&p[pi..], &text[ti..], flags);
+                        if matched != WildResult::NoMatch {
+                            if !match_slash || matched != WildResult::AbortToStarStar {
+                                return matched;
+                            }
+                        } else if !match_slash && text[ti] == b'/' {
+                            return WildResult::AbortToStarStar;
+                        }
+                        ti += 1;
+                    }
+                    return WildResult::AbortAll;
+                }
+            },
+            b'[' => {
+                pi += 1;
+                let mut p_ch = byte_at(p, pi);
+                if p_ch == b'^' {
+                    p_ch = b'!';
+                }
+                let negated = p_ch == b'!';
+                if negated {
+                    pi += 1;
+                    p_ch = byte_at(p, pi);
+                }
+
+                let mut prev_ch = 0;
+                let mut matched = false;
+                loop {
+                    if p_ch == 0 {
+                        return WildResult::AbortAll;
+                    }
+
+                    if p_ch == b'\\' {
+                        pi += 1;
+                        p_ch = byte_at(p, pi);
+                        if p_ch == 0 {
+                            return WildResult::AbortAll;
+                        }
+                        if t_ch == p_ch {
+                            matched = true;
+                        }
+                    } else if p_ch == b'-' && prev_ch != 0 && byte_at(p, pi + 1) != 0 &&
+                            byte_at(p, pi + 1) != b']' {
+                        pi += 1;
+                        p_ch = byte_at(p, pi);
+                        if p_ch == b'\\' {
+                            pi += 1;
+                            p_ch = byte_at(p, pi);
+                            if p_ch == 0 {
+                                return WildResult::AbortAll;
+                            }
+                        }
+                        if t_ch <= p_ch && t_ch >= prev_ch {
+                            matched = true;
+                        } else if flags & WM_CASEFOLD != 0 && t_ch.is_ascii_lowercase() {
+                            let upper = t_ch.to_ascii_uppercase();
+                            if upper <= p_ch && upper >= prev_ch {
+                                matched = true;
+                            }
+                        }
+                        // This makes the previous character be reset below.
+                        p_ch = 0;
+                    } else if p_ch == b'[' && byte_at(p, pi + 1) == b':' {
+                        let start = pi + 2;
+                        let mut end = start;
+                        while byte_at(p, end) != 0 && byte_at(p, end) != b']' {
+                            end += 1;
+                        }
+                        if byte_at(p, end) == 0 {
+                            return WildResult::AbortAll;
+                        }
+
+                        if end == start || p[end - 1] != b':' {
+                            // Didn't find ":]", so treat the "[" like a normal character.
+                            p_ch = b'[';
+                            if t_ch == p_ch {
+                                matched = true;
+                            }
+                        } else {
+                            match matches_class(&p[start..end - 1], t_ch) {
+                                Some(true) => matched = true,
+                                Some(false) => {},
+                                None => return WildResult::AbortAll,
+                            }
+                            pi = end;
+                            p_ch = 0;
+                        }
+                    } else if t_ch == p_ch {
+                        matched = true;
+                    }
+
+                    prev_ch = p_ch;
+                    pi += 1;
+                    p_ch = byte_at(p, pi);
+                    if p_ch == b']' {
+                        break;
+                    }
+                }
+
+                if matched == negated || (pathname && t_ch == b'/') {
+                    return WildResult::NoMatch;
+                }
+            },
+            _ => {
+                if t_ch != p_ch {
+                    return WildResult::NoMatch;
+                }
+            },
+        }
+
+        pi += 1;
+        ti += 1;
+    }
+
+    if ti < text.len() {
+        WildResult::NoMatch
+    } else {
+        WildResult::Match
+    }
+}
+
+/// Match the text against a glob pattern, following the semantics of git's `wildmatch`. The flags
+/// are a combination of `WM_CASEFOLD` and `WM_PATHNAME`.
+pub fn wildmatch(pattern: &str, text: &str, flags: u32) -> bool {
+    dowild(pattern.as_bytes(), text.as_bytes(), flags) == WildResult::Match
+}
+
+/// Where a pattern came from, so that it can be reported back to the user, as in
+/// `check-ignore -v`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub file: PathBuf,
+    pub line_number: usize,
+}
+
+/// A single exclude pattern, as it appears on one line of an ignore file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    /// The line as it appeared in the file, minus any trailing whitespace.
+    pub original: String,
+
+    /// The source of the pattern, if it came from a file.
+    pub source: Option<Source>,
+
+    /// The directory, relative to the root of the repository, containing the file the pattern was
+    /// read from. Patterns containing a slash are matched relative to this directory. Empty for
+    /// the root of the repository, otherwise it ends with a slash.
+    base: String,
+
+    /// The glob itself, without any leading `!` or trailing `/`.
+    glob: String,
+
+    /// Set for patterns starting with `!`, which re-include previously excluded paths.
+    pub negated: bool,
+
+    /// Set for patterns ending in `/`, which only match directories.
+    pub directory_only: bool,
+
+    /// Set for patterns with no slash in them, which match the file name at any depth.
+    basename_only: bool,
+}
+
+impl Pattern {
+    /// Parse one line of an ignore file. Blank lines and comments don't contain a pattern, so
+    /// `None` is returned for them.
+    pub fn parse(line: &str, base: &str, source: Option<Source>) -> Option<Pattern> {
+        let original = trim_trailing_spaces(line);
+        if original.is_empty() || original.starts_with('#') {
+            return None;
+        }
+
+        let mut glob = original.as_str();
+        let negated = glob.starts_with('!');
+        if negated {
+            glob = &glob[1..];
+        }
+
+        let directory_only = glob.ends_with('/');
+        if directory_only {
+            glob = &glob[..glob.len() - 1];
+        }
+
+        if glob.is_empty() {
+            return None;
+        }
+
+        let basename_only = !glob.contains('/');
+        let glob = if glob.starts_with('/') { &glob[1..] } else { glob };
+
+        Some(Pattern {
+            original: original.clone(),
+            source: source,
+            base: base.to_string(),
+            glob: glob.to_string(),
+            negated: negated,
+            directory_only: directory_only,
+            basename_only: basename_only,
+        })
+    }
+
+    /// Check whether the given path, relative to the root of the repository, matches this
+    /// pattern. The negation of the pattern is not taken into account.
+    pub fn matches(&self, path: &str, is_dir: bool, flags: u32) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+
+        if self.basename_only {
+            let basename = match path.rfind('/') {
+                Some(index) => &path[index + 1..],
+                None => path,
+            };
+            return wildmatch(&self.glob, basename, flags & WM_CASEFOLD);
+        }
+
+        if !path.starts_with(&self.base) {
+            return false;
+        }
+        wildmatch(&self.glob, &path[self.base.len()..], flags | WM_PATHNAME)
+    }
+}
+
+/// Remove trailing spaces from the line, unless they are escaped with a backslash.
+fn trim_trailing_spaces(line: &str) -> String {
+    let line = line.trim_right_matches(|c| c == '\n' || c == '\r');
+    let mut trimmed = line.trim_right_matches(' ').to_string();
+    if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+        trimmed.push(' ');
+    }
+    trimmed
+}
+
+/// An ordered list of patterns, typically read from a single file. Later patterns take
+/// precedence over earlier ones.
+#[derive(Debug, Clone, Default)]
+pub struct PatternList {
+    pub patterns: Vec<Pattern>,
+}
+
+impl PatternList {
+    pub fn new() -> PatternList {
+        PatternList { patterns: Vec::new() }
+    }
+
+    pub fn from_string(contents: &str, base: &str, file: Option<&Path>) -> PatternList {
+        let patterns = contents
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let source = file.map(|f| Source { file: f.to_path_buf(), line_number: i + 1 });
+                Pattern::parse(line, base, source)
+            })
+            .collect();
+        PatternList { patterns: patterns }
+    }
+
+    /// Read the patterns from the given file. A missing file is the same as an empty one, since
+    /// none of the ignore files are required to exist.
+    pub fn from_file(file: &Path, base: &str) -> Result<PatternList, Error> {
+        let mut contents = String::new();
+        match File::open(file) {
+            Ok(mut f) => {
+                try!(f.read_to_string(&mut contents).map_err(|e| Error::IOError(e)));
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(Error::IOError(e)),
+        }
+        Ok(PatternList::from_string(&contents, base, Some(file)))
+    }
+
+    pub fn push(&mut self, pattern: Pattern) {
+        self.patterns.push(pattern);
+    }
+
+    /// Find the last pattern in the list matching the path, which is the one that decides whether
+    /// the path is excluded.
+    pub fn last_match(&self, path: &str, is_dir: bool, flags: u32) -> Option<&Pattern> {
+        self.patterns.iter().rev().find(|p| p.matches(path, is_dir, flags))
+    }
+}
+
+/// The full set of exclude patterns that apply to a working directory, in order of precedence:
+///
+/// - patterns given on the command line
+/// - `.gitignore` files, with those in deeper directories taking precedence
+/// - `.git/info/exclude`
+/// - the file named by `core.excludesFile`
+///
+/// The `.gitignore` files are read lazily, as the directories containing them are visited.
+pub struct Excludes {
+    command_line: PatternList,
+    per_directory: Option<String>,
+    directories: HashMap<String, PatternList>,
+    files: Vec<PatternList>,
+    flags: u32,
+}
+
+impl Excludes {
+    /// An empty set of excludes, under which no file is ignored.
+    pub fn new() -> Excludes {
+        Excludes {
+            command_line: PatternList::new(),
+            per_directory: None,
+            directories: HashMap::new(),
+            files: Vec::new(),
+            flags: 0,
+        }
+    }
+
+    /// The standard excludes used by git porcelain commands, as with `--exclude-standard`.
+    pub fn standard(config: &config::Config) -> Result<Excludes, Error> {
+        let mut excludes = Excludes::new();
+
+        if let Some(path) = excludes_file_path(config) {
+            try!(excludes.add_file(&path));
+        }
+
+        try!(excludes.add_file(&repository::git_path("info/exclude")));
+        excludes.set_per_directory(".gitignore");
+
+        if let Ok(Some(true)) = config.get_bool("core.ignorecase") {
+            excludes.flags |= WM_CASEFOLD;
+        }
+
+        Ok(excludes)
+    }
+
+    /// Add a pattern given on the command line. These take precedence over all other patterns.
+    pub fn add_pattern(&mut self, pattern: &str) {
+        if let Some(pattern) = Pattern::parse(pattern, "", None) {
+            self.command_line.push(pattern);
+        }
+    }
+
+    /// Add the patterns from an exclude file. Files added later take precedence over those added
+    /// earlier, but all of them have a lower precedence than the per-directory files.
+    pub fn add_file(&mut self, file: &Path) -> Result<(), Error> {
+        let list = try!(PatternList::from_file(file, ""));
+        self.files.push(list);
+        Ok(())
+    }
+
+    /// Read patterns from the file with the given name in every directory that is checked.
+    pub fn set_per_directory(&mut self, filename: &str) {
+        self.per_directory = Some(filename.to_string());
+        self.directories.clear();
+    }
+
+    fn load_directory(&mut self, dir: &str) -> Result<(), Error> {
+        if self.directories.contains_key(dir) {
+            return Ok(());
+        }
+
+        let list = match self.per_directory {
+            Some(ref filename) => {
+                let file = Path::new(dir).join(filename);
+                try!(PatternList::from_file(&file, dir))
+            },
+            None => PatternList::new(),
+        };
+        self.directories.insert(dir.to_string(), list);
+        Ok(())
+    }
+
+    /// Find the pattern that decides whether the path itself is excluded, without looking at the
+    /// directories containing it.
+    fn last_match_for(&mut self, path: &str, is_dir: bool) -> Result<Option<Pattern>, Error> {
+        let flags = self.flags;
+        if let Some(pattern) = self.command_line.last_match(path, is_dir, flags) {
+            return Ok(Some(pattern.clone()));
+        }
+
+        // Directory prefixes of the path, from the deepest to the root of the repository. Each
+        // one is written the way `Pattern::base` expects: empty or ending with a slash.
+        let mut dirs = vec![String::new()];
+        for (i, _) in path.match_indices('/') {
+            dirs.push(path[..i + 1].to_string());
+        }
+
+        for dir in dirs.iter().rev() {
+            try!(self.load_directory(dir));
+            if let Some(pattern) = self.directories[dir].last_match(path, is_dir, flags) {
+                return Ok(Some(pattern.clone()));
+            }
+        }
+
+        for list in self.files.iter().rev() {
+            if let Some(pattern) = list.last_match(path, is_dir, flags) {
+                return Ok(Some(pattern.clone()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Find the pattern that decides whether the path, relative to the root of the repository, is
+    /// excluded. A path inside an excluded directory is excluded by the pattern matching that
+    /// directory, and can't be re-included by a negated pattern, matching git's behavior. Returns
+    /// `None` if no pattern matches, and a negated pattern if the path is explicitly re-included.
+    pub fn matching_pattern(&mut self, path: &Path, is_dir: bool)
+            -> Result<Option<Pattern>, Error> {
+        let path = path.to_string_lossy().into_owned();
+
+        let mut prefix_end = 0;
+        while let Some(offset) = path[prefix_end..].find('/') {
+            let dir = &path[..prefix_end + offset];
+            match try!(self.last_match_for(dir, true)) {
+                Some(ref pattern) if !pattern.negated => return Ok(Some(pattern.clone())),
+                _ => {},
+            }
+            prefix_end += offset + 1;
+        }
+
+        self.last_match_for(&path, is_dir)
+    }
+
+    /// Check whether the path, relative to the root of the repository, is excluded.
+    pub fn is_excluded(&mut self, path: &Path, is_dir: bool) -> Result<bool, Error> {
+        let pattern = try!(self.matching_pattern(path, is_dir));
+        Ok(pattern.map(|p| !p.negated).unwrap_or(false))
+    }
+}
+
+/// The location of the user's exclude file, as given by `core.excludesFile`.
+pub fn excludes_file_path(config: &config::Config) -> Option<PathBuf> {
+    config
+        .get_path("core.excludesfile")
+        .unwrap_or(None)
+        .or_else(default_excludes_file)
+}
+
+/// The location of the exclude file when `core.excludesFile` is not set:
+/// `$XDG_CONFIG_HOME/git/ignore`, falling back to `~/.config/git/ignore`.
+fn default_excludes_file() -> Option<PathBuf> {
+    match ::std::env::var_os("XDG_CONFIG_HOME") {
+        Some(ref xdg) if !xdg.is_empty() => Some(PathBuf::from(xdg).join("git/ignore")),
+        _ => ::std::env::home_dir().map(|home| home.join(".config/git/ignore")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildmatch_follows_git_semantics() {
+        assert!(wildmatch("foo", "foo", 0));
+        assert!(!wildmatch("foo", "bar", 0));
+        assert!(wildmatch("*.o", "main.o", WM_PATHNAME));
+        assert!(!wildmatch("*.o", "dir/main.o", WM_PATHNAME));
+        assert!(wildmatch("*.o", "dir/main.o", 0));
+        assert!(wildmatch("f?o", "foo", 0));
+        assert!(!wildmatch("f?o", "f/o", WM_PATHNAME));
+        assert!(wildmatch("**/foo", "foo", WM_PATHNAME));
+        assert!(wildmatch("**/foo", "a/b/foo", WM_PATHNAME));
+        assert!(wildmatch("a/**/b", "a/b", WM_PATHNAME));
+        assert!(wildmatch("a/**/b", "a/x/y/b", WM_PATHNAME));
+        assert!(wildmatch("abc/**", "abc/x/y", WM_PATHNAME));
+        assert!(!wildmatch("abc/**", "abc", WM_PATHNAME));
+        assert!(wildmatch("a/*/c", "a/b/c", WM_PATHNAME));
+        assert!(!wildmatch("a/*/c", "a/b/x/c", WM_PATHNAME));
+        assert!(wildmatch("[a-c]at", "bat", 0));
+        assert!(!wildmatch("[!a-c]at", "bat", 0));
+        assert!(wildmatch("[^a-c]at", "rat", 0));
+        assert!(wildmatch("[[:digit:]]x", "1x", 0));
+        assert!(wildmatch("\\*", "*", 0));
+        assert!(!wildmatch("\\*", "a", 0));
+        assert!(wildmatch("FOO", "foo", WM_CASEFOLD));
+    }
+
+    #[test]
+    fn parses_patterns() {
+        assert_eq!(Pattern::parse("# comment", "", None), None);
+        assert_eq!(Pattern::parse("   ", "", None), None);
+
+        let pattern = Pattern::parse("!build/  ", "", None).unwrap();
+        assert!(pattern.negated);
+        assert!(pattern.directory_only);
+        assert_eq!(pattern.original, "!build/");
+
+        let pattern = Pattern::parse("trailing\\ ", "", None).unwrap();
+        assert!(pattern.matches("trailing ", false, 0));
+    }
+
+    #[test]
+    fn matches_patterns_relative_to_base() {
+        let basename = Pattern::parse("*.log", "sub/", None).unwrap();
+        assert!(basename.matches("sub/deep/debug.log", false, 0));
+
+        let anchored = Pattern::parse("/target", "sub/", None).unwrap();
+        assert!(anchored.matches("sub/target", true, 0));
+        assert!(!anchored.matches("sub/deep/target", true, 0));
+        assert!(!anchored.matches("target", true, 0));
+
+        let dir_only = Pattern::parse("out/", "", None).unwrap();
+        assert!(dir_only.matches("out", true, 0));
+        assert!(!dir_only.matches("out", false, 0));
+    }
+
+    #[test]
+    fn last_matching_pattern_wins() {
+        let mut excludes = Excludes::new();
+        excludes.add_pattern("*.log");
+        excludes.add_pattern("!important.log");
+
+        assert!(excludes.is_excluded(Path::new("debug.log"), false).unwrap());
+        assert!(!excludes.is_excluded(Path::new("important.log"), false).unwrap());
+        assert!(!excludes.is_excluded(Path::new("main.rs"), false).unwrap());
+    }
+
+    #[test]
+    fn paths_in_excluded_directories_are_excluded() {
+        let mut excludes = Excludes::new();
+        excludes.add_pattern("target/");
+        excludes.add_pattern("!*.rs");
+
+        assert!(excludes.is_excluded(Path::new("target/debug/main.rs"), false).unwrap());
+        assert!(!excludes.is_excluded(Path::new("src/main.rs"), false).unwrap());
+    }
+}
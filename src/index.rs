@@ -3,27 +3,58 @@
 
 use std::collections::HashSet;
 use std::error::Error as StdError;
+use std::ffi::OsStr;
 use std::fmt;
 use std::fs;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
-use std::iter::FromIterator;
-use std::path::PathBuf;
+use std::io;
+use std::io::{BufRead, Cursor, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 
-use byteorder::{NetworkEndian, ReadBytesExt};
-use walkdir::{DirEntry, WalkDir, WalkDirIterator};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use sha1::Sha1;
 
-#[derive(Debug, PartialEq, Eq)]
+use config;
+use ignore;
+use lockfile::LockFile;
+use objects;
+use repository;
+use untracked;
+
+#[derive(Debug)]
 pub enum Error {
+    /// The index file couldn't be read.
+    IOError(PathBuf, io::Error),
     InvalidIndex(String),
     InvalidEntry(String),
+
+    /// The untracked cache extension is present, but it can't be parsed.
+    InvalidUntrackedCache(io::Error),
+
+    /// The exclude patterns used to find untracked files couldn't be read.
+    ExcludeError(ignore::Error),
+
+    /// A directory in the working tree couldn't be read while looking for untracked files.
+    ScanError(PathBuf, io::Error),
+
+    /// The index file couldn't be locked or replaced.
+    WriteError(io::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Error::IOError(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
             Error::InvalidIndex(ref reason) => write!(f, "invalid index file: {}", reason),
             Error::InvalidEntry(ref reason) => write!(f, "invalid index entry: {}", reason),
+            Error::InvalidUntrackedCache(ref err) =>
+                write!(f, "invalid index file: invalid untracked cache: {}", err),
+            Error::ExcludeError(ref err) => write!(f, "unable to read excludes: {}", err),
+            Error::ScanError(ref path, ref err) =>
+                write!(f, "unable to read directory {}: {}", path.display(), err),
+            Error::WriteError(ref err) => write!(f, "unable to write index: {}", err),
         }
     }
 }
@@ -31,98 +62,564 @@ impl fmt::Display for Error {
 impl StdError for Error {
     fn description(&self) -> &str {
         match *self {
+            Error::IOError(_, ref err) => err.description(),
             Error::InvalidIndex(ref reason) => reason,
             Error::InvalidEntry(ref reason) => reason,
+            Error::InvalidUntrackedCache(_) => "invalid untracked cache",
+            Error::ExcludeError(ref err) => err.description(),
+            Error::ScanError(_, ref err) => err.description(),
+            Error::WriteError(ref err) => err.description(),
         }
     }
 
-    fn cause(&self) -> Option<&StdError> {
+    fn source(&self) -> Option<&(StdError + 'static)> {
         match *self {
-            Error::InvalidIndex(_) => None,
-            Error::InvalidEntry(_) => None,
+            Error::IOError(_, ref err) => Some(err),
+            Error::InvalidIndex(_) | Error::InvalidEntry(_) => None,
+            Error::InvalidUntrackedCache(ref err) => Some(err),
+            Error::ExcludeError(ref err) => Some(err),
+            Error::ScanError(_, ref err) => Some(err),
+            Error::WriteError(ref err) => Some(err),
         }
     }
 }
 
+/// The name of the index file inside the repository.
+const INDEX_FILE: &'static str = "index";
+
+const INDEX_SIGNATURE: [u8; 4] = [0x44, 0x49, 0x52, 0x43];  // DIRC
+
+/// The signature of the untracked cache extension.
+pub const UNTRACKED_CACHE_SIGNATURE: [u8; 4] = [0x55, 0x4e, 0x54, 0x52];  // UNTR
+
+/// The signature of the cached tree extension.
+pub const CACHE_TREE_SIGNATURE: [u8; 4] = [0x54, 0x52, 0x45, 0x45];  // TREE
+
+/// The signatures of extensions recording byte offsets into the index file. These describe one
+/// particular serialization of the index, so they are dropped whenever the index is rewritten.
+const OFFSET_EXTENSION_SIGNATURES: [[u8; 4]; 2] = [
+    [0x45, 0x4f, 0x49, 0x45],  // EOIE
+    [0x49, 0x45, 0x4f, 0x54],  // IEOT
+];
+
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_STAGE_SHIFT: u16 = 12;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+
+/// Marks an entry recorded with "git add -N": the path will be added, but its contents haven't been
+/// staged yet, so the entry records the empty blob.
+const EXTENDED_FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+/// The name of the empty blob, which is recorded for intent-to-add entries.
+const EMPTY_BLOB_SHA1: &'static str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+/// Read a variable-width integer as used by git, for example in the path names of version 4
+/// index files and in the untracked cache. Each byte contributes seven bits, with the high bit
+/// indicating that more bytes follow.
+pub fn read_varint(reader: &mut Read) -> io::Result<u64> {
+    let mut byte = [0; 1];
+    try!(reader.read_exact(&mut byte));
+    let mut value = (byte[0] & 0x7f) as u64;
+    while byte[0] & 0x80 != 0 {
+        try!(reader.read_exact(&mut byte));
+        value = ((value + 1) << 7) + (byte[0] & 0x7f) as u64;
+    }
+    Ok(value)
+}
+
+/// Write a variable-width integer in the format understood by `read_varint`.
+pub fn write_varint(writer: &mut Write, value: u64) -> io::Result<()> {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value != 0 {
+        value -= 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    bytes.reverse();
+    writer.write_all(&bytes)
+}
+
+/// A timestamp, as stored in the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Timestamp {
+    pub seconds: u32,
+    pub nanoseconds: u32,
+}
+
+/// The subset of the file metadata that git records, in order to detect changes to a file without
+/// having to read its contents. All the fields are truncated to 32 bits, as they are on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StatData {
+    pub ctime: Timestamp,
+    pub mtime: Timestamp,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+impl StatData {
+    pub fn from_metadata(metadata: &fs::Metadata) -> StatData {
+        StatData {
+            ctime: Timestamp {
+                seconds: metadata.ctime() as u32,
+                nanoseconds: metadata.ctime_nsec() as u32,
+            },
+            mtime: Timestamp {
+                seconds: metadata.mtime() as u32,
+                nanoseconds: metadata.mtime_nsec() as u32,
+            },
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            uid: metadata.uid() as u32,
+            gid: metadata.gid() as u32,
+            size: metadata.size() as u32,
+        }
+    }
+
+    /// Read the stat data in the layout used by the untracked cache, which is the same as the one
+    /// used by index entries except that there is no mode between the inode and the user id.
+    pub fn read(reader: &mut Read) -> io::Result<StatData> {
+        let mut fields = [0; 9];
+        for field in fields.iter_mut() {
+            *field = try!(reader.read_u32::<NetworkEndian>());
+        }
+
+        Ok(StatData {
+            ctime: Timestamp { seconds: fields[0], nanoseconds: fields[1] },
+            mtime: Timestamp { seconds: fields[2], nanoseconds: fields[3] },
+            dev: fields[4],
+            ino: fields[5],
+            uid: fields[6],
+            gid: fields[7],
+            size: fields[8],
+        })
+    }
+
+    /// Write the stat data in the layout read by `StatData::read`.
+    pub fn write(&self, writer: &mut Write) -> io::Result<()> {
+        let fields = [
+            self.ctime.seconds, self.ctime.nanoseconds,
+            self.mtime.seconds, self.mtime.nanoseconds,
+            self.dev, self.ino, self.uid, self.gid, self.size,
+        ];
+        for field in fields.iter() {
+            try!(writer.write_u32::<NetworkEndian>(*field));
+        }
+        Ok(())
+    }
+}
+
+/// An index extension, kept as raw bytes so that extensions that aren't understood by this module
+/// can still be written back out unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extension {
+    pub signature: [u8; 4],
+    pub data: Vec<u8>,
+}
+
 pub struct Index {
     pub version: u32,
     pub entries: Vec<Entry>,
+    pub extensions: Vec<Extension>,
+
+    /// The modification time of the index file when it was read. Files modified at the same time
+    /// or later may have changed without their stat data reflecting it, which is the "racy git"
+    /// problem.
+    pub timestamp: Option<Timestamp>,
+
+    /// The paths added or removed since the untracked cache was read, whose directories the cache
+    /// has to forget. Rather than parsing and writing the cache again for every path, they're all
+    /// applied whenever the cache is next read or written.
+    untracked_invalidations: Vec<Vec<u8>>,
 }
 
 impl Index {
+    /// An index with no entries, as used for a repository in which nothing has been added yet.
+    pub fn new() -> Index {
+        Index {
+            version: 2,
+            entries: Vec::new(),
+            extensions: Vec::new(),
+            timestamp: None,
+            untracked_invalidations: Vec::new(),
+        }
+    }
+
+    /// Read the index file of the repository. A missing index file is treated the same as an empty
+    /// index, as is the case in a newly initialized repository.
     pub fn read() -> Result<Index, Error> {
-        let index_file =
-            try!(File::open(".git/index")
-                 .map_err(|_| Error::InvalidIndex("unable to read index file".to_string())));
-        let mut reader = BufReader::new(index_file);
+        let path = repository::git_path(INDEX_FILE);
+        let io_error = |err| Error::IOError(path.clone(), err);
+        let mut index_file = match File::open(&path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Index::new()),
+            Err(err) => return Err(io_error(err)),
+        };
+
+        let timestamp =
+            try!(index_file.metadata()
+                 .map(|metadata| StatData::from_metadata(&metadata).mtime)
+                 .map_err(&io_error));
+
+        let mut contents = Vec::new();
+        try!(index_file.read_to_end(&mut contents).map_err(&io_error));
+
+        let mut index = try!(Index::parse(&contents));
+        index.timestamp = Some(timestamp);
+        Ok(index)
+    }
+
+    fn parse(contents: &[u8]) -> Result<Index, Error> {
+        if contents.len() < 20 {
+            return Err(Error::InvalidIndex("index file is too short".to_string()));
+        }
+
+        // The last 20 bytes are a SHA-1 checksum of everything preceding them.
+        let (body, checksum) = contents.split_at(contents.len() - 20);
+        let mut hasher = Sha1::new();
+        hasher.update(body);
+        if hasher.digest().bytes() != checksum {
+            return Err(Error::InvalidIndex("index file checksum mismatch".to_string()));
+        }
+
+        let mut reader = Cursor::new(body);
 
         // Read first four bytes, make sure they're DIRC
         let mut header_buffer = [0; 4];
         try!(reader.read_exact(&mut header_buffer)
              .map_err(|_| Error::InvalidIndex("unable to read header".to_string())));
-        if header_buffer != [0x44, 0x49, 0x52, 0x43] {
+        if header_buffer != INDEX_SIGNATURE {
             return Err(Error::InvalidIndex(format!("invalid header: {:?}", header_buffer)));
         }
 
         let version = try!(reader.read_u32::<NetworkEndian>()
-                           .map_err(|err| Error::InvalidIndex(err.description().to_string())));
+                           .map_err(|_| Error::InvalidIndex("unable to read version".to_string())));
+        if version < 2 || version > 4 {
+            return Err(Error::InvalidIndex(format!("unsupported version: {}", version)));
+        }
 
         let num_entries =
             try!(reader.read_u32::<NetworkEndian>()
-                 .map_err(|err| Error::InvalidIndex(err.description().to_string())));
-        let mut entries = Vec::with_capacity(num_entries as usize);
+                 .map_err(|_| Error::InvalidIndex("unable to read entry count".to_string())));
+        let mut entries: Vec<Entry> = Vec::with_capacity(num_entries as usize);
         for _ in 0..num_entries {
-            let entry = try!(Entry::read(version, &mut reader));
+            let entry = {
+                let previous_path = entries.last().map(|e| e.path.as_slice()).unwrap_or(&[]);
+                try!(Entry::read(version, &mut reader, previous_path))
+            };
             entries.push(entry);
         }
 
-        // Deliberately ignore any extensions. Some of the extensions can have a large impact on
-        // how the index file should be parsed, but for the repo tracking this project, ignoring
-        // the extensions seems safe for now.
+        // Everything after the entries is a sequence of extensions, each with a four-byte
+        // signature and a four-byte length.
+        let mut extensions = Vec::new();
+        while (reader.position() as usize) < body.len() {
+            let mut signature = [0; 4];
+            try!(reader.read_exact(&mut signature)
+                 .map_err(|_| Error::InvalidIndex("unable to read extension".to_string())));
+            let size =
+                try!(reader.read_u32::<NetworkEndian>()
+                     .map_err(|_| Error::InvalidIndex("unable to read extension".to_string())));
+            let mut data = vec![0; size as usize];
+            try!(reader.read_exact(&mut data)
+                 .map_err(|_| Error::InvalidIndex(
+                         format!("unable to read extension: {}",
+                                 String::from_utf8_lossy(&signature)))));
+
+            // Extensions starting with an upper-case letter are optional, so they may be skipped
+            // by implementations that don't understand them. The others change how the index
+            // must be interpreted, so we can't proceed without supporting them.
+            if signature[0] < b'A' || signature[0] > b'Z' {
+                return Err(Error::InvalidIndex(
+                        format!("unsupported extension: {}",
+                                String::from_utf8_lossy(&signature))));
+            }
+
+            extensions.push(Extension { signature: signature, data: data });
+        }
 
         Ok(Index {
             version: version,
-            entries: entries
+            entries: entries,
+            extensions: extensions,
+            timestamp: None,
+            untracked_invalidations: Vec::new(),
         })
     }
+
+    /// Find the extension with the given signature, if it is present.
+    pub fn extension(&self, signature: &[u8; 4]) -> Option<&Extension> {
+        self.extensions.iter().find(|e| e.signature == *signature)
+    }
+
+    /// Add the extension, replacing any existing extension with the same signature.
+    pub fn set_extension(&mut self, extension: Extension) {
+        self.remove_extension(&extension.signature);
+        self.extensions.push(extension);
+    }
+
+    pub fn remove_extension(&mut self, signature: &[u8; 4]) {
+        self.extensions.retain(|e| e.signature != *signature);
+        if *signature == UNTRACKED_CACHE_SIGNATURE {
+            self.untracked_invalidations.clear();
+        }
+    }
+
+    /// Parse the untracked cache stored in the index, if there is one, forgetting the directories
+    /// of the paths that have changed since.
+    fn parse_untracked_cache(&self) -> Option<io::Result<untracked::UntrackedCache>> {
+        self.extension(&UNTRACKED_CACHE_SIGNATURE).map(|extension| {
+            let mut cache = try!(untracked::UntrackedCache::parse(&extension.data));
+            for path in self.untracked_invalidations.iter() {
+                cache.invalidate_path(path);
+            }
+            Ok(cache)
+        })
+    }
+
+    /// Read the untracked cache stored in the index, if there is one.
+    pub fn untracked_cache(&self) -> Result<Option<untracked::UntrackedCache>, Error> {
+        match self.parse_untracked_cache() {
+            Some(Ok(cache)) => Ok(Some(cache)),
+            Some(Err(err)) => Err(Error::InvalidUntrackedCache(err)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_untracked_cache(&mut self, cache: &untracked::UntrackedCache) {
+        let mut data = Vec::new();
+        cache.write(&mut data).expect("writing to a Vec can't fail");
+        self.set_extension(Extension { signature: UNTRACKED_CACHE_SIGNATURE, data: data });
+    }
+
+    /// Find the position of the entry with the given path and stage. If there is no such entry, the
+    /// error contains the position at which it would be inserted to keep the entries sorted.
+    fn position(&self, path: &[u8], stage: u16) -> Result<usize, usize> {
+        self.entries.binary_search_by(|entry| {
+            (entry.path.as_slice(), entry.stage()).cmp(&(path, stage))
+        })
+    }
+
+    /// Find the entry for the given path, if it is tracked and not in conflict.
+    pub fn entry(&self, path: &[u8]) -> Option<&Entry> {
+        self.position(path, 0).ok().map(|position| &self.entries[position])
+    }
+
+    /// Add an entry to the index, replacing any existing entries for the same path, including those
+    /// left behind by a merge conflict. As a path can't be both a file and a directory, entries
+    /// for any of the parent directories of the path, and for any paths inside it, are removed too.
+    pub fn add(&mut self, entry: Entry) {
+        self.remove(&entry.path);
+
+        {
+            let path = &entry.path;
+            self.entries.retain(|existing| {
+                let existing_path = &existing.path;
+                let is_parent = path.len() > existing_path.len() &&
+                    path.starts_with(existing_path) && path[existing_path.len()] == b'/';
+                let is_child = existing_path.len() > path.len() &&
+                    existing_path.starts_with(path) && existing_path[path.len()] == b'/';
+                !is_parent && !is_child
+            });
+        }
+
+        self.invalidate_path(&entry.path);
+        let position = self.position(&entry.path, entry.stage()).unwrap_or_else(|p| p);
+        self.entries.insert(position, entry);
+    }
+
+    /// Remove all the entries for the given path, in every stage. Returns whether there were any.
+    pub fn remove(&mut self, path: &[u8]) -> bool {
+        let original_length = self.entries.len();
+        self.entries.retain(|entry| entry.path != path);
+
+        let removed = self.entries.len() != original_length;
+        if removed {
+            self.invalidate_path(path);
+        }
+        removed
+    }
+
+    /// Remove the entries of all the given paths, going through the index once rather than once
+    /// per path, as `remove` would.
+    pub fn remove_all(&mut self, paths: &HashSet<&[u8]>) {
+        let mut removed = Vec::new();
+        self.entries.retain(|entry| {
+            let keep = !paths.contains(entry.path.as_slice());
+            if !keep && removed.last() != Some(&entry.path) {
+                removed.push(entry.path.clone());
+            }
+            keep
+        });
+
+        for path in removed.iter() {
+            self.invalidate_path(path);
+        }
+    }
+
+    /// Keep the extensions caching information about the tracked paths consistent with a change to
+    /// the given path. The cached trees are dropped entirely, as they are only an optimization for
+    /// writing trees, while the untracked cache only forgets the directories containing the path.
+    fn invalidate_path(&mut self, path: &[u8]) {
+        self.remove_extension(&CACHE_TREE_SIGNATURE);
+
+        if self.extension(&UNTRACKED_CACHE_SIGNATURE).is_some() {
+            self.untracked_invalidations.push(path.to_vec());
+        }
+    }
+
+    /// Serialize the index, including the trailing checksum.
+    fn serialize(&self) -> io::Result<Vec<u8>> {
+        let needs_extended_flags = self.entries.iter().any(|e| e.extended_flags != 0);
+        let version = if self.version == 2 && needs_extended_flags { 3 } else { self.version };
+
+        let mut contents = Vec::new();
+        try!(contents.write_all(&INDEX_SIGNATURE));
+        try!(contents.write_u32::<NetworkEndian>(version));
+        try!(contents.write_u32::<NetworkEndian>(self.entries.len() as u32));
+
+        let mut previous_path: &[u8] = &[];
+        for entry in self.entries.iter() {
+            try!(entry.write(version, &mut contents, previous_path));
+            previous_path = &entry.path;
+        }
+
+        for extension in self.extensions.iter() {
+            if OFFSET_EXTENSION_SIGNATURES.contains(&extension.signature) {
+                continue;
+            }
+
+            // An untracked cache that has to forget some directories is written again, unless it
+            // can't be parsed, in which case it's dropped rather than kept as it is.
+            let mut untracked_cache = Vec::new();
+            let data = if extension.signature == UNTRACKED_CACHE_SIGNATURE &&
+                    !self.untracked_invalidations.is_empty() {
+                match self.parse_untracked_cache() {
+                    Some(Ok(cache)) => {
+                        try!(cache.write(&mut untracked_cache));
+                        &untracked_cache
+                    },
+                    _ => continue,
+                }
+            } else {
+                &extension.data
+            };
+
+            try!(contents.write_all(&extension.signature));
+            try!(contents.write_u32::<NetworkEndian>(data.len() as u32));
+            try!(contents.write_all(data));
+        }
+
+        let mut hasher = Sha1::new();
+        hasher.update(&contents);
+        contents.extend_from_slice(&hasher.digest().bytes());
+        Ok(contents)
+    }
+
+    /// Take the lock on the index file, so that the index can be read, modified and written back
+    /// without any other process updating it in between.
+    pub fn lock() -> Result<LockFile, Error> {
+        LockFile::acquire(repository::git_path(INDEX_FILE)).map_err(Error::WriteError)
+    }
+
+    /// Write the index to disk, replacing the existing index file atomically.
+    pub fn write(&self) -> Result<(), Error> {
+        let lock = try!(Index::lock());
+        self.write_locked(lock)
+    }
+
+    /// Write the index using a lock that was previously taken with `Index::lock`.
+    pub fn write_locked(&self, mut lock: LockFile) -> Result<(), Error> {
+        let contents = self.serialize().expect("writing to a Vec can't fail");
+        try!(lock.write_all(&contents).map_err(Error::WriteError));
+        lock.commit().map_err(Error::WriteError)
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Entry {
+    pub stat: StatData,
+    pub mode: u32,
     pub sha1: String,
-    pub path: PathBuf,
+    pub flags: u16,
+    pub extended_flags: u16,
+
+    /// The path of the file, relative to the root of the repository, exactly as it is stored in
+    /// the index. Git doesn't mandate any encoding for path names, so these are kept as raw bytes.
+    pub path: Vec<u8>,
 }
 
 impl Entry {
-    fn read(version: u32, reader: &mut BufRead) -> Result<Entry, Error> {
-        // We'll deliberately not read many of the fields for now, choosing to add in more
-        // functionality as it is needed. For example, the big chunk of bytes at the beginning of
-        // each entry is ignored for now, but it can be parsed correctly as the need arises.
+    /// Create a stage zero entry for a file with the given contents and stat data.
+    pub fn new(path: Vec<u8>, mode: u32, sha1: String, stat: StatData) -> Entry {
+        Entry {
+            stat: stat,
+            mode: mode,
+            sha1: sha1,
+            flags: 0,
+            extended_flags: 0,
+            path: path,
+        }
+    }
+
+    /// Create an entry recording that the file will be added later, as with "git add -N". The size
+    /// is recorded as zero, matching the empty blob, so the entry is never considered up to date.
+    pub fn intent_to_add(path: Vec<u8>, mode: u32, stat: StatData) -> Entry {
+        let stat = StatData { size: 0, ..stat };
+        let mut entry = Entry::new(path, mode, EMPTY_BLOB_SHA1.to_string(), stat);
+        entry.extended_flags |= EXTENDED_FLAG_INTENT_TO_ADD;
+        entry
+    }
 
+    fn read(version: u32, reader: &mut BufRead, previous_path: &[u8]) -> Result<Entry, Error> {
         let mut entry_length = 0;
 
-        try!(reader.read_exact(&mut [0; 40])
-             .map_err(|_| Error::InvalidEntry("unable to read entry: prefix".to_string())));
+        let mut fields = [0; 10];
+        for field in fields.iter_mut() {
+            *field = try!(reader.read_u32::<NetworkEndian>()
+                          .map_err(|_| Error::InvalidEntry(
+                                  "unable to read entry: stat data".to_string())));
+        }
         entry_length += 40;
 
         let mut sha1_bytes = [0; 20];
         try!(reader.read_exact(&mut sha1_bytes)
              .map_err(|_| Error::InvalidEntry("unable to read entry: sha1".to_string())));
-        let sha1 = sha1_bytes
-            .iter()
-            .map(|n| format!("{:02x}", n))
-            .collect::<Vec<_>>()
-            .concat();
+        let objects::Name(sha1) = objects::Name::from_bytes(&sha1_bytes);
         entry_length += 20;
 
-        try!(reader.read_exact(&mut [0; 2])
-             .map_err(|_| Error::InvalidEntry("unable to read entry: flags".to_string())));
+        let flags = try!(reader.read_u16::<NetworkEndian>()
+                         .map_err(|_| Error::InvalidEntry(
+                                 "unable to read entry: flags".to_string())));
         entry_length += 2;
 
-        if version >= 3 {
-            try!(reader.read_exact(&mut [0; 2])
-                 .map_err(|_| Error::InvalidEntry(
-                         "unable to read entry: additional flags".to_string())));
+        let extended_flags = if version >= 3 && flags & FLAG_EXTENDED != 0 {
             entry_length += 2;
+            try!(reader.read_u16::<NetworkEndian>()
+                 .map_err(|_| Error::InvalidEntry(
+                         "unable to read entry: additional flags".to_string())))
+        } else {
+            0
+        };
+
+        // Starting with version 4, the path name is prefix-compressed relative to the previous
+        // entry: a number of bytes to remove from the end of the previous path, followed by the
+        // bytes to append to what remains.
+        let mut path = Vec::new();
+        if version >= 4 {
+            let strip_length =
+                try!(read_varint(reader)
+                     .map_err(|_| Error::InvalidEntry(
+                             "unable to read entry: path prefix".to_string()))) as usize;
+            if strip_length > previous_path.len() {
+                return Err(Error::InvalidEntry(
+                        "unable to read entry: invalid path prefix".to_string()));
+            }
+            path.extend_from_slice(&previous_path[..previous_path.len() - strip_length]);
         }
 
         let mut path_name_bytes = Vec::new();
@@ -131,11 +628,8 @@ impl Entry {
                  .map_err(|_| Error::InvalidEntry(
                          "unable to read entry: path name".to_string())));
         path_name_bytes.pop();  // remove the null byte
-        let path_name =
-            try!(String::from_utf8(path_name_bytes)
-                 .map_err(|_| Error::InvalidEntry(
-                         "unable to read entry: path name from UTF8".to_string())));
         entry_length += path_name_length;
+        path.extend_from_slice(&path_name_bytes);
 
         let path_name_padding =
             if version >= 4 {
@@ -148,49 +642,208 @@ impl Entry {
              .map_err(|_| Error::InvalidEntry(
                      "unable to read entry: path name padding".to_string())));
 
-        let path = try!(
-            fs::canonicalize(path_name.clone())
-            .map_err(|_| Error::InvalidEntry(
-                    format!("unable to parse path name: {}", path_name))));
-
         Ok(Entry {
+            stat: StatData {
+                ctime: Timestamp { seconds: fields[0], nanoseconds: fields[1] },
+                mtime: Timestamp { seconds: fields[2], nanoseconds: fields[3] },
+                dev: fields[4],
+                ino: fields[5],
+                uid: fields[7],
+                gid: fields[8],
+                size: fields[9],
+            },
+            mode: fields[6],
             sha1: sha1,
+            flags: flags,
+            extended_flags: extended_flags,
             path: path,
         })
     }
+
+    fn write(&self, version: u32, writer: &mut Write, previous_path: &[u8]) -> io::Result<()> {
+        let mut entry_length = 0;
+
+        let fields = [
+            self.stat.ctime.seconds, self.stat.ctime.nanoseconds,
+            self.stat.mtime.seconds, self.stat.mtime.nanoseconds,
+            self.stat.dev, self.stat.ino, self.mode, self.stat.uid, self.stat.gid, self.stat.size,
+        ];
+        for field in fields.iter() {
+            try!(writer.write_u32::<NetworkEndian>(*field));
+        }
+        entry_length += 40;
+
+        try!(writer.write_all(&objects::Name(self.sha1.clone()).to_bytes()));
+        entry_length += 20;
+
+        // The length of the path is recomputed, in case the path was changed after the entry was
+        // read. Paths too long to fit in the flags are marked with the maximum length.
+        let name_length = ::std::cmp::min(self.path.len(), FLAG_NAME_MASK as usize) as u16;
+        let mut flags = (self.flags & !FLAG_NAME_MASK & !FLAG_EXTENDED) | name_length;
+        if version >= 3 && self.extended_flags != 0 {
+            flags |= FLAG_EXTENDED;
+        }
+        try!(writer.write_u16::<NetworkEndian>(flags));
+        entry_length += 2;
+
+        if flags & FLAG_EXTENDED != 0 {
+            try!(writer.write_u16::<NetworkEndian>(self.extended_flags));
+            entry_length += 2;
+        }
+
+        if version >= 4 {
+            let common_prefix = previous_path
+                .iter()
+                .zip(self.path.iter())
+                .take_while(|&(a, b)| a == b)
+                .count();
+            try!(write_varint(writer, (previous_path.len() - common_prefix) as u64));
+            try!(writer.write_all(&self.path[common_prefix..]));
+            try!(writer.write_all(&[0]));
+        } else {
+            try!(writer.write_all(&self.path));
+            entry_length += self.path.len();
+
+            // The path is NUL-terminated, and padded with further NUL bytes so that the length of
+            // the entry is a multiple of eight.
+            let padding = 8 - (entry_length % 8);
+            try!(writer.write_all(&vec![0; padding]));
+        }
+
+        Ok(())
+    }
+
+    /// The path of this entry as a `Path`, still relative to the root of the repository. No
+    /// filesystem access takes place, so this works even if the file no longer exists.
+    pub fn path(&self) -> &Path {
+        Path::new(OsStr::from_bytes(&self.path))
+    }
+
+    /// The merge stage of the entry. Zero for normal entries, and one through three for the
+    /// different sides of a conflicted merge.
+    pub fn stage(&self) -> u16 {
+        (self.flags & FLAG_STAGE_MASK) >> FLAG_STAGE_SHIFT
+    }
+
+    pub fn is_intent_to_add(&self) -> bool {
+        self.extended_flags & EXTENDED_FLAG_INTENT_TO_ADD != 0
+    }
 }
 
-// Currently, this function is being used solely for "git ls-files --others", so it's okay to read
-// the index and walk the working directly right in this function. In the future, when this is used
-// for "git status", we'll want to read the index and the working directory outside this function
-// so that work can be re-used for multiple operations.
-pub fn untracked_files() -> Result<HashSet<PathBuf>, Error> {
-    let index = try!(Index::read());
-    let tracked_files: HashSet<PathBuf> =
-        HashSet::from_iter(index.entries
-                           .iter()
-                           .map(|e| e.path.to_path_buf())
-                           .collect::<Vec<_>>());
+/// The files in the working directory that are not tracked by the index, split into those that
+/// are excluded by the ignore rules and those that aren't. Like the paths in the index, these paths
+/// are relative to the root of the repository.
+pub struct UntrackedFiles {
+    pub untracked: HashSet<PathBuf>,
+    pub ignored: HashSet<PathBuf>,
+}
 
-    fn is_git_dir(entry: &DirEntry) -> bool {
-        entry
-            .file_name()
-            .to_str()
-            .map(|s| s == ".git")
-            .unwrap_or(false)
+// This function is used for "git ls-files --others", which doesn't need the index for anything
+// else, so it's okay to read the index and walk the working directory right in this function.
+// "git status" compares the index against the working tree as well, so it calls `untracked::scan`
+// on its own copy of the index instead, allowing the index to be written only once.
+//
+// If the untracked cache was updated as part of the scan, the index is written back out so the
+// next scan can make use of it. This is only an optimization, so failing to take the lock on the
+// index, because another process is updating it, is not an error. The lock is taken before reading
+// the index, so that saving the cache can't undo that process's changes.
+pub fn untracked_files(config: &config::Config,
+                       excludes: &mut ignore::Excludes,
+                       options: &untracked::Options) -> Result<UntrackedFiles, Error> {
+    let lock = Index::lock().ok();
+    let mut index = try!(Index::read());
+    let (files, index_changed) = try!(untracked::scan(&mut index, config, excludes, options));
+
+    if let Some(lock) = lock {
+        if index_changed {
+            try!(index.write_locked(lock));
+        }
     }
 
-    let all_files = HashSet::from_iter(
-        WalkDir::new(".")
-        .into_iter()
-        .filter_entry(|e| !is_git_dir(e))
-        .filter_map(|e| e.ok())
-        .filter_map(|e| fs::canonicalize(e.path()).ok())
-        .filter(|e| !e.is_dir()));
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repository::TestRepository;
+
+    fn entry(path: &str) -> Entry {
+        let stat = StatData { size: 6, ..StatData::default() };
+        Entry::new(path.as_bytes().to_vec(), 0o100644, EMPTY_BLOB_SHA1.to_string(), stat)
+    }
 
-    let untracked: HashSet<PathBuf> = all_files.difference(&tracked_files)
-        .cloned()
-        .collect();
+    #[test]
+    fn writes_entries_that_read_back_the_same() {
+        let _repo = TestRepository::new("index-round-trip");
+        let mut index = Index::new();
+        index.add(entry("src/lib.rs"));
+        index.add(entry("README.md"));
+        index.add(entry("src/bin/add.rs"));
+        index.write().unwrap();
 
-    Ok(untracked)
+        let read = Index::read().unwrap();
+        assert_eq!(2, read.version);
+        let paths: Vec<_> = read.entries.iter().map(|e| e.path.as_slice()).collect();
+        assert_eq!(vec![&b"README.md"[..], b"src/bin/add.rs", b"src/lib.rs"], paths);
+        for (written, read) in index.entries.iter().zip(read.entries.iter()) {
+            assert_eq!((&written.stat, written.mode, &written.sha1),
+                       (&read.stat, read.mode, &read.sha1));
+        }
+    }
+
+    #[test]
+    fn writes_version_3_for_extended_flags() {
+        let _repo = TestRepository::new("index-extended-flags");
+        let mut index = Index::new();
+        index.add(entry("tracked"));
+        index.add(Entry::intent_to_add(b"new".to_vec(), 0o100644, StatData::default()));
+        index.write().unwrap();
+
+        let read = Index::read().unwrap();
+        assert_eq!(3, read.version);
+        assert!(read.entry(b"new").unwrap().is_intent_to_add());
+        assert!(!read.entry(b"tracked").unwrap().is_intent_to_add());
+    }
+
+    #[test]
+    fn drops_cached_trees_when_paths_change() {
+        let _repo = TestRepository::new("index-cache-tree");
+        let mut index = Index::new();
+        index.add(entry("a"));
+        index.set_extension(Extension { signature: CACHE_TREE_SIGNATURE, data: vec![0] });
+        index.add(entry("b"));
+        index.write().unwrap();
+
+        let read = Index::read().unwrap();
+        assert!(read.extension(&CACHE_TREE_SIGNATURE).is_none());
+        assert_eq!(2, read.entries.len());
+    }
+
+    #[test]
+    fn removes_every_given_path() {
+        let mut index = Index::new();
+        for path in ["a", "b", "c", "d"].iter() {
+            index.add(entry(path));
+        }
+        index.set_extension(Extension { signature: CACHE_TREE_SIGNATURE, data: vec![0] });
+
+        let paths: HashSet<&[u8]> = [&b"b"[..], b"d", b"missing"].iter().cloned().collect();
+        index.remove_all(&paths);
+        let remaining: Vec<_> = index.entries.iter().map(|e| e.path.as_slice()).collect();
+        assert_eq!(vec![&b"a"[..], b"c"], remaining);
+        assert!(index.extension(&CACHE_TREE_SIGNATURE).is_none());
+    }
+
+    #[test]
+    fn refuses_to_write_while_locked() {
+        let _repo = TestRepository::new("index-locked");
+        let _lock = Index::lock().unwrap();
+        match Index::new().write() {
+            Err(Error::WriteError(ref err)) => {
+                assert_eq!(io::ErrorKind::AlreadyExists, err.kind())
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
@@ -4,13 +4,32 @@ extern crate flate2;
 #[macro_use]
 extern crate lazy_static;
 extern crate regex;
-extern crate walkdir;
+extern crate sha1;
+
+#[macro_use]
+pub mod trace;
 
 pub mod branch;
 pub mod cli;
+pub mod color;
 pub mod commits;
 pub mod config;
+pub mod date;
+pub mod error;
+pub mod graph;
+pub mod ignore;
 pub mod index;
+pub mod lockfile;
 pub mod objects;
 pub mod pager;
+pub mod pathspec;
+pub mod pretty;
+pub mod refs;
+pub mod repository;
 pub mod revisions;
+pub mod revwalk;
+pub mod status;
+pub mod trees;
+pub mod untracked;
+
+pub use error::Error;
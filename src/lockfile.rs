@@ -0,0 +1,94 @@
+//! Provides a means of atomically replacing files inside the repository, using the same protocol as
+//! git: the new contents are written to `<file>.lock`, which is created exclusively so that only
+//! one process can be updating the file at a time, and then renamed over the original file.
+
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    /// Take the lock for the given file by creating the corresponding `.lock` file. Fails if the
+    /// lock file already exists, which means another process is updating the same file.
+    pub fn acquire<P>(path: P) -> io::Result<LockFile>
+            where P: AsRef<Path> {
+        let path = path.as_ref().to_path_buf();
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        let file = try!(
+            OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .map_err(|e| if e.kind() == io::ErrorKind::AlreadyExists {
+                io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("unable to create '{}': File exists", lock_path.display()))
+            } else {
+                e
+            }));
+
+        Ok(LockFile { path: path, lock_path: lock_path, file: Some(file) })
+    }
+
+    /// The file that will be replaced when the lock is committed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Set the permissions the file will have once the lock is committed, such as to keep those of
+    /// the original file.
+    pub fn set_permissions(&self, permissions: fs::Permissions) -> io::Result<()> {
+        match self.file {
+            Some(ref file) => file.set_permissions(permissions),
+            None => Err(io::Error::new(io::ErrorKind::Other, "lock file already committed")),
+        }
+    }
+
+    /// Replace the original file with everything written to the lock file, releasing the lock.
+    pub fn commit(mut self) -> io::Result<()> {
+        let result = match self.file.take() {
+            Some(mut file) => file.flush().and_then(|_| file.sync_all()),
+            None => Ok(()),
+        }.and_then(|_| fs::rename(&self.lock_path, &self.path));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+        result
+    }
+}
+
+impl Write for LockFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.file {
+            Some(ref mut file) => file.write(buf),
+            None => Err(io::Error::new(io::ErrorKind::Other, "lock file already committed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file {
+            Some(ref mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for LockFile {
+    /// A lock that was never committed is rolled back, leaving the original file untouched.
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
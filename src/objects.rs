@@ -2,20 +2,50 @@
 //! database that is git.
 
 use commits;
+use repository;
+use trees;
 
+use flate2::Compression;
 use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use sha1::Sha1;
 
-use std::{env, fmt, io, path, str};
+use std::{env, fmt, fs, io, path, process, str};
 use std::error::Error as StdError;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
 
 /// An object name, which must be a 40-byte hexadecimal string containing the SHA-1 of the object
 /// being referenced. It is expected that such an object name is constructed either when the object
 /// is first being written, or by resolving a reference or revision.
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Name(pub String);
 
+impl Name {
+    /// Build an object name from the raw 20-byte SHA-1, as stored in binary formats like the index.
+    pub fn from_bytes(bytes: &[u8]) -> Name {
+        Name(bytes
+             .iter()
+             .map(|n| format!("{:02x}", n))
+             .collect::<Vec<_>>()
+             .concat())
+    }
+
+    /// The raw 20-byte SHA-1 for this object name. Any characters that are not valid hexadecimal
+    /// digits are treated as zeroes, as the name is expected to be well-formed.
+    pub fn to_bytes(&self) -> [u8; 20] {
+        let Name(ref value) = *self;
+        let mut bytes = [0; 20];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = value.get(i * 2..i * 2 + 2)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .unwrap_or(0);
+        }
+        bytes
+    }
+}
+
 impl fmt::Display for Name {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let Name(ref value) = *self;
@@ -28,6 +58,9 @@ pub enum Type {
     Blob,
     Tree,
     Commit,
+
+    /// An annotated tag, which points to another object, usually a commit.
+    Tag,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -38,15 +71,23 @@ pub struct Header {
 
 #[derive(Debug)]
 pub enum Error {
-    IOError(io::Error),
-    InvalidFile(String),
+    /// A file in the object database couldn't be read or written.
+    IOError(path::PathBuf, io::Error),
+
+    /// The object isn't in the database.
+    NotFound(Name),
+
+    /// The object is in the database, but it can't be read, for the given reason.
+    Corrupt(Name, String),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::IOError(ref err) => write!(f, "IO error: {}", err),
-            Error::InvalidFile(ref description) => write!(f, "invalid file: {}", description),
+            Error::IOError(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            Error::NotFound(ref name) => write!(f, "object not found: {}", name.0),
+            Error::Corrupt(ref name, ref reason) =>
+                write!(f, "object {} is corrupt: {}", name.0, reason),
         }
     }
 }
@@ -54,44 +95,106 @@ impl fmt::Display for Error {
 impl StdError for Error {
     fn description(&self) -> &str {
         match *self {
-            Error::IOError(ref err) => err.description(),
-            Error::InvalidFile(ref description) => description,
+            Error::IOError(_, ref err) => err.description(),
+            Error::NotFound(_) => "object not found",
+            Error::Corrupt(_, ref reason) => reason,
         }
     }
 
-    fn cause(&self) -> Option<&StdError> {
+    fn source(&self) -> Option<&(StdError + 'static)> {
         match *self {
-            Error::IOError(ref err) => Some(err),
-            ref err => Some(err)
+            Error::IOError(_, ref err) => Some(err),
+            Error::NotFound(_) | Error::Corrupt(..) => None,
         }
     }
 }
 
+fn type_name(object_type: Type) -> &'static str {
+    match object_type {
+        Type::Blob => "blob",
+        Type::Tree => "tree",
+        Type::Commit => "commit",
+        Type::Tag => "tag",
+    }
+}
+
+/// Compute the name that an object with the given type and contents would have, without writing
+/// the object to the database.
+pub fn hash_object(object_type: Type, contents: &[u8]) -> Name {
+    let mut hasher = Sha1::new();
+    hasher.update(object_header(object_type, contents).as_bytes());
+    hasher.update(contents);
+    Name::from_bytes(&hasher.digest().bytes())
+}
+
+fn object_header(object_type: Type, contents: &[u8]) -> String {
+    format!("{} {}\0", type_name(object_type), contents.len())
+}
+
+/// Write an object with the given type and contents to the database, returning its name. Objects
+/// are immutable, so if the object already exists, it is left as is. Otherwise, the compressed
+/// object is written to a temporary file first, then renamed into place, so that a partially
+/// written object is never visible to other processes.
+pub fn write_object(object_type: Type, contents: &[u8]) -> Result<Name, Error> {
+    let name = hash_object(object_type, contents);
+    let object_path = try!(get_object_path(&name));
+    if object_path.exists() {
+        return Ok(name);
+    }
+
+    // The object is always inside a directory named after the start of its name.
+    let object_dir = object_path.parent().unwrap();
+    try!(fs::create_dir_all(object_dir)
+         .map_err(|err| Error::IOError(object_dir.to_path_buf(), err)));
+
+    let temp_path = object_dir.join(format!("tmp_obj_{}", process::id()));
+    let result = File::create(&temp_path)
+        .and_then(|file| {
+            let mut encoder = ZlibEncoder::new(file, Compression::Default);
+            try!(encoder.write_all(object_header(object_type, contents).as_bytes()));
+            try!(encoder.write_all(contents));
+            encoder.finish()
+        })
+        .and_then(|file| file.sync_all())
+        .and_then(|_| fs::set_permissions(&temp_path, fs::Permissions::from_mode(0o444)))
+        .and_then(|_| fs::rename(&temp_path, &object_path));
+
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(Error::IOError(object_path, err));
+    }
+    Ok(name)
+}
+
 pub enum Object {
     Blob(String),
-    Tree,
+    Tree(trees::Tree),
     Commit(commits::Commit),
+
+    /// The contents of an annotated tag, which are only parsed as far as `peel` needs.
+    Tag(String),
 }
 
 fn get_object_path(name: &Name) -> Result<path::PathBuf, Error> {
-    let cwd = try!(env::current_dir().map_err(|e| Error::IOError(e)));
+    let cwd = try!(env::current_dir().map_err(|err| Error::IOError(path::PathBuf::from("."), err)));
     let Name(ref sha1) = *name;
     let (dir, file) = sha1.split_at(2);
     Ok(cwd
-       .join(".git/objects")
+       .join(repository::git_path("objects"))
        .join(dir)
        .join(file))
 }
 
-fn std_error_to_objects_error<T>(e: T) -> Error
-        where T: StdError {
-    Error::InvalidFile(e.description().to_string())
+/// Describe why an object couldn't be parsed, for the errors found while parsing it.
+fn to_reason<T>(e: T) -> String
+        where T: ToString {
+    e.to_string()
 }
 
-fn read_until<R>(mut reader: &mut R, until: char) -> Result<String, Error>
+fn read_until<R>(mut reader: &mut R, until: char) -> Result<String, String>
         where R: BufRead, {
     let mut buffer = Vec::new();
-    try!(reader.read_until(until as u8, &mut buffer).map_err(std_error_to_objects_error));
+    try!(reader.read_until(until as u8, &mut buffer).map_err(to_reason));
 
     // The last character is the one that we're reading up to, so discard that before processing
     // the bytes that were read.
@@ -99,10 +202,10 @@ fn read_until<R>(mut reader: &mut R, until: char) -> Result<String, Error>
 
     str::from_utf8(&buffer)
         .map(|s| s.to_string())
-        .map_err(std_error_to_objects_error)
+        .map_err(to_reason)
 }
 
-fn read_type<R>(mut reader: &mut R) -> Result<Type, Error>
+fn read_type<R>(mut reader: &mut R) -> Result<Type, String>
         where R: BufRead, {
     let type_str = try!(read_until(&mut reader, ' '));
 
@@ -110,59 +213,120 @@ fn read_type<R>(mut reader: &mut R) -> Result<Type, Error>
         "blob" => Ok(Type::Blob),
         "tree" => Ok(Type::Tree),
         "commit" => Ok(Type::Commit),
-        value => Err(Error::InvalidFile(format!("invalid type: {}", value))),
+        "tag" => Ok(Type::Tag),
+        value => Err(format!("invalid type: {}", value)),
     });
 
     Ok(object_type)
 }
 
-fn read_size<R>(mut reader: &mut R) -> Result<u64, Error>
+fn read_size<R>(mut reader: &mut R) -> Result<u64, String>
         where R: BufRead, {
     let mut buffer = Vec::new();
-    try!(reader.read_until('\0' as u8, &mut buffer).map_err(std_error_to_objects_error));
+    try!(reader.read_until('\0' as u8, &mut buffer).map_err(to_reason));
     buffer.pop();
-    let size_str = try!(str::from_utf8(&buffer).map_err(std_error_to_objects_error));
+    let size_str = try!(str::from_utf8(&buffer).map_err(to_reason));
     let size = try!(size_str.parse::<u64>()
-                    .map_err(|e| Error::InvalidFile(e.description().to_string())));
+                    .map_err(|e| format!("invalid size: {}: {}", size_str, e)));
 
     Ok(size)
 }
 
 fn read_file(name: &Name) -> Result<BufReader<ZlibDecoder<File>>, Error> {
     let path = try!(get_object_path(name));
+    trace!("objects: reading {}", name.0);
 
-    // read file
-    let file = try!(File::open(path.as_path()).map_err(|e| Error::IOError(e)));
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound =>
+            return Err(Error::NotFound(name.clone())),
+        Err(err) => return Err(Error::IOError(path, err)),
+    };
     Ok(BufReader::new(ZlibDecoder::new(file)))
 }
 
-fn read_header_from_reader<R>(mut reader: &mut R) -> Result<Header, Error>
+fn read_header_from_reader<R>(name: &Name, mut reader: &mut R) -> Result<Header, Error>
         where R: BufRead {
-    let object_type = try!(read_type(&mut reader));
-    let size = try!(read_size(&mut reader));
+    let corrupt = |reason| Error::Corrupt(name.clone(), reason);
+    let object_type = try!(read_type(&mut reader).map_err(&corrupt));
+    let size = try!(read_size(&mut reader).map_err(&corrupt));
     Ok(Header { object_type: object_type, content_length: size })
 }
 
 pub fn read_header(name: &Name) -> Result<Header, Error> {
     let mut reader = try!(read_file(name));
-    read_header_from_reader(&mut reader)
+    read_header_from_reader(name, &mut reader)
 }
 
 pub fn read_object(name: &Name) -> Result<Object, Error> {
     let mut reader = try!(read_file(name));
-    let header = try!(read_header_from_reader(&mut reader));
+    let header = try!(read_header_from_reader(name, &mut reader));
+    let corrupt = |reason| Error::Corrupt(name.clone(), reason);
 
     match header.object_type {
         Type::Commit => {
-            let commit = try!(commits::parse_commit(&mut reader, name)
-                              .map_err(std_error_to_objects_error));
+            let commit = try!(commits::parse_commit(&mut reader, name).map_err(to_reason)
+                              .map_err(&corrupt));
             Ok(Object::Commit(commit))
         },
+        Type::Tree => {
+            let tree = try!(trees::parse_tree(&mut reader, name).map_err(to_reason)
+                            .map_err(&corrupt));
+            Ok(Object::Tree(tree))
+        },
         Type::Blob => {
             let mut contents = String::new();
-            try!(reader.read_to_string(&mut contents).map_err(std_error_to_objects_error));
+            try!(reader.read_to_string(&mut contents).map_err(to_reason).map_err(&corrupt));
             Ok(Object::Blob(contents))
         },
-        typ => Err(Error::InvalidFile(format!("unhandled object type: {:?}", typ)))
+        Type::Tag => {
+            let mut contents = String::new();
+            try!(reader.read_to_string(&mut contents).map_err(to_reason).map_err(&corrupt));
+            Ok(Object::Tag(contents))
+        },
+    }
+}
+
+/// Follow annotated tags until reaching the object they point to, which is usually a commit. Any
+/// other object is returned as it is.
+pub fn peel(name: &Name) -> Result<Name, Error> {
+    let mut name = name.clone();
+    loop {
+        let contents = match try!(read_object(&name)) {
+            Object::Tag(contents) => contents,
+            _ => return Ok(name),
+        };
+
+        let target = contents.lines().next()
+            .filter(|line| line.starts_with("object "))
+            .map(|line| Name(line["object ".len()..].trim().to_string()));
+        name = try!(target.ok_or_else(|| {
+            Error::Corrupt(name.clone(), "tag without an object".to_string())
+        }));
+    }
+}
+
+/// Shorten an object name for display to the given length, or longer if that's needed to keep it
+/// unambiguous among the objects in the database.
+pub fn abbreviate(name: &Name, length: usize) -> String {
+    let Name(ref sha1) = *name;
+    if sha1.len() != 40 {
+        return sha1.clone();
+    }
+
+    // Objects sharing the first two characters of the name are stored in the same directory.
+    let (dir, file) = sha1.split_at(2);
+    let others: Vec<String> = fs::read_dir(repository::git_path("objects").join(dir))
+        .map(|files| {
+            files.filter_map(|file| file.ok().and_then(|file| file.file_name().into_string().ok()))
+                .filter(|other| other != file)
+                .collect()
+        })
+        .unwrap_or(Vec::new());
+
+    let mut length = length.max(4).min(40);
+    while length < 40 && others.iter().any(|other| other.starts_with(&sha1[2..length])) {
+        length += 1;
     }
+    sha1[..length].to_string()
 }
@@ -3,35 +3,47 @@
 //! after the call, content can be printed to STDOUT and it will automatically be displayed in the
 //! pager.
 //!
-//! TODO: need to implement this part
 //! The pager is chosen based on the following, in the specified order:
 //!
 //! - `$GIT_PAGER`
+//! - `pager.<command>`, if it's set to a pager rather than a boolean
 //! - `core.pager`
 //! - `$PAGER`
 //! - compile-time default
 //!
-//! This code is mostly copied, but simplied and adapted, from the pager-rs project at
+//! Whether a command is paged at all depends on the command, unless `pager.<command>` is set. An
+//! empty pager, or `cat`, turns paging off. Output that isn't going to a terminal isn't paged
+//! either, unless paging is forced with `gitters --paginate`.
+//!
+//! This code was originally copied, but simplied and adapted, from the pager-rs project at
 //! https://gitlab.com/imp/pager-rs. That code is under the Apache 2 and MIT licenses.
 
 extern crate errno;
 extern crate libc;
 
+use config;
+
+use std::env;
 use std::error::Error as StdError;
-use std::ffi::{CString, OsString};
 use std::fmt;
-use std::os::unix::ffi::OsStringExt;
-use std::ptr;
+use std::io::{self, Write};
+use std::os::unix::io::IntoRawFd;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicIsize, Ordering, ATOMIC_ISIZE_INIT};
 
 #[derive(Debug)]
 pub enum Error {
-    SetupError(String),
+    /// The pager couldn't be started, for the given reason.
+    SetupError(String, io::Error),
+    ConfigError(config::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::SetupError(ref description) => write!(f, "setup error: {}", description),
+            Error::SetupError(ref description, ref err) =>
+                write!(f, "setup error: {}: {}", description, err),
+            Error::ConfigError(ref err) => write!(f, "{}", err),
         }
     }
 }
@@ -39,88 +51,232 @@ impl fmt::Display for Error {
 impl StdError for Error {
     fn description(&self) -> &str {
         match *self {
-            Error::SetupError(ref description) => description,
+            Error::SetupError(ref description, _) => description,
+            Error::ConfigError(ref err) => err.description(),
         }
     }
 
-    fn cause(&self) -> Option<&StdError> {
-        None
+    fn source(&self) -> Option<&(StdError + 'static)> {
+        match *self {
+            Error::SetupError(_, ref err) => Some(err),
+            Error::ConfigError(ref err) => Some(err),
+        }
+    }
+}
+
+/// The pager used when none is configured.
+const DEFAULT_PAGER: &'static str = "less";
+
+/// The environment variable `gitters --paginate` sets to page a command's output, even if it isn't
+/// going to a terminal.
+pub const PAGINATE_VARIABLE: &'static str = "GITTERS_PAGINATE";
+
+/// The options the pager is run with, unless they're already set, the same as in git. For `less`,
+/// these quit if the output fits on one screen, pass colors through and don't clear the screen.
+const PAGER_ENV: [(&'static str, &'static str); 2] = [("LESS", "FRX"), ("LV", "-c")];
+
+/// How `pager.<command>` applies to a command: either turning paging on or off, or choosing the
+/// pager to use, which turns paging on as well.
+#[derive(Debug, PartialEq, Eq)]
+enum CommandSetting {
+    Enabled(bool),
+    Pager(String),
+}
+
+fn command_setting(config: &config::Config, command: &str)
+        -> Result<Option<CommandSetting>, config::Error> {
+    let name = format!("pager.{}", command);
+    match config.get_bool(&name) {
+        Ok(enabled) => Ok(enabled.map(CommandSetting::Enabled)),
+        Err(config::Error::InvalidValue(_)) => {
+            let pager = try!(config.get_string(&name));
+            Ok(pager.map(|pager| CommandSetting::Pager(pager.to_string())))
+        },
+        Err(err) => Err(err),
     }
 }
 
+/// Choose the pager for a command, given the values of `$GIT_PAGER` and `$PAGER`, or nothing if
+/// the command shouldn't be paged. Forcing the command to be paged overrides `pager.<command>`, but
+/// not a pager that turns paging off.
+fn choose_pager(config: &config::Config, command: &str, paged_by_default: bool, forced: bool,
+                git_pager: Option<String>, pager: Option<String>)
+        -> Result<Option<String>, config::Error> {
+    let setting = try!(command_setting(config, command));
+    let command_pager = match setting {
+        Some(CommandSetting::Enabled(false)) if !forced => return Ok(None),
+        None if !paged_by_default && !forced => return Ok(None),
+        Some(CommandSetting::Pager(pager)) => Some(pager),
+        _ => None,
+    };
+
+    let core_pager = try!(config.get_string("core.pager")).map(|pager| pager.to_string());
+    let chosen = git_pager
+        .or(command_pager)
+        .or(core_pager)
+        .or(pager)
+        .unwrap_or(DEFAULT_PAGER.to_string());
+    if chosen.is_empty() || chosen == "cat" {
+        Ok(None)
+    } else {
+        Ok(Some(chosen))
+    }
+}
 
 macro_rules! check_libc_call {
     ($success: expr, $msg: expr) => {
         if !$success {
-            return Err(Error::SetupError($msg.to_string()));
+            return Err(Error::SetupError($msg.to_string(), io::Error::last_os_error()));
         }
     }
 }
 
-// TODO: accept config object
-pub fn setup() -> Result<(), Error> {
-    // TODO: find correct pager command. This should also involve checking if we're outputting to a
-    // tty, and setting up environment variables like in
-    // https://github.com/git/git/blob/398dd4bd039680ba98497fbedffa415a43583c16/pager.c#L83-L93
-    let cmd = "less -R";
-
-    let mut pipe_fds = [0; 2];
-    unsafe { libc::pipe(pipe_fds.as_mut_ptr()); } // TODO: error checking
-    let (pager_stdin, main_stdout) = (pipe_fds[0], pipe_fds[1]);
-
-    match unsafe { libc::fork() } {
-        -1 => {
-            // Fork failed. Clean up.
-            unsafe {
-                // Don't bother with error checking. The setup failed anyway.
-                libc::close(pager_stdin);
-                libc::close(main_stdout);
-            }
-
-            Err(Error::SetupError("unable to fork".to_string()))
-        },
-        0 => {
-            // We are in the child process. This will continue running the current program, but
-            // with STDOUT pointing to the output end of the created pipe. Close the input end of
-            // the pipe because only the parent process will be reading from the pipe.
-            unsafe {
-                check_libc_call!(
-                    libc::dup2(main_stdout, libc::STDOUT_FILENO) > -1,
-                    "unable to reroute STDOUT");
-                check_libc_call!(libc::close(pager_stdin) == 0, "unable to close STDIN");
-            }
-
-            Ok(())
-        },
-        _ => {
-            // We are in the parent process. Replace this process with the pager, but with the
-            // STDIN pointing to the input end of the created pipe. Close the output end of the
-            // pipe because the child process is the one that will be writing to the pipe.
-            unsafe {
-                check_libc_call!(
-                    libc::dup2(pager_stdin, libc::STDIN_FILENO) > -1,
-                    "unable to reroute STDIN");
-                check_libc_call!(libc::close(main_stdout) == 0, "unable to close STDOUT");
-
-                let cstrings = cmd
-                    .split_whitespace()
-                    .map(|s| {
-                        let bytes = OsString::from(s).into_vec();
-                        CString::from_vec_unchecked(bytes)
-                    })
-                    .collect::<Vec<_>>();
-
-                let mut args = cstrings
-                    .iter()
-                    .map(|c| c.as_ptr())
-                    .collect::<Vec<_>>();
-                args.push(ptr::null());
-
-                errno::set_errno(errno::Errno(0));
-                libc::execvp(args[0], args.as_ptr());
-            }
-
-            Ok(())
+/// The process ID of the running pager, or zero if there isn't one.
+static PAGER_PID: AtomicIsize = ATOMIC_ISIZE_INIT;
+
+/// The signals that stop the command early, after which the pager still needs to be waited for,
+/// the same ones git handles. `SIGPIPE` is among them, since it's sent when the pager quits before
+/// reading everything.
+const SIGNALS: [libc::c_int; 5] =
+    [libc::SIGINT, libc::SIGHUP, libc::SIGTERM, libc::SIGQUIT, libc::SIGPIPE];
+
+/// Tell the pager there's no more output by closing STDOUT and STDERR, which may both be going to
+/// it, and wait for the user to quit it. Only uses functions that are safe to call from a signal
+/// handler.
+fn wait_for_pager() {
+    let pid = PAGER_PID.swap(0, Ordering::SeqCst) as libc::pid_t;
+    if pid == 0 {
+        return;
+    }
+
+    unsafe {
+        libc::close(libc::STDOUT_FILENO);
+        libc::close(libc::STDERR_FILENO);
+
+        let mut status = 0;
+        while libc::waitpid(pid, &mut status, 0) < 0 && errno::errno().0 == libc::EINTR {}
+    }
+}
+
+extern "C" fn wait_for_pager_at_exit() {
+    // Anything still buffered has to reach the pager before it's told there's no more output.
+    let _ = io::stdout().flush();
+    wait_for_pager();
+}
+
+/// Wait for the pager, then die from the signal as if it hadn't been handled, so that the exit
+/// status still shows the command was stopped by it.
+extern "C" fn wait_for_pager_on_signal(signal: libc::c_int) {
+    wait_for_pager();
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+/// Whether the output of this process, or of the command that started it, is going to a pager.
+/// Such output is still shown on a terminal, even though STDOUT isn't one.
+pub fn in_use() -> bool {
+    env::var("GIT_PAGER_IN_USE").ok().and_then(|value| config::parse_bool(&value)).unwrap_or(false)
+}
+
+/// Whether the output is shown on a terminal, either directly or through the pager.
+pub fn output_to_terminal() -> bool {
+    in_use() || unsafe { libc::isatty(libc::STDOUT_FILENO) != 0 }
+}
+
+/// Send the output of the command to the configured pager, if it's paged. Commands that are paged
+/// by default, like `log`, can still be stopped from being paged with `pager.<command>`, and the
+/// others can be paged with it. Nothing is paged when STDOUT isn't a terminal, such as when the
+/// output is piped to another command, unless paging is forced.
+///
+/// The pager runs as a child process, reading from a pipe that replaces STDOUT, and STDERR too if
+/// it's a terminal, so the command keeps running as itself and its exit status is left alone. When
+/// the command exits, or is stopped by a signal, it waits for the user to quit the pager first.
+pub fn setup(config: &config::Config, command: &str, paged_by_default: bool)
+        -> Result<(), Error> {
+    // Paging is only forced for this command, not for any commands it runs in turn.
+    let forced = env::var_os(PAGINATE_VARIABLE).is_some();
+    env::remove_var(PAGINATE_VARIABLE);
+    if !forced && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 0 {
+        return Ok(());
+    }
+
+    let cmd = match try!(choose_pager(config, command, paged_by_default, forced,
+                                      env::var("GIT_PAGER").ok(), env::var("PAGER").ok())
+                         .map_err(Error::ConfigError)) {
+        Some(cmd) => cmd,
+        None => return Ok(()),
+    };
+
+    // Like in git, the pager is run by the shell, so that it can be given arguments with any
+    // quoting they need.
+    let mut pager = Command::new("sh");
+    pager.arg("-c").arg(&cmd).stdin(Stdio::piped());
+    for &(name, value) in PAGER_ENV.iter() {
+        if env::var_os(name).is_none() {
+            pager.env(name, value);
         }
     }
+    let mut child = try!(pager.spawn().map_err(|err| {
+        Error::SetupError(format!("unable to start pager '{}'", cmd), err)
+    }));
+
+    // Anything printed so far goes straight to the terminal, ahead of the pager.
+    let _ = io::stdout().flush();
+    let pipe = child.stdin.take().unwrap().into_raw_fd();
+    unsafe {
+        check_libc_call!(libc::dup2(pipe, libc::STDOUT_FILENO) > -1, "unable to reroute STDOUT");
+        if libc::isatty(libc::STDERR_FILENO) != 0 {
+            check_libc_call!(libc::dup2(pipe, libc::STDERR_FILENO) > -1,
+                             "unable to reroute STDERR");
+        }
+        check_libc_call!(libc::close(pipe) == 0, "unable to close pager pipe");
+    }
+
+    PAGER_PID.store(child.id() as isize, Ordering::SeqCst);
+    env::set_var("GIT_PAGER_IN_USE", "true");
+    unsafe {
+        check_libc_call!(libc::atexit(wait_for_pager_at_exit) == 0,
+                         "unable to wait for pager at exit");
+        for &signal in SIGNALS.iter() {
+            libc::signal(signal, wait_for_pager_on_signal as libc::sighandler_t);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::choose_pager;
+    use config;
+
+    #[test]
+    fn chooses_pager() {
+        let mut config = config::Config::new();
+        config.add_from_string("[core]\npager = less -S\n\
+                                [pager]\nlog = false\nbranch = more\nstatus\n".to_string())
+            .unwrap();
+        let choose = |command, default, git_pager: Option<&str>, pager: Option<&str>| {
+            choose_pager(&config, command, default, false, git_pager.map(String::from),
+                         pager.map(String::from)).unwrap()
+        };
+
+        assert_eq!(None, choose("log", true, None, None));
+        assert_eq!(Some("more".to_string()), choose("branch", false, None, None));
+        assert_eq!(Some("most".to_string()), choose("branch", false, Some("most"), None));
+        assert_eq!(Some("less -S".to_string()), choose("status", false, None, Some("pg")));
+        assert_eq!(Some("less -S".to_string()), choose("config", true, None, None));
+        assert_eq!(None, choose("config", false, None, None));
+        assert_eq!(None, choose("config", true, Some("cat"), None));
+        assert_eq!(None, choose("config", true, Some(""), None));
+
+        let force = |command, git_pager: Option<&str>| {
+            choose_pager(&config, command, false, true, git_pager.map(String::from), None).unwrap()
+        };
+        assert_eq!(Some("less -S".to_string()), force("log", None));
+        assert_eq!(Some("less -S".to_string()), force("config", None));
+        assert_eq!(None, force("config", Some("cat")));
+    }
 }
@@ -0,0 +1,115 @@
+//! Provides functionality for matching paths against pathspecs, the patterns used on the command
+//! line to limit a command to a subset of the paths in the repository. See the "pathspec" entry in
+//! gitglossary(7) for the full specification, of which this module provides the default, non-magic
+//! matching: a pathspec matches a path that is equal to it, a path inside the directory it names,
+//! or a path it matches as a wildcard pattern.
+
+use ignore;
+
+pub struct Pathspec {
+    /// The pathspecs as given on the command line, for reporting the ones that didn't match.
+    pub originals: Vec<String>,
+    items: Vec<String>,
+}
+
+fn is_glob(item: &str) -> bool {
+    item.contains(|c| c == '*' || c == '?' || c == '[' || c == '\\')
+}
+
+/// Remove the redundant parts of a pathspec: a leading "./" and a trailing slash. The pathspec "."
+/// refers to the root of the repository, and matches every path.
+fn normalize(item: &str) -> String {
+    let mut item = item;
+    while item.starts_with("./") {
+        item = &item[2..];
+    }
+    if item == "." {
+        item = "";
+    }
+    item.trim_right_matches('/').to_string()
+}
+
+impl Pathspec {
+    pub fn new(items: &[String]) -> Pathspec {
+        Pathspec {
+            originals: items.to_vec(),
+            items: items.iter().map(|item| normalize(item)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Find the index of the first pathspec matching the given path. An empty set of pathspecs
+    /// matches nothing.
+    pub fn matching_item(&self, path: &[u8]) -> Option<usize> {
+        let path = String::from_utf8_lossy(path);
+        self.items.iter().position(|item| {
+            item.is_empty() || *item == path ||
+                (path.starts_with(item.as_str()) && path.as_bytes()[item.len()] == b'/') ||
+                (is_glob(item) && ignore::wildmatch(item, &path, 0))
+        })
+    }
+
+    pub fn matches(&self, path: &[u8]) -> bool {
+        self.matching_item(path).is_some()
+    }
+
+    /// Whether the pathspec at the given index is a wildcard pattern.
+    pub fn is_wildcard(&self, item: usize) -> bool {
+        is_glob(&self.items[item])
+    }
+
+    /// Whether the pathspec at the given index names a specific file or directory, as opposed to
+    /// being a wildcard pattern or referring to the whole repository.
+    pub fn is_literal(&self, item: usize) -> bool {
+        !self.items[item].is_empty() && !self.is_wildcard(item)
+    }
+
+    /// Whether the pathspec at the given index names the path itself, rather than matching it by
+    /// naming one of its parent directories or by a wildcard.
+    pub fn names_exactly(&self, item: usize, path: &[u8]) -> bool {
+        self.items[item].as_bytes() == path
+    }
+
+    /// Whether the pathspec at the given index names a path inside the given directory, without
+    /// being a wildcard pattern.
+    pub fn names_path_inside(&self, item: usize, dir: &[u8]) -> bool {
+        let named = self.items[item].as_bytes();
+        !self.is_wildcard(item) && named.len() > dir.len() && named.starts_with(dir) &&
+            named[dir.len()] == b'/'
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_exact_paths_directories_and_wildcards() {
+        let pathspec = Pathspec::new(&["./src/".to_string(),
+                                       "README.md".to_string(),
+                                       "*.toml".to_string()]);
+
+        assert_eq!(Some(0), pathspec.matching_item(b"src/lib.rs"));
+        assert_eq!(Some(0), pathspec.matching_item(b"src/bin/log.rs"));
+        assert_eq!(Some(1), pathspec.matching_item(b"README.md"));
+        assert_eq!(Some(2), pathspec.matching_item(b"Cargo.toml"));
+        assert_eq!(Some(2), pathspec.matching_item(b"sub/Cargo.toml"));
+        assert_eq!(None, pathspec.matching_item(b"srcfile"));
+        assert_eq!(None, pathspec.matching_item(b"README.md.orig"));
+
+        let everything = Pathspec::new(&[".".to_string()]);
+        assert!(everything.matches(b"any/path"));
+    }
+
+    #[test]
+    fn names_paths_inside_directories() {
+        let pathspec = Pathspec::new(&["build/out/".to_string(), "build/*.o".to_string()]);
+        assert!(pathspec.names_path_inside(0, b"build"));
+        assert!(!pathspec.names_path_inside(0, b"build/out"));
+        assert!(!pathspec.names_path_inside(0, b"buil"));
+        assert!(!pathspec.names_path_inside(1, b"build"));
+    }
+}
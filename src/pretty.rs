@@ -0,0 +1,1081 @@
+//! Formats commits for `log`, either in one of the built-in formats, such as `medium` or
+//! `oneline`, or from a template of placeholders, such as `%h %s`, as described under "PRETTY
+//! FORMATS" in git-log(1).
+//!
+//! A template is parsed once, up front, so that mistakes in it are reported before anything is
+//! printed. Placeholders that aren't recognized are printed as they are, the same as in git.
+
+use color;
+use commits;
+use config;
+use objects;
+use refs;
+
+use chrono::format::{Item, StrftimeItems};
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidFormat(String),
+    InvalidDateFormat(String),
+    InvalidColor(String),
+    RefError(refs::Error),
+    ObjectError(objects::Error),
+    ConfigError(config::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidFormat(ref format) => write!(f, "invalid --pretty format: {}", format),
+            Error::InvalidDateFormat(ref format) => write!(f, "unknown date format {}", format),
+            Error::InvalidColor(ref color) => write!(f, "invalid color value: {}", color),
+            Error::RefError(ref err) => write!(f, "{}", err),
+            Error::ObjectError(ref err) => write!(f, "{}", err),
+            Error::ConfigError(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidFormat(_) => "invalid format",
+            Error::InvalidDateFormat(_) => "invalid date format",
+            Error::InvalidColor(_) => "invalid color",
+            Error::RefError(ref err) => err.description(),
+            Error::ObjectError(ref err) => err.description(),
+            Error::ConfigError(ref err) => err.description(),
+        }
+    }
+
+    fn source(&self) -> Option<&(StdError + 'static)> {
+        match *self {
+            Error::InvalidFormat(_) | Error::InvalidDateFormat(_) | Error::InvalidColor(_) => None,
+            Error::RefError(ref err) => Some(err),
+            Error::ObjectError(ref err) => Some(err),
+            Error::ConfigError(ref err) => Some(err),
+        }
+    }
+}
+
+/// How dates are shown, as chosen with `--date`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DateFormat {
+    /// Like `Thu Nov 16 01:46:40 2023 +0100`, in the commit's own timezone.
+    Default,
+
+    /// Like `3 weeks ago`.
+    Relative,
+    Short,
+    Iso,
+    IsoStrict,
+    Rfc,
+
+    /// The timestamp and timezone, as stored in the commit.
+    Raw,
+    Unix,
+
+    /// A `strftime` format, given as `format:<format>`.
+    Custom(String),
+}
+
+pub fn parse_date_format(value: &str) -> Result<DateFormat, Error> {
+    if value.starts_with("format:") {
+        let format = &value["format:".len()..];
+        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+            return Err(Error::InvalidDateFormat(value.to_string()));
+        }
+        return Ok(DateFormat::Custom(format.to_string()));
+    }
+
+    match value {
+        "default" => Ok(DateFormat::Default),
+        "relative" => Ok(DateFormat::Relative),
+        "short" => Ok(DateFormat::Short),
+        "iso" | "iso8601" => Ok(DateFormat::Iso),
+        "iso-strict" | "iso8601-strict" => Ok(DateFormat::IsoStrict),
+        "rfc" | "rfc2822" => Ok(DateFormat::Rfc),
+        "raw" => Ok(DateFormat::Raw),
+        "unix" => Ok(DateFormat::Unix),
+        _ => Err(Error::InvalidDateFormat(value.to_string())),
+    }
+}
+
+/// Describe how long ago something happened, given the number of seconds since, rounding the same
+/// way as git.
+fn relative_date(seconds: i64) -> String {
+    fn plural(count: i64, unit: &str) -> String {
+        format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+    }
+
+    if seconds < 0 {
+        return "in the future".to_string();
+    } else if seconds < 90 {
+        return format!("{} ago", plural(seconds, "second"));
+    }
+
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return format!("{} ago", plural(minutes, "minute"));
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return format!("{} ago", plural(hours, "hour"));
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return format!("{} ago", plural(days, "day"));
+    } else if days < 70 {
+        return format!("{} ago", plural((days + 3) / 7, "week"));
+    } else if days < 365 {
+        return format!("{} ago", plural((days + 15) / 30, "month"));
+    } else if days < 1825 {
+        let total_months = (days * 12 * 2 + 365) / (365 * 2);
+        let (years, months) = (total_months / 12, total_months % 12);
+        if months == 0 {
+            return format!("{} ago", plural(years, "year"));
+        }
+        return format!("{}, {} ago", plural(years, "year"), plural(months, "month"));
+    }
+
+    format!("{} ago", plural((days + 183) / 365, "year"))
+}
+
+/// Show a date in the given format. Relative dates are relative to `now`, a Unix timestamp.
+pub fn format_date(date: &commits::CommitDateTime, format: &DateFormat, now: i64) -> String {
+    match *format {
+        DateFormat::Default => date.format("%a %b %-d %H:%M:%S %Y %z").to_string(),
+        DateFormat::Relative => relative_date(now - date.timestamp()),
+        DateFormat::Short => date.format("%Y-%m-%d").to_string(),
+        DateFormat::Iso => date.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        DateFormat::IsoStrict => date.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+        DateFormat::Rfc => date.format("%a, %-d %b %Y %H:%M:%S %z").to_string(),
+        DateFormat::Raw => date.format("%s %z").to_string(),
+        DateFormat::Unix => date.timestamp().to_string(),
+        DateFormat::Custom(ref format) => date.format(format).to_string(),
+    }
+}
+
+/// The refs pointing to each commit, shown next to it by `--decorate` and `%d`.
+pub struct Decorations {
+    /// The names of the refs pointing to each commit, already in the order they're shown in.
+    names: HashMap<String, Vec<(Kind, String)>>,
+
+    /// The current branch, as it's shown, if `HEAD` is on one.
+    head_branch: Option<String>,
+    head_commit: Option<objects::Name>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Head,
+    Branch,
+    RemoteBranch,
+    Tag,
+    Other,
+}
+
+impl Decorations {
+    /// Find the refs pointing to every commit, shortening their names unless `full` is set. Tags
+    /// decorate the commit they point to, rather than the tag object.
+    pub fn load(full: bool) -> Result<Decorations, Error> {
+        let show = |refname: &str| {
+            if full { refname.to_string() } else { refs::shorten(refname).to_string() }
+        };
+
+        let mut names = HashMap::new();
+        let all_refs = try!(refs::all_refs().map_err(Error::RefError));
+
+        // Like in git, the refs are shown in the reverse order of their names.
+        for (refname, target) in all_refs.into_iter().rev() {
+            let (kind, target) = if refname.starts_with("refs/heads/") {
+                (Kind::Branch, target)
+            } else if refname.starts_with("refs/remotes/") {
+                (Kind::RemoteBranch, target)
+            } else if refname.starts_with("refs/tags/") {
+                (Kind::Tag, try!(objects::peel(&target).map_err(Error::ObjectError)))
+            } else {
+                (Kind::Other, target)
+            };
+
+            let name = if kind == Kind::Tag {
+                format!("tag: {}", show(&refname))
+            } else if kind == Kind::Other {
+                refname.clone()
+            } else {
+                show(&refname)
+            };
+            names.entry(target.0).or_insert(Vec::new()).push((kind, name));
+        }
+
+        let head = try!(refs::read_head().map_err(Error::RefError));
+        Ok(Decorations {
+            names: names,
+            head_branch: head.branch.map(|branch| {
+                if full { format!("refs/heads/{}", branch) } else { branch }
+            }),
+            head_commit: head.commit,
+        })
+    }
+
+    /// The decorations of a commit, with `HEAD` first, followed by the branch it's on.
+    fn for_commit(&self, name: &objects::Name) -> Vec<(Kind, String)> {
+        let mut decorations = self.names.get(&name.0).cloned().unwrap_or(Vec::new());
+        if self.head_commit.as_ref() == Some(name) {
+            let head = match self.head_branch {
+                Some(ref branch) => {
+                    let position = decorations.iter()
+                        .position(|&(kind, ref name)| kind == Kind::Branch && name == branch);
+                    if let Some(position) = position {
+                        decorations.remove(position);
+                    }
+                    format!("HEAD -> {}", branch)
+                },
+                None => "HEAD".to_string(),
+            };
+            decorations.insert(0, (Kind::Head, head));
+        }
+        decorations
+    }
+}
+
+/// The colors used for the parts of a commit, all of which are empty if the output isn't colored.
+pub struct Palette {
+    enabled: bool,
+    commit: String,
+    head: String,
+    branch: String,
+    remote_branch: String,
+    tag: String,
+    reset: &'static str,
+}
+
+impl Palette {
+    pub fn new(colors: &color::Colors) -> Result<Palette, config::Error> {
+        Ok(Palette {
+            enabled: colors.enabled(),
+            commit: try!(colors.get(&color::DIFF_COMMIT)),
+            head: try!(colors.get(&color::DECORATE_HEAD)),
+            branch: try!(colors.get(&color::DECORATE_BRANCH)),
+            remote_branch: try!(colors.get(&color::DECORATE_REMOTE)),
+            tag: try!(colors.get(&color::DECORATE_TAG)),
+            reset: colors.reset(),
+        })
+    }
+
+    fn for_kind(&self, kind: Kind) -> &str {
+        match kind {
+            Kind::Head => &self.head,
+            Kind::Branch => &self.branch,
+            Kind::RemoteBranch => &self.remote_branch,
+            Kind::Tag => &self.tag,
+            Kind::Other => "",
+        }
+    }
+
+    /// Wrap text in a color, if it has one.
+    fn paint(&self, color: &str, text: &str) -> String {
+        if color.is_empty() {
+            text.to_string()
+        } else {
+            format!("{}{}{}", color, text, self.reset)
+        }
+    }
+}
+
+/// Everything about how commits are shown that doesn't depend on the format.
+pub struct Context<'a> {
+    pub palette: Palette,
+
+    /// The minimum length of abbreviated object names.
+    pub abbrev: usize,
+
+    /// Whether the built-in formats abbreviate the name of the commit itself.
+    pub abbrev_commit: bool,
+
+    /// The format of dates, if it was chosen. Otherwise, each format uses its own default.
+    pub date_format: Option<DateFormat>,
+
+    /// The refs pointing to commits, if they were loaded. The built-in formats only show them if
+    /// `decorate` is set, while `%d` always does.
+    pub decorations: Option<&'a Decorations>,
+    pub decorate: bool,
+
+    /// The current time, as a Unix timestamp, which relative dates are relative to.
+    pub now: i64,
+}
+
+impl<'a> Context<'a> {
+    fn abbreviate(&self, name: &objects::Name) -> String {
+        objects::abbreviate(name, self.abbrev)
+    }
+
+    fn date(&self, date: &commits::CommitDateTime, format: Option<&DateFormat>) -> String {
+        let format = format.or(self.date_format.as_ref()).unwrap_or(&DateFormat::Default);
+        format_date(date, format, self.now)
+    }
+
+    /// Show the refs pointing to a commit, joined by commas and wrapped in the given prefix and
+    /// suffix, such as ` (HEAD -> master, tag: v1.0)`. Nothing is shown if there are none.
+    fn decorations(&self, name: &objects::Name, prefix: &str, suffix: &str, colored: bool)
+            -> String {
+        let decorations = match self.decorations {
+            Some(decorations) => decorations.for_commit(name),
+            None => return String::new(),
+        };
+        if decorations.is_empty() {
+            return String::new();
+        }
+
+        let palette = &self.palette;
+        let punctuation = |text: &str| {
+            if colored { palette.paint(&palette.commit, text) } else { text.to_string() }
+        };
+
+        let mut shown = punctuation(prefix);
+        for (i, &(kind, ref name)) in decorations.iter().enumerate() {
+            if i > 0 {
+                shown.push_str(&punctuation(", "));
+            }
+            if !colored {
+                shown.push_str(name);
+            } else if kind == Kind::Head && name.starts_with("HEAD -> ") {
+                shown.push_str(&palette.paint(&palette.head, "HEAD -> "));
+                shown.push_str(&palette.paint(&palette.branch, &name["HEAD -> ".len()..]));
+            } else {
+                shown.push_str(&palette.paint(palette.for_kind(kind), name));
+            }
+        }
+        shown.push_str(&punctuation(suffix));
+        shown
+    }
+}
+
+/// The first paragraph of the message, which is its subject.
+fn subject_lines(message: &str) -> Vec<&str> {
+    message.lines().take_while(|line| !line.trim().is_empty()).collect()
+}
+
+/// The subject of the message, with its lines joined into one.
+fn subject(message: &str) -> String {
+    subject_lines(message).iter().map(|line| line.trim()).collect::<Vec<_>>().join(" ")
+}
+
+/// Everything in the message after the subject, with each line ending in a newline.
+fn body(message: &str) -> String {
+    message.lines()
+        .skip_while(|line| !line.trim().is_empty())
+        .skip_while(|line| line.trim().is_empty())
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Indent each line of a message the way the built-in formats do.
+fn indent<'b, I>(lines: I) -> String
+        where I: IntoIterator<Item = &'b str> {
+    lines.into_iter().map(|line| format!("    {}\n", line)).collect()
+}
+
+/// The name and email of someone, given as `Name <email>` in the commit.
+fn split_ident(ident: &str) -> (&str, &str) {
+    match (ident.find('<'), ident.rfind('>')) {
+        (Some(start), Some(end)) if start < end => (ident[..start].trim(), &ident[start + 1..end]),
+        _ => (ident.trim(), ""),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Who {
+    Author,
+    Committer,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Placeholder {
+    Hash,
+    AbbrevHash,
+    Tree,
+    AbbrevTree,
+    Parents,
+    AbbrevParents,
+    Name(Who),
+    Email(Who),
+
+    /// A date, in the given format, or else in the format chosen with `--date`.
+    Date(Who, Option<DateFormat>),
+    Subject,
+    Body,
+    RawBody,
+    Decorations,
+    BareDecorations,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Truncation {
+    None,
+    Right,
+    Left,
+    Middle,
+}
+
+/// Padding, and possibly truncation, of the next placeholder to a width, or up to a column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Alignment {
+    width: usize,
+    column: bool,
+    side: Side,
+    truncation: Truncation,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+
+    /// A color, which is only used if the output is colored, unless it's always to be used.
+    Color(String, bool),
+
+    /// Color the following placeholders in the same way as the built-in formats do.
+    AutoColor,
+    Align(Alignment),
+}
+
+/// A format given as a template of placeholders, like `%h %s`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+fn parse_date_placeholder(chr: char) -> Option<Option<DateFormat>> {
+    match chr {
+        'd' => Some(None),
+        'D' => Some(Some(DateFormat::Rfc)),
+        'r' => Some(Some(DateFormat::Relative)),
+        't' => Some(Some(DateFormat::Unix)),
+        'i' => Some(Some(DateFormat::Iso)),
+        'I' => Some(Some(DateFormat::IsoStrict)),
+        's' => Some(Some(DateFormat::Short)),
+        _ => None,
+    }
+}
+
+/// Parse the arguments of an alignment placeholder, like `20,trunc` in `%<(20,trunc)`.
+fn parse_alignment(arguments: &str, side: Side, column: bool) -> Option<Alignment> {
+    let mut arguments = arguments.splitn(2, ',');
+    let width = match arguments.next().and_then(|width| width.trim().parse().ok()) {
+        Some(width) => width,
+        None => return None,
+    };
+    let truncation = match arguments.next().map(|truncation| truncation.trim()) {
+        None => Truncation::None,
+        Some("trunc") => Truncation::Right,
+        Some("ltrunc") => Truncation::Left,
+        Some("mtrunc") => Truncation::Middle,
+        Some(_) => return None,
+    };
+    Some(Alignment { width: width, column: column, side: side, truncation: truncation })
+}
+
+/// Parse what follows a `%` at the start of the text, returning the part it stands for and how
+/// much of the text it takes up, or nothing if it isn't a placeholder.
+fn parse_placeholder(text: &str) -> Result<Option<(Part, usize)>, Error> {
+    let mut chars = text.chars();
+    let first = match chars.next() {
+        Some(chr) => chr,
+        None => return Ok(None),
+    };
+    let second = chars.next();
+
+    let simple = |placeholder| Ok(Some((Part::Placeholder(placeholder), 1)));
+    match first {
+        '%' => return Ok(Some((Part::Literal("%".to_string()), 1))),
+        'n' => return Ok(Some((Part::Literal("\n".to_string()), 1))),
+        'H' => return simple(Placeholder::Hash),
+        'h' => return simple(Placeholder::AbbrevHash),
+        'T' => return simple(Placeholder::Tree),
+        't' => return simple(Placeholder::AbbrevTree),
+        'P' => return simple(Placeholder::Parents),
+        'p' => return simple(Placeholder::AbbrevParents),
+        's' => return simple(Placeholder::Subject),
+        'b' => return simple(Placeholder::Body),
+        'B' => return simple(Placeholder::RawBody),
+        'd' => return simple(Placeholder::Decorations),
+        'D' => return simple(Placeholder::BareDecorations),
+        'x' => {
+            return Ok(text.get(1..3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .map(|byte| (Part::Literal((byte as char).to_string()), 3)));
+        },
+        _ => {},
+    }
+
+    if first == 'a' || first == 'c' {
+        let who = if first == 'a' { Who::Author } else { Who::Committer };
+        let placeholder = match second {
+            Some('n') => Placeholder::Name(who),
+            Some('e') => Placeholder::Email(who),
+            Some(chr) => match parse_date_placeholder(chr) {
+                Some(format) => Placeholder::Date(who, format),
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        return Ok(Some((Part::Placeholder(placeholder), 2)));
+    }
+
+    if first == 'C' {
+        for &(name, spec) in [("red", "red"), ("green", "green"), ("blue", "blue"),
+                              ("reset", "reset")].iter() {
+            if text[1..].starts_with(name) {
+                return Ok(Some((Part::Color(color::parse(spec).unwrap(), false),
+                                1 + name.len())));
+            }
+        }
+
+        if !text[1..].starts_with('(') {
+            return Ok(None);
+        }
+        let end = match text.find(')') {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+        let spec = &text[2..end];
+        if spec == "auto" {
+            return Ok(Some((Part::AutoColor, end + 1)));
+        }
+
+        let (spec, always) = if spec.starts_with("always,") {
+            (&spec["always,".len()..], true)
+        } else if spec.starts_with("auto,") {
+            (&spec["auto,".len()..], false)
+        } else {
+            (spec, false)
+        };
+        let escape = try!(color::parse(spec).ok_or(Error::InvalidColor(spec.to_string())));
+        return Ok(Some((Part::Color(escape, always), end + 1)));
+    }
+
+    if first == '<' || first == '>' {
+        let (side, rest) = if text.starts_with("><") {
+            (Side::Center, &text[2..])
+        } else if first == '<' {
+            (Side::Left, &text[1..])
+        } else {
+            (Side::Right, &text[1..])
+        };
+        let (column, rest) = if rest.starts_with('|') { (true, &rest[1..]) } else { (false, rest) };
+        if !rest.starts_with('(') {
+            return Ok(None);
+        }
+        let end = match rest.find(')') {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+
+        let length = text.len() - rest.len() + end + 1;
+        return Ok(parse_alignment(&rest[1..end], side, column)
+                  .map(|alignment| (Part::Align(alignment), length)));
+    }
+
+    Ok(None)
+}
+
+pub fn parse_template(format: &str) -> Result<Template, Error> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut rest = format;
+    while let Some(start) = rest.find('%') {
+        literal.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        match try!(parse_placeholder(rest)) {
+            Some((Part::Literal(text), length)) => {
+                literal.push_str(&text);
+                rest = &rest[length..];
+            },
+            Some((part, length)) => {
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(literal.clone()));
+                    literal.clear();
+                }
+                parts.push(part);
+                rest = &rest[length..];
+            },
+            None => literal.push('%'),
+        }
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+
+    Ok(Template { parts: parts })
+}
+
+/// The number of characters the text takes up on the screen, leaving out color escapes.
+fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for chr in text.chars() {
+        if in_escape {
+            in_escape = chr != 'm';
+        } else if chr == '\x1b' {
+            in_escape = true;
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+/// Replace the characters of the text in the given range with the replacement, counting only the
+/// characters that show up on the screen. Like in git, color escapes are always kept, even those
+/// within the range, so that a color that's started is still reset.
+fn replace_visible(text: &str, range: Range<usize>, replacement: &str) -> String {
+    let mut replaced = String::new();
+    let mut position = 0;
+    let mut in_escape = false;
+    for chr in text.chars() {
+        if in_escape || chr == '\x1b' {
+            in_escape = chr != 'm';
+            replaced.push(chr);
+            continue;
+        }
+
+        if position == range.start {
+            replaced.push_str(replacement);
+        }
+        if !range.contains(&position) {
+            replaced.push(chr);
+        }
+        position += 1;
+    }
+    replaced
+}
+
+/// Pad or truncate text to the width given by an alignment, starting at the given column.
+fn align(text: String, alignment: &Alignment, column: usize) -> String {
+    let width = if alignment.column {
+        alignment.width.saturating_sub(column)
+    } else {
+        alignment.width
+    };
+
+    let length = display_width(&text);
+    if length > width {
+        let kept = width.saturating_sub(2);
+        let dropped = match alignment.truncation {
+            Truncation::None => return text,
+            Truncation::Right => kept..length,
+            Truncation::Left => 0..length - kept,
+            Truncation::Middle => kept / 2..length - (kept - kept / 2),
+        };
+        return replace_visible(&text, dropped, "..");
+    }
+
+    let padding = width - length;
+    let spaces = |count| " ".repeat(count);
+    match alignment.side {
+        Side::Left => text + &spaces(padding),
+        Side::Right => spaces(padding) + &text,
+        Side::Center => spaces(padding / 2) + &text + &spaces(padding - padding / 2),
+    }
+}
+
+impl Template {
+    /// Whether the template shows any decorations, which then have to be loaded.
+    pub fn uses_decorations(&self) -> bool {
+        self.parts.iter().any(|part| {
+            *part == Part::Placeholder(Placeholder::Decorations) ||
+                *part == Part::Placeholder(Placeholder::BareDecorations)
+        })
+    }
+
+    fn expand(&self, placeholder: &Placeholder, commit: &commits::Commit, context: &Context,
+              auto_color: bool) -> String {
+        let palette = &context.palette;
+        let user = |who| if who == Who::Author { &commit.author } else { &commit.committer };
+        let hash_color = |hash: String| {
+            if auto_color { palette.paint(&palette.commit, &hash) } else { hash }
+        };
+
+        match *placeholder {
+            Placeholder::Hash => hash_color(commit.name.0.clone()),
+            Placeholder::AbbrevHash => hash_color(context.abbreviate(&commit.name)),
+            Placeholder::Tree => commit.tree.0.clone(),
+            Placeholder::AbbrevTree => context.abbreviate(&commit.tree),
+            Placeholder::Parents => {
+                commit.parents.iter().map(|parent| parent.0.clone()).collect::<Vec<_>>().join(" ")
+            },
+            Placeholder::AbbrevParents => {
+                commit.parents.iter().map(|parent| context.abbreviate(parent))
+                    .collect::<Vec<_>>().join(" ")
+            },
+            Placeholder::Name(who) => split_ident(&user(who).name).0.to_string(),
+            Placeholder::Email(who) => split_ident(&user(who).name).1.to_string(),
+            Placeholder::Date(who, ref format) => context.date(&user(who).date, format.as_ref()),
+            Placeholder::Subject => subject(&commit.message),
+            Placeholder::Body => body(&commit.message),
+            Placeholder::RawBody => format!("{}\n", commit.message),
+            Placeholder::Decorations =>
+                context.decorations(&commit.name, " (", ")", auto_color),
+            Placeholder::BareDecorations => context.decorations(&commit.name, "", "", auto_color),
+        }
+    }
+
+    fn render(&self, commit: &commits::Commit, context: &Context) -> String {
+        let mut output = String::new();
+        let mut alignment = None;
+        let mut auto_color = false;
+        for part in self.parts.iter() {
+            match *part {
+                Part::Literal(ref text) => output.push_str(text),
+                Part::Placeholder(ref placeholder) => {
+                    let text = self.expand(placeholder, commit, context, auto_color);
+                    match alignment.take() {
+                        Some(alignment) => {
+                            let line_start = output.rfind('\n').map(|i| i + 1).unwrap_or(0);
+                            let column = display_width(&output[line_start..]);
+                            output.push_str(&align(text, &alignment, column));
+                        },
+                        None => output.push_str(&text),
+                    }
+                },
+                Part::Color(ref escape, always) => {
+                    auto_color = false;
+                    if always || context.palette.enabled {
+                        output.push_str(escape);
+                    }
+                },
+                Part::AutoColor => {
+                    // Like in git, the colors before it are reset, if there was anything before.
+                    auto_color = context.palette.enabled;
+                    if !output.is_empty() {
+                        output.push_str(context.palette.reset);
+                    }
+                },
+                Part::Align(next) => alignment = Some(next),
+            }
+        }
+        output
+    }
+}
+
+/// The format commits are shown in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Oneline,
+    Short,
+    Medium,
+    Full,
+    Fuller,
+    Reference,
+    Email,
+    Raw,
+
+    /// A template, which is either followed by a newline, like with `tformat:`, or separated from
+    /// the next commit by one, like with `format:`.
+    Template(Template, bool),
+}
+
+/// Parse a format given to `--pretty`: the name of a built-in format, a template prefixed with
+/// `format:` or `tformat:`, or the name of a format defined by `pretty.<name>`. Anything with a
+/// `%` in it is taken as a template, the same as with `tformat:`.
+pub fn parse_format(value: &str, config: &config::Config) -> Result<Format, Error> {
+    let mut value = value.to_string();
+
+    // Formats defined in the config can refer to others, but not in a loop.
+    let mut seen = Vec::new();
+    loop {
+        if value.starts_with("format:") {
+            return Ok(Format::Template(try!(parse_template(&value["format:".len()..])), false));
+        } else if value.starts_with("tformat:") {
+            return Ok(Format::Template(try!(parse_template(&value["tformat:".len()..])), true));
+        } else if value.contains('%') {
+            return Ok(Format::Template(try!(parse_template(&value)), true));
+        }
+
+        match value.as_str() {
+            "oneline" => return Ok(Format::Oneline),
+            "short" => return Ok(Format::Short),
+            "medium" => return Ok(Format::Medium),
+            "full" => return Ok(Format::Full),
+            "fuller" => return Ok(Format::Fuller),
+            "reference" => return Ok(Format::Reference),
+            "email" => return Ok(Format::Email),
+            "raw" => return Ok(Format::Raw),
+            _ => {},
+        }
+
+        let defined = try!(config.get_string(&format!("pretty.{}", value))
+                           .map_err(Error::ConfigError));
+        match defined {
+            Some(defined) if !seen.contains(&value) => {
+                seen.push(value.clone());
+                value = defined.to_string();
+            },
+            _ => return Err(Error::InvalidFormat(value)),
+        }
+    }
+}
+
+impl Format {
+    /// What goes between the commits, in addition to whatever ends each of them. The formats that
+    /// take up more than one line have a blank line between commits.
+    pub fn separator(&self) -> &'static str {
+        match *self {
+            Format::Oneline | Format::Reference | Format::Template(_, true) => "",
+            _ => "\n",
+        }
+    }
+
+    /// What follows each commit, after it's formatted. Only the formats without a separator are
+    /// terminated by a newline, and not even them if the template is empty, like `--format=`.
+    pub fn terminator(&self) -> &'static str {
+        match *self {
+            Format::Template(ref template, true) if template.parts.is_empty() => "",
+            Format::Oneline | Format::Reference | Format::Template(_, true) => "\n",
+            _ => "",
+        }
+    }
+
+    /// Whether the format shows decorations, which then have to be loaded, even if `--decorate`
+    /// isn't on.
+    pub fn uses_decorations(&self) -> bool {
+        match *self {
+            Format::Template(ref template, _) => template.uses_decorations(),
+            _ => false,
+        }
+    }
+}
+
+/// The first line of the formats showing the whole commit.
+fn commit_line(commit: &commits::Commit, context: &Context) -> String {
+    let name = if context.abbrev_commit {
+        context.abbreviate(&commit.name)
+    } else {
+        commit.name.0.clone()
+    };
+    let decorations = if context.decorate {
+        context.decorations(&commit.name, " (", ")", true)
+    } else {
+        String::new()
+    };
+
+    let palette = &context.palette;
+    let mut line = palette.paint(&palette.commit, &format!("commit {}", name));
+    line.push_str(&decorations);
+    line.push('\n');
+    if commit.parents.len() > 1 {
+        let parents: Vec<_> =
+            commit.parents.iter().map(|parent| context.abbreviate(parent)).collect();
+        line.push_str(&format!("Merge: {}\n", parents.join(" ")));
+    }
+    line
+}
+
+/// Show a commit in the given format, without the terminator that follows the formats that are
+/// terminated by newlines, rather than separated by them.
+pub fn format_commit(commit: &commits::Commit, format: &Format, context: &Context) -> String {
+    let author = &commit.author;
+    let committer = &commit.committer;
+    let message = &commit.message;
+    match *format {
+        Format::Oneline => {
+            let name = if context.abbrev_commit {
+                context.abbreviate(&commit.name)
+            } else {
+                commit.name.0.clone()
+            };
+            let decorations = if context.decorate {
+                context.decorations(&commit.name, " (", ")", true)
+            } else {
+                String::new()
+            };
+
+            format!("{}{} {}", context.palette.paint(&context.palette.commit, &name),
+                    decorations, subject(message))
+        },
+        Format::Short => {
+            format!("{}Author: {}\n\n{}", commit_line(commit, context), author.name,
+                    indent(subject_lines(message)))
+        },
+        Format::Medium => {
+            format!("{}Author: {}\nDate:   {}\n\n{}", commit_line(commit, context), author.name,
+                    context.date(&author.date, None), indent(message.lines()))
+        },
+        Format::Full => {
+            format!("{}Author: {}\nCommit: {}\n\n{}", commit_line(commit, context), author.name,
+                    committer.name, indent(message.lines()))
+        },
+        Format::Fuller => {
+            format!("{}Author:     {}\nAuthorDate: {}\nCommit:     {}\nCommitDate: {}\n\n{}",
+                    commit_line(commit, context), author.name, context.date(&author.date, None),
+                    committer.name, context.date(&committer.date, None), indent(message.lines()))
+        },
+        Format::Reference => {
+            let name = context.palette.paint(&context.palette.commit,
+                                             &context.abbreviate(&commit.name));
+            format!("{} ({}, {})", name, subject(message),
+                    context.date(&author.date, Some(&DateFormat::Short)))
+        },
+        Format::Email => {
+            format!("From {} Mon Sep 17 00:00:00 2001\nFrom: {}\nDate: {}\n\
+                     Subject: [PATCH] {}\n\n{}",
+                    commit.name.0, author.name, format_date(&author.date, &DateFormat::Rfc, 0),
+                    subject(message), body(message))
+        },
+        Format::Raw => {
+            let mut headers = format!("tree {}\n", commit.tree.0);
+            for parent in commit.parents.iter() {
+                headers.push_str(&format!("parent {}\n", parent.0));
+            }
+            let raw_date = |date| format_date(date, &DateFormat::Raw, 0);
+            format!("{}\n{}author {} {}\ncommitter {} {}\n\n{}",
+                    commit_line(commit, context).lines().next().unwrap_or(""), headers,
+                    author.name, raw_date(&author.date), committer.name,
+                    raw_date(&committer.date), indent(message.lines()))
+        },
+        Format::Template(ref template, _) => template.render(commit, context),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::{relative_date, Palette};
+
+    use chrono::{FixedOffset, TimeZone};
+    use commits;
+    use objects::Name;
+
+    fn commit() -> commits::Commit {
+        let name = |hex: &str| Name(hex.repeat(40 / hex.len()));
+        commits::Commit {
+            name: name("77a2452729"),
+            tree: name("3683f870be"),
+            parents: vec![name("1c1ae27343")],
+            author: commits::CommitUser {
+                name: "Ann Author <ann@example.com>".to_string(),
+                date: FixedOffset::west(5 * 3600).timestamp(1700003600, 0),
+            },
+            committer: commits::CommitUser {
+                name: "Cy Committer <cy@example.com>".to_string(),
+                date: FixedOffset::west(5 * 3600).timestamp(1700007200, 0),
+            },
+            message: "Add b on topic\n\nThis adds the b file\nacross two lines.".to_string(),
+        }
+    }
+
+    fn context<'a>() -> Context<'a> {
+        Context {
+            palette: Palette {
+                enabled: false,
+                commit: String::new(),
+                head: String::new(),
+                branch: String::new(),
+                remote_branch: String::new(),
+                tag: String::new(),
+                reset: "",
+            },
+            abbrev: 40,
+            abbrev_commit: false,
+            date_format: None,
+            decorations: None,
+            decorate: false,
+            now: 1700007200 + 3 * 86400,
+        }
+    }
+
+    fn render(format: &str) -> String {
+        format_commit(&commit(), &Format::Template(parse_template(format).unwrap(), false),
+                      &context())
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        assert_eq!("Ann Author <ann@example.com> / Cy Committer", render("%an <%ae> / %cn"));
+        assert_eq!("Tue Nov 14 18:13:20 2023 -0500, 3 days ago, 2023-11-14",
+                   render("%ad, %cr, %as"));
+        assert_eq!("Add b on topic|This adds the b file\nacross two lines.\n|",
+                   render("%s|%b|"));
+        assert_eq!(format!("{}..{}", "1c1ae27343".repeat(4), "3683f870be".repeat(4)),
+                   render("%P..%T"));
+        assert_eq!("100% %z%n\n", render("100%% %z%%n%n"));
+    }
+
+    #[test]
+    fn aligns_placeholders() {
+        assert_eq!("[Ann Author  ]", render("[%<(12)%an]"));
+        assert_eq!("[  Ann Author]", render("[%>(12)%an]"));
+        assert_eq!("[ Ann Author ]", render("[%><(12)%an]"));
+        assert_eq!("[Add b ..]", render("[%<(8,trunc)%s]"));
+        assert_eq!("[..topic]", render("[%<(7,ltrunc)%s]"));
+        assert_eq!("[Add..pic]", render("[%<(8,mtrunc)%s]"));
+        assert_eq!("[Ann Author <ann@example.com>]", render("[%<(5)%an <%ae>]"));
+        assert_eq!("[Ann Author   ]", render("[%<|(14)%an]"));
+        assert_eq!("Ann AuthorAdd b on topic", render("%an%<|(12)%s"));
+    }
+
+    #[test]
+    fn truncates_colored_placeholders_without_losing_escapes() {
+        let mut context = context();
+        context.palette.enabled = true;
+        context.palette.commit = "\x1b[33m".to_string();
+        context.palette.reset = "\x1b[m";
+        let format = Format::Template(parse_template("%C(auto)%<(6,trunc)%h|").unwrap(), false);
+        assert_eq!("\x1b[33m77a2..\x1b[m|", format_commit(&commit(), &format, &context));
+
+        let format = Format::Template(parse_template("%C(auto)%<(6,ltrunc)%h|").unwrap(), false);
+        assert_eq!("\x1b[33m..2729\x1b[m|", format_commit(&commit(), &format, &context));
+    }
+
+    #[test]
+    fn shows_built_in_formats() {
+        let context = context();
+        assert_eq!(format!("commit {}\nAuthor: Ann Author <ann@example.com>\n\
+                            Date:   Tue Nov 14 18:13:20 2023 -0500\n\n    Add b on topic\n    \n\
+                            \x20   This adds the b file\n    across two lines.\n",
+                           commit().name.0),
+                   format_commit(&commit(), &Format::Medium, &context));
+        assert_eq!(format!("{} (Add b on topic, 2023-11-14)", commit().name.0),
+                   format_commit(&commit(), &Format::Reference, &context));
+    }
+
+    #[test]
+    fn describes_relative_dates() {
+        assert_eq!("1 second ago", relative_date(1));
+        assert_eq!("89 seconds ago", relative_date(89));
+        assert_eq!("2 minutes ago", relative_date(90));
+        assert_eq!("3 days ago", relative_date(3 * 86400));
+        assert_eq!("5 weeks ago", relative_date(35 * 86400));
+        assert_eq!("1 year, 2 months ago", relative_date(430 * 86400));
+        assert_eq!("6 years ago", relative_date(2200 * 86400));
+        assert_eq!("in the future", relative_date(-1));
+    }
+
+    #[test]
+    fn parses_formats() {
+        let config = config::Config::new();
+        assert_eq!(Format::Fuller, parse_format("fuller", &config).unwrap());
+        assert_eq!(Format::Template(parse_template("%h").unwrap(), false),
+                   parse_format("format:%h", &config).unwrap());
+        assert_eq!(Format::Template(parse_template("%h").unwrap(), true),
+                   parse_format("%h", &config).unwrap());
+        assert!(parse_format("nonsense", &config).is_err());
+        assert!(parse_template("%C(nonsense)").is_err());
+        assert_eq!(DateFormat::Custom("%Y".to_string()), parse_date_format("format:%Y").unwrap());
+    }
+}
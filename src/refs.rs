@@ -0,0 +1,246 @@
+//! Provides functionality for reading refs, the named pointers into the object database such as
+//! branches and `HEAD`. Refs are stored either as "loose" files under `.git/`, or collected into
+//! the `.git/packed-refs` file, with the loose files taking precedence.
+
+use objects;
+use repository;
+
+use regex::Regex;
+
+use std::{error, fmt, fs, io};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Read};
+
+#[derive(Debug)]
+pub enum Error {
+    /// A file storing refs couldn't be read.
+    IOError(PathBuf, io::Error),
+
+    /// The ref exists, but doesn't point to anything, such as when its file can't be parsed or it's
+    /// part of a loop of symbolic refs.
+    InvalidRef(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::IOError(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            Error::InvalidRef(ref name) => write!(f, "invalid ref: {}", name),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::IOError(_, ref err) => err.description(),
+            Error::InvalidRef(ref name) => name,
+        }
+    }
+
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::IOError(_, ref err) => Some(err),
+            Error::InvalidRef(_) => None,
+        }
+    }
+}
+
+/// Symbolic refs pointing to other symbolic refs are followed at most this many times, the same
+/// limit git uses, so that a cycle of symbolic refs is reported instead of looping forever.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+const BRANCH_PREFIX: &'static str = "refs/heads/";
+
+/// The state of `HEAD`: either attached to a branch, which may not have any commits yet, or
+/// detached at a specific commit.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Head {
+    /// The name of the current branch, without the `refs/heads/` prefix. `None` when `HEAD` is
+    /// detached.
+    pub branch: Option<String>,
+
+    /// The commit `HEAD` points to. `None` on an unborn branch, such as in a freshly initialized
+    /// repository.
+    pub commit: Option<objects::Name>,
+}
+
+enum RefContents {
+    Symbolic(String),
+    Direct(objects::Name),
+}
+
+fn read_loose_ref(refname: &str) -> Result<Option<RefContents>, Error> {
+    lazy_static! {
+        static ref SYMBOLIC_REF_REGEX: Regex = Regex::new(r"^ref: (?P<ref>.+)\s*$").unwrap();
+        static ref SHA1_REGEX: Regex = Regex::new(r"^(?P<sha1>[0-9a-f]{40})\s*$").unwrap();
+    }
+
+    let ref_filename = repository::git_path(refname);
+
+    let mut file = match File::open(&ref_filename) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        // A directory exists with the name of the ref, like `refs/heads` itself.
+        Err(_) if ref_filename.is_dir() => return Ok(None),
+        Err(err) => return Err(Error::IOError(ref_filename, err)),
+    };
+
+    let mut contents = String::new();
+    try!(file.read_to_string(&mut contents).map_err(|err| Error::IOError(ref_filename, err)));
+
+    if let Some(caps) = SYMBOLIC_REF_REGEX.captures(&contents) {
+        Ok(Some(RefContents::Symbolic(caps["ref"].trim().to_string())))
+    } else if let Some(caps) = SHA1_REGEX.captures(&contents) {
+        Ok(Some(RefContents::Direct(objects::Name(caps["sha1"].to_string()))))
+    } else {
+        Err(Error::InvalidRef(refname.to_string()))
+    }
+}
+
+fn read_packed_ref(refname: &str) -> Result<Option<objects::Name>, Error> {
+    let path = repository::git_path("packed-refs");
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::IOError(path, err)),
+    };
+
+    // Each line is either a header comment, a "<sha1> <refname>" pair, or a "^<sha1>" line giving
+    // the commit an annotated tag on the preceding line points to.
+    for line in BufReader::new(file).lines() {
+        let line = try!(line.map_err(|err| Error::IOError(path.clone(), err)));
+        if line.starts_with('#') || line.starts_with('^') {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, ' ');
+        match (parts.next(), parts.next()) {
+            (Some(sha1), Some(name)) if name == refname =>
+                return Ok(Some(objects::Name(sha1.to_string()))),
+            _ => continue,
+        }
+    }
+
+    Ok(None)
+}
+
+/// Resolve a fully-qualified ref, such as `refs/heads/master` or `HEAD`, to the object it points
+/// to, following any symbolic refs along the way. Returns `None` if the ref doesn't exist.
+pub fn resolve(refname: &str) -> Result<Option<objects::Name>, Error> {
+    let mut current = refname.to_string();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        match try!(read_loose_ref(&current)) {
+            Some(RefContents::Direct(name)) => {
+                trace!("refs: resolved {} to {}", refname, name.0);
+                return Ok(Some(name));
+            },
+            Some(RefContents::Symbolic(target)) => {
+                trace!("refs: {} points to {}", current, target);
+                current = target;
+            },
+            None => {
+                let name = try!(read_packed_ref(&current));
+                match name {
+                    Some(ref name) => trace!("refs: resolved {} to {} from packed-refs",
+                                             refname, name.0),
+                    None => trace!("refs: {} not found", current),
+                }
+                return Ok(name);
+            },
+        }
+    }
+
+    Err(Error::InvalidRef(refname.to_string()))
+}
+
+/// Collect the names of the loose refs in a directory under `.git/`, such as `refs/heads`,
+/// including those in its subdirectories.
+fn loose_ref_names(dir: &str, names: &mut Vec<String>) -> Result<(), Error> {
+    let path = repository::git_path(dir);
+    let entries = match fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(Error::IOError(path, err)),
+    };
+
+    for entry in entries {
+        let entry = try!(entry.map_err(|err| Error::IOError(path.clone(), err)));
+        let name = match entry.file_name().into_string() {
+            Ok(name) => format!("{}/{}", dir, name),
+            Err(_) => continue,
+        };
+
+        if entry.path().is_dir() {
+            try!(loose_ref_names(&name, names));
+        } else {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// List every ref under `refs/`, such as branches and tags, along with the object it points to,
+/// sorted by name. Like when resolving a single ref, loose refs take precedence over packed ones.
+pub fn all_refs() -> Result<Vec<(String, objects::Name)>, Error> {
+    let mut refs = BTreeMap::new();
+
+    let path = repository::git_path("packed-refs");
+    match File::open(&path) {
+        Ok(file) => for line in BufReader::new(file).lines() {
+            let line = try!(line.map_err(|err| Error::IOError(path.clone(), err)));
+            if line.starts_with('#') || line.starts_with('^') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, ' ');
+            if let (Some(sha1), Some(name)) = (parts.next(), parts.next()) {
+                refs.insert(name.to_string(), objects::Name(sha1.to_string()));
+            }
+        },
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {},
+        Err(err) => return Err(Error::IOError(path, err)),
+    }
+
+    let mut names = Vec::new();
+    try!(loose_ref_names("refs", &mut names));
+    for name in names {
+        // A symbolic ref that doesn't lead anywhere, like the default branch of a remote that was
+        // never fetched, is left out, the same as in git.
+        if let Some(target) = try!(resolve(&name)) {
+            refs.insert(name, target);
+        }
+    }
+
+    Ok(refs.into_iter().collect())
+}
+
+/// Read `HEAD` to determine the current branch, if any, and the commit it points to.
+pub fn read_head() -> Result<Head, Error> {
+    match try!(read_loose_ref("HEAD")) {
+        Some(RefContents::Direct(name)) => Ok(Head { branch: None, commit: Some(name) }),
+        Some(RefContents::Symbolic(target)) => {
+            let commit = try!(resolve(&target));
+            let branch = if target.starts_with(BRANCH_PREFIX) {
+                target[BRANCH_PREFIX.len()..].to_string()
+            } else {
+                target
+            };
+            Ok(Head { branch: Some(branch), commit: commit })
+        },
+        None => Err(Error::InvalidRef("HEAD".to_string())),
+    }
+}
+
+/// Shorten a fully-qualified ref name for display, such as `refs/remotes/origin/master` to
+/// `origin/master`.
+pub fn shorten(refname: &str) -> &str {
+    for prefix in ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"].iter() {
+        if refname.starts_with(prefix) {
+            return &refname[prefix.len()..];
+        }
+    }
+    refname
+}
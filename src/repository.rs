@@ -0,0 +1,61 @@
+//! Locates the files that make up the repository. Commands run from the root of the working tree,
+//! where the repository is normally the `.git` directory, but `$GIT_DIR` can point somewhere else,
+//! such as when it's set with `gitters --git-dir`.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use std::fs;
+#[cfg(test)]
+use std::process;
+#[cfg(test)]
+use std::sync::{Mutex, MutexGuard};
+
+/// The directory containing the repository: `$GIT_DIR` if it's set, or else `.git`.
+pub fn git_dir() -> PathBuf {
+    match env::var_os("GIT_DIR") {
+        Some(ref dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(".git"),
+    }
+}
+
+/// The path of a file inside the repository, such as `HEAD` or `refs/heads/master`.
+pub fn git_path<P>(path: P) -> PathBuf
+        where P: AsRef<Path> {
+    git_dir().join(path)
+}
+
+#[cfg(test)]
+lazy_static! {
+    static ref TEST_REPOSITORY_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// An empty repository in a temporary directory, which `$GIT_DIR` points to until it's dropped.
+/// As the environment is shared by every test, only one test can use a repository at a time.
+#[cfg(test)]
+pub struct TestRepository {
+    pub dir: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl TestRepository {
+    pub fn new(name: &str) -> TestRepository {
+        let lock = TEST_REPOSITORY_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let dir = env::temp_dir().join(format!("gitters-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("objects")).unwrap();
+        fs::create_dir_all(dir.join("refs/heads")).unwrap();
+        env::set_var("GIT_DIR", &dir);
+        TestRepository { dir: dir, _lock: lock }
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestRepository {
+    fn drop(&mut self) {
+        env::remove_var("GIT_DIR");
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
@@ -2,25 +2,35 @@
 //! objects. See gitrevisions(7) for the full specification on how revisions are specified, of
 //! which this module will provide a subset.
 
-use std::{error, fmt, fs};
+use std::{error, fmt, fs, io};
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use regex::Regex;
 use commits;
 use objects;
+use repository;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub enum Error {
-    /// Currently the only error is a generic "this revision is invalid" error. As we try to handle
-    /// more types of revisions, we'll have more specific errors that can occur.
-    InvalidRevision,
+    /// The revision doesn't name any object, such as a branch that doesn't exist or the parent of
+    /// a root commit.
+    InvalidRevision(String),
+
+    /// A file needed to resolve the revision, such as the ref it names, couldn't be read.
+    IOError(PathBuf, io::Error),
+
+    /// An object needed to resolve the revision, such as the commit whose parent it names, couldn't
+    /// be read.
+    ObjectError(objects::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::InvalidRevision => write!(f, "invalid revision"),
+            Error::InvalidRevision(ref rev) => write!(f, "bad revision '{}'", rev),
+            Error::IOError(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            Error::ObjectError(ref err) => write!(f, "{}", err),
         }
     }
 }
@@ -28,26 +38,40 @@ impl fmt::Display for Error {
 impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
-            Error::InvalidRevision => "invalid revision",
+            Error::InvalidRevision(_) => "invalid revision",
+            Error::IOError(_, ref err) => err.description(),
+            Error::ObjectError(ref err) => err.description(),
         }
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
         match *self {
-            Error::InvalidRevision => None,
+            Error::InvalidRevision(_) => None,
+            Error::IOError(_, ref err) => Some(err),
+            Error::ObjectError(ref err) => Some(err),
         }
     }
 }
 
-fn parent_of_commit(rev: &str) -> Result<objects::Name, Error> {
-    let resolved = try!(resolve(rev));
-    let object =
-        try!(objects::read_object(&resolved).map_err(|_| Error::InvalidRevision));
+/// Read a file the revision is resolved through, such as a ref. If the file doesn't exist, the
+/// revision doesn't either.
+fn read_to_string(path: &Path, rev: &str) -> Result<String, Error> {
+    let mut contents = String::new();
+    let result = File::open(path).and_then(|mut file| file.read_to_string(&mut contents));
+    match result {
+        Ok(_) => Ok(contents),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound =>
+            Err(Error::InvalidRevision(rev.to_string())),
+        Err(err) => Err(Error::IOError(path.to_path_buf(), err)),
+    }
+}
 
-    match object {
-        objects::Object::Commit(
-            commits::Commit { parent: Some(parent), .. }) => return Ok(parent),
-        _ => return Err(Error::InvalidRevision),
+/// The first parent of a commit, or nothing if the object isn't a commit or it's a root commit.
+fn parent_of_commit(name: &objects::Name) -> Result<Option<objects::Name>, Error> {
+    match try!(objects::read_object(name).map_err(Error::ObjectError)) {
+        objects::Object::Commit(commits::Commit { mut parents, .. }) if !parents.is_empty() =>
+            Ok(Some(parents.remove(0))),
+        _ => Ok(None),
     }
 }
 
@@ -63,43 +87,45 @@ pub fn resolve(rev: &str) -> Result<objects::Name, Error> {
         static ref SYMBOLIC_REF_REGEX: Regex = Regex::new(r"^ref: (?P<ref>.+)\s*$").unwrap();
     }
 
-    if rev == "HEAD" {
-        let mut head_file = try!(File::open(".git/HEAD").map_err(|_| Error::InvalidRevision));
-        let mut head_contents = String::new();
-        try!(head_file.read_to_string(&mut head_contents).map_err(|_| Error::InvalidRevision));
+    let invalid = || Error::InvalidRevision(rev.to_string());
 
-        let caps = try!(SYMBOLIC_REF_REGEX.captures(&head_contents).ok_or(Error::InvalidRevision));
-        let mut ref_filename = PathBuf::from(".git");
-        ref_filename.push(&caps["ref"]);
+    if rev == "HEAD" {
+        let head_contents = try!(read_to_string(&repository::git_path("HEAD"), rev));
 
-        let mut ref_file = try!(File::open(ref_filename).map_err(|_| Error::InvalidRevision));
-        let mut ref_contents = String::new();
-        try!(ref_file.read_to_string(&mut ref_contents).map_err(|_| Error::InvalidRevision));
+        let caps = try!(SYMBOLIC_REF_REGEX.captures(&head_contents).ok_or_else(&invalid));
+        let ref_filename = repository::git_path(&caps["ref"]);
+        let ref_contents = try!(read_to_string(&ref_filename, rev));
 
+        trace!("revisions: resolved HEAD through {} to {}", &caps["ref"], ref_contents.trim());
         return Ok(objects::Name(ref_contents.trim().to_string()));
     } else if rev.ends_with("^") {
-        let child = &rev[..(rev.len() - 1)];
-        return parent_of_commit(child);
+        let child = try!(resolve(&rev[..(rev.len() - 1)]));
+        return try!(parent_of_commit(&child)).ok_or_else(&invalid);
     } else if FULL_SHA1_REGEX.is_match(rev) {
         return Ok(objects::Name(rev.to_string()));
     } else if PARTIAL_SHA1_REGEX.is_match(rev) {
         let prefix = &rev[..2];
         let suffix = &rev[2..];
 
-        let dir = format!(".git/objects/{}", prefix);
-        let files = try!(fs::read_dir(dir).map_err(|_| Error::InvalidRevision));
+        let dir = repository::git_path("objects").join(prefix);
+        let files = match fs::read_dir(&dir) {
+            Ok(files) => files,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Err(invalid()),
+            Err(err) => return Err(Error::IOError(dir, err)),
+        };
 
         let mut matching_files = Vec::new();
         for file in files {
-            let filename = try!(file.map_err(|_| Error::InvalidRevision)).file_name();
-            let filename = try!(filename.into_string().map_err(|_| Error::InvalidRevision));
-            if filename.starts_with(suffix) {
-                matching_files.push(filename);
+            let filename = try!(file.map_err(|err| Error::IOError(dir.clone(), err))).file_name();
+            match filename.into_string() {
+                Ok(ref filename) if filename.starts_with(suffix) =>
+                    matching_files.push(filename.clone()),
+                _ => continue,
             }
         }
 
         if matching_files.is_empty() {
-            return Err(Error::InvalidRevision);
+            return Err(invalid());
         }
 
         // Because we don't have an example of an ambiguous four-character SHA1, we'll ignore that
@@ -109,26 +135,20 @@ pub fn resolve(rev: &str) -> Result<objects::Name, Error> {
         let full_sha1 = format!("{}{}", prefix, matching_files[0]);
         return Ok(objects::Name(full_sha1));
     } else if ANCESTOR_REGEX.is_match(rev) {
-        let caps = try!(ANCESTOR_REGEX.captures(rev).ok_or(Error::InvalidRevision));
-        let num = try!(caps["num"].parse::<u64>().map_err(|_| Error::InvalidRevision));
+        let caps = try!(ANCESTOR_REGEX.captures(rev).ok_or_else(&invalid));
+        let num = try!(caps["num"].parse::<u64>().map_err(|_| invalid()));
 
-        let child = try!(resolve(&caps["child"]));
-        let mut parent = child;
+        let mut ancestor = try!(resolve(&caps["child"]));
         for _ in 0..num {
-            let objects::Name(name) = parent;
-            parent = try!(parent_of_commit(&name));
+            ancestor = try!(try!(parent_of_commit(&ancestor)).ok_or_else(&invalid));
         }
 
-        return Ok(parent);
+        return Ok(ancestor);
     } else {
-        let mut ref_filename = PathBuf::from(".git/refs/heads");
-        ref_filename.push(rev);
-
-        let mut file = try!(File::open(ref_filename).map_err(|_| Error::InvalidRevision));
-
-        let mut contents = String::new();
-        try!(file.read_to_string(&mut contents).map_err(|_| Error::InvalidRevision));
+        let ref_filename = repository::git_path("refs/heads").join(rev);
+        let contents = try!(read_to_string(&ref_filename, rev));
 
+        trace!("revisions: resolved refs/heads/{} to {}", rev, contents.trim());
         return Ok(objects::Name(contents.trim().to_string()));
     }
 }
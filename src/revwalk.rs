@@ -0,0 +1,805 @@
+//! Walks the history of commits, starting from a set of commits and following their parents, the
+//! way `git log` does. Commits are visited newest first, by commit date, and each is visited only
+//! once, however many paths lead to it.
+//!
+//! Which of the visited commits are actually returned can be limited, such as to the ones by a
+//! particular author, or to a number of them. See "Commit Limiting" in git-log(1).
+
+use commits;
+use date;
+use objects;
+use revisions;
+
+use regex;
+use regex::Regex;
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::error::Error as StdError;
+use std::fmt;
+use std::vec;
+
+#[derive(Debug)]
+pub enum Error {
+    ObjectError(objects::Error),
+    RevisionError(revisions::Error),
+
+    /// An object reached while walking isn't a commit, such as a parent that's really a tree.
+    NotACommit(objects::Name),
+    InvalidDate(String),
+
+    /// A pattern given to an option like `--author` isn't a valid regular expression.
+    InvalidPattern(String, regex::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::ObjectError(ref err) => write!(f, "{}", err),
+            Error::RevisionError(ref err) => write!(f, "{}", err),
+            Error::NotACommit(ref name) => write!(f, "object {} is not a commit", name.0),
+            Error::InvalidDate(ref date) => write!(f, "invalid date: {}", date),
+            Error::InvalidPattern(ref pattern, ref err) =>
+                write!(f, "invalid regex '{}': {}", pattern, err),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::ObjectError(ref err) => err.description(),
+            Error::RevisionError(ref err) => err.description(),
+            Error::NotACommit(_) => "not a commit",
+            Error::InvalidDate(_) => "invalid date",
+            Error::InvalidPattern(..) => "invalid regex",
+        }
+    }
+
+    fn source(&self) -> Option<&(StdError + 'static)> {
+        match *self {
+            Error::ObjectError(ref err) => Some(err),
+            Error::RevisionError(ref err) => Some(err),
+            Error::InvalidPattern(_, ref err) => Some(err),
+            Error::NotACommit(_) | Error::InvalidDate(_) => None,
+        }
+    }
+}
+
+/// Parse a date for options like `--since`, as described by `date::approxidate`.
+pub fn approxidate(text: &str, now: i64) -> Result<i64, Error> {
+    date::approxidate(text, now).ok_or_else(|| Error::InvalidDate(text.trim().to_lowercase()))
+}
+
+/// Which of the commits that are walked are returned, and how many of them.
+#[derive(Debug, Default)]
+pub struct Limits {
+    /// Stop after returning this many commits.
+    pub max_count: Option<usize>,
+
+    /// Skip this many commits before returning any.
+    pub skip: usize,
+
+    /// Only return commits made since this time, as a Unix timestamp. The parents of older commits
+    /// aren't walked at all.
+    pub since: Option<i64>,
+
+    /// Only return commits made until this time, as a Unix timestamp.
+    pub until: Option<i64>,
+
+    /// Only return commits whose author matches one of these patterns, if there are any. Patterns
+    /// are matched against both the name and the email, in the form `Name <email>`.
+    pub authors: Vec<Regex>,
+
+    /// Only return commits whose committer matches one of these patterns, if there are any.
+    pub committers: Vec<Regex>,
+
+    /// Only return commits whose message matches one of these patterns, if there are any, or all
+    /// of them if `all_match` is set. If `invert_grep` is set, only the commits whose message
+    /// doesn't match are returned instead.
+    pub greps: Vec<Regex>,
+    pub all_match: bool,
+    pub invert_grep: bool,
+
+    /// Only return commits with at least this many parents. Merges have at least 2.
+    pub min_parents: usize,
+
+    /// Only return commits with at most this many parents.
+    pub max_parents: Option<usize>,
+}
+
+impl Limits {
+    /// Whether any of the limits depend on the commit itself, rather than how many have been
+    /// returned.
+    fn filters_commits(&self) -> bool {
+        self.since.is_some() || self.until.is_some() || !self.authors.is_empty() ||
+            !self.committers.is_empty() || !self.greps.is_empty() || self.min_parents > 0 ||
+            self.max_parents.is_some()
+    }
+
+    /// Whether a commit is returned, leaving aside the number of commits returned so far.
+    fn matches(&self, commit: &commits::Commit) -> bool {
+        let any_match = |patterns: &[Regex], text: &str| {
+            patterns.is_empty() || patterns.iter().any(|pattern| pattern.is_match(text))
+        };
+
+        let date = commit.committer.date.timestamp();
+        if self.since.map(|since| date < since).unwrap_or(false) ||
+            self.until.map(|until| date > until).unwrap_or(false) {
+            return false;
+        }
+
+        let parents = commit.parents.len();
+        if parents < self.min_parents ||
+            self.max_parents.map(|max| parents > max).unwrap_or(false) {
+            return false;
+        }
+
+        if !any_match(&self.authors, &commit.author.name) ||
+            !any_match(&self.committers, &commit.committer.name) {
+            return false;
+        }
+
+        if self.greps.is_empty() {
+            return true;
+        }
+        let grep_matches = if self.all_match {
+            self.greps.iter().all(|pattern| pattern.is_match(&commit.message))
+        } else {
+            any_match(&self.greps, &commit.message)
+        };
+        grep_matches != self.invert_grep
+    }
+}
+
+/// The options for walking history that are shared by commands like `log` and `rev-list`, as
+/// they're given on the command line, so that they're interpreted the same way by each of them.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub topo_order: bool,
+    pub date_order: bool,
+    pub author_date_order: bool,
+    pub reverse: bool,
+    pub first_parent: bool,
+    pub ancestry_path: bool,
+
+    pub max_count: Option<usize>,
+    pub skip: Option<usize>,
+
+    /// Dates, as given to `--since` and `--until`, or their synonyms `--after` and `--before`.
+    pub since: Option<String>,
+    pub after: Option<String>,
+    pub until: Option<String>,
+    pub before: Option<String>,
+
+    /// Patterns, as given to `--author`, `--committer` and `--grep`, which are matched regardless
+    /// of case if `ignore_case` is set.
+    pub authors: Vec<String>,
+    pub committers: Vec<String>,
+    pub greps: Vec<String>,
+    pub ignore_case: bool,
+    pub all_match: bool,
+    pub invert_grep: bool,
+
+    /// `--merges` and `--no-merges`, which are overridden by `--min-parents` and `--max-parents`.
+    pub merges: bool,
+    pub no_merges: bool,
+    pub min_parents: Option<usize>,
+    pub max_parents: Option<usize>,
+}
+
+impl Options {
+    /// The order to return commits in. The ordering options override each other, rather than
+    /// whichever comes last winning.
+    pub fn order(&self) -> Order {
+        if self.topo_order {
+            Order::Topo
+        } else if self.author_date_order {
+            Order::AuthorDate
+        } else if self.date_order {
+            Order::Date
+        } else {
+            Order::Walk
+        }
+    }
+
+    fn compile_patterns(&self, patterns: &[String]) -> Result<Vec<Regex>, Error> {
+        patterns.iter().map(|pattern| {
+            let source =
+                if self.ignore_case { format!("(?i){}", pattern) } else { pattern.clone() };
+            Regex::new(&source).map_err(|err| Error::InvalidPattern(pattern.clone(), err))
+        }).collect()
+    }
+
+    /// Which commits to return, with relative dates taken relative to `now`.
+    pub fn limits(&self, now: i64) -> Result<Limits, Error> {
+        let parse_date = |date: Option<&String>| match date {
+            Some(date) => approxidate(date, now).map(Some),
+            None => Ok(None),
+        };
+
+        Ok(Limits {
+            max_count: self.max_count,
+            skip: self.skip.unwrap_or(0),
+            since: try!(parse_date(self.since.as_ref().or(self.after.as_ref()))),
+            until: try!(parse_date(self.until.as_ref().or(self.before.as_ref()))),
+            authors: try!(self.compile_patterns(&self.authors)),
+            committers: try!(self.compile_patterns(&self.committers)),
+            greps: try!(self.compile_patterns(&self.greps)),
+            all_match: self.all_match,
+            invert_grep: self.invert_grep,
+            min_parents: self.min_parents.unwrap_or(if self.merges { 2 } else { 0 }),
+            max_parents: self.max_parents.or(if self.no_merges { Some(1) } else { None }),
+        })
+    }
+
+    /// Start a walk set up according to the options, to which the commits to walk from are then
+    /// pushed.
+    pub fn walk(&self, now: i64) -> Result<RevWalk, Error> {
+        let mut walk = RevWalk::new(try!(self.limits(now)));
+        walk.sort(self.order())
+            .reverse(self.reverse)
+            .first_parent(self.first_parent)
+            .ancestry_path(self.ancestry_path);
+        Ok(walk)
+    }
+}
+
+/// The order commits are returned in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// The order they're walked in, newest first by commit date. When dates are skewed, such as by
+    /// a clock that was wrong, a commit can come before one of its children.
+    Walk,
+
+    /// Newest first by commit date, but never before any of the commit's children.
+    Date,
+
+    /// Newest first by author date, but never before any of the commit's children.
+    AuthorDate,
+
+    /// Never before any of the commit's children, and without interleaving the commits from
+    /// separate lines of history.
+    Topo,
+}
+
+/// Something waiting in a queue ordered by date, such as a commit waiting to be visited. The
+/// newest comes first, and those with the same date come in the order they were queued.
+struct Queued<T> {
+    date: i64,
+    order: usize,
+    item: T,
+}
+
+impl<T> PartialEq for Queued<T> {
+    fn eq(&self, other: &Queued<T>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Queued<T> {}
+
+impl<T> PartialOrd for Queued<T> {
+    fn partial_cmp(&self, other: &Queued<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Queued<T> {
+    fn cmp(&self, other: &Queued<T>) -> Ordering {
+        self.date.cmp(&other.date).then(other.order.cmp(&self.order))
+    }
+}
+
+/// After only uninteresting commits are left to walk, walk this many more before stopping, in
+/// case an older-looking one turns out to lead back to interesting history, like in git.
+const SLOP: usize = 5;
+
+/// Walks the commits reachable from the ones it's pushed, but not from the ones it's told to hide,
+/// returning them as an iterator.
+///
+/// Commits are returned as they're walked, unless they have to be sorted, or some are hidden. In
+/// that case, they're all found before the first one is returned.
+pub struct RevWalk {
+    queue: BinaryHeap<Queued<commits::Commit>>,
+
+    /// Every commit that has been queued, so that none is visited twice.
+    seen: HashSet<objects::Name>,
+    queued: usize,
+
+    /// The parents of every commit that has been queued, to mark the history behind newly
+    /// uninteresting commits.
+    parents: HashMap<objects::Name, Vec<objects::Name>>,
+
+    /// The commits that are hidden, along with everything reachable from them.
+    uninteresting: HashSet<objects::Name>,
+
+    /// The commits that were hidden directly, which `--ancestry-path` finds the descendants of.
+    bottoms: Vec<objects::Name>,
+
+    order: Order,
+    reverse: bool,
+    first_parent: bool,
+    ancestry_path: bool,
+
+    limits: Limits,
+    skipped: usize,
+    returned: usize,
+
+    /// The commits left to return, once they've all been found and sorted.
+    prepared: Option<vec::IntoIter<commits::Commit>>,
+
+    /// The commits returned so far.
+    shown: HashSet<objects::Name>,
+}
+
+/// Read a commit that the walk reaches.
+fn read_commit(name: &objects::Name) -> Result<commits::Commit, Error> {
+    match try!(objects::read_object(name).map_err(Error::ObjectError)) {
+        objects::Object::Commit(commit) => Ok(commit),
+        _ => Err(Error::NotACommit(name.clone())),
+    }
+}
+
+/// Sort commits so that none comes before its children, keeping them in the given order, by date,
+/// otherwise. Like in git, `Order::Topo` follows each line of history back as far as it can before
+/// moving on to the next one.
+fn sort_topologically(commits: Vec<commits::Commit>, order: Order) -> Vec<commits::Commit> {
+    let index: HashMap<_, _> =
+        commits.iter().enumerate().map(|(i, commit)| (commit.name.clone(), i)).collect();
+
+    // The number of children each commit has yet to wait for, plus one.
+    let mut indegree = vec![1; commits.len()];
+    for commit in commits.iter() {
+        for parent in commit.parents.iter().filter_map(|parent| index.get(parent)) {
+            indegree[*parent] += 1;
+        }
+    }
+
+    let dates: Vec<_> = commits.iter().map(|commit| match order {
+        Order::AuthorDate => commit.author.date.timestamp(),
+        _ => commit.committer.date.timestamp(),
+    }).collect();
+
+    // The commits at the tips of history are taken off the stack in the same order they were
+    // walked, so they're pushed onto it in reverse.
+    let mut ready: Vec<_> = (0..commits.len()).filter(|&i| indegree[i] == 1).collect();
+    if order == Order::Topo {
+        ready.reverse();
+    }
+
+    let mut stack = Vec::new();
+    let mut queue = BinaryHeap::new();
+    let mut queued = 0;
+    let mut commits: Vec<_> = commits.into_iter().map(Some).collect();
+    let mut sorted = Vec::new();
+    loop {
+        for i in ready.drain(..) {
+            if order == Order::Topo {
+                stack.push(i);
+            } else {
+                queue.push(Queued { date: dates[i], order: queued, item: i });
+                queued += 1;
+            }
+        }
+
+        let next = if order == Order::Topo { stack.pop() } else { queue.pop().map(|q| q.item) };
+        let commit = match next {
+            Some(i) => commits[i].take().unwrap(),
+            None => break,
+        };
+        for &parent in commit.parents.iter().filter_map(|parent| index.get(parent)) {
+            indegree[parent] -= 1;
+            if indegree[parent] == 1 {
+                ready.push(parent);
+            }
+        }
+        sorted.push(commit);
+    }
+    sorted
+}
+
+impl RevWalk {
+    pub fn new(limits: Limits) -> RevWalk {
+        RevWalk {
+            queue: BinaryHeap::new(),
+            seen: HashSet::new(),
+            queued: 0,
+            parents: HashMap::new(),
+            uninteresting: HashSet::new(),
+            bottoms: Vec::new(),
+            order: Order::Walk,
+            reverse: false,
+            first_parent: false,
+            ancestry_path: false,
+            limits: limits,
+            skipped: 0,
+            returned: 0,
+            prepared: None,
+            shown: HashSet::new(),
+        }
+    }
+
+    /// Return the commits in the given order, rather than the order they're walked in.
+    pub fn sort(&mut self, order: Order) -> &mut RevWalk {
+        self.order = order;
+        self
+    }
+
+    /// Return the commits in reverse, after they've been sorted and limited.
+    pub fn reverse(&mut self, reverse: bool) -> &mut RevWalk {
+        self.reverse = reverse;
+        self
+    }
+
+    /// Only follow the first parent of each commit, such as the branch that was merged into.
+    pub fn first_parent(&mut self, first_parent: bool) -> &mut RevWalk {
+        self.first_parent = first_parent;
+        self
+    }
+
+    /// Only return commits that are descendants of the hidden commits, such as the ones between
+    /// the two ends of a range like `A..B`.
+    pub fn ancestry_path(&mut self, ancestry_path: bool) -> &mut RevWalk {
+        self.ancestry_path = ancestry_path;
+        self
+    }
+
+    /// Queue a commit to be visited, unless it already has been.
+    fn enqueue(&mut self, name: &objects::Name) -> Result<(), Error> {
+        if !self.seen.insert(name.clone()) {
+            return Ok(());
+        }
+
+        let commit = try!(read_commit(name));
+        self.parents.insert(name.clone(), commit.parents.clone());
+        self.queue.push(Queued {
+            date: commit.committer.date.timestamp(),
+            order: self.queued,
+            item: commit,
+        });
+        self.queued += 1;
+        Ok(())
+    }
+
+    /// Start walking from a commit, in addition to any others the walk was started from.
+    pub fn push(&mut self, name: &objects::Name) -> Result<(), Error> {
+        self.enqueue(name)
+    }
+
+    /// Hide a commit, along with everything reachable from it, like `^<commit>`.
+    pub fn hide(&mut self, name: &objects::Name) -> Result<(), Error> {
+        self.bottoms.push(name.clone());
+        self.mark_uninteresting(name);
+        self.enqueue(name)
+    }
+
+    /// Whether a commit is one the walk returns if it reaches it, which is if it hasn't been
+    /// returned already, isn't hidden, and matches the limits. This is what decides which parents
+    /// of a commit `log --graph` draws lines to.
+    pub fn is_interesting(&self, name: &objects::Name) -> Result<bool, Error> {
+        if self.shown.contains(name) || self.uninteresting.contains(name) {
+            return Ok(false);
+        } else if !self.limits.filters_commits() {
+            return Ok(true);
+        }
+        Ok(self.limits.matches(&try!(read_commit(name))))
+    }
+
+    /// Push or hide the commits named by a revision given on the command line: either a single
+    /// commit, a commit prefixed with `^` to hide it, or a range like `A..B`, which hides `A` and
+    /// pushes `B`. Either end of a range defaults to `HEAD`.
+    pub fn push_revision(&mut self, rev: &str) -> Result<(), Error> {
+        // Like in git, a part of the revision that doesn't exist is reported as the whole of it.
+        let whole = rev;
+        let resolve = |rev: &str| {
+            revisions::resolve(if rev.is_empty() { "HEAD" } else { rev }).map_err(|err| match err {
+                revisions::Error::InvalidRevision(_) =>
+                    Error::RevisionError(revisions::Error::InvalidRevision(whole.to_string())),
+                err => Error::RevisionError(err),
+            })
+        };
+
+        if rev.starts_with('^') {
+            self.hide(&try!(resolve(&rev[1..])))
+        } else if let Some(dots) = rev.find("..") {
+            try!(self.hide(&try!(resolve(&rev[..dots]))));
+            self.push(&try!(resolve(&rev[dots + 2..])))
+        } else {
+            self.push(&try!(resolve(rev)))
+        }
+    }
+
+    /// Mark a commit as uninteresting, along with the history behind it that's been reached so
+    /// far. The history reached later is marked as it's visited.
+    fn mark_uninteresting(&mut self, name: &objects::Name) {
+        let mut pending = vec![name.clone()];
+        while let Some(name) = pending.pop() {
+            if self.uninteresting.insert(name.clone()) {
+                if let Some(parents) = self.parents.get(&name) {
+                    pending.extend(parents.iter().cloned());
+                }
+            }
+        }
+    }
+
+    /// Visit the next commit, queueing its parents, whether or not it's returned.
+    fn visit(&mut self) -> Option<Result<commits::Commit, Error>> {
+        let Queued { date, item: commit, .. } = match self.queue.pop() {
+            Some(queued) => queued,
+            None => return None,
+        };
+
+        // The history behind uninteresting commits is uninteresting too, however it's reached,
+        // while the history behind commits older than `since` is older still, so it isn't walked.
+        let uninteresting = self.uninteresting.contains(&commit.name);
+        if !uninteresting && self.limits.since.map(|since| date < since).unwrap_or(false) {
+            return Some(Ok(commit));
+        }
+
+        let parents = if self.first_parent && !uninteresting { 1 } else { commit.parents.len() };
+        for parent in commit.parents.iter().take(parents) {
+            if uninteresting {
+                self.mark_uninteresting(parent);
+            }
+            if let Err(err) = self.enqueue(parent) {
+                return Some(Err(err));
+            }
+        }
+        Some(Ok(commit))
+    }
+
+    /// Whether only uninteresting commits are left to walk, given the date of the last one walked.
+    fn everybody_uninteresting(&self, date: i64) -> bool {
+        match self.queue.peek() {
+            Some(newest) if newest.date >= date => false,
+            _ => self.queue.iter().all(|queued| self.uninteresting.contains(&queued.item.name)),
+        }
+    }
+
+    /// Find every commit to return, stopping once only uninteresting commits are left to walk.
+    fn walk_all(&mut self) -> Result<Vec<commits::Commit>, Error> {
+        let mut commits = Vec::new();
+        let mut slop = SLOP;
+        while let Some(commit) = self.visit() {
+            let commit = try!(commit);
+            if !self.uninteresting.contains(&commit.name) {
+                commits.push(commit);
+                continue;
+            }
+
+            slop = if self.everybody_uninteresting(commit.committer.date.timestamp()) {
+                slop - 1
+            } else {
+                SLOP
+            };
+            if slop == 0 {
+                break;
+            }
+        }
+
+        // Commits can turn out to be uninteresting after they're walked, if the dates are skewed.
+        commits.retain(|commit| !self.uninteresting.contains(&commit.name));
+        if self.ancestry_path && !self.bottoms.is_empty() {
+            commits = self.limit_to_ancestry(commits);
+        }
+        if self.order != Order::Walk {
+            commits = sort_topologically(commits, self.order);
+        }
+        Ok(commits)
+    }
+
+    /// Keep only the commits that are descendants of the hidden commits. Like in git, the others
+    /// are marked uninteresting, so that `log --graph` doesn't draw lines to them.
+    fn limit_to_ancestry(&mut self, mut commits: Vec<commits::Commit>) -> Vec<commits::Commit> {
+        let mut descendants: HashSet<_> = self.bottoms.iter().cloned().collect();
+        loop {
+            let found = commits.iter().rev()
+                .filter(|commit| !descendants.contains(&commit.name) &&
+                        commit.parents.iter().any(|parent| descendants.contains(parent)))
+                .map(|commit| commit.name.clone())
+                .collect::<Vec<_>>();
+            if found.is_empty() {
+                break;
+            }
+            descendants.extend(found);
+        }
+
+        for commit in commits.iter().filter(|commit| !descendants.contains(&commit.name)) {
+            self.uninteresting.insert(commit.name.clone());
+        }
+        commits.retain(|commit| descendants.contains(&commit.name));
+        commits
+    }
+
+    /// Whether a commit that was found is returned, counting it towards the limits.
+    fn accept(&mut self, commit: &commits::Commit) -> bool {
+        if !self.limits.matches(commit) {
+            return false;
+        } else if self.skipped < self.limits.skip {
+            self.skipped += 1;
+            return false;
+        }
+
+        self.returned += 1;
+        true
+    }
+
+    fn exhausted(&self) -> bool {
+        self.limits.max_count.map(|max| self.returned >= max).unwrap_or(false)
+    }
+
+    /// Find, sort and limit every commit to return, before returning the first one.
+    fn prepare(&mut self) -> Result<(), Error> {
+        let mut commits = Vec::new();
+        for commit in try!(self.walk_all()) {
+            if self.exhausted() {
+                break;
+            } else if self.accept(&commit) {
+                commits.push(commit);
+            }
+        }
+
+        if self.reverse {
+            commits.reverse();
+        }
+        self.prepared = Some(commits.into_iter());
+        Ok(())
+    }
+
+    /// Find the next commit to return.
+    fn next_commit(&mut self) -> Option<Result<commits::Commit, Error>> {
+        let walk_all = !self.uninteresting.is_empty() || self.order != Order::Walk ||
+            self.reverse || self.ancestry_path;
+        if walk_all && self.prepared.is_none() {
+            if let Err(err) = self.prepare() {
+                self.prepared = Some(Vec::new().into_iter());
+                return Some(Err(err));
+            }
+        }
+        if let Some(ref mut prepared) = self.prepared {
+            return prepared.next().map(Ok);
+        }
+
+        while !self.exhausted() {
+            let commit = match self.visit() {
+                Some(Ok(commit)) => commit,
+                other => return other,
+            };
+            if self.accept(&commit) {
+                return Some(Ok(commit));
+            }
+        }
+        None
+    }
+}
+
+impl Iterator for RevWalk {
+    type Item = Result<commits::Commit, Error>;
+
+    fn next(&mut self) -> Option<Result<commits::Commit, Error>> {
+        let next = self.next_commit();
+        if let Some(Ok(ref commit)) = next {
+            self.shown.insert(commit.name.clone());
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::sort_topologically;
+
+    use chrono::{FixedOffset, TimeZone};
+    use commits;
+    use graph::Graph;
+    use objects::Name;
+    use regex::Regex;
+
+    fn commit(parents: usize, author: &str, message: &str) -> commits::Commit {
+        let user = |name: &str| commits::CommitUser {
+            name: name.to_string(),
+            date: FixedOffset::east(0).timestamp(1700000000, 0),
+        };
+        commits::Commit {
+            name: Name("4ddb0025ef5914b51fb835495f5259a6d962df21".to_string()),
+            tree: Name("3683f870be446c7cc05ffaef9fa06415276e1828".to_string()),
+            parents: vec![Name("1c1ae27343b536397d9184e52f33aeba96988394".to_string()); parents],
+            author: user(author),
+            committer: user("Cy Committer <cy@example.com>"),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn sorts_topologically() {
+        // A merge of two lines of history, where the commit on the merged line is older than the
+        // one on the line that was merged into.
+        let commit = |name: &str, parents: &[&str], date: i64| commits::Commit {
+            name: Name(name.to_string()),
+            parents: parents.iter().map(|parent| Name(parent.to_string())).collect(),
+            committer: commits::CommitUser {
+                name: "Cy Committer <cy@example.com>".to_string(),
+                date: FixedOffset::east(0).timestamp(date, 0),
+            },
+            ..commit(0, "Ann <ann@example.com>", name)
+        };
+        let history = || vec![commit("m", &["c", "d"], 5), commit("c", &["b"], 4),
+                              commit("d", &["a"], 3), commit("b", &["a"], 2),
+                              commit("a", &[], 1)];
+        let names = |commits: Vec<commits::Commit>| {
+            commits.into_iter().map(|commit| commit.name.0).collect::<Vec<_>>().join(" ")
+        };
+
+        assert_eq!("m c d b a", names(sort_topologically(history(), Order::Date)));
+        assert_eq!("m d c b a", names(sort_topologically(history(), Order::Topo)));
+    }
+
+    #[test]
+    fn hides_commits_off_the_ancestry_path() {
+        // With x..top, the merge m is a descendant of x through y, but the line it merges in
+        // isn't, so it's drawn without the line to s.
+        let commit = |name: &str, parents: &[&str]| commits::Commit {
+            name: Name(name.to_string()),
+            parents: parents.iter().map(|parent| Name(parent.to_string())).collect(),
+            ..commit(0, "Ann <ann@example.com>", name)
+        };
+        let mut walk = RevWalk::new(Limits::default());
+        walk.bottoms.push(Name("x".to_string()));
+        walk.uninteresting.insert(Name("x".to_string()));
+        walk.uninteresting.insert(Name("a".to_string()));
+        let history = vec![commit("top", &["m"]), commit("m", &["y", "s"]), commit("y", &["x"]),
+                           commit("s", &["a"])];
+
+        let mut graph = Graph::new(Vec::new());
+        let mut output = String::new();
+        for commit in walk.limit_to_ancestry(history) {
+            let parents = commit.parents.iter()
+                .filter(|parent| walk.is_interesting(parent).unwrap())
+                .cloned()
+                .collect();
+            graph.update(commit.name.clone(), parents);
+            output.push_str(&graph.commit_lines());
+            output.push_str(&graph.message_lines(&commit.name.0));
+            output.push('\n');
+            walk.shown.insert(commit.name);
+        }
+        assert_eq!("* top\n* m\n* y\n", output);
+    }
+
+    #[test]
+    fn reports_unparseable_dates() {
+        let now = 1700000000;
+        assert_eq!(now - 86400, approxidate("yesterday", now).unwrap());
+        match approxidate(" Someday ", now) {
+            Err(Error::InvalidDate(ref date)) => assert_eq!("someday", date),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn limits_commits() {
+        let mut limits = Limits::default();
+        assert!(limits.matches(&commit(1, "Ann <ann@example.com>", "Fix the bug")));
+
+        limits.authors = vec![Regex::new("ann@").unwrap(), Regex::new("^Bo ").unwrap()];
+        limits.greps = vec![Regex::new("Fix").unwrap(), Regex::new("bug").unwrap()];
+        assert!(limits.matches(&commit(1, "Ann <ann@example.com>", "Fix the typo")));
+        assert!(!limits.matches(&commit(1, "Cy <cy@example.com>", "Fix the typo")));
+
+        limits.all_match = true;
+        assert!(!limits.matches(&commit(1, "Bo <bo@example.com>", "Fix the typo")));
+        assert!(limits.matches(&commit(1, "Bo <bo@example.com>", "Fix the bug")));
+
+        limits.invert_grep = true;
+        assert!(limits.matches(&commit(1, "Bo <bo@example.com>", "Fix the typo")));
+
+        let mut limits = Limits::default();
+        limits.min_parents = 2;
+        assert!(!limits.matches(&commit(1, "Ann <ann@example.com>", "Fix the bug")));
+        assert!(limits.matches(&commit(2, "Ann <ann@example.com>", "Merge")));
+        limits.until = Some(1699999999);
+        assert!(!limits.matches(&commit(2, "Ann <ann@example.com>", "Merge")));
+    }
+}
@@ -0,0 +1,608 @@
+//! Provides functionality for computing the state of the working tree, as reported by "git status":
+//! the changes staged in the index relative to `HEAD`, the changes in the working tree that have
+//! not yet been staged, and the files that are not tracked at all.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use config;
+use error::{Error, Result};
+use ignore;
+use index;
+use index::{Index, StatData};
+use objects;
+use objects::Name;
+use refs;
+use repository;
+use revwalk;
+use trees;
+use untracked;
+
+/// The file inside the repository that records the commits being merged, while a merge is in
+/// progress.
+const MERGE_HEAD_FILE: &'static str = "MERGE_HEAD";
+
+/// How a file differs between two of `HEAD`, the index and the working tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+
+    /// The file changed between being a regular file, a symbolic link or a submodule.
+    TypeChanged,
+}
+
+impl Change {
+    /// The single-letter code used for the change in the short format.
+    pub fn code(&self) -> char {
+        match *self {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+            Change::TypeChanged => 'T',
+        }
+    }
+
+    /// The label used for the change in the long format.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            Change::Added => "new file",
+            Change::Modified => "modified",
+            Change::Deleted => "deleted",
+            Change::TypeChanged => "typechange",
+        }
+    }
+}
+
+/// The mode and object name of one version of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub mode: u32,
+    pub sha1: Name,
+}
+
+/// A tracked file with a change between `HEAD` and the index, between the index and the working
+/// tree, or both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStatus {
+    pub path: Vec<u8>,
+    pub head: Option<Version>,
+    pub index: Option<Version>,
+
+    /// The mode of the file in the working tree, or `None` if it has been deleted.
+    pub worktree_mode: Option<u32>,
+
+    /// The change from `HEAD` to the index, which will be included in the next commit.
+    pub staged: Option<Change>,
+
+    /// The change from the index to the working tree, which has not been staged yet.
+    pub unstaged: Option<Change>,
+}
+
+/// The ways a path can be left conflicted by a merge, depending on which of the common ancestor
+/// ("base"), the current branch ("us") and the branch being merged ("them") contain the path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    BothDeleted,
+    AddedByUs,
+    DeletedByThem,
+    AddedByThem,
+    DeletedByUs,
+    BothAdded,
+    BothModified,
+}
+
+impl Conflict {
+    fn from_stages(base: bool, ours: bool, theirs: bool) -> Conflict {
+        match (base, ours, theirs) {
+            (true, false, false) => Conflict::BothDeleted,
+            (false, true, false) => Conflict::AddedByUs,
+            (true, true, false) => Conflict::DeletedByThem,
+            (false, false, true) => Conflict::AddedByThem,
+            (true, false, true) => Conflict::DeletedByUs,
+            (false, true, true) => Conflict::BothAdded,
+            _ => Conflict::BothModified,
+        }
+    }
+
+    /// The two-letter code used for the conflict in the short format.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Conflict::BothDeleted => "DD",
+            Conflict::AddedByUs => "AU",
+            Conflict::DeletedByThem => "UD",
+            Conflict::AddedByThem => "UA",
+            Conflict::DeletedByUs => "DU",
+            Conflict::BothAdded => "AA",
+            Conflict::BothModified => "UU",
+        }
+    }
+
+    /// The label used for the conflict in the long format.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            Conflict::BothDeleted => "both deleted",
+            Conflict::AddedByUs => "added by us",
+            Conflict::DeletedByThem => "deleted by them",
+            Conflict::AddedByThem => "added by them",
+            Conflict::DeletedByUs => "deleted by us",
+            Conflict::BothAdded => "both added",
+            Conflict::BothModified => "both modified",
+        }
+    }
+}
+
+/// A path with unresolved merge conflicts, recorded in the index as up to three entries in the
+/// stages other than zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnmergedFile {
+    pub path: Vec<u8>,
+    pub conflict: Conflict,
+
+    /// The base, our and their versions of the file, from stages one, two and three respectively.
+    pub stages: [Option<Version>; 3],
+    pub worktree_mode: Option<u32>,
+}
+
+/// The branch that the current branch is configured to track, via `branch.<name>.remote` and
+/// `branch.<name>.merge`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+    /// The upstream branch, shortened for display, such as `origin/master`.
+    pub name: String,
+
+    /// The number of commits on the current branch but not the upstream, and vice versa. `None` if
+    /// the upstream branch no longer exists.
+    pub divergence: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UntrackedMode {
+    /// Don't look for untracked files.
+    No,
+
+    /// Show untracked files, with wholly untracked directories collapsed into one entry.
+    Normal,
+
+    /// Show every untracked file individually.
+    All,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    pub untracked: UntrackedMode,
+    pub show_ignored: bool,
+}
+
+pub struct Status {
+    pub head: refs::Head,
+    pub upstream: Option<Upstream>,
+
+    /// Whether a merge has been started but not yet committed.
+    pub merge_in_progress: bool,
+
+    /// The tracked files with staged or unstaged changes, sorted by path.
+    pub files: Vec<FileStatus>,
+    pub unmerged: Vec<UnmergedFile>,
+
+    /// The untracked and ignored files, sorted, with directories reported with a trailing slash.
+    pub untracked: Vec<PathBuf>,
+    pub ignored: Vec<PathBuf>,
+}
+
+impl Status {
+    pub fn has_staged_changes(&self) -> bool {
+        self.files.iter().any(|file| file.staged.is_some())
+    }
+
+    /// Whether there are changes in the working tree that would need to be added before they can be
+    /// committed, which includes any unresolved conflicts.
+    pub fn has_unstaged_changes(&self) -> bool {
+        !self.unmerged.is_empty() || self.files.iter().any(|file| file.unstaged.is_some())
+    }
+}
+
+/// Convert a path relative to the root of the repository, as stored in the index, into a `Path`.
+pub fn fs_path(path: &[u8]) -> &Path {
+    Path::new(OsStr::from_bytes(path))
+}
+
+/// Whether two modes describe the same kind of object, ignoring the executable bit.
+fn same_type(a: u32, b: u32) -> bool {
+    (a & 0o170000) == (b & 0o170000)
+}
+
+/// The mode git would record for a file with the given metadata. Regular files are normalized to
+/// either 100644 or 100755, as git doesn't track any other permissions. When `core.fileMode` is
+/// false, the executable bit can't be trusted, so the one in the index is kept instead.
+pub fn worktree_mode(metadata: &fs::Metadata, index_mode: u32, trust_executable_bit: bool) -> u32 {
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        trees::MODE_SYMLINK
+    } else if file_type.is_dir() {
+        trees::MODE_GITLINK
+    } else if !trust_executable_bit && same_type(index_mode, trees::MODE_FILE) {
+        index_mode
+    } else if metadata.permissions().mode() & 0o100 != 0 {
+        trees::MODE_EXECUTABLE
+    } else {
+        trees::MODE_FILE
+    }
+}
+
+/// Read the contents of the file in the working tree, as they would be stored in a blob. Symbolic
+/// links are stored as their target.
+pub fn read_worktree_file(path: &[u8], metadata: &fs::Metadata) -> Result<Vec<u8>> {
+    let io_error = |e| Error::Io { path: fs_path(path).to_path_buf(), source: e };
+
+    if metadata.file_type().is_symlink() {
+        let target = try!(fs::read_link(fs_path(path)).map_err(&io_error));
+        Ok(target.as_os_str().as_bytes().to_vec())
+    } else {
+        let mut file = try!(fs::File::open(fs_path(path)).map_err(&io_error));
+        let mut contents = Vec::new();
+        try!(file.read_to_end(&mut contents).map_err(&io_error));
+        Ok(contents)
+    }
+}
+
+/// Whether the executable bit of files in the working tree reflects the mode they should be
+/// recorded with, as configured by `core.fileMode`.
+pub fn trust_executable_bit(config: &config::Config) -> bool {
+    match config.get_bool("core.filemode") {
+        Ok(Some(false)) => false,
+        _ => true,
+    }
+}
+
+/// Compare a stage zero index entry to the corresponding file in the working tree.
+///
+/// When the stat data of the file matches the one recorded in the index, the file is assumed to be
+/// unchanged without reading it. That assumption doesn't hold if the file was modified in the same
+/// instant the index was written ("racy git"), which is detected by the file being modified no
+/// earlier than the index itself, so such files are always hashed. A file whose contents turn out
+/// to be unchanged has its stat data refreshed, so it doesn't need to be hashed again next time.
+/// Returns the change, the mode in the working tree, and whether the entry was refreshed.
+pub fn compare_worktree(entry: &mut index::Entry,
+                        index_timestamp: Option<index::Timestamp>,
+                        trust_executable_bit: bool)
+        -> Result<(Option<Change>, Option<u32>, bool)> {
+    let metadata = match fs::symlink_metadata(fs_path(&entry.path)) {
+        Ok(metadata) => metadata,
+        // ENOTDIR means one of the parent directories has been replaced by a file.
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound ||
+            err.raw_os_error() == Some(20) => return Ok((Some(Change::Deleted), None, false)),
+        Err(err) => return Err(Error::Io { path: entry.path().to_path_buf(), source: err }),
+    };
+
+    let mode = worktree_mode(&metadata, entry.mode, trust_executable_bit);
+    if entry.mode == trees::MODE_GITLINK {
+        // Submodules are only checked for existence, as their contents belong to another
+        // repository.
+        return Ok((if mode == trees::MODE_GITLINK { None } else { Some(Change::TypeChanged) },
+                   Some(mode), false));
+    }
+    if mode == trees::MODE_GITLINK {
+        // A directory in place of a tracked file means the file is gone, and the directory's
+        // contents are untracked.
+        return Ok((Some(Change::Deleted), None, false));
+    }
+    if !same_type(mode, entry.mode) {
+        return Ok((Some(Change::TypeChanged), Some(mode), false));
+    }
+    if mode != entry.mode {
+        return Ok((Some(Change::Modified), Some(mode), false));
+    }
+
+    let stat = StatData::from_metadata(&metadata);
+    let is_racy = index_timestamp.map(|t| t <= entry.stat.mtime).unwrap_or(false);
+    if stat == entry.stat && !is_racy {
+        return Ok((None, Some(mode), false));
+    }
+    if stat.size != entry.stat.size && !is_racy {
+        return Ok((Some(Change::Modified), Some(mode), false));
+    }
+
+    let contents = try!(read_worktree_file(&entry.path, &metadata));
+    let sha1 = objects::hash_object(objects::Type::Blob, &contents);
+    if sha1.0 != entry.sha1 {
+        return Ok((Some(Change::Modified), Some(mode), false));
+    }
+
+    let refreshed = entry.stat != stat;
+    entry.stat = stat;
+    Ok((None, Some(mode), refreshed))
+}
+
+fn compare_head(head: Option<&Version>, index: &Version) -> Option<Change> {
+    match head {
+        None => Some(Change::Added),
+        Some(head) if !same_type(head.mode, index.mode) => Some(Change::TypeChanged),
+        Some(head) if *head != *index => Some(Change::Modified),
+        Some(_) => None,
+    }
+}
+
+pub fn read_head_tree(head: &refs::Head) -> Result<BTreeMap<Vec<u8>, trees::TreeEntry>> {
+    let commit_name = match head.commit {
+        Some(ref name) => name,
+        None => return Ok(BTreeMap::new()),
+    };
+
+    match try!(objects::read_object(commit_name)) {
+        objects::Object::Commit(commit) => Ok(try!(trees::flatten(&commit.tree))),
+        _ => Err(Error::InvalidRef("HEAD".to_string())),
+    }
+}
+
+/// Count the commits that are only in the history of one of the two commits, as how far the local
+/// one is ahead of, and behind, the upstream one. Each side is walked with the other hidden, so
+/// the walk stops once only their shared history is left, rather than going through all of it.
+pub fn divergence(local: &Name, upstream: &Name) -> Result<(usize, usize)> {
+    let count = |from: &Name, hidden: &Name| -> Result<usize> {
+        let mut walk = revwalk::RevWalk::new(revwalk::Limits::default());
+        try!(walk.push(from));
+        try!(walk.hide(hidden));
+
+        let mut count = 0;
+        for commit in walk {
+            try!(commit);
+            count += 1;
+        }
+        Ok(count)
+    };
+
+    Ok((try!(count(local, upstream)), try!(count(upstream, local))))
+}
+
+/// Map the `branch.<name>.merge` ref, which names a branch in the remote repository, to the
+/// remote-tracking ref it is fetched into, using the `remote.<remote>.fetch` refspec if there is
+/// one. A remote of "." refers to the local repository itself.
+fn upstream_ref(config: &config::Config, branch: &str) -> Option<String> {
+    let remote = match config.get(&format!("branch.{}.remote", branch)) {
+        Some(remote) => remote,
+        None => return None,
+    };
+    let merge = match config.get(&format!("branch.{}.merge", branch)) {
+        Some(merge) => merge,
+        None => return None,
+    };
+
+    if remote == "." {
+        return Some(merge.to_string());
+    }
+
+    let refspec = config.get(&format!("remote.{}.fetch", remote))
+        .map(|refspec| refspec.trim_left_matches('+'))
+        .and_then(|refspec| {
+            let mut sides = refspec.splitn(2, ':');
+            match (sides.next(), sides.next()) {
+                (Some(src), Some(dst)) if src.ends_with("/*") && dst.ends_with("/*") =>
+                    Some((src[..src.len() - 1].to_string(), dst[..dst.len() - 1].to_string())),
+                _ => None,
+            }
+        });
+
+    match refspec {
+        Some((ref src, ref dst)) if merge.starts_with(src.as_str()) =>
+            Some(format!("{}{}", dst, &merge[src.len()..])),
+        _ => {
+            let branch_name = merge.trim_left_matches("refs/heads/");
+            Some(format!("refs/remotes/{}/{}", remote, branch_name))
+        },
+    }
+}
+
+fn read_upstream(config: &config::Config, head: &refs::Head) -> Result<Option<Upstream>> {
+    let branch = match head.branch {
+        Some(ref branch) => branch,
+        None => return Ok(None),
+    };
+    let refname = match upstream_ref(config, branch) {
+        Some(refname) => refname,
+        None => return Ok(None),
+    };
+
+    let name = refs::shorten(&refname).to_string();
+    let upstream_commit = try!(refs::resolve(&refname));
+    let divergence = match (head.commit.as_ref(), upstream_commit) {
+        (Some(local), Some(upstream)) => Some(try!(divergence(local, &upstream))),
+        (None, Some(_)) => Some((0, 0)),
+        (_, None) => None,
+    };
+
+    Ok(Some(Upstream { name: name, divergence: divergence }))
+}
+
+fn sorted(paths: HashSet<PathBuf>) -> Vec<PathBuf> {
+    let mut paths = paths.into_iter().collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Compute the status of the repository. Refreshing the stat data of unchanged files and updating
+/// the untracked cache are only optimizations, so the index is written back if possible, but
+/// failing to take the lock on the index is not an error.
+pub fn status(config: &config::Config, options: &Options) -> Result<Status> {
+    let head = try!(refs::read_head());
+    let upstream = try!(read_upstream(config, &head));
+    let mut head_tree = try!(read_head_tree(&head));
+
+    // The lock is taken before reading the index, so that writing it back can't undo the changes
+    // of another process that updated the index in the meantime.
+    let lock = Index::lock().ok();
+    let mut index = try!(Index::read());
+    let index_timestamp = index.timestamp;
+    let trust_executable_bit = trust_executable_bit(config);
+
+    let mut files = BTreeMap::new();
+    let mut unmerged: BTreeMap<Vec<u8>, UnmergedFile> = BTreeMap::new();
+    let mut index_changed = false;
+
+    for entry in index.entries.iter_mut() {
+        let version = Version { mode: entry.mode, sha1: Name(entry.sha1.clone()) };
+        let stage = entry.stage() as usize;
+
+        if stage > 0 {
+            head_tree.remove(&entry.path);
+            let worktree = fs::symlink_metadata(fs_path(&entry.path)).ok()
+                .map(|metadata| worktree_mode(&metadata, entry.mode, trust_executable_bit));
+            let file = unmerged.entry(entry.path.clone()).or_insert_with(|| UnmergedFile {
+                path: entry.path.clone(),
+                conflict: Conflict::BothModified,
+                stages: [None, None, None],
+                worktree_mode: worktree,
+            });
+            file.stages[stage - 1] = Some(version);
+            continue;
+        }
+
+        let head_version = head_tree.remove(&entry.path)
+            .map(|head| Version { mode: head.mode, sha1: head.sha1 });
+        let (mut unstaged, worktree, refreshed) =
+            try!(compare_worktree(entry, index_timestamp, trust_executable_bit));
+        index_changed = index_changed || refreshed;
+
+        // An intent-to-add entry doesn't record any contents yet, so the file is reported as an
+        // addition that hasn't been staged.
+        let (staged, index_version) = if entry.is_intent_to_add() {
+            if unstaged != Some(Change::Deleted) {
+                unstaged = Some(Change::Added);
+                (None, None)
+            } else {
+                (None, Some(version))
+            }
+        } else {
+            (compare_head(head_version.as_ref(), &version), Some(version))
+        };
+
+        if staged.is_some() || unstaged.is_some() {
+            files.insert(entry.path.clone(), FileStatus {
+                path: entry.path.clone(),
+                head: head_version,
+                index: index_version,
+                worktree_mode: worktree,
+                staged: staged,
+                unstaged: unstaged,
+            });
+        }
+    }
+
+    // Whatever remains of the HEAD tree is no longer in the index.
+    for (path, head_entry) in head_tree {
+        files.insert(path.clone(), FileStatus {
+            path: path,
+            head: Some(Version { mode: head_entry.mode, sha1: head_entry.sha1 }),
+            index: None,
+            worktree_mode: None,
+            staged: Some(Change::Deleted),
+            unstaged: None,
+        });
+    }
+
+    for file in unmerged.values_mut() {
+        file.conflict = Conflict::from_stages(
+            file.stages[0].is_some(), file.stages[1].is_some(), file.stages[2].is_some());
+    }
+
+    let (untracked, ignored) = if options.untracked == UntrackedMode::No && !options.show_ignored {
+        (Vec::new(), Vec::new())
+    } else {
+        let mut excludes = try!(ignore::Excludes::standard(config));
+        let scan_options = untracked::Options {
+            collapse_directories: options.untracked != UntrackedMode::All,
+            hide_empty_directories: options.untracked != UntrackedMode::All,
+            show_ignored: options.show_ignored,
+            collapse_ignored_directories: false,
+            use_untracked_cache: true,
+        };
+        let (files, cache_changed) =
+            try!(untracked::scan(&mut index, config, &mut excludes, &scan_options));
+        index_changed = index_changed || cache_changed;
+
+        let untracked = if options.untracked == UntrackedMode::No {
+            Vec::new()
+        } else {
+            sorted(files.untracked)
+        };
+        (untracked, sorted(files.ignored))
+    };
+
+    if let Some(lock) = lock {
+        if index_changed {
+            try!(index.write_locked(lock));
+        }
+    }
+
+    Ok(Status {
+        head: head,
+        upstream: upstream,
+        merge_in_progress: repository::git_path(MERGE_HEAD_FILE).exists(),
+        files: files.into_iter().map(|(_, file)| file).collect(),
+        unmerged: unmerged.into_iter().map(|(_, file)| file).collect(),
+        untracked: untracked,
+        ignored: ignored,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use objects;
+    use objects::Name;
+    use repository::TestRepository;
+
+    /// Write a commit with the given parents, at a time that puts it after them.
+    fn commit(parents: &[&Name], time: i64) -> Name {
+        let tree = objects::write_object(objects::Type::Tree, b"").unwrap();
+        let mut contents = format!("tree {}\n", tree.0);
+        for parent in parents {
+            contents.push_str(&format!("parent {}\n", parent.0));
+        }
+        contents.push_str(&format!("author A U Thor <author@example.com> {} +0000\n", time));
+        contents.push_str(&format!("committer C O Mitter <committer@example.com> {} +0000\n", time));
+        contents.push_str(&format!("\ncommit at {}\n", time));
+        objects::write_object(objects::Type::Commit, contents.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn counts_commits_ahead_and_behind() {
+        let _repository = TestRepository::new("status-divergence");
+        let base = commit(&[], 1000);
+        let shared = commit(&[&base], 2000);
+        let local = commit(&[&commit(&[&shared], 3000)], 4000);
+        let upstream = commit(&[&shared], 3500);
+        let merged = commit(&[&local, &upstream], 5000);
+
+        assert_eq!((0, 0), divergence(&local, &local).unwrap());
+        assert_eq!((2, 1), divergence(&local, &upstream).unwrap());
+        assert_eq!((1, 2), divergence(&upstream, &local).unwrap());
+        assert_eq!((3, 0), divergence(&merged, &upstream).unwrap());
+        assert_eq!((0, 3), divergence(&base, &local).unwrap());
+    }
+
+    #[test]
+    fn stops_counting_at_shared_history() {
+        let repository = TestRepository::new("status-shared-history");
+        let root = commit(&[], 1000);
+        let mut shared = root.clone();
+        for time in 1..20 {
+            shared = commit(&[&shared], 1000 + time * 100);
+        }
+        let local = commit(&[&shared], 5000);
+        let upstream = commit(&[&commit(&[&shared], 5100)], 5200);
+
+        // The start of history is never read, so it doesn't matter that it's gone.
+        let root_path = repository.dir.join("objects").join(&root.0[..2]).join(&root.0[2..]);
+        fs::remove_file(root_path).unwrap();
+        assert_eq!((1, 2), divergence(&local, &upstream).unwrap());
+    }
+}
@@ -0,0 +1,142 @@
+//! Provides `trace!`, which prints diagnostics about what a command is doing, such as which config
+//! files it reads, which objects it loads and how refs are resolved. Like in git, tracing is off
+//! unless `$GIT_TRACE` is set, to one of:
+//!
+//! - `1`, `2` or `true`, to trace to STDERR
+//! - a file descriptor from `3` to `9`, which the caller is expected to have opened
+//! - an absolute path, which is opened for appending
+//!
+//! Each line is prefixed with the time and the source location it was traced from.
+
+use chrono::Local;
+use cli;
+
+use std::env;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The environment variable that turns tracing on.
+const TRACE_VARIABLE: &'static str = "GIT_TRACE";
+
+/// Where the trace goes, according to the value of `$GIT_TRACE`.
+#[derive(Debug, PartialEq, Eq)]
+enum Target {
+    Disabled,
+    Stderr,
+    Descriptor(i32),
+    File(String),
+
+    /// A value that's neither a switch, a file descriptor nor an absolute path, which disables
+    /// tracing with a warning.
+    Unknown,
+}
+
+fn parse_target(value: &str) -> Target {
+    let lowercase = value.to_ascii_lowercase();
+    if value.is_empty() || value == "0" || lowercase == "false" {
+        Target::Disabled
+    } else if value == "1" || value == "2" || lowercase == "true" {
+        Target::Stderr
+    } else if value.len() == 1 && value.chars().all(|chr| chr.is_ascii_digit()) {
+        Target::Descriptor(value.parse().unwrap())
+    } else if Path::new(value).is_absolute() {
+        Target::File(value.to_string())
+    } else {
+        Target::Unknown
+    }
+}
+
+/// Open wherever `$GIT_TRACE` points to, warning about values that can't be traced to in the same
+/// way as git.
+fn open_output() -> Option<Box<Write + Send>> {
+    let value = match env::var(TRACE_VARIABLE) {
+        Ok(value) => value,
+        Err(_) => return None,
+    };
+
+    match parse_target(&value) {
+        Target::Disabled => None,
+        Target::Stderr => Some(Box::new(io::stderr())),
+        Target::Descriptor(fd) => Some(Box::new(unsafe { File::from_raw_fd(fd) })),
+        Target::File(path) => match OpenOptions::new().append(true).create(true).open(&path) {
+            Ok(file) => Some(Box::new(file)),
+            Err(err) => {
+                cli::warning(&format!("could not open '{}' for tracing: {}", path, err));
+                None
+            },
+        },
+        Target::Unknown => {
+            cli::warning(&format!("unknown trace value for '{}': {}\n         \
+                                   If you want to trace into a file, then please set {}\n         \
+                                   to an absolute pathname (starting with /)",
+                                  TRACE_VARIABLE, value, TRACE_VARIABLE));
+            None
+        },
+    }
+}
+
+lazy_static! {
+    static ref OUTPUT: Mutex<Option<Box<Write + Send>>> = Mutex::new(open_output());
+}
+
+/// Whether tracing is on, so that callers can skip working out what to trace when it isn't.
+pub fn enabled() -> bool {
+    OUTPUT.lock().map(|output| output.is_some()).unwrap_or(false)
+}
+
+/// Write a single line to the trace, prefixed with the time and the location it was traced from.
+/// Used through `trace!`, which fills in the location. If the trace can't be written to, tracing
+/// is turned off, rather than failing the command.
+pub fn write_line(file: &str, line: u32, args: fmt::Arguments) {
+    let mut output = match OUTPUT.lock() {
+        Ok(output) => output,
+        Err(_) => return,
+    };
+
+    let failed = match *output {
+        Some(ref mut output) => {
+            // The message is aligned to the same column as git's, which fits most file names.
+            let location = format!("{}:{}", file, line);
+            let message = format!("{} {:<23} trace: {}\n",
+                                  Local::now().format("%H:%M:%S%.6f"), location, args);
+            output.write_all(message.as_bytes()).is_err()
+        },
+        None => false,
+    };
+
+    if failed {
+        cli::warning(&format!("unable to write trace for {}", TRACE_VARIABLE));
+        *output = None;
+    }
+}
+
+/// Trace a message, formatted like with `format!`, if `$GIT_TRACE` is set.
+#[macro_export]
+macro_rules! trace {
+    ($($arg: tt)*) => {
+        if $crate::trace::enabled() {
+            $crate::trace::write_line(file!(), line!(), format_args!($($arg)*));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_target, Target};
+
+    #[test]
+    fn parses_trace_targets() {
+        assert_eq!(Target::Disabled, parse_target(""));
+        assert_eq!(Target::Disabled, parse_target("FALSE"));
+        assert_eq!(Target::Stderr, parse_target("1"));
+        assert_eq!(Target::Stderr, parse_target("True"));
+        assert_eq!(Target::Descriptor(7), parse_target("7"));
+        assert_eq!(Target::File("/tmp/trace".to_string()), parse_target("/tmp/trace"));
+        assert_eq!(Target::Unknown, parse_target("12"));
+        assert_eq!(Target::Unknown, parse_target("trace.log"));
+    }
+}
@@ -0,0 +1,192 @@
+//! Provides functionality for reading/writing tree objects, which record the contents of a
+//! directory: the name, mode and object name of each file and subdirectory within it.
+
+use objects;
+use objects::Name;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::error::Error as StdError;
+use std::io;
+use std::io::BufRead;
+use std::str;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidTree(String),
+    ObjectError(objects::Error),
+
+    /// The contents of the tree couldn't be read.
+    IOError(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidTree(ref description) => write!(f, "invalid tree: {}", description),
+            Error::ObjectError(ref err) => write!(f, "unable to read tree: {}", err),
+            Error::IOError(ref err) => write!(f, "unable to read tree: {}", err),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidTree(ref description) => description,
+            Error::ObjectError(ref err) => err.description(),
+            Error::IOError(ref err) => err.description(),
+        }
+    }
+
+    fn source(&self) -> Option<&(StdError + 'static)> {
+        match *self {
+            Error::InvalidTree(_) => None,
+            Error::ObjectError(ref err) => Some(err),
+            Error::IOError(ref err) => Some(err),
+        }
+    }
+}
+
+/// The mode of a subdirectory within a tree.
+pub const MODE_TREE: u32 = 0o040000;
+
+/// The mode of a regular, non-executable file.
+pub const MODE_FILE: u32 = 0o100644;
+
+/// The mode of an executable file.
+pub const MODE_EXECUTABLE: u32 = 0o100755;
+
+/// The mode of a symbolic link, whose target is stored as the contents of a blob.
+pub const MODE_SYMLINK: u32 = 0o120000;
+
+/// The mode of a submodule, which refers to a commit in another repository.
+pub const MODE_GITLINK: u32 = 0o160000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub mode: u32,
+
+    /// The name of the entry within the tree, or, when the tree has been flattened, the path
+    /// relative to the root tree.
+    pub name: Vec<u8>,
+    pub sha1: Name,
+}
+
+impl TreeEntry {
+    pub fn is_tree(&self) -> bool {
+        self.mode == MODE_TREE
+    }
+}
+
+pub struct Tree {
+    pub name: Name,
+    pub entries: Vec<TreeEntry>,
+}
+
+pub fn parse_tree<R>(reader: &mut R, name: &Name) -> Result<Tree, Error>
+        where R: BufRead {
+    let mut entries = Vec::new();
+
+    // Each entry consists of the mode in octal, a space, the NUL-terminated name and the raw SHA-1
+    // of the object.
+    loop {
+        let mut mode_bytes = Vec::new();
+        let read = try!(reader.read_until(b' ', &mut mode_bytes)
+                        .map_err(Error::IOError));
+        if read == 0 {
+            break;
+        }
+        if mode_bytes.pop() != Some(b' ') {
+            return Err(Error::InvalidTree(format!("truncated entry in tree {}", name.0)));
+        }
+
+        let mode = try!(str::from_utf8(&mode_bytes).ok()
+                        .and_then(|mode_str| u32::from_str_radix(mode_str, 8).ok())
+                        .ok_or_else(|| Error::InvalidTree(
+                                format!("invalid mode: {}", String::from_utf8_lossy(&mode_bytes)))));
+
+        let mut entry_name = Vec::new();
+        try!(reader.read_until(0, &mut entry_name).map_err(Error::IOError));
+        if entry_name.pop() != Some(0) {
+            return Err(Error::InvalidTree(format!("truncated entry in tree {}", name.0)));
+        }
+
+        let mut sha1_bytes = [0; 20];
+        try!(reader.read_exact(&mut sha1_bytes).map_err(Error::IOError));
+
+        entries.push(TreeEntry {
+            mode: mode,
+            name: entry_name,
+            sha1: Name::from_bytes(&sha1_bytes),
+        });
+    }
+
+    Ok(Tree { name: name.clone(), entries: entries })
+}
+
+pub fn read_tree(name: &Name) -> Result<Tree, Error> {
+    match objects::read_object(name) {
+        Ok(objects::Object::Tree(tree)) => Ok(tree),
+        Ok(_) => Err(Error::InvalidTree(format!("object {} is not a tree", name.0))),
+        Err(err) => Err(Error::ObjectError(err)),
+    }
+}
+
+/// Read the tree and all its subtrees, producing a map from the path of every file, relative to
+/// the given tree, to its entry. The names of the returned entries are those full paths.
+pub fn flatten(name: &Name) -> Result<BTreeMap<Vec<u8>, TreeEntry>, Error> {
+    let mut files = BTreeMap::new();
+    try!(flatten_into(name, &[], &mut files));
+    Ok(files)
+}
+
+fn flatten_into(name: &Name, prefix: &[u8], files: &mut BTreeMap<Vec<u8>, TreeEntry>)
+        -> Result<(), Error> {
+    let tree = try!(read_tree(name));
+    for entry in tree.entries {
+        let mut path = prefix.to_vec();
+        path.extend_from_slice(&entry.name);
+
+        if entry.is_tree() {
+            path.push(b'/');
+            try!(flatten_into(&entry.sha1, &path, files));
+        } else {
+            let file = TreeEntry { mode: entry.mode, name: path.clone(), sha1: entry.sha1 };
+            files.insert(path, file);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use objects::Name;
+
+    #[test]
+    fn parses_tree_entries() {
+        let mut contents = Vec::new();
+        contents.extend_from_slice(b"100644 README.md\0");
+        contents.extend_from_slice(&[0x11; 20]);
+        contents.extend_from_slice(b"40000 src\0");
+        contents.extend_from_slice(&[0xab; 20]);
+
+        let name = Name("0123456789012345678901234567890123456789".to_string());
+        let tree = parse_tree(&mut &contents[..], &name).unwrap();
+
+        assert_eq!(vec![
+            TreeEntry {
+                mode: MODE_FILE,
+                name: b"README.md".to_vec(),
+                sha1: Name("1111111111111111111111111111111111111111".to_string()),
+            },
+            TreeEntry {
+                mode: MODE_TREE,
+                name: b"src".to_vec(),
+                sha1: Name("abababababababababababababababababababab".to_string()),
+            },
+        ], tree.entries);
+    }
+}
@@ -0,0 +1,1148 @@
+//! Provides functionality for finding the files in the working directory that are not tracked by
+//! the index, along with the untracked cache, an index extension that remembers the untracked files
+//! in each directory so that directories which haven't changed since the last scan don't need to
+//! be read again. See the "Untracked cache" section of git's index-format.txt for the on-disk
+//! format of the cache.
+
+extern crate libc;
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::{CStr, OsStr};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, Cursor, Read, Write};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+
+use config;
+use ignore;
+use index;
+use index::{Error, Index, StatData, Timestamp};
+use objects;
+use repository;
+
+/// Report wholly untracked directories as a single entry, rather than listing their contents.
+pub const DIR_SHOW_OTHER_DIRECTORIES: u32 = 1 << 1;
+
+/// Don't report untracked directories that contain no files.
+pub const DIR_HIDE_EMPTY_DIRECTORIES: u32 = 1 << 2;
+
+/// The name of the file in each directory containing exclude patterns.
+const PER_DIRECTORY_EXCLUDE_FILE: &'static str = ".gitignore";
+
+/// The file inside the repository with exclude patterns that aren't shared with other clones.
+const INFO_EXCLUDE_FILE: &'static str = "info/exclude";
+
+/// The deepest a directory in the untracked cache can be nested. Every level adds at least two
+/// bytes to a path, so no deeper directory fits within the longest path the system allows.
+const MAX_DIRECTORY_DEPTH: usize = 4096 / 2;
+
+/// Options controlling which untracked files are reported, and how.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Options {
+    /// Report a directory that contains no tracked files once, as `dir/`, instead of listing every
+    /// file inside it.
+    pub collapse_directories: bool,
+
+    /// When collapsing directories, don't report directories that contain no untracked files.
+    pub hide_empty_directories: bool,
+
+    /// Collect the ignored files as well. Otherwise, ignored directories are not visited at all.
+    pub show_ignored: bool,
+
+    /// When collecting ignored files without collapsing directories, report an ignored directory
+    /// once, as `dir/`, instead of listing every file inside it.
+    pub collapse_ignored_directories: bool,
+
+    /// Read the untracked cache from the index, and update it according to `core.untrackedCache`.
+    /// The cache is only used when the options match the ones it was built with, namely collapsed
+    /// and non-empty directories without ignored files, along with the standard excludes.
+    pub use_untracked_cache: bool,
+}
+
+impl Options {
+    fn dir_flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.collapse_directories {
+            flags |= DIR_SHOW_OTHER_DIRECTORIES;
+        }
+        if self.hide_empty_directories {
+            flags |= DIR_HIDE_EMPTY_DIRECTORIES;
+        }
+        flags
+    }
+
+    fn can_use_untracked_cache(&self) -> bool {
+        self.use_untracked_cache && !self.show_ignored &&
+            self.dir_flags() == DIR_SHOW_OTHER_DIRECTORIES | DIR_HIDE_EMPTY_DIRECTORIES
+    }
+}
+
+/// The cached state of one directory in the working tree.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CachedDirectory {
+    /// The name of the directory within its parent. Empty for the root of the working tree.
+    pub name: Vec<u8>,
+
+    /// The untracked entries directly inside this directory. Wholly untracked directories end in a
+    /// slash.
+    pub untracked: Vec<Vec<u8>>,
+
+    /// The subdirectories that were scanned: the ones containing tracked files, and the untracked
+    /// ones that were only checked for untracked files, which have their own stat data so that
+    /// files added inside them are noticed.
+    pub dirs: Vec<CachedDirectory>,
+
+    /// Whether `untracked` can be trusted, as long as the directory itself hasn't changed.
+    pub valid: bool,
+
+    /// Set when the directory was only scanned to check whether it was empty.
+    pub check_only: bool,
+
+    /// The stat data of the directory when it was scanned.
+    pub stat: StatData,
+
+    /// The object name of the `.gitignore` in this directory when it was scanned, or `None` if
+    /// there was no such file.
+    pub exclude_sha1: Option<[u8; 20]>,
+}
+
+impl CachedDirectory {
+    fn new(name: &[u8]) -> CachedDirectory {
+        CachedDirectory { name: name.to_vec(), ..CachedDirectory::default() }
+    }
+
+    /// Mark this directory and everything under it as needing to be rescanned.
+    fn invalidate_all(&mut self) {
+        self.valid = false;
+        for dir in self.dirs.iter_mut() {
+            dir.invalidate_all();
+        }
+    }
+
+    fn count(&self) -> usize {
+        1 + self.dirs.iter().map(|d| d.count()).sum::<usize>()
+    }
+
+    /// Visit this directory and everything under it in the depth-first order used when
+    /// serializing, passing the position of each directory in that order.
+    fn each_mut<F>(&mut self, position: &mut usize, f: &mut F) -> io::Result<()>
+            where F: FnMut(usize, &mut CachedDirectory) -> io::Result<()> {
+        try!(f(*position, self));
+        *position += 1;
+        for dir in self.dirs.iter_mut() {
+            try!(dir.each_mut(position, f));
+        }
+        Ok(())
+    }
+
+    /// List the directories in the same depth-first order as they are serialized.
+    fn flatten<'a>(&'a self, list: &mut Vec<&'a CachedDirectory>) {
+        list.push(self);
+        for dir in self.dirs.iter() {
+            dir.flatten(list);
+        }
+    }
+}
+
+/// The stat data and object name of an exclude file at the time the cache was built, so that the
+/// cache can be thrown away if the file changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExcludeFileState {
+    pub stat: StatData,
+    pub sha1: Option<[u8; 20]>,
+}
+
+impl ExcludeFileState {
+    fn for_file(path: Option<&Path>) -> ExcludeFileState {
+        let path = match path {
+            Some(path) => path,
+            None => return ExcludeFileState::default(),
+        };
+
+        let stat = fs::metadata(path)
+            .map(|metadata| StatData::from_metadata(&metadata))
+            .unwrap_or_default();
+        ExcludeFileState { stat: stat, sha1: hash_file(path) }
+    }
+}
+
+/// The contents of the untracked cache index extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UntrackedCache {
+    /// A list of NUL-terminated strings identifying the environments in which the cache may be
+    /// used, which guards against the working tree being moved or shared across machines.
+    pub ident: Vec<u8>,
+    pub info_exclude: ExcludeFileState,
+    pub excludes_file: ExcludeFileState,
+    pub dir_flags: u32,
+    pub exclude_per_dir: Vec<u8>,
+    pub root: Option<CachedDirectory>,
+}
+
+fn invalid_cache(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn read_sha1(reader: &mut Read) -> io::Result<Option<[u8; 20]>> {
+    let mut sha1 = [0; 20];
+    try!(reader.read_exact(&mut sha1));
+    Ok(if sha1 == [0; 20] { None } else { Some(sha1) })
+}
+
+fn write_sha1(writer: &mut Write, sha1: &Option<[u8; 20]>) -> io::Result<()> {
+    writer.write_all(sha1.as_ref().unwrap_or(&[0; 20]))
+}
+
+fn read_nul_terminated(reader: &mut BufRead) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    try!(reader.read_until(0, &mut bytes));
+    if bytes.pop() != Some(0) {
+        return Err(invalid_cache("unterminated string"));
+    }
+    Ok(bytes)
+}
+
+/// The number of bytes left to read from the cursor.
+fn remaining(reader: &Cursor<&[u8]>) -> usize {
+    reader.get_ref().len().saturating_sub(reader.position() as usize)
+}
+
+/// Read a bitmap compressed using the EWAH scheme, as serialized by git's `ewah_io.c`. The bits
+/// are returned uncompressed. As every bitmap in the untracked cache has one bit per directory, a
+/// bitmap claiming more than `max_bits` bits is rejected, so that a corrupt index can't make us
+/// expand runs of words without end.
+fn read_ewah(reader: &mut Cursor<&[u8]>, max_bits: usize) -> io::Result<Vec<bool>> {
+    let bit_size = try!(reader.read_u32::<NetworkEndian>()) as usize;
+    let word_count = try!(reader.read_u32::<NetworkEndian>()) as usize;
+    if bit_size > max_bits {
+        return Err(invalid_cache("bitmap is too large"));
+    }
+    if word_count.saturating_mul(8) > remaining(reader) {
+        return Err(invalid_cache("truncated bitmap"));
+    }
+
+    let mut words = Vec::with_capacity(word_count);
+    for _ in 0..word_count {
+        words.push(try!(reader.read_u64::<NetworkEndian>()));
+    }
+    try!(reader.read_u32::<NetworkEndian>());  // position of the last marker word
+
+    // The words alternate between a marker word, describing a run of identical words followed by
+    // a number of literal words, and those literal words. Words past the end of the bitmap are
+    // never looked at, so runs are only expanded as far as it.
+    let total_words = (bit_size + 63) / 64;
+    let mut uncompressed = Vec::with_capacity(total_words);
+    let mut position = 0;
+    while position < words.len() {
+        let marker = words[position];
+        let running_bit = marker & 1 != 0;
+        let running_length = ((marker >> 1) & 0xffffffff) as usize;
+        let literal_count = (marker >> 33) as usize;
+
+        let run = running_length.min(total_words.saturating_sub(uncompressed.len()));
+        uncompressed.extend((0..run).map(|_| if running_bit { !0 } else { 0 }));
+
+        if position + 1 + literal_count > words.len() {
+            return Err(invalid_cache("truncated bitmap"));
+        }
+        uncompressed.extend_from_slice(&words[position + 1..position + 1 + literal_count]);
+        position += 1 + literal_count;
+    }
+
+    Ok((0..bit_size)
+       .map(|i| uncompressed.get(i / 64).map(|w| w & (1 << (i % 64)) != 0).unwrap_or(false))
+       .collect())
+}
+
+/// Write a bitmap using the EWAH scheme. No attempt is made to compress runs, as the bitmaps in
+/// the untracked cache have one bit per directory, so they are small.
+fn write_ewah(writer: &mut Write, bits: &[bool]) -> io::Result<()> {
+    let bit_size = bits.iter().rposition(|&b| b).map(|i| i + 1).unwrap_or(0);
+    let mut literals = vec![0u64; (bit_size + 63) / 64];
+    for i in 0..bit_size {
+        if bits[i] {
+            literals[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    try!(writer.write_u32::<NetworkEndian>(bit_size as u32));
+    try!(writer.write_u32::<NetworkEndian>(literals.len() as u32 + 1));
+    try!(writer.write_u64::<NetworkEndian>((literals.len() as u64) << 33));
+    for word in literals.iter() {
+        try!(writer.write_u64::<NetworkEndian>(*word));
+    }
+    writer.write_u32::<NetworkEndian>(0)
+}
+
+/// Read the header of a directory's block, returning the directory along with the number of
+/// directories inside it, whose blocks follow.
+fn read_directory_header(reader: &mut Cursor<&[u8]>) -> io::Result<(CachedDirectory, u64)> {
+    let untracked_count = try!(index::read_varint(reader));
+    let dir_count = try!(index::read_varint(reader));
+    let name = try!(read_nul_terminated(reader));
+
+    let mut untracked = Vec::new();
+    for _ in 0..untracked_count {
+        untracked.push(try!(read_nul_terminated(reader)));
+    }
+
+    Ok((CachedDirectory { name: name, untracked: untracked, ..Default::default() }, dir_count))
+}
+
+/// Read the block of a directory, followed by those of the directories inside it, depth-first.
+/// The blocks are read with a stack of their own rather than by recursion, and a corrupt cache
+/// can't claim more directories than `dir_count`, the number it says it holds, or nest them more
+/// deeply than a path can go.
+fn read_directory_blocks(reader: &mut Cursor<&[u8]>, dir_count: usize)
+        -> io::Result<CachedDirectory> {
+    let mut budget = dir_count;
+    let mut read_header = |reader: &mut Cursor<&[u8]>| {
+        if budget == 0 {
+            return Err(invalid_cache("more directories than counted"));
+        }
+        budget -= 1;
+        read_directory_header(reader)
+    };
+
+    let mut stack = vec![try!(read_header(reader))];
+    loop {
+        if stack.last().map(|&(_, children)| children > 0).unwrap_or(false) {
+            if stack.len() > MAX_DIRECTORY_DEPTH {
+                return Err(invalid_cache("directories nested too deeply"));
+            }
+            let child = try!(read_header(reader));
+            stack.last_mut().unwrap().1 -= 1;
+            stack.push(child);
+            continue;
+        }
+
+        let (dir, _) = stack.pop().unwrap();
+        match stack.last_mut() {
+            Some(&mut (ref mut parent, _)) => parent.dirs.push(dir),
+            None => return Ok(dir),
+        }
+    }
+}
+
+fn write_directory_block(writer: &mut Write, dir: &CachedDirectory) -> io::Result<()> {
+    // Only the untracked entries of valid directories are meaningful.
+    let untracked: &[Vec<u8>] = if dir.valid { &dir.untracked } else { &[] };
+
+    try!(index::write_varint(writer, untracked.len() as u64));
+    try!(index::write_varint(writer, dir.dirs.len() as u64));
+    try!(writer.write_all(&dir.name));
+    try!(writer.write_all(&[0]));
+    for name in untracked {
+        try!(writer.write_all(name));
+        try!(writer.write_all(&[0]));
+    }
+
+    for child in dir.dirs.iter() {
+        try!(write_directory_block(writer, child));
+    }
+    Ok(())
+}
+
+impl UntrackedCache {
+    /// A new, empty cache for the current environment.
+    pub fn new(dir_flags: u32) -> UntrackedCache {
+        let mut ident = current_ident().into_bytes();
+        ident.push(0);
+
+        UntrackedCache {
+            ident: ident,
+            info_exclude: ExcludeFileState::default(),
+            excludes_file: ExcludeFileState::default(),
+            dir_flags: dir_flags,
+            exclude_per_dir: PER_DIRECTORY_EXCLUDE_FILE.as_bytes().to_vec(),
+            root: None,
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> io::Result<UntrackedCache> {
+        let mut reader = Cursor::new(data);
+
+        let ident_length = try!(index::read_varint(&mut reader)) as usize;
+        if ident_length > remaining(&reader) {
+            return Err(invalid_cache("truncated ident"));
+        }
+        let mut ident = vec![0; ident_length];
+        try!(reader.read_exact(&mut ident));
+
+        let info_exclude_stat = try!(StatData::read(&mut reader));
+        let excludes_file_stat = try!(StatData::read(&mut reader));
+        let dir_flags = try!(reader.read_u32::<NetworkEndian>());
+        let info_exclude_sha1 = try!(read_sha1(&mut reader));
+        let excludes_file_sha1 = try!(read_sha1(&mut reader));
+        let exclude_per_dir = try!(read_nul_terminated(&mut reader));
+
+        let mut cache = UntrackedCache {
+            ident: ident,
+            info_exclude: ExcludeFileState { stat: info_exclude_stat, sha1: info_exclude_sha1 },
+            excludes_file: ExcludeFileState { stat: excludes_file_stat, sha1: excludes_file_sha1 },
+            dir_flags: dir_flags,
+            exclude_per_dir: exclude_per_dir,
+            root: None,
+        };
+
+        if reader.position() as usize >= data.len() {
+            return Ok(cache);
+        }
+
+        let dir_count = try!(index::read_varint(&mut reader)) as usize;
+        if dir_count == 0 {
+            return Ok(cache);
+        }
+
+        let mut root = try!(read_directory_blocks(&mut reader, dir_count));
+        if root.count() != dir_count {
+            return Err(invalid_cache("directory count mismatch"));
+        }
+
+        let valid = try!(read_ewah(&mut reader, dir_count));
+        let check_only = try!(read_ewah(&mut reader, dir_count));
+        let sha1_valid = try!(read_ewah(&mut reader, dir_count));
+
+        // The remaining per-directory data is stored in the same depth-first order as the blocks,
+        // so walk the tree in that order, filling in each directory.
+        try!(root.each_mut(&mut 0, &mut |i, dir| {
+            dir.check_only = check_only.get(i).cloned().unwrap_or(false);
+            Ok(())
+        }));
+        try!(root.each_mut(&mut 0, &mut |i, dir| {
+            if valid.get(i).cloned().unwrap_or(false) {
+                dir.valid = true;
+                dir.stat = try!(StatData::read(&mut reader));
+            }
+            Ok(())
+        }));
+        try!(root.each_mut(&mut 0, &mut |i, dir| {
+            if sha1_valid.get(i).cloned().unwrap_or(false) {
+                dir.exclude_sha1 = try!(read_sha1(&mut reader));
+            }
+            Ok(())
+        }));
+
+        cache.root = Some(root);
+        Ok(cache)
+    }
+
+    pub fn write(&self, writer: &mut Write) -> io::Result<()> {
+        try!(index::write_varint(writer, self.ident.len() as u64));
+        try!(writer.write_all(&self.ident));
+        try!(self.info_exclude.stat.write(writer));
+        try!(self.excludes_file.stat.write(writer));
+        try!(writer.write_u32::<NetworkEndian>(self.dir_flags));
+        try!(write_sha1(writer, &self.info_exclude.sha1));
+        try!(write_sha1(writer, &self.excludes_file.sha1));
+        try!(writer.write_all(&self.exclude_per_dir));
+        try!(writer.write_all(&[0]));
+
+        let root = match self.root {
+            Some(ref root) => root,
+            None => return index::write_varint(writer, 0),
+        };
+
+        let mut dirs = Vec::new();
+        root.flatten(&mut dirs);
+
+        try!(index::write_varint(writer, dirs.len() as u64));
+        try!(write_directory_block(writer, root));
+
+        let valid = dirs.iter().map(|d| d.valid).collect::<Vec<_>>();
+        let check_only = dirs.iter().map(|d| d.valid && d.check_only).collect::<Vec<_>>();
+        let sha1_valid = dirs.iter().map(|d| d.exclude_sha1.is_some()).collect::<Vec<_>>();
+        try!(write_ewah(writer, &valid));
+        try!(write_ewah(writer, &check_only));
+        try!(write_ewah(writer, &sha1_valid));
+
+        for dir in dirs.iter().filter(|d| d.valid) {
+            try!(dir.stat.write(writer));
+        }
+        for dir in dirs.iter().filter(|d| d.exclude_sha1.is_some()) {
+            try!(write_sha1(writer, &dir.exclude_sha1));
+        }
+
+        writer.write_all(&[0])
+    }
+
+    fn is_for_current_environment(&self) -> bool {
+        let ident = current_ident();
+        self.ident.split(|&b| b == 0).any(|i| i == ident.as_bytes())
+    }
+
+    /// Invalidate the directories containing the given path, which should be called whenever the
+    /// path is added to or removed from the index, as that changes whether it is untracked.
+    pub fn invalidate_path(&mut self, path: &[u8]) {
+        let mut dir = match self.root {
+            Some(ref mut root) => root,
+            None => return,
+        };
+
+        dir.valid = false;
+        let mut components = path.split(|&b| b == b'/').peekable();
+        while let Some(component) = components.next() {
+            if components.peek().is_none() {
+                break;
+            }
+
+            let current = dir;
+            dir = match current.dirs.iter_mut().find(|d| d.name == component) {
+                Some(child) => child,
+                None => return,
+            };
+            dir.valid = false;
+        }
+    }
+}
+
+/// Identifies the working tree and operating system, so that a cache created elsewhere isn't used.
+fn current_ident() -> String {
+    let work_tree = env::current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+
+    let sysname = unsafe {
+        let mut uts: libc::utsname = mem::zeroed();
+        if libc::uname(&mut uts) == 0 {
+            CStr::from_ptr(uts.sysname.as_ptr()).to_string_lossy().into_owned()
+        } else {
+            String::new()
+        }
+    };
+
+    format!("Location {}, system {}", work_tree, sysname)
+}
+
+/// The object name recorded for an exclude file, or `None` if there is no such file. Git appends a
+/// newline to the contents of exclude files before parsing them, and hashes them as blobs after
+/// doing so, so the same is done here in order to share the cache with git.
+fn hash_file(path: &Path) -> Option<[u8; 20]> {
+    let mut contents = Vec::new();
+    match File::open(path).and_then(|mut file| file.read_to_end(&mut contents)) {
+        Ok(_) => {
+            contents.push(b'\n');
+            Some(objects::hash_object(objects::Type::Blob, &contents).to_bytes())
+        },
+        Err(_) => None,
+    }
+}
+
+fn join(dir: &[u8], name: &[u8]) -> Vec<u8> {
+    let mut path = dir.to_vec();
+    if !path.is_empty() {
+        path.push(b'/');
+    }
+    path.extend_from_slice(name);
+    path
+}
+
+fn bytes_to_path(bytes: &[u8]) -> &Path {
+    Path::new(OsStr::from_bytes(bytes))
+}
+
+/// The path to pass to the filesystem for the given directory, relative to the root of the working
+/// tree.
+fn fs_path(dir: &[u8]) -> &Path {
+    if dir.is_empty() { Path::new(".") } else { bytes_to_path(dir) }
+}
+
+fn to_scan_error(dir: &[u8], err: io::Error) -> Error {
+    Error::ScanError(fs_path(dir).to_path_buf(), err)
+}
+
+/// The results of scanning the contents of a directory that contains no tracked files.
+#[derive(Default)]
+struct DirectoryContents {
+    has_untracked: bool,
+    ignored: Vec<Vec<u8>>,
+}
+
+struct Scanner<'a> {
+    tracked: HashSet<Vec<u8>>,
+    tracked_dirs: HashSet<Vec<u8>>,
+    tracked_exclude_files: HashMap<Vec<u8>, (StatData, String)>,
+    excludes: &'a mut ignore::Excludes,
+    options: Options,
+    index_timestamp: Option<Timestamp>,
+    files: index::UntrackedFiles,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(index: &Index, excludes: &'a mut ignore::Excludes, options: Options) -> Scanner<'a> {
+        let mut tracked = HashSet::new();
+        let mut tracked_dirs = HashSet::new();
+        let mut tracked_exclude_files = HashMap::new();
+        for entry in index.entries.iter() {
+            tracked.insert(entry.path.clone());
+            if entry.path().file_name() == Some(OsStr::new(PER_DIRECTORY_EXCLUDE_FILE)) {
+                tracked_exclude_files.insert(entry.path.clone(), (entry.stat, entry.sha1.clone()));
+            }
+            for (i, _) in entry.path.iter().enumerate().filter(|&(_, &b)| b == b'/') {
+                tracked_dirs.insert(entry.path[..i].to_vec());
+            }
+        }
+
+        Scanner {
+            tracked: tracked,
+            tracked_dirs: tracked_dirs,
+            tracked_exclude_files: tracked_exclude_files,
+            excludes: excludes,
+            options: options,
+            index_timestamp: index.timestamp,
+            files: index::UntrackedFiles { untracked: HashSet::new(), ignored: HashSet::new() },
+        }
+    }
+
+    /// List the entries of a directory, other than `.git`, along with whether each is a directory.
+    /// Symbolic links are not followed, so a link to a directory is reported like a file.
+    fn read_directory(&self, dir: &[u8]) -> Result<Vec<(Vec<u8>, bool)>, Error> {
+        let entries = try!(fs::read_dir(fs_path(dir)).map_err(|e| to_scan_error(dir, e)));
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = try!(entry.map_err(|e| to_scan_error(dir, e)));
+            let name = entry.file_name().as_bytes().to_vec();
+            if name == b".git" {
+                continue;
+            }
+
+            let is_dir = try!(entry.file_type().map_err(|e| to_scan_error(dir, e))).is_dir();
+            names.push((name, is_dir));
+        }
+
+        names.sort();
+        Ok(names)
+    }
+
+    fn is_excluded(&mut self, path: &[u8], is_dir: bool) -> Result<bool, Error> {
+        self.excludes.is_excluded(bytes_to_path(path), is_dir).map_err(Error::ExcludeError)
+    }
+
+    fn report_untracked(&mut self, path: &[u8], is_dir: bool) {
+        self.files.untracked.insert(display_path(path, is_dir));
+    }
+
+    fn report_ignored(&mut self, path: &[u8], is_dir: bool) {
+        if self.options.show_ignored {
+            self.files.ignored.insert(display_path(path, is_dir));
+        }
+    }
+
+    /// Scan a directory without consulting the untracked cache.
+    fn scan(&mut self, dir: &[u8]) -> Result<(), Error> {
+        for (name, is_dir) in try!(self.read_directory(dir)) {
+            let path = join(dir, &name);
+            try!(self.scan_entry(&path, is_dir));
+        }
+        Ok(())
+    }
+
+    fn scan_entry(&mut self, path: &[u8], is_dir: bool) -> Result<(), Error> {
+        if !is_dir {
+            if self.tracked.contains(path) {
+                return Ok(());
+            }
+
+            if try!(self.is_excluded(path, false)) {
+                self.report_ignored(path, false);
+            } else {
+                self.report_untracked(path, false);
+            }
+            return Ok(());
+        }
+
+        if self.tracked_dirs.contains(path) {
+            return self.scan(path);
+        }
+
+        if try!(self.is_excluded(path, true)) {
+            if self.options.show_ignored {
+                if self.options.collapse_directories ||
+                        self.options.collapse_ignored_directories {
+                    self.report_ignored(path, true);
+                } else {
+                    try!(self.scan(path));
+                }
+            }
+            return Ok(());
+        }
+
+        // A directory containing its own repository is never descended into.
+        if is_nested_repository(path) {
+            self.report_untracked(path, true);
+            return Ok(());
+        }
+
+        if !self.options.collapse_directories {
+            return self.scan(path);
+        }
+
+        let contents = try!(self.scan_untracked_directory(path));
+        if contents.has_untracked {
+            self.report_untracked(path, true);
+            for ignored in contents.ignored {
+                self.report_ignored(&ignored, ignored.ends_with(b"/"));
+            }
+        } else if !contents.ignored.is_empty() {
+            self.report_ignored(path, true);
+        } else if !self.options.hide_empty_directories {
+            self.report_untracked(path, true);
+        }
+
+        Ok(())
+    }
+
+    /// Find out whether a directory containing no tracked files has any untracked files in it, and
+    /// which of its files are ignored. Stops early if the ignored files are not needed.
+    fn scan_untracked_directory(&mut self, dir: &[u8]) -> Result<DirectoryContents, Error> {
+        let mut contents = DirectoryContents::default();
+
+        for (name, is_dir) in try!(self.read_directory(dir)) {
+            let path = join(dir, &name);
+            if try!(self.is_excluded(&path, is_dir)) {
+                let mut ignored = path;
+                if is_dir {
+                    ignored.push(b'/');
+                }
+                contents.ignored.push(ignored);
+            } else if is_dir {
+                let sub_contents = try!(self.scan_untracked_directory(&path));
+                contents.has_untracked = contents.has_untracked || sub_contents.has_untracked;
+                contents.ignored.extend(sub_contents.ignored);
+            } else {
+                contents.has_untracked = true;
+            }
+
+            if contents.has_untracked && !self.options.show_ignored {
+                break;
+            }
+        }
+
+        Ok(contents)
+    }
+
+    /// The object name recorded for the `.gitignore` in a directory. If the file is tracked and
+    /// unchanged, git records the object name from the index, so the same is done here.
+    fn hash_per_directory_exclude_file(&self, dir: &[u8]) -> Option<[u8; 20]> {
+        let path = join(dir, PER_DIRECTORY_EXCLUDE_FILE.as_bytes());
+        if let Some(&(ref stat, ref sha1)) = self.tracked_exclude_files.get(&path) {
+            let unchanged = fs::symlink_metadata(bytes_to_path(&path))
+                .map(|metadata| StatData::from_metadata(&metadata) == *stat)
+                .unwrap_or(false);
+            if unchanged && !self.is_racy(stat) {
+                return Some(objects::Name(sha1.clone()).to_bytes());
+            }
+        }
+
+        hash_file(bytes_to_path(&path))
+    }
+
+    fn is_racy(&self, stat: &StatData) -> bool {
+        self.index_timestamp.map(|t| t <= stat.mtime).unwrap_or(false)
+    }
+
+    /// Scan a directory that contains tracked files, reusing the cached results for it if neither
+    /// the directory nor the exclude patterns that apply to it have changed since it was cached.
+    fn scan_cached(&mut self, dir: &[u8], cached: &mut CachedDirectory,
+                   excludes_changed: bool) -> Result<(), Error> {
+        let metadata = try!(fs::symlink_metadata(fs_path(dir)).map_err(|e| to_scan_error(dir, e)));
+        let stat = StatData::from_metadata(&metadata);
+        let exclude_sha1 = self.hash_per_directory_exclude_file(dir);
+
+        // A change to the exclude patterns of a directory affects every directory below it too.
+        let excludes_changed = excludes_changed || exclude_sha1 != cached.exclude_sha1;
+        if excludes_changed {
+            cached.invalidate_all();
+        }
+
+        let is_valid = cached.valid && !cached.check_only && cached.stat == stat &&
+            !self.is_racy(&cached.stat);
+
+        if is_valid {
+            // The entries of this directory haven't changed, but files may have been added to or
+            // removed from the untracked directories in it, which decides whether they're listed.
+            // Nested repositories are the only untracked directories without a cached entry.
+            let mut untracked: Vec<Vec<u8>> = cached.untracked.iter().filter(|name| {
+                !name.ends_with(b"/") ||
+                    !cached.dirs.iter().any(|child| child.name == name[..name.len() - 1])
+            }).cloned().collect();
+
+            for child in cached.dirs.iter_mut() {
+                let path = join(dir, &child.name);
+                if self.tracked_dirs.contains(&path) {
+                    try!(self.scan_cached(&path, child, excludes_changed));
+                } else if try!(self.check_cached(&path, child, excludes_changed)) {
+                    let mut entry = child.name.clone();
+                    entry.push(b'/');
+                    untracked.push(entry);
+                }
+            }
+
+            untracked.sort();
+            for name in untracked.iter() {
+                let is_dir = name.ends_with(b"/");
+                let path = join(dir, if is_dir { &name[..name.len() - 1] } else { name });
+                self.report_untracked(&path, is_dir);
+            }
+            cached.untracked = untracked;
+            return Ok(());
+        }
+
+        // The directory has to be read again. The results for this directory are the same as the
+        // ones `scan_entry` would report with the options the cache is built for: untracked files,
+        // and collapsed directories containing at least one untracked file.
+        let mut previous_dirs = mem::replace(&mut cached.dirs, Vec::new());
+        let mut untracked = Vec::new();
+
+        for (name, is_dir) in try!(self.read_directory(dir)) {
+            let path = join(dir, &name);
+            if is_dir && self.tracked_dirs.contains(&path) {
+                let mut child = take_cached_directory(&mut previous_dirs, &name);
+                try!(self.scan_cached(&path, &mut child, excludes_changed));
+                cached.dirs.push(child);
+            } else if !is_dir && self.tracked.contains(&path) {
+                continue;
+            } else if try!(self.is_excluded(&path, is_dir)) {
+                continue;
+            } else if !is_dir {
+                self.report_untracked(&path, false);
+                untracked.push(name);
+            } else {
+                let has_untracked = if is_nested_repository(&path) {
+                    true
+                } else {
+                    let mut child = take_cached_directory(&mut previous_dirs, &name);
+                    let has_untracked =
+                        try!(self.check_cached(&path, &mut child, excludes_changed));
+                    cached.dirs.push(child);
+                    has_untracked
+                };
+
+                if has_untracked {
+                    self.report_untracked(&path, true);
+                    let mut entry = name;
+                    entry.push(b'/');
+                    untracked.push(entry);
+                }
+            }
+        }
+
+        untracked.sort();
+        cached.dirs.sort_by(|a, b| a.name.cmp(&b.name));
+        cached.untracked = untracked;
+        cached.stat = stat;
+        cached.exclude_sha1 = exclude_sha1;
+        cached.check_only = false;
+        cached.valid = !self.is_racy(&stat);
+        Ok(())
+    }
+
+    /// Check whether a directory containing no tracked files has any untracked files in it, like
+    /// `scan_untracked_directory` does for the options the cache is built for, reusing the cached
+    /// result if neither it nor the directories it was checked through have changed. Like in git,
+    /// the check stops at the first untracked entry, which is all the cached directory lists.
+    fn check_cached(&mut self, dir: &[u8], cached: &mut CachedDirectory,
+                    excludes_changed: bool) -> Result<bool, Error> {
+        let metadata = try!(fs::symlink_metadata(fs_path(dir)).map_err(|e| to_scan_error(dir, e)));
+        let stat = StatData::from_metadata(&metadata);
+        let exclude_sha1 = self.hash_per_directory_exclude_file(dir);
+
+        let excludes_changed = excludes_changed || exclude_sha1 != cached.exclude_sha1;
+        if excludes_changed {
+            cached.invalidate_all();
+        }
+
+        if cached.valid && cached.check_only && cached.stat == stat &&
+                !self.is_racy(&cached.stat) {
+            // The subdirectories that were checked have to be checked again, as adding a file to
+            // one of them doesn't change this directory. The cached result only stands if they
+            // still agree with it, as the check stopped at the first untracked entry.
+            let mut has_untracked = cached.untracked.iter().any(|name| !name.ends_with(b"/"));
+            for child in cached.dirs.iter_mut() {
+                let path = join(dir, &child.name);
+                if try!(self.check_cached(&path, child, excludes_changed)) {
+                    has_untracked = true;
+                }
+            }
+            if has_untracked == !cached.untracked.is_empty() {
+                return Ok(has_untracked);
+            }
+        }
+
+        let mut previous_dirs = mem::replace(&mut cached.dirs, Vec::new());
+        let mut untracked = Vec::new();
+        for (name, is_dir) in try!(self.read_directory(dir)) {
+            let path = join(dir, &name);
+            if try!(self.is_excluded(&path, is_dir)) {
+                continue;
+            } else if !is_dir {
+                untracked.push(name);
+                break;
+            } else if is_nested_repository(&path) {
+                let mut entry = name;
+                entry.push(b'/');
+                untracked.push(entry);
+                break;
+            }
+
+            let mut child = take_cached_directory(&mut previous_dirs, &name);
+            let has_untracked = try!(self.check_cached(&path, &mut child, excludes_changed));
+            cached.dirs.push(child);
+            if has_untracked {
+                let mut entry = name;
+                entry.push(b'/');
+                untracked.push(entry);
+                break;
+            }
+        }
+
+        let has_untracked = !untracked.is_empty();
+        cached.untracked = untracked;
+        cached.stat = stat;
+        cached.exclude_sha1 = exclude_sha1;
+        cached.check_only = true;
+        cached.valid = !self.is_racy(&stat);
+        Ok(has_untracked)
+    }
+}
+
+/// Remove the cached directory with the given name from the ones cached before a directory was
+/// scanned again, or create a new one if it wasn't cached.
+fn take_cached_directory(previous: &mut Vec<CachedDirectory>, name: &[u8]) -> CachedDirectory {
+    match previous.iter().position(|dir| dir.name == name) {
+        Some(i) => previous.swap_remove(i),
+        None => CachedDirectory::new(name),
+    }
+}
+
+/// Whether the directory contains its own repository, in which case it is never descended into.
+fn is_nested_repository(path: &[u8]) -> bool {
+    bytes_to_path(path).join(".git").exists()
+}
+
+/// The path reported for an untracked entry, with a trailing slash for directories.
+fn display_path(path: &[u8], is_dir: bool) -> PathBuf {
+    let mut path = path.to_vec();
+    if is_dir {
+        path.push(b'/');
+    }
+    PathBuf::from(OsStr::from_bytes(&path))
+}
+
+/// Decide what to do with the untracked cache based on `core.untrackedCache`: `Some(true)` to
+/// create or update it, `Some(false)` to remove it, and `None`, such as for `keep`, to update it
+/// only if it exists.
+fn untracked_cache_setting(config: &config::Config) -> Option<bool> {
+    config.get_bool("core.untrackedcache").unwrap_or(None)
+}
+
+/// Find the untracked files in the working directory, as described by the options. Like the paths
+/// in the index, the returned paths are relative to the root of the repository. Directories are
+/// reported with a trailing slash.
+pub fn scan(index: &mut Index, config: &config::Config, excludes: &mut ignore::Excludes,
+            options: &Options) -> Result<(index::UntrackedFiles, bool), Error> {
+    let setting = untracked_cache_setting(config);
+    let existing_cache = if options.use_untracked_cache && setting != Some(false) {
+        try!(index.untracked_cache())
+    } else {
+        None
+    };
+
+    let use_cache = options.can_use_untracked_cache() &&
+        (setting == Some(true) || existing_cache.is_some());
+
+    let mut scanner = Scanner::new(index, excludes, *options);
+    if !use_cache {
+        try!(scanner.scan(b""));
+
+        let cache_removed = options.use_untracked_cache && setting == Some(false) &&
+            index.extension(&index::UNTRACKED_CACHE_SIGNATURE).is_some();
+        if cache_removed {
+            index.remove_extension(&index::UNTRACKED_CACHE_SIGNATURE);
+        }
+        return Ok((scanner.files, cache_removed));
+    }
+
+    let dir_flags = options.dir_flags();
+    let mut cache = match existing_cache {
+        Some(ref cache) if cache.is_for_current_environment() &&
+            cache.dir_flags == dir_flags &&
+            cache.exclude_per_dir == PER_DIRECTORY_EXCLUDE_FILE.as_bytes() => cache.clone(),
+        _ => UntrackedCache::new(dir_flags),
+    };
+
+    // Any change to the global exclude files affects every directory.
+    let info_exclude = ExcludeFileState::for_file(Some(&repository::git_path(INFO_EXCLUDE_FILE)));
+    let excludes_file_path = ignore::excludes_file_path(config);
+    let excludes_file =
+        ExcludeFileState::for_file(excludes_file_path.as_ref().map(|p| p.as_path()));
+    if info_exclude.sha1 != cache.info_exclude.sha1 ||
+            excludes_file.sha1 != cache.excludes_file.sha1 {
+        cache.root = None;
+    }
+    cache.info_exclude = info_exclude;
+    cache.excludes_file = excludes_file;
+
+    let mut root = cache.root.take().unwrap_or_else(|| CachedDirectory::new(b""));
+    try!(scanner.scan_cached(b"", &mut root, false));
+    cache.root = Some(root);
+
+    let changed = existing_cache.as_ref() != Some(&cache);
+    if changed {
+        index.set_untracked_cache(&cache);
+    }
+    Ok((scanner.files, changed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ewah_bitmaps_round_trip() {
+        let bits = vec![true, false, false, true, false, true];
+        let mut data = Vec::new();
+        write_ewah(&mut data, &bits).unwrap();
+        assert_eq!(read_ewah(&mut Cursor::new(&data[..]), bits.len()).unwrap(), bits);
+    }
+
+    /// Scan a working tree without tracked files, given by its absolute path, using and updating
+    /// the cached directory for its root. Returns the untracked paths relative to the root.
+    fn scan_with_cache(root: &Path, cached: &mut CachedDirectory) -> Vec<String> {
+        let index = Index::new();
+        let mut excludes = ignore::Excludes::new();
+        let options = Options {
+            collapse_directories: true,
+            hide_empty_directories: true,
+            show_ignored: false,
+            collapse_ignored_directories: false,
+            use_untracked_cache: true,
+        };
+        let mut scanner = Scanner::new(&index, &mut excludes, options);
+        scanner.scan_cached(root.as_os_str().as_bytes(), cached, false).unwrap();
+
+        let prefix = format!("{}/", root.display());
+        let mut paths: Vec<_> = scanner.files.untracked.iter()
+            .map(|path| path.display().to_string()[prefix.len()..].to_string())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn notices_files_added_to_untracked_directories() {
+        use std::process;
+
+        let root = env::temp_dir().join(format!("gitters-untracked-test-{}", process::id()));
+        fs::create_dir_all(root.join("newdir")).unwrap();
+
+        let mut cached = CachedDirectory::new(b"");
+        assert_eq!(scan_with_cache(&root, &mut cached), Vec::<String>::new());
+        assert_eq!(cached.dirs.len(), 1);
+        assert!(cached.dirs[0].check_only);
+
+        File::create(root.join("newdir/file")).unwrap();
+        let untracked = scan_with_cache(&root, &mut cached);
+        let reused = scan_with_cache(&root, &mut cached);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(untracked, vec!["newdir/"]);
+        assert_eq!(reused, vec!["newdir/"]);
+    }
+
+    #[test]
+    fn rejects_truncated_ewah_bitmaps() {
+        let mut data = Vec::new();
+        write_ewah(&mut data, &[true; 130]).unwrap();
+
+        // Claim one fewer word than was written, cutting off the last literal word.
+        data[7] -= 1;
+        data.drain(8 + 8 * 3..8 + 8 * 4);
+        let err = read_ewah(&mut Cursor::new(&data[..]), 130).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_ewah_bitmaps_with_impossible_sizes() {
+        // A word count far beyond the bytes that follow it.
+        let mut data = Vec::new();
+        data.write_u32::<NetworkEndian>(2).unwrap();
+        data.write_u32::<NetworkEndian>(0xffffffff).unwrap();
+        data.write_u64::<NetworkEndian>(0).unwrap();
+        let err = read_ewah(&mut Cursor::new(&data[..]), 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // More bits than there are directories.
+        let mut data = Vec::new();
+        data.write_u32::<NetworkEndian>(0xffffffff).unwrap();
+        data.write_u32::<NetworkEndian>(0).unwrap();
+        data.write_u32::<NetworkEndian>(0).unwrap();
+        let err = read_ewah(&mut Cursor::new(&data[..]), 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn stops_expanding_ewah_runs_at_the_bit_size() {
+        // A single marker word with the longest possible run of set words.
+        let mut data = Vec::new();
+        data.write_u32::<NetworkEndian>(3).unwrap();
+        data.write_u32::<NetworkEndian>(1).unwrap();
+        data.write_u64::<NetworkEndian>(0xffffffff << 1 | 1).unwrap();
+        data.write_u32::<NetworkEndian>(0).unwrap();
+        assert_eq!(read_ewah(&mut Cursor::new(&data[..]), 3).unwrap(), vec![true; 3]);
+    }
+
+    #[test]
+    fn rejects_directory_blocks_beyond_the_count_or_depth() {
+        // Each block has no untracked files and a single directory inside it, named "a".
+        let chain = |blocks: usize| {
+            let mut data = Vec::new();
+            for _ in 0..blocks {
+                data.extend_from_slice(b"\x00\x01a\x00");
+            }
+            data
+        };
+
+        let data = chain(10);
+        let err = read_directory_blocks(&mut Cursor::new(&data[..]), 5).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let data = chain(1000000);
+        let err = read_directory_blocks(&mut Cursor::new(&data[..]), 1000000).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // As deep as a directory can be nested, the blocks are still read.
+        let mut data = chain(MAX_DIRECTORY_DEPTH);
+        data.extend_from_slice(b"\x00\x00a\x00");
+        let root = read_directory_blocks(&mut Cursor::new(&data[..]), 1000000).unwrap();
+        assert_eq!(MAX_DIRECTORY_DEPTH + 1, root.count());
+    }
+
+    #[test]
+    fn untracked_cache_round_trips() {
+        let mut cache =
+            UntrackedCache::new(DIR_SHOW_OTHER_DIRECTORIES | DIR_HIDE_EMPTY_DIRECTORIES);
+        cache.info_exclude.sha1 = Some([1; 20]);
+
+        let mut child = CachedDirectory::new(b"src");
+        child.valid = true;
+        child.untracked = vec![b"new.rs".to_vec(), b"scratch/".to_vec()];
+        child.stat.mtime.seconds = 42;
+
+        let mut root = CachedDirectory::new(b"");
+        root.exclude_sha1 = Some([2; 20]);
+        root.dirs.push(child);
+        root.dirs.push(CachedDirectory::new(b"tests"));
+        cache.root = Some(root);
+
+        let mut data = Vec::new();
+        cache.write(&mut data).unwrap();
+        assert_eq!(UntrackedCache::parse(&data).unwrap(), cache);
+    }
+}
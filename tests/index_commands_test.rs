@@ -0,0 +1,217 @@
+//! Runs the commands that work with the index in an empty repository, checking what they report
+//! and, through `ls-files`, what they leave in the index.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// A working tree with an empty repository, in a temporary directory that's removed once dropped.
+struct Worktree {
+    dir: PathBuf,
+}
+
+impl Worktree {
+    fn new(name: &str) -> Worktree {
+        let dir = env::temp_dir().join(format!("gitters-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join(".git/objects")).unwrap();
+        fs::create_dir_all(dir.join(".git/refs/heads")).unwrap();
+        fs::write(dir.join(".git/HEAD"), "ref: refs/heads/master\n").unwrap();
+        Worktree { dir: dir }
+    }
+
+    fn write(&self, path: &str, contents: &str) {
+        let path = self.dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.dir.join(path).exists()
+    }
+
+    /// Run one of the commands from the root of the working tree, without reading the
+    /// configuration of the user running the tests.
+    fn run(&self, command: &str, args: &[&str]) -> Output {
+        Command::new(command)
+            .args(args)
+            .current_dir(&self.dir)
+            .env_remove("GIT_DIR")
+            .env("HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", self.dir.join(".config"))
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .output()
+            .unwrap()
+    }
+
+    fn ls_files(&self) -> Vec<String> {
+        let output = self.run(env!("CARGO_BIN_EXE_ls-files"), &[]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap().lines().map(|line| line.to_string()).collect()
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn add(worktree: &Worktree, args: &[&str]) -> Output {
+    worktree.run(env!("CARGO_BIN_EXE_add"), args)
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+fn assert_success(output: &Output) {
+    assert!(output.status.success(), "{}", stderr(output));
+}
+
+#[test]
+fn add_stages_files_and_directories() {
+    let worktree = Worktree::new("add-files");
+    worktree.write("README.md", "readme\n");
+    worktree.write("src/lib.rs", "lib\n");
+    worktree.write("src/main.rs", "main\n");
+    worktree.write("notes.txt", "notes\n");
+
+    assert_success(&add(&worktree, &["README.md", "src"]));
+    assert_eq!(vec!["README.md", "src/lib.rs", "src/main.rs"], worktree.ls_files());
+}
+
+#[test]
+fn add_skips_ignored_files_inside_a_named_directory() {
+    let worktree = Worktree::new("add-ignored-inside");
+    worktree.write(".gitignore", "*.o\n");
+    worktree.write("src/lib.rs", "lib\n");
+    worktree.write("src/lib.o", "object\n");
+
+    assert_success(&add(&worktree, &["src"]));
+    assert_eq!(vec!["src/lib.rs"], worktree.ls_files());
+}
+
+#[test]
+fn add_refuses_ignored_paths_it_names() {
+    let worktree = Worktree::new("add-ignored-named");
+    worktree.write(".gitignore", "build/\n*.o\n");
+    worktree.write("lib.o", "object\n");
+    worktree.write("build/out.bin", "binary\n");
+    worktree.write("src/lib.rs", "lib\n");
+
+    for pathspec in ["lib.o", "build", "build/out.bin"].iter() {
+        let output = add(&worktree, &["src", pathspec]);
+        assert_eq!(Some(1), output.status.code());
+        assert!(stderr(&output).contains(&format!("ignored by one of your .gitignore files:\n{}\n",
+                                                  pathspec)),
+                "{}", stderr(&output));
+    }
+
+    // The paths that aren't ignored are still added.
+    assert_eq!(vec!["src/lib.rs"], worktree.ls_files());
+}
+
+#[test]
+fn add_stages_ignored_paths_when_forced() {
+    let worktree = Worktree::new("add-force");
+    worktree.write(".gitignore", "build/\n");
+    worktree.write("build/out.bin", "binary\n");
+
+    assert_success(&add(&worktree, &["-f", "build"]));
+    assert_eq!(vec!["build/out.bin"], worktree.ls_files());
+}
+
+#[test]
+fn add_fails_for_pathspecs_matching_nothing() {
+    let worktree = Worktree::new("add-no-match");
+    worktree.write("README.md", "readme\n");
+
+    let output = add(&worktree, &["README.md", "missing"]);
+    assert_eq!(Some(128), output.status.code());
+    assert!(stderr(&output).contains("pathspec 'missing' did not match any files"));
+    assert!(worktree.ls_files().is_empty());
+}
+
+#[test]
+fn rm_refuses_staged_files_unless_forced_or_cached() {
+    let worktree = Worktree::new("rm");
+    worktree.write("a.txt", "a\n");
+    worktree.write("b.txt", "b\n");
+    assert_success(&add(&worktree, &["a.txt", "b.txt"]));
+
+    let rm = env!("CARGO_BIN_EXE_rm");
+    let output = worktree.run(rm, &["a.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(stderr(&output).contains("changes staged in the index"), "{}", stderr(&output));
+    assert_eq!(vec!["a.txt", "b.txt"], worktree.ls_files());
+
+    assert_success(&worktree.run(rm, &["--cached", "a.txt"]));
+    assert!(worktree.exists("a.txt"));
+    assert_eq!(vec!["b.txt"], worktree.ls_files());
+
+    assert_success(&worktree.run(rm, &["-f", "b.txt"]));
+    assert!(!worktree.exists("b.txt"));
+    assert!(worktree.ls_files().is_empty());
+}
+
+#[test]
+fn mv_renames_tracked_files() {
+    let worktree = Worktree::new("mv");
+    worktree.write("a.txt", "a\n");
+    worktree.write("untracked.txt", "untracked\n");
+    assert_success(&add(&worktree, &["a.txt"]));
+
+    let mv = env!("CARGO_BIN_EXE_mv");
+    assert_success(&worktree.run(mv, &["a.txt", "b.txt"]));
+    assert!(!worktree.exists("a.txt"));
+    assert!(worktree.exists("b.txt"));
+    assert_eq!(vec!["b.txt"], worktree.ls_files());
+
+    let output = worktree.run(mv, &["untracked.txt", "c.txt"]);
+    assert_eq!(Some(128), output.status.code());
+    assert!(stderr(&output).contains("not under version control"), "{}", stderr(&output));
+    assert!(worktree.exists("untracked.txt"));
+}
+
+fn check_ignore(worktree: &Worktree, args: &[&str]) -> (Option<i32>, String) {
+    let output = worktree.run(env!("CARGO_BIN_EXE_check-ignore"), args);
+    (output.status.code(), String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn check_ignore_lists_ignored_paths() {
+    let worktree = Worktree::new("check-ignore");
+    worktree.write(".gitignore", "*.o\nbuild/\n");
+    worktree.write("lib.o", "object\n");
+    worktree.write("lib.rs", "lib\n");
+
+    assert_eq!((Some(0), "lib.o\nbuild/out.bin\n".to_string()),
+               check_ignore(&worktree, &["lib.o", "lib.rs", "build/out.bin"]));
+    assert_eq!((Some(1), String::new()), check_ignore(&worktree, &["lib.rs"]));
+}
+
+#[test]
+fn check_ignore_shows_matching_patterns_when_verbose() {
+    let worktree = Worktree::new("check-ignore-verbose");
+    worktree.write(".gitignore", "# objects\n*.o\n");
+
+    assert_eq!((Some(0), ".gitignore:2:*.o\tlib.o\n".to_string()),
+               check_ignore(&worktree, &["-v", "lib.o"]));
+    assert_eq!((Some(1), "::\tlib.rs\n".to_string()),
+               check_ignore(&worktree, &["-v", "-n", "lib.rs"]));
+    assert_eq!((Some(1), String::new()), check_ignore(&worktree, &["-n", "lib.rs"]));
+}
+
+#[test]
+fn check_ignore_skips_tracked_files_unless_told_not_to_use_the_index() {
+    let worktree = Worktree::new("check-ignore-tracked");
+    worktree.write(".gitignore", "*.o\n");
+    worktree.write("lib.o", "object\n");
+    assert_success(&add(&worktree, &["-f", "lib.o"]));
+
+    assert_eq!((Some(1), String::new()), check_ignore(&worktree, &["lib.o"]));
+    assert_eq!((Some(0), "lib.o\n".to_string()),
+               check_ignore(&worktree, &["--no-index", "lib.o"]));
+}